@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use optionalize_macro::Optionalize;
+
+/// Bench struct shaped like a typical request body
+#[derive(Optionalize, Clone)]
+#[optionalize(derive(Clone), impls(apply, merge))]
+pub struct Account {
+    #[optionalize(immutable)]
+    pub id: u64,
+    pub name: String,
+    pub email: String,
+    pub tags: Vec<String>,
+    #[optionalize(range(max = 150))]
+    pub age: u8,
+    #[optionalize(nested)]
+    pub limits: Limits,
+}
+
+#[derive(Optionalize, Clone)]
+#[optionalize(derive(Clone), impls(apply, merge))]
+pub struct Limits {
+    pub requests: u32,
+    pub burst: u32,
+}
+
+fn account() -> Account {
+    Account {
+        id: 1,
+        name: "Ada".to_string(),
+        email: "ada@example.com".to_string(),
+        tags: vec!["admin".to_string()],
+        age: 36,
+        limits: Limits { requests: 10, burst: 20 },
+    }
+}
+
+fn patch() -> AccountOptional {
+    AccountOptional {
+        id: None,
+        name: Some("Grace".to_string()),
+        email: None,
+        tags: Some(vec!["ops".to_string()]),
+        age: Some(37),
+        limits: Some(LimitsOptional { requests: Some(20), burst: None }),
+    }
+}
+
+fn apply(c: &mut Criterion) {
+    c.bench_function("apply_to", |b| {
+        b.iter_batched(|| (patch(), account()), |(patch, mut account)| {
+            patch.apply_to(&mut account);
+            account
+        }, BatchSize::SmallInput)
+    });
+    c.bench_function("try_apply", |b| {
+        b.iter_batched(|| (patch(), account()), |(patch, mut account)| {
+            patch.try_apply(&mut account).unwrap();
+            account
+        }, BatchSize::SmallInput)
+    });
+    c.bench_function("merge", |b| {
+        b.iter_batched(|| (patch(), patch()), |(first, second)| first.merge(second), BatchSize::SmallInput)
+    });
+}
+
+criterion_group!(benches, apply);
+criterion_main!(benches);
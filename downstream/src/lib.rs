@@ -0,0 +1,3 @@
+//! A crate using the macros only through the `optionalize` runtime crate, as
+//! downstream crates do, so generated paths that assume a direct dependency
+//! on `optionalize_macro` fail to build here.
@@ -0,0 +1,24 @@
+use optionalize::{ApplyPatch, Optionalize};
+
+/// Test module deriving through the re-exported attribute
+#[optionalize::optionalize_all(derive(Debug, Default), impls(apply))]
+mod settings {
+    #[derive(Debug, PartialEq)]
+    pub struct Theme {
+        pub dark: bool,
+        pub accent: String,
+    }
+}
+
+#[test]
+fn test_optionalize_all() {
+    fn field_count<T: Optionalize>() -> usize {
+        T::FIELDS.len()
+    }
+
+    let mut theme = settings::Theme { dark: false, accent: "blue".to_string() };
+    let patch = settings::ThemeOptional { dark: Some(true), ..Default::default() };
+    ApplyPatch::apply_to(patch, &mut theme);
+    assert_eq!(theme, settings::Theme { dark: true, accent: "blue".to_string() });
+    assert_eq!(field_count::<settings::Theme>(), 2);
+}
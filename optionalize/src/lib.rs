@@ -0,0 +1,523 @@
+//! Traits and types shared by the code `optionalize_macro` generates, so
+//! patches of different structs, or from different crates, can be handled
+//! generically.
+//!
+//! The derives are re-exported here along with the traits, and every struct
+//! deriving [`Optionalize`](derive@Optionalize) implements those its picked
+//! groups of impls back: `Optionalize`, `ApplyPatch` and `AnyPatch` with
+//! `apply`, `Merge` and `Patch` with `merge` as well, and `Diff` with `diff`.
+//!
+//! ```rust
+//! use optionalize::{ApplyPatch, Optionalize};
+//!
+//! #[derive(Optionalize)]
+//! #[optionalize(impls(apply))]
+//! pub struct User {
+//!     pub name: String,
+//!     pub age: u32,
+//! }
+//!
+//! fn apply_all<T: Optionalize>(target: &mut T, patches: Vec<T::Optional>) {
+//!     for patch in patches {
+//!         patch.apply_to(target);
+//!     }
+//! }
+//!
+//! let mut user = User { name: "Ada".to_string(), age: 36 };
+//! apply_all(&mut user, vec![UserOptional { name: None, age: Some(37) }]);
+//! assert_eq!(user.age, 37);
+//! ```
+
+use std::any::Any;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+
+pub use optionalize_macro::{optionalize_all, Deoptionalize, Optionalize, Partial};
+
+/// A struct with a generated optional twin, its patch type.
+pub trait Optionalize: Sized {
+    /// The generated struct, e.g. `UserOptional` for `User`.
+    type Optional: ApplyPatch<Self> + From<Self>;
+
+    /// The fields of the struct, in declaration order.
+    const FIELDS: &'static [FieldMeta];
+}
+
+/// Description of a field of a struct deriving [`Optionalize`](derive@Optionalize),
+/// for tools that introspect patch types at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldMeta {
+    /// Name of the field.
+    pub name: &'static str,
+    /// The field's type in the original struct, as written, e.g. `Option<String>`.
+    pub ty: &'static str,
+    /// Whether the field was already an `Option` in the original struct.
+    pub optional: bool,
+    /// The field's doc comment, without the leading `///`, or an empty string.
+    pub doc: &'static str,
+}
+
+/// A patch that can be written onto a value of `T`.
+pub trait ApplyPatch<T> {
+    /// Writes every set field onto `target`, leaving unset fields untouched.
+    fn apply_to(self, target: &mut T);
+}
+
+/// A patch of any struct, usable as `dyn AnyPatch` so patches for different
+/// types can be queued and processed in one collection.
+///
+/// Implemented for the generated struct of every struct without lifetime
+/// parameters.
+pub trait AnyPatch: Any {
+    /// Name of the struct the patch applies to, e.g. `User`.
+    fn target_name(&self) -> &'static str;
+
+    /// Names of the fields that are set, in declaration order.
+    fn set_fields(&self) -> Vec<&'static str>;
+
+    /// Writes every set field onto `target` if it is a value of the struct the
+    /// patch applies to, and hands the patch back otherwise.
+    fn apply_any(self: Box<Self>, target: &mut dyn Any) -> Result<(), Box<dyn AnyPatch>>;
+
+    /// The patch as JSON, if the generated struct derives `Serialize` and has
+    /// `#[optionalize(serde_json)]`.
+    fn to_json(&self) -> Option<String> {
+        None
+    }
+
+    /// The patch as `Any`, to downcast it back to the generated struct.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A patch that can be combined with another one of the same type.
+///
+/// Only implemented for patches whose `merge` can't fail, i.e. without
+/// `#[optionalize(merge = "error_on_conflict")]`.
+pub trait Merge {
+    /// Combines two patches according to the struct's merge policy.
+    fn merge(self, other: Self) -> Self;
+}
+
+/// The methods of a generated struct that the patch of another struct relies
+/// on when it nests it through a type parameter, e.g. `Entry<T>` with
+/// `#[optionalize(nested)] value: T`, whose patch holds a `T::Optional`.
+///
+/// Implemented for the generated struct of every struct whose `merge` can't
+/// fail, forwarding to its own methods.
+pub trait Patch<T>: ApplyPatch<T> + Merge + Sized {
+    /// The patch with every field unset.
+    fn empty() -> Self;
+
+    /// Whether no field is set.
+    fn is_empty(&self) -> bool;
+
+    /// Names of the required fields left unset, which building a value from
+    /// the patch needs.
+    fn missing_fields(&self) -> Vec<&'static str>;
+
+    /// Unsets every field whose name isn't in `mask`.
+    fn retain_masked(&mut self, mask: &[&str]);
+
+    /// Writes every set field onto `target` and returns the patch undoing it.
+    fn apply_with_inverse(self, target: &mut T) -> Self;
+
+    #[doc(hidden)]
+    fn __optionalize_fill(self, rest: Self) -> Self;
+}
+
+/// The diffing methods of a generated struct, which [`Patch`] leaves out as
+/// they need the fields to be comparable and cloneable.
+///
+/// Implemented for the generated struct of every struct that generates
+/// `diff`.
+pub trait Diff<T>: Sized {
+    /// The patch that turns `base` into `target`.
+    fn diff(base: &T, target: &T) -> Self;
+
+    /// The patch undoing this one on `base`.
+    fn invert(&self, base: &T) -> Self;
+
+    /// Names of the fields applying the patch would change on `base`.
+    fn changed_fields(&self, base: &T) -> Vec<&'static str>;
+}
+
+/// Error converting a value into one whose fields are all required, listing
+/// the fields that were missing. Every error generated by
+/// [`Deoptionalize`](derive@Deoptionalize) converts into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFieldsError {
+    /// The required fields that were `None`, in declaration order.
+    pub missing: Vec<&'static str>,
+}
+
+impl fmt::Display for MissingFieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing required fields: {}", self.missing.join(", "))
+    }
+}
+
+impl Error for MissingFieldsError {}
+
+/// A tri-state value telling an absent field apart from one explicitly set to
+/// `null`, as found in GraphQL and JSON merge patches.
+///
+/// Use it with `#[optionalize(option_like(MaybeUndefined = "is_undefined"))]`
+/// to keep it as-is in the generated struct.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MaybeUndefined<T> {
+    /// The field was absent, leaving the value untouched.
+    #[default]
+    Undefined,
+    /// The field was explicitly cleared.
+    Null,
+    /// The field was set to a value.
+    Value(T),
+}
+
+impl<T> MaybeUndefined<T> {
+    /// Whether the field was absent.
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, MaybeUndefined::Undefined)
+    }
+
+    /// Whether the field was explicitly cleared.
+    pub fn is_null(&self) -> bool {
+        matches!(self, MaybeUndefined::Null)
+    }
+
+    /// The value as a double `Option`: `None` when absent, `Some(None)` when
+    /// cleared and `Some(Some(value))` when set.
+    pub fn into_option(self) -> Option<Option<T>> {
+        match self {
+            MaybeUndefined::Undefined => None,
+            MaybeUndefined::Null => Some(None),
+            MaybeUndefined::Value(value) => Some(Some(value)),
+        }
+    }
+}
+
+impl<T> From<Option<Option<T>>> for MaybeUndefined<T> {
+    fn from(value: Option<Option<T>>) -> Self {
+        match value {
+            None => MaybeUndefined::Undefined,
+            Some(None) => MaybeUndefined::Null,
+            Some(Some(value)) => MaybeUndefined::Value(value),
+        }
+    }
+}
+
+/// A wrapper holding each field of a patch generated with
+/// `#[optionalize(wrapper)]`, as a type constructor: `Option`, `Cell<Option<T>>`
+/// or [`MaybeUndefined`], or one of your own.
+///
+/// The generated `{Optional}Wrapped<W>`, e.g. `UserOptionalWrapped<W>`, stores
+/// each field as `W::Of<T>` and converts to and from the plain patch through
+/// [`wrap`](Self::wrap) and [`unwrap`](Self::unwrap).
+pub trait Wrapper {
+    /// The wrapped form of a field holding a `T`.
+    type Of<T>;
+
+    /// Wraps the value of a field, `None` when it is unset.
+    fn wrap<T>(value: Option<T>) -> Self::Of<T>;
+
+    /// The value of a wrapped field, `None` when it is unset.
+    fn unwrap<T>(wrapped: Self::Of<T>) -> Option<T>;
+}
+
+/// Fields held as plain `Option`s, as in the generated struct itself.
+pub enum OptionWrapper {}
+
+impl Wrapper for OptionWrapper {
+    type Of<T> = Option<T>;
+
+    fn wrap<T>(value: Option<T>) -> Option<T> {
+        value
+    }
+
+    fn unwrap<T>(wrapped: Option<T>) -> Option<T> {
+        wrapped
+    }
+}
+
+/// Fields held in `Cell<Option<T>>`s, so a shared patch can be filled in
+/// field by field.
+pub enum CellWrapper {}
+
+impl Wrapper for CellWrapper {
+    type Of<T> = Cell<Option<T>>;
+
+    fn wrap<T>(value: Option<T>) -> Cell<Option<T>> {
+        Cell::new(value)
+    }
+
+    fn unwrap<T>(wrapped: Cell<Option<T>>) -> Option<T> {
+        wrapped.into_inner()
+    }
+}
+
+/// Fields held as [`MaybeUndefined`]s. A plain patch has no way to clear a
+/// field, so `Null` reads back as unset, like `Undefined`.
+pub enum MaybeUndefinedWrapper {}
+
+impl Wrapper for MaybeUndefinedWrapper {
+    type Of<T> = MaybeUndefined<T>;
+
+    fn wrap<T>(value: Option<T>) -> MaybeUndefined<T> {
+        match value {
+            Some(value) => MaybeUndefined::Value(value),
+            None => MaybeUndefined::Undefined,
+        }
+    }
+
+    fn unwrap<T>(wrapped: MaybeUndefined<T>) -> Option<T> {
+        match wrapped {
+            MaybeUndefined::Value(value) => Some(value),
+            MaybeUndefined::Null | MaybeUndefined::Undefined => None,
+        }
+    }
+}
+
+/// A comparison a field of a filter generated with `#[optionalize(filter)]`
+/// holds against its value.
+///
+/// Comparisons follow SQL semantics: a field that is `None` in the value
+/// matches no comparison, not even `Ne`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cmp<T> {
+    /// The value is equal to this one.
+    Eq(T),
+    /// The value isn't equal to this one.
+    Ne(T),
+    /// The value is greater than this one.
+    Gt(T),
+    /// The value is less than this one.
+    Lt(T),
+    /// The value, as a string, matches this SQL `LIKE` pattern, where `%`
+    /// stands for any run of characters and `_` for a single one.
+    Like(String),
+    /// The value is one of these. An empty list matches nothing.
+    In(Vec<T>),
+}
+
+impl<T> Cmp<T> {
+    /// Whether `value` satisfies the comparison.
+    pub fn matches(&self, value: &T) -> bool
+    where
+        T: PartialOrd + fmt::Display,
+    {
+        match self {
+            Cmp::Eq(other) => value == other,
+            Cmp::Ne(other) => value != other,
+            Cmp::Gt(other) => value > other,
+            Cmp::Lt(other) => value < other,
+            Cmp::Like(pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let text: Vec<char> = value.to_string().chars().collect();
+                like(&pattern, &text)
+            }
+            Cmp::In(others) => others.contains(value),
+        }
+    }
+
+    /// Renders the comparison of `column` as an SQL condition, pushing the
+    /// values to bind onto `values`. `placeholder` renders the placeholder of
+    /// the value at a 1-based index for a column, as for `to_set_clause`.
+    pub fn to_sql<V>(
+        &self,
+        column: &str,
+        placeholder: &impl Fn(usize, &str) -> String,
+        values: &mut Vec<V>,
+    ) -> String
+    where
+        T: Clone + Into<V>,
+        String: Into<V>,
+    {
+        let mut bind = |value: V| {
+            values.push(value);
+            placeholder(values.len(), column)
+        };
+        match self {
+            Cmp::Eq(value) => format!("\"{}\" = {}", column, bind(value.clone().into())),
+            Cmp::Ne(value) => format!("\"{}\" <> {}", column, bind(value.clone().into())),
+            Cmp::Gt(value) => format!("\"{}\" > {}", column, bind(value.clone().into())),
+            Cmp::Lt(value) => format!("\"{}\" < {}", column, bind(value.clone().into())),
+            Cmp::Like(pattern) => format!("\"{}\" LIKE {}", column, bind(pattern.clone().into())),
+            Cmp::In(others) if others.is_empty() => "1 = 0".to_string(),
+            Cmp::In(others) => {
+                let placeholders: Vec<String> = others.iter().map(|value| bind(value.clone().into())).collect();
+                format!("\"{}\" IN ({})", column, placeholders.join(", "))
+            }
+        }
+    }
+}
+
+/// Whether `text` matches the SQL `LIKE` `pattern`.
+fn like(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|skip| like(rest, &text[skip..])),
+        Some(('_', rest)) => !text.is_empty() && like(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && like(rest, &text[1..]),
+    }
+}
+
+/// A value shared through a `tokio::sync::watch` channel and updated with
+/// patches, e.g. for configuration hot-reload or broadcasting live state.
+///
+/// Every applied patch notifies the receivers, which see the whole updated
+/// value.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct Watched<T> {
+    sender: tokio::sync::watch::Sender<T>,
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Optionalize> Watched<T> {
+    /// Shares `value` through a new channel.
+    pub fn new(value: T) -> Self {
+        Watched {
+            sender: tokio::sync::watch::Sender::new(value),
+        }
+    }
+
+    /// Applies `patch` onto the value and notifies every receiver.
+    pub fn patch(&self, patch: T::Optional) {
+        self.sender.send_modify(|value| patch.apply_to(value));
+    }
+
+    /// A new receiver of the value, seeing it as of the latest patch.
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<T> {
+        self.sender.subscribe()
+    }
+
+    /// The current value. Holding the reference blocks patches, so it
+    /// shouldn't be kept across `.await`s.
+    pub fn borrow(&self) -> tokio::sync::watch::Ref<'_, T> {
+        self.sender.borrow()
+    }
+
+    /// The underlying sender, e.g. to replace the value as a whole.
+    pub fn sender(&self) -> &tokio::sync::watch::Sender<T> {
+        &self.sender
+    }
+}
+
+/// Error deserializing a patch from the registry with [`deserialize_patch`].
+#[cfg(feature = "registry")]
+#[derive(Debug)]
+pub enum RegistryError {
+    /// The value isn't a `{ "type": ..., "data": ... }` object.
+    InvalidEnvelope(serde_json::Error),
+    /// No registered patch type has this name.
+    UnknownType(String),
+    /// The data doesn't deserialize into the named patch type.
+    InvalidData {
+        /// The name of the patch type.
+        name: String,
+        /// The error deserializing it.
+        source: serde_json::Error,
+    },
+}
+
+#[cfg(feature = "registry")]
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::InvalidEnvelope(err) => write!(f, "invalid patch envelope: {}", err),
+            RegistryError::UnknownType(name) => write!(f, "unknown patch type `{}`", name),
+            RegistryError::InvalidData { name, source } => write!(f, "invalid `{}` patch: {}", name, source),
+        }
+    }
+}
+
+#[cfg(feature = "registry")]
+impl Error for RegistryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RegistryError::InvalidEnvelope(err) | RegistryError::InvalidData { source: err, .. } => Some(err),
+            RegistryError::UnknownType(_) => None,
+        }
+    }
+}
+
+/// Deserializes a patch of any registered type from a
+/// `{ "type": "app::models::UserOptional", "data": { ... } }` object, e.g. for
+/// job queues and webhook processors handling patches of many structs.
+///
+/// With the `registry` feature, every generated struct deriving `Deserialize`
+/// registers itself under its path, the `module_path!()` it is declared in
+/// followed by its name, as long as it isn't generic.
+#[cfg(feature = "registry")]
+pub fn deserialize_patch(value: serde_json::Value) -> Result<Box<dyn AnyPatch>, RegistryError> {
+    #[derive(serde::Deserialize)]
+    struct Envelope {
+        #[serde(rename = "type")]
+        name: String,
+        data: serde_json::Value,
+    }
+
+    let envelope: Envelope = serde_json::from_value(value).map_err(RegistryError::InvalidEnvelope)?;
+    let registered = inventory::iter::<__private::PatchType>
+        .into_iter()
+        .find(|registered| registered.name == envelope.name);
+    match registered {
+        Some(registered) => (registered.deserialize)(envelope.data).map_err(|source| RegistryError::InvalidData {
+            name: envelope.name,
+            source,
+        }),
+        None => Err(RegistryError::UnknownType(envelope.name)),
+    }
+}
+
+/// The paths of every registered patch type.
+#[cfg(feature = "registry")]
+pub fn registered_patches() -> Vec<&'static str> {
+    inventory::iter::<__private::PatchType>
+        .into_iter()
+        .map(|registered| registered.name)
+        .collect()
+}
+
+/// Items the generated code refers to, not meant to be used directly.
+#[cfg(feature = "registry")]
+#[doc(hidden)]
+pub mod __private {
+    pub use inventory;
+    pub use serde_json;
+
+    /// A patch type in the registry.
+    pub struct PatchType {
+        pub name: &'static str,
+        pub deserialize: fn(serde_json::Value) -> serde_json::Result<Box<dyn crate::AnyPatch>>,
+    }
+
+    inventory::collect!(PatchType);
+}
+
+/// Registers a generated struct with the patch registry. Expands to nothing
+/// without the `registry` feature, so the generated code can always call it.
+#[cfg(feature = "registry")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_patch {
+    ($name:expr, $ty:ty) => {
+        $crate::__private::inventory::submit! {
+            $crate::__private::PatchType {
+                name: $name,
+                deserialize: |value| {
+                    let patch: $ty = $crate::__private::serde_json::from_value(value)?;
+                    ::std::result::Result::Ok(::std::boxed::Box::new(patch))
+                },
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "registry"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_patch {
+    ($name:expr, $ty:ty) => {};
+}
@@ -0,0 +1,427 @@
+use std::convert::TryFrom;
+
+use optionalize::{AnyPatch, ApplyPatch, Cmp, Deoptionalize, FieldMeta, MaybeUndefined, Merge, MissingFieldsError, Optionalize};
+
+/// Test structs handled through the shared traits
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(option_like(MaybeUndefined = "is_undefined"), impls(apply, merge))]
+pub struct Profile {
+    pub name: String,
+    pub bio: MaybeUndefined<String>,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(merge = "prefer_self", derive(Debug, PartialEq), impls(apply, merge, diff))]
+pub struct Limits {
+    pub requests: u32,
+    pub burst: u32,
+}
+
+/// Generic code written once for every optionalized struct
+fn apply_merged<T: Optionalize>(target: &mut T, first: T::Optional, second: T::Optional)
+where
+    T::Optional: Merge,
+{
+    first.merge(second).apply_to(target);
+}
+
+#[test]
+fn test_shared_traits() {
+    let mut profile = Profile {
+        name: "Ada".to_string(),
+        bio: MaybeUndefined::Value("Mathematician".to_string()),
+    };
+    apply_merged(
+        &mut profile,
+        ProfileOptional {
+            name: Some("Ada L.".to_string()),
+            bio: MaybeUndefined::Undefined,
+        },
+        ProfileOptional {
+            name: None,
+            bio: MaybeUndefined::Null,
+        },
+    );
+    assert_eq!(
+        profile,
+        Profile {
+            name: "Ada L.".to_string(),
+            bio: MaybeUndefined::Null,
+        }
+    );
+
+    // Each struct keeps its own merge policy
+    let mut limits = Limits { requests: 10, burst: 2 };
+    apply_merged(
+        &mut limits,
+        LimitsOptional {
+            requests: Some(100),
+            burst: None,
+        },
+        LimitsOptional {
+            requests: Some(50),
+            burst: Some(5),
+        },
+    );
+    assert_eq!(limits, Limits { requests: 100, burst: 5 });
+
+    // The associated patch type converts from the struct itself
+    let patch = <Limits as Optionalize>::Optional::from(Limits { requests: 1, burst: 1 });
+    patch.apply_to(&mut limits);
+    assert_eq!(limits, Limits { requests: 1, burst: 1 });
+}
+
+/// Test struct finalized through the shared error type
+#[derive(Deoptionalize)]
+pub struct Draft {
+    pub title: Option<String>,
+    pub body: Option<String>,
+}
+
+#[test]
+fn test_missing_fields_error() {
+    let draft = Draft {
+        title: None,
+        body: None,
+    };
+    let error: MissingFieldsError = DraftRequired::try_from(draft).map(drop).unwrap_err().into();
+    assert_eq!(error.missing, ["title", "body"]);
+    assert_eq!(error.to_string(), "missing required fields: title, body");
+
+    assert_eq!(MaybeUndefined::from(Some(None::<u8>)), MaybeUndefined::Null);
+    assert_eq!(MaybeUndefined::Value(3).into_option(), Some(Some(3)));
+}
+
+/// Test struct introspected at runtime
+#[derive(Optionalize)]
+#[optionalize(impls(apply, merge))]
+#[allow(dead_code)]
+pub struct Article<'a> {
+    /// Headline shown in listings.
+    ///
+    /// At most 80 characters.
+    pub title: &'a str,
+    pub tags: Vec<(u8, String)>,
+    pub summary: Option<String>,
+}
+
+/// Field names of any optionalized struct
+fn field_names<T: Optionalize>() -> Vec<&'static str> {
+    T::FIELDS.iter().map(|field| field.name).collect()
+}
+
+#[test]
+fn test_field_metadata() {
+    assert_eq!(
+        ArticleOptional::FIELDS,
+        [
+            FieldMeta {
+                name: "title",
+                ty: "&'a str",
+                optional: false,
+                doc: "Headline shown in listings.\n\nAt most 80 characters.",
+            },
+            FieldMeta {
+                name: "tags",
+                ty: "Vec<(u8, String)>",
+                optional: false,
+                doc: "",
+            },
+            FieldMeta {
+                name: "summary",
+                ty: "Option<String>",
+                optional: true,
+                doc: "",
+            },
+        ]
+    );
+    assert_eq!(field_names::<Article>(), ["title", "tags", "summary"]);
+}
+
+/// Test struct searched by example
+#[derive(Optionalize, Debug)]
+#[optionalize(filter, impls(apply, merge))]
+#[allow(dead_code)]
+struct Employee {
+    pub name: String,
+    pub age: u32,
+    pub team: Option<String>,
+}
+
+/// Driver-agnostic value type the where clause binds
+#[derive(Debug, PartialEq)]
+enum SqlValue {
+    Int(i64),
+    Text(String),
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+
+impl From<u32> for SqlValue {
+    fn from(value: u32) -> Self {
+        SqlValue::Int(value.into())
+    }
+}
+
+#[test]
+fn test_filter() {
+    let ada = Employee { name: "Ada Lovelace".to_string(), age: 36, team: Some("engines".to_string()) };
+    let alan = Employee { name: "Alan Turing".to_string(), age: 41, team: None };
+
+    let filter = EmployeeFilter {
+        name: Some(Cmp::Like("A%a_e".to_string())),
+        age: Some(Cmp::Lt(40)),
+        ..Default::default()
+    };
+    assert!(filter.matches(&ada));
+    assert!(!filter.matches(&alan));
+    assert!(EmployeeFilter::default().matches(&alan));
+
+    // `None` matches no comparison, as in SQL
+    let filter = EmployeeFilter { team: Some(Cmp::Ne("engines".to_string())), ..Default::default() };
+    assert!(!filter.matches(&ada));
+    assert!(!filter.matches(&alan));
+    let filter = EmployeeFilter { age: Some(Cmp::In(vec![36, 41])), ..Default::default() };
+    assert!(filter.matches(&ada) && filter.matches(&alan));
+
+    let filter = EmployeeFilter {
+        name: Some(Cmp::Like("A%".to_string())),
+        age: Some(Cmp::In(vec![36, 41])),
+        team: Some(Cmp::Eq("engines".to_string())),
+    };
+    let (clause, values) = filter.to_where_clause::<SqlValue>(|index, _| format!("${}", index));
+    assert_eq!(clause, "\"name\" LIKE $1 AND \"age\" IN ($2, $3) AND \"team\" = $4");
+    assert_eq!(
+        values,
+        [
+            SqlValue::Text("A%".to_string()),
+            SqlValue::Int(36),
+            SqlValue::Int(41),
+            SqlValue::Text("engines".to_string()),
+        ]
+    );
+    assert_eq!(EmployeeFilter::default().to_where_clause::<SqlValue>(|_, _| "?".to_string()).0, "1 = 1");
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_watched() {
+    use optionalize::Watched;
+
+    let watched = Watched::new(Limits { requests: 10, burst: 2 });
+    let mut receiver = watched.subscribe();
+    assert!(!receiver.has_changed().unwrap());
+
+    watched.patch(LimitsOptional { requests: Some(20), burst: None });
+    receiver.changed().await.unwrap();
+    assert_eq!(*receiver.borrow_and_update(), Limits { requests: 20, burst: 2 });
+    assert_eq!(watched.borrow().requests, 20);
+}
+
+/// Test struct queued next to others as a `dyn AnyPatch`
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(serde::Serialize), serde_json, impls(merge, serde))]
+pub struct Invoice {
+    pub total: u32,
+    pub paid: bool,
+}
+
+#[test]
+fn test_any_patch() {
+    let queue: Vec<Box<dyn AnyPatch>> = vec![
+        Box::new(LimitsOptional { requests: Some(5), burst: None }),
+        Box::new(InvoiceOptional { total: None, paid: Some(true) }),
+    ];
+    assert_eq!(queue[0].target_name(), "Limits");
+    assert_eq!(queue[1].set_fields(), vec!["paid"]);
+    assert!(queue[1].as_any().downcast_ref::<InvoiceOptional>().is_some());
+    assert_eq!(queue[1].to_json().as_deref(), Some(r#"{"total":null,"paid":true}"#));
+    assert_eq!(queue[0].to_json(), None);
+
+    let mut limits = Limits { requests: 1, burst: 1 };
+    let mut invoice = Invoice { total: 10, paid: false };
+    for patch in queue {
+        // Each patch finds the value of its own type
+        let patch = match patch.apply_any(&mut limits) {
+            Ok(()) => continue,
+            Err(patch) => patch,
+        };
+        patch.apply_any(&mut invoice).unwrap_or_else(|_| panic!("no target"));
+    }
+    assert_eq!(limits, Limits { requests: 5, burst: 1 });
+    assert_eq!(invoice, Invoice { total: 10, paid: true });
+}
+
+/// Test struct whose nested field is a type parameter
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), impls(apply, merge, diff))]
+pub struct Entry<T> {
+    pub key: String,
+    #[optionalize(nested)]
+    pub value: T,
+    #[optionalize(nested)]
+    pub previous: Option<T>,
+}
+
+#[test]
+fn test_nested_type_parameter() {
+    let mut entry = Entry {
+        key: "limits".to_string(),
+        value: Limits { requests: 5, burst: 1 },
+        previous: None,
+    };
+    let patch = EntryOptional::<Limits> {
+        value: Some(LimitsOptional {
+            burst: Some(3),
+            ..LimitsOptional::empty()
+        }),
+        previous: Some(LimitsOptional {
+            requests: Some(1),
+            burst: Some(1),
+        }),
+        ..EntryOptional::empty()
+    };
+    let merged = patch.merge(EntryOptional {
+        value: Some(LimitsOptional {
+            requests: Some(8),
+            ..LimitsOptional::empty()
+        }),
+        ..EntryOptional::empty()
+    });
+    merged.apply_to(&mut entry);
+    assert_eq!(
+        entry,
+        Entry {
+            key: "limits".to_string(),
+            value: Limits { requests: 8, burst: 3 },
+            previous: Some(Limits { requests: 1, burst: 1 }),
+        }
+    );
+
+    // The twin is diffed field by field, like any other nested patch
+    let base = Entry {
+        key: "limits".to_string(),
+        value: Limits { requests: 8, burst: 1 },
+        previous: None,
+    };
+    assert_eq!(
+        EntryOptional::diff(&base, &entry),
+        EntryOptional {
+            value: Some(LimitsOptional {
+                burst: Some(3),
+                ..LimitsOptional::empty()
+            }),
+            ..EntryOptional::empty()
+        }
+    );
+}
+
+/// Test struct with a patch generic over the wrapper of its fields
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(wrapper, derive(Debug, PartialEq), impls(apply, merge))]
+pub struct Theme {
+    pub name: String,
+    pub accent: Option<u32>,
+    pub dark: bool,
+}
+
+#[test]
+fn test_wrapper() {
+    use optionalize::{CellWrapper, MaybeUndefinedWrapper};
+
+    let plain: ThemeOptionalWrapped = ThemeOptional {
+        name: Some("Solar".to_string()),
+        ..ThemeOptional::empty()
+    }
+    .into();
+    assert_eq!(plain.name.as_deref(), Some("Solar"));
+
+    // Cells can be filled in through a shared reference
+    let cells = ThemeOptionalWrapped::<CellWrapper>::from(ThemeOptional::empty());
+    let fill = |wrapped: &ThemeOptionalWrapped<CellWrapper>| wrapped.dark.set(Some(true));
+    fill(&cells);
+    let mut theme = Theme {
+        name: "Default".to_string(),
+        accent: None,
+        dark: false,
+    };
+    cells.apply_to(&mut theme);
+    assert!(theme.dark);
+
+    let wrapped = ThemeOptionalWrapped::<MaybeUndefinedWrapper> {
+        name: MaybeUndefined::Undefined,
+        accent: MaybeUndefined::Value(0xff8800),
+        dark: MaybeUndefined::Null,
+    };
+    assert_eq!(
+        ThemeOptional::from(wrapped),
+        ThemeOptional {
+            accent: Some(0xff8800),
+            ..ThemeOptional::empty()
+        }
+    );
+    plain.apply_to(&mut theme);
+    assert_eq!(
+        theme,
+        Theme {
+            name: "Solar".to_string(),
+            accent: None,
+            dark: true,
+        }
+    );
+}
+
+/// Test struct deserialized from the patch registry by name
+#[cfg(feature = "registry")]
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(serde::Deserialize), impls(apply, merge))]
+pub struct Webhook {
+    pub url: String,
+    pub active: bool,
+}
+
+/// Test struct of the same name in another module
+#[cfg(feature = "registry")]
+mod hooks {
+    use optionalize::Optionalize;
+
+    #[derive(Optionalize)]
+    #[optionalize(derive(serde::Deserialize), impls(apply, merge))]
+    pub struct Webhook {
+        pub retries: u32,
+    }
+}
+
+#[cfg(feature = "registry")]
+#[test]
+fn test_patch_registry() {
+    use optionalize::{deserialize_patch, registered_patches, RegistryError};
+    use serde_json::json;
+
+    assert!(registered_patches().contains(&"runtime_test::WebhookOptional"));
+    assert!(registered_patches().contains(&"runtime_test::hooks::WebhookOptional"));
+
+    let patch = deserialize_patch(json!({ "type": "runtime_test::WebhookOptional", "data": { "active": false } })).unwrap();
+    assert_eq!(patch.target_name(), "Webhook");
+    let mut webhook = Webhook {
+        url: "https://example.com/hook".to_string(),
+        active: true,
+    };
+    assert!(patch.apply_any(&mut webhook).is_ok());
+    assert!(!webhook.active);
+
+    let patch = deserialize_patch(json!({ "type": "runtime_test::hooks::WebhookOptional", "data": { "retries": 3 } }));
+    assert!(patch.unwrap().apply_any(&mut hooks::Webhook { retries: 0 }).is_ok());
+
+    let err = deserialize_patch(json!({ "type": "Missing", "data": {} })).err().unwrap();
+    assert!(matches!(err, RegistryError::UnknownType(name) if name == "Missing"));
+    let err = deserialize_patch(json!({ "type": "runtime_test::WebhookOptional", "data": { "active": 3 } }))
+        .err()
+        .unwrap();
+    assert!(matches!(err, RegistryError::InvalidData { .. }));
+    assert!(matches!(deserialize_patch(json!([])), Err(RegistryError::InvalidEnvelope(_))));
+}
@@ -0,0 +1,78 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container};
+
+/// Generates an actix-web `FromRequest` impl for the optional struct and a
+/// `ResponseError` impl for its error type. Only emitted with
+/// `#[optionalize(actix_web)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("actix_web") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+    let where_clause = cx.where_clause_with(inner_bounds(
+        &cx.fields,
+        quote!(::serde::de::DeserializeOwned),
+    ));
+
+    quote! {
+        /// Extracts the patch from an `application/json` or
+        /// `application/merge-patch+json` request body.
+        ///
+        /// Other content types are rejected with `415 Unsupported Media Type`,
+        /// malformed JSON and empty patches with `400 Bad Request`, and unknown
+        /// or badly typed fields with `422 Unprocessable Entity`.
+        impl #impl_generics ::actix_web::FromRequest for #optional_ident #ty_generics
+        #where_clause
+        {
+            type Error = ::actix_web::Error;
+            type Future = ::std::pin::Pin<
+                Box<dyn ::std::future::Future<Output = Result<Self, Self::Error>>>,
+            >;
+
+            fn from_request(
+                req: &::actix_web::HttpRequest,
+                payload: &mut ::actix_web::dev::Payload,
+            ) -> Self::Future {
+                use ::actix_web::HttpMessage;
+
+                let content_type = req.content_type().to_string();
+                let body = <::actix_web::web::Bytes as ::actix_web::FromRequest>::from_request(req, payload);
+
+                Box::pin(async move {
+                    if content_type != "application/json" && content_type != "application/merge-patch+json" {
+                        return Err(::actix_web::error::ErrorUnsupportedMediaType(format!(
+                            "expected `application/json` or `application/merge-patch+json`, found `{}`",
+                            content_type
+                        )));
+                    }
+
+                    let body = body.await?;
+                    let value: ::serde_json::Value = ::serde_json::from_slice(&body)
+                        .map_err(::actix_web::error::ErrorBadRequest)?;
+                    let patch = <Self as ::std::convert::TryFrom<::serde_json::Value>>::try_from(value)?;
+
+                    if patch.set_fields().is_empty() {
+                        return Err(::actix_web::error::ErrorBadRequest("patch does not set any fields"));
+                    }
+
+                    Ok(patch)
+                })
+            }
+        }
+
+        impl ::actix_web::ResponseError for #error_ident {
+            fn status_code(&self) -> ::actix_web::http::StatusCode {
+                match self {
+                    Self::VersionConflict { .. } => ::actix_web::http::StatusCode::CONFLICT,
+                    Self::ExpectedObject { .. } => ::actix_web::http::StatusCode::BAD_REQUEST,
+                    _ => ::actix_web::http::StatusCode::UNPROCESSABLE_ENTITY,
+                }
+            }
+        }
+    }
+}
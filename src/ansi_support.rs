@@ -0,0 +1,65 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Generates `render_diff` and `render_against`, which render the fields that
+/// differ between two values as colored `-`/`+` lines. Only emitted with
+/// `#[optionalize(ansi)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("ansi") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Rendered output ends up on terminals and in logs, so sensitive values stay out
+    let fields: Vec<_> = cx.fields.iter().filter(|field| !field.is_sensitive()).collect();
+    let tys: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let lines = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        quote! {
+            if ::std::cmp::PartialEq::ne(&base.#ident, &target.#ident) {
+                out.push_str(&format!("\x1b[31m- {}: {:?}\x1b[0m\n", #name, base.#ident));
+                out.push_str(&format!("\x1b[32m+ {}: {:?}\x1b[0m\n", #name, target.#ident));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Renders the fields that differ between `base` and `target`, one
+            /// red `- field: old` line and one green `+ field: new` line each,
+            /// in declaration order, e.g. to show users what an update will
+            /// change before confirming it.
+            ///
+            /// Values are formatted with `Debug`, and every line ends with an
+            /// ANSI reset. Sensitive fields are never rendered. Returns an
+            /// empty string when nothing differs.
+            pub fn render_diff(base: &#ident #ty_generics, target: &#ident #ty_generics) -> String
+            where
+                #( for<'__optionalize> #tys: ::std::cmp::PartialEq + ::std::fmt::Debug, )*
+            {
+                let mut out = String::new();
+                #( #lines )*
+                out
+            }
+
+            /// Renders what applying the patch onto `base` would change, like
+            /// [`render_diff`](Self::render_diff). `base` is left untouched.
+            pub fn render_against(&self, base: &#ident #ty_generics) -> String
+            where
+                for<'__optionalize> Self: ::std::clone::Clone,
+                for<'__optionalize> #ident #ty_generics: ::std::clone::Clone,
+                #( for<'__optionalize> #tys: ::std::cmp::PartialEq + ::std::fmt::Debug, )*
+            {
+                let mut target = ::std::clone::Clone::clone(base);
+                ::std::clone::Clone::clone(self).apply_to(&mut target);
+                Self::render_diff(base, &target)
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{pointer_inner, type_ends_with, Container, Field};
+use crate::{collection, metrics_support};
+
+/// Generates `apply_to`, `try_apply` and `try_apply_async`, which write the set fields onto a value
+/// of the original struct, and `apply_all` on the original struct.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("apply") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let assignments = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable)
+        .map(assignment);
+
+    let immutable_names = cx
+        .fields
+        .iter()
+        .filter(|field| field.attrs.immutable)
+        .map(|field| field.name());
+    let immutable_set: Vec<_> = cx
+        .fields
+        .iter()
+        .filter(|field| field.attrs.immutable)
+        .map(Field::is_set)
+        .collect();
+    // Most patches leave immutable fields alone, so that path allocates nothing
+    let immutable_check = &immutable_set;
+
+    let creatable = cx
+        .fields
+        .iter()
+        .filter(|field| field.nested_ty.is_some() && field.is_option() && !field.attrs.immutable)
+        .map(|field| {
+            let ident = &field.ident;
+            let name = field.name();
+            let missing_fields = field.twin_fn("missing_fields");
+            quote! {
+                if let (Some(patch), None) = (&self.#ident, &target.#ident) {
+                    let missing = #missing_fields(patch);
+                    if !missing.is_empty() {
+                        return Err(#error_ident::InvalidField {
+                            field: #name,
+                            message: format!("no value to patch, and missing fields to create one: {}", missing.join(", ")),
+                        });
+                    }
+                }
+            }
+        });
+
+    let (mutability, before) = match &cx.attrs.before_apply {
+        Some(before) => (quote!(mut), quote! { #before(&mut self, target); }),
+        None => (quote!(), quote!()),
+    };
+    let after = cx.attrs.after_apply.as_ref().map(|after| quote! { #after(target); });
+    let trace = trace(cx);
+    let metrics_start = metrics_support::start(cx);
+    let metrics_finish = metrics_support::finish(cx);
+    let metrics_rejected = metrics_support::rejected(cx, quote!(checked));
+
+    let try_apply_async = cx.attrs.async_validate.as_ref().map(|validate| {
+        quote! {
+            /// Like [`try_apply`](Self::try_apply), but also awaits the
+            /// `async_validate` hook once the synchronous checks pass, so
+            /// validations that need IO can reject the patch. `target` is left
+            /// untouched on error.
+            pub async fn try_apply_async(#mutability self, target: &mut #ident #ty_generics) -> Result<(), #error_ident> {
+                #before
+                self.check_all()?;
+                #validate(&self, target).await?;
+                self.write_to(target);
+                Ok(())
+            }
+        }
+    });
+
+    let apply_all_doc = format!(
+        "Applies every patch in `patches` in order with [`{}::apply_to`], e.g. to replay queued updates.",
+        optional_ident
+    );
+
+    let with_patch_doc = format!(
+        "A copy of the value with `patch` applied by [`{}::apply_to`], leaving the value itself untouched.",
+        optional_ident
+    );
+
+    // A bound on a concrete type must hold, so `with_patch` is only generated
+    // for structs deriving `Clone` or generic over their parameters
+    let own: Vec<_> = cx
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates)
+        .collect();
+    let with_patch = if cx.derives_clone || !cx.generics.params.is_empty() {
+        quote! {
+            impl #impl_generics #ident #ty_generics
+            where
+                #( #own, )*
+                Self: ::std::clone::Clone,
+            {
+                #[doc = #with_patch_doc]
+                ///
+                /// Like Kotlin's `copy`, for code treating values as immutable.
+                /// Fields marked `#[optionalize(immutable)]` keep their value.
+                #[must_use]
+                pub fn with_patch(&self, patch: #optional_ident #ty_generics) -> Self {
+                    let mut value = ::std::clone::Clone::clone(self);
+                    patch.apply_to(&mut value);
+                    value
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Writes every set field onto `target`, leaving unset fields untouched.
+            ///
+            /// Fields marked `#[optionalize(immutable)]` are never written; use
+            /// [`try_apply`](Self::try_apply) to reject patches that set them.
+            /// With `#[optionalize(tracing)]`, skipping them logs a warning.
+            /// A `nested` patch for an `Option` the target leaves `None`
+            /// creates the value when it sets every required field, and is
+            /// skipped otherwise.
+            /// The `before_apply` and `after_apply` hooks run around the writes.
+            #[inline]
+            pub fn apply_to(#mutability self, target: &mut #ident #ty_generics) {
+                #before
+                self.write_to(target);
+            }
+
+            /// Like [`apply_to`](Self::apply_to), but first runs
+            /// [`validate`](Self::validate) and rejects patches that set an
+            /// immutable field, or a `nested` patch too incomplete to create
+            /// the value it targets. `target` is left untouched on error.
+            /// The checks see the patch as the `before_apply` hook leaves it.
+            ///
+            /// When several checks fail, they are all reported at once as
+            /// `Multiple`, so clients can fix every field in one round trip.
+            #[inline]
+            pub fn try_apply(#mutability self, target: &mut #ident #ty_generics) -> Result<(), #error_ident> {
+                #before
+                let checked = self.check_all().and_then(|()| self.check_creatable(target));
+                #metrics_rejected
+                checked?;
+                self.write_to(target);
+                Ok(())
+            }
+
+            /// Writes every set field onto `target` once the `before_apply`
+            /// hook has run, then runs the `after_apply` hook.
+            #[inline]
+            fn write_to(self, target: &mut #ident #ty_generics) {
+                #trace
+                #metrics_start
+                #( #assignments )*
+                #metrics_finish
+                #after
+            }
+
+            /// Like [`apply_to`](Self::apply_to), but writes only the set fields
+            /// selected by the field mask `mask`, e.g. to honor the `update_mask`
+            /// of a gRPC update request. See
+            /// [`retain_masked`](Self::retain_masked) for the paths it takes.
+            /// Immutable fields are skipped as by `apply_to`.
+            pub fn apply_masked(mut self, target: &mut #ident #ty_generics, mask: &[&str]) {
+                self.retain_masked(mask);
+                self.apply_to(target);
+            }
+
+            #try_apply_async
+
+            /// Runs every check of `try_apply`, failing with the one error found
+            /// or with `Multiple` when there are several.
+            #[inline]
+            fn check_all(&self) -> Result<(), #error_ident> {
+                let mut errors = self.validate_all();
+                if let Err(err) = self.check_immutable() {
+                    errors.push(err);
+                }
+                match errors.len() {
+                    0 => Ok(()),
+                    1 => Err(errors.remove(0)),
+                    _ => Err(#error_ident::Multiple { errors }),
+                }
+            }
+
+            /// Fails with `InvalidField` when a `nested` patch targets an
+            /// `Option` the target leaves `None` and is missing fields to
+            /// create its value from.
+            #[inline]
+            fn check_creatable(&self, target: &#ident #ty_generics) -> Result<(), #error_ident> {
+                #( #creatable )*
+                let _ = target;
+                Ok(())
+            }
+
+            /// Fails with `Immutable` when the patch sets an immutable field.
+            #[inline]
+            fn check_immutable(&self) -> Result<(), #error_ident> {
+                if !(false #( || #immutable_check )*) {
+                    return Ok(());
+                }
+                let immutable_fields: &[(&'static str, bool)] = &[
+                    #( (#immutable_names, #immutable_set), )*
+                ];
+                let immutable: Vec<&'static str> = immutable_fields
+                    .iter()
+                    .filter(|(_, set)| *set)
+                    .map(|(name, _)| *name)
+                    .collect();
+                if !immutable.is_empty() {
+                    return Err(#error_ident::Immutable { fields: immutable });
+                }
+                Ok(())
+            }
+
+            /// Applies a batch of keyed patches onto `items` in a single pass:
+            /// each item gets the patch stored under its `key`, if any. Returns
+            /// the patches whose key matched no item. Immutable fields are
+            /// skipped as by [`apply_to`](Self::apply_to); check the patches
+            /// with [`try_apply`](Self::try_apply) to reject them instead.
+            pub fn apply_to_all<K>(
+                items: &mut [#ident #ty_generics],
+                key: impl Fn(&#ident #ty_generics) -> K,
+                mut patches: ::std::collections::HashMap<K, Self>,
+            ) -> ::std::collections::HashMap<K, Self>
+            where
+                K: ::std::cmp::Eq + ::std::hash::Hash,
+            {
+                for item in items {
+                    if patches.is_empty() {
+                        break;
+                    }
+                    if let Some(patch) = patches.remove(&key(item)) {
+                        patch.apply_to(item);
+                    }
+                }
+                patches
+            }
+        }
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            #[doc = #apply_all_doc]
+            ///
+            /// Immutable fields are skipped; use `try_apply` on each patch to
+            /// reject them instead.
+            #[inline]
+            pub fn apply_all(&mut self, patches: impl IntoIterator<Item = #optional_ident #ty_generics>) {
+                for patch in patches {
+                    patch.apply_to(self);
+                }
+            }
+        }
+
+        #with_patch
+    }
+}
+
+/// Generates the statements recording the patch about to be applied on the
+/// current `tracing` span. Only emitted with `#[optionalize(tracing)]`.
+pub fn trace(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("tracing") {
+        return quote! {};
+    }
+
+    let patch = cx.optional_ident.to_string();
+    // The generated `Debug` redacts sensitive fields
+    let values = if cx.attrs.trace_values {
+        quote! { value = ?self, }
+    } else {
+        quote! {}
+    };
+    // Immutable fields are skipped rather than rejected, which callers only
+    // learn of from the warning
+    let immutable = if cx.fields.iter().any(|field| field.attrs.immutable) {
+        quote! {
+            if let Err(err) = self.check_immutable() {
+                ::tracing::warn!(patch = #patch, error = %err, "skipping immutable fields set by the patch");
+            }
+        }
+    } else {
+        quote! {}
+    };
+    // The field list is only built when something will record it
+    quote! {
+        {
+            let span = ::tracing::Span::current();
+            if !span.is_disabled() || ::tracing::enabled!(::tracing::Level::DEBUG) {
+                let fields = self.set_fields().join(",");
+                span.record("patched_fields", fields.as_str());
+                ::tracing::debug!(patch = #patch, fields = %fields, #values "applying patch");
+            }
+            #immutable
+        }
+    }
+}
+
+/// Generates the statement writing a single set field onto `target`.
+fn assignment(field: &Field) -> TokenStream {
+    let assignment = write(field);
+    // The overwritten value of a sensitive field is wiped first
+    if field.zeroizes() && field.attrs.collection.is_none() {
+        let field_name = &field.ident;
+        let is_set = field.is_set();
+        return quote! {
+            if #is_set {
+                ::zeroize::Zeroize::zeroize(&mut target.#field_name);
+            }
+            #assignment
+        };
+    }
+    assignment
+}
+
+/// Generates the statement creating the value of a `nested` `Option` field
+/// the target leaves `None` from `patch`, when it's complete enough to build one.
+pub fn create_nested(field: &Field) -> TokenStream {
+    let field_name = &field.ident;
+    let boxed = type_ends_with(field.inner_ty(), "Box");
+    let patch = if boxed { quote!((*patch)) } else { quote!(patch) };
+    let value_ty = field.value_ty();
+    let (source, created) = if boxed {
+        (pointer_inner(value_ty).unwrap_or(value_ty), quote!(::std::boxed::Box::new(value)))
+    } else {
+        (value_ty, quote!(value))
+    };
+    quote! {
+        if let Ok(value) = <#source as ::std::convert::TryFrom<_>>::try_from(#patch) {
+            target.#field_name = Some(#created);
+        }
+    }
+}
+
+/// Generates the statement writing a single set field onto `target`, without
+/// wiping the value it replaces.
+fn write(field: &Field) -> TokenStream {
+    let field_name = &field.ident;
+
+    if field.attrs.flatten {
+        return quote! { self.#field_name.apply_to(&mut target.#field_name); };
+    }
+
+    // Nested patches are applied onto the existing value in place
+    if field.nested_ty.is_some() {
+        let boxed = type_ends_with(field.inner_ty(), "Box");
+        let patch = if boxed { quote!((*patch)) } else { quote!(patch) };
+        let apply_to = field.twin_fn("apply_to");
+        return if field.is_option() {
+            let inner = if boxed { quote!(&mut **inner) } else { quote!(inner) };
+            let create = create_nested(field);
+            quote! {
+                if let Some(patch) = self.#field_name {
+                    match &mut target.#field_name {
+                        Some(inner) => #apply_to(#patch, #inner),
+                        None => #create,
+                    }
+                }
+            }
+        } else {
+            let inner = if boxed {
+                quote!(&mut *target.#field_name)
+            } else {
+                quote!(&mut target.#field_name)
+            };
+            quote! {
+                if let Some(patch) = self.#field_name {
+                    #apply_to(#patch, #inner);
+                }
+            }
+        };
+    }
+
+    // Option-like fields are moved as-is when set
+    if field.option_like.is_some() {
+        let is_set = field.is_set();
+        let value = match &field.attrs.sanitize_with {
+            Some(sanitize) => quote! { #sanitize(self.#field_name) },
+            None => quote! { self.#field_name },
+        };
+        return quote! {
+            if #is_set {
+                target.#field_name = #value;
+            }
+        };
+    }
+
+    let value = match &field.attrs.sanitize_with {
+        Some(sanitize) => quote! { #sanitize(value) },
+        None => quote! { value },
+    };
+
+    // Collections may combine their elements with the existing ones
+    if field.attrs.collection.is_some() {
+        let merge = collection::merge_into(field, quote!(target.#field_name), value, None);
+        return quote! {
+            if let Some(value) = self.#field_name {
+                #merge
+            }
+        };
+    }
+
+    let value = field.to_source(value);
+
+    quote! {
+        if let Some(value) = self.#field_name {
+            target.#field_name = #value;
+        }
+    }
+}
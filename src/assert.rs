@@ -0,0 +1,37 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::Ident;
+
+use crate::ast::Container;
+
+/// Generates the compile-time checks that the generated struct implements the
+/// traits listed in `#[optionalize(assert(...))]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if cx.attrs.asserts.is_empty() {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Spanned on the trait so a missing impl points at the attribute
+    let checks = cx.attrs.asserts.iter().map(|path| {
+        let optional_ident = Ident::new(&optional_ident.to_string(), path.span());
+        quote_spanned! {path.span()=>
+            {
+                fn implements<T: #path>() {}
+                implements::<#optional_ident #ty_generics>();
+            }
+        }
+    });
+
+    quote! {
+        const _: () = {
+            #[allow(dead_code)]
+            fn __optionalize_assert #impl_generics () #where_clause {
+                #( #checks )*
+            }
+        };
+    }
+}
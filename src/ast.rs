@@ -0,0 +1,1069 @@
+use proc_macro2::TokenStream;
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, quote};
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    ext::IdentExt, Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Generics, Ident, parse_quote, Path, PathArguments, Result,
+    Type, TypePath, Visibility,
+};
+
+use crate::attr::{forwarded_derives, is_serde_skipped, source_derives, CollectionMerge, ContainerAttrs, FieldAttrs, PointerPolicy, INTEGRATIONS};
+
+/// The struct being derived, along with everything the generators need to know about it.
+pub struct Container {
+    /// Name of the original struct.
+    pub ident: Ident,
+    /// Name of the generated "optionalized" struct.
+    pub optional_ident: Ident,
+    pub generics: Generics,
+    pub attrs: ContainerAttrs,
+    pub fields: Vec<Field>,
+    /// `PhantomData` fields, copied into the generated struct unchanged so
+    /// generic marker parameters keep working.
+    pub markers: Vec<syn::Field>,
+    /// Fields serde never deserializes, left out of the generated struct, with
+    /// their types.
+    pub skipped: Vec<(Ident, Type)>,
+    /// The original struct derives `Clone` itself.
+    pub derives_clone: bool,
+    /// Path of the `optionalize` runtime crate, when the calling crate
+    /// depends on it.
+    pub runtime: Option<Path>,
+}
+
+/// A single named field of the original struct.
+pub struct Field {
+    pub ident: Ident,
+    pub vis: Visibility,
+    pub ty: Type,
+    pub attrs: FieldAttrs,
+    /// Attributes written on the original field.
+    pub source_attrs: Vec<Attribute>,
+    /// The field is already an `Option`, or one of the container's
+    /// `option_alias` types.
+    pub option: bool,
+    /// For fields of an `option_like` type, kept as-is in the generated struct,
+    /// the method telling whether the value is unset.
+    pub option_like: Option<Ident>,
+    /// The `Option` field is wrapped in a second `Option` in the generated
+    /// struct, and handled like any other value.
+    pub double_option: bool,
+    /// The field is a smart pointer whose contents are stored unboxed in the
+    /// generated struct.
+    pub unwrap_pointer: bool,
+    /// For `#[optionalize(nested)]` fields, the optional twin that replaces the
+    /// original value type, such as `Box<NodeOptional>` for `Box<Node>`.
+    pub nested_ty: Option<Type>,
+    /// For `nested` fields of a type parameter, the parameter, whose twin is
+    /// looked up through the runtime crate.
+    pub twin_param: Option<Ident>,
+    /// The container integrates with `secrecy`, whose secrets are sensitive.
+    pub secrecy: bool,
+    /// The container integrates with `zeroize`, which wipes sensitive values.
+    pub zeroize: bool,
+}
+
+impl Container {
+    pub fn from_input(input: DeriveInput, partial: bool) -> Result<Self> {
+        let mut attrs = ContainerAttrs::from_attrs(&input.attrs)?;
+        attrs.partial = partial;
+        let derives_clone = source_derives(&input.attrs)?
+            .iter()
+            .any(|path| path_ends_with(path, "Clone"));
+        if attrs.forward_derives {
+            for path in forwarded_derives(&input.attrs)? {
+                let name = &path.segments.last().expect("derive path").ident;
+                if !attrs.derives.iter().any(|derive| path_ends_with(derive, &name.to_string())) {
+                    attrs.derives.push(path);
+                }
+            }
+        }
+
+        // Only work with structs
+        let data_struct = match input.data {
+            Data::Struct(data_struct) => data_struct,
+            Data::Enum(data_enum) => {
+                return Err(Error::new_spanned(
+                    data_enum.enum_token,
+                    "Optionalize can only be used on structs; \
+                     derive it on a struct holding each variant's data instead of the enum",
+                ));
+            }
+            Data::Union(data_union) => {
+                return Err(Error::new_spanned(
+                    data_union.union_token,
+                    "Optionalize can only be used on structs; \
+                     unions have no per-field state to patch, use a struct with named fields",
+                ));
+            }
+        };
+
+        // `Self` would name the generated struct, so spell out the original
+        let (_, ty_generics, _) = input.generics.split_for_impl();
+        let ident = &input.ident;
+        let mut self_ty = ReplaceSelf(parse_quote!(#ident #ty_generics));
+
+        let (markers, fields): (Vec<_>, Vec<_>) = match data_struct.fields {
+            Fields::Named(named) => named
+                .named
+                .into_iter()
+                .map(|mut field| {
+                    self_ty.visit_type_mut(&mut field.ty);
+                    field
+                })
+                .partition(|field| type_ends_with(&field.ty, "PhantomData")),
+            Fields::Unit => (Vec::new(), Vec::new()),
+            Fields::Unnamed(unnamed) => {
+                return Err(Error::new_spanned(
+                    unnamed,
+                    "Optionalize requires a struct with named fields; \
+                     name the fields, e.g. `struct Point { x: i32, y: i32 }`",
+                ));
+            }
+        };
+
+        let params: Vec<&Ident> = input.generics.type_params().map(|param| &param.ident).collect();
+        let fields = fields
+            .into_iter()
+            .map(|field| Field::from_syn(field, &attrs, &params))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Fields serde never reads are internal, so the patch can't set them
+        let (skipped, fields): (Vec<Field>, Vec<Field>) = fields
+            .into_iter()
+            .partition(|field| !field.attrs.include && is_serde_skipped(&field.source_attrs));
+        let skipped = skipped.into_iter().map(|field| (field.ident, field.ty)).collect();
+
+        // The twin of a type parameter is looked up through the runtime
+        // crate's `Optionalize`, whose `Patch` and `Diff` stand in for its methods
+        let mut generics = input.generics;
+        let mut fields = fields;
+        let mut bounded = Vec::new();
+        let runtime = runtime_crate();
+        for field in fields.iter_mut().filter(|field| field.nested_ty.is_some()) {
+            if !matches!(&field.attrs.nested, None | Some(None)) || field.attrs.flatten {
+                continue;
+            }
+            let value_ty = field.value_ty();
+            let boxed = type_ends_with(value_ty, "Box");
+            let value_ty = if boxed {
+                first_type_argument(value_ty).unwrap_or(value_ty)
+            } else {
+                value_ty
+            };
+            let param = match generics.type_params().find(|param| type_is_ident(value_ty, &param.ident)) {
+                Some(param) => param.ident.clone(),
+                None => continue,
+            };
+            let runtime = match &runtime {
+                Some(runtime) => runtime,
+                None => {
+                    return Err(Error::new_spanned(
+                        &field.ty,
+                        format!(
+                            "`nested` on the type parameter `{}` requires depending on the `optionalize` crate",
+                            param
+                        ),
+                    ));
+                }
+            };
+            let twin: Type = parse_quote!(#param::Optional);
+            field.nested_ty = Some(if boxed {
+                parse_quote!(::std::boxed::Box<#twin>)
+            } else {
+                twin
+            });
+            if !bounded.contains(&param) {
+                let predicates = &mut generics.make_where_clause().predicates;
+                predicates.push(parse_quote! {
+                    #param: #runtime::Optionalize + ::std::convert::TryFrom<#param::Optional>
+                });
+                predicates.push(parse_quote!(#param::Optional: #runtime::Patch<#param>));
+                bounded.push(param.clone());
+            }
+            field.twin_param = Some(param);
+        }
+
+        // Patches of plain values are cheap to pass around by value
+        let all_copy = !fields.is_empty() && fields.iter().all(|field| is_copy(field.inner_ty()));
+        if attrs.copy || all_copy {
+            for name in ["Clone", "Copy"] {
+                if !attrs.derives.iter().any(|derive| path_ends_with(derive, name)) {
+                    let ident = format_ident!("{}", name);
+                    attrs.derives.push(parse_quote!(#ident));
+                }
+            }
+        }
+
+        if attrs.trace_values && !attrs.integrates("tracing") {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`trace_values` requires `#[optionalize(tracing)]`",
+            ));
+        }
+        if attrs.trace_values && !attrs.derives.iter().any(|path| path.is_ident("Debug")) {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "`trace_values` requires `#[optionalize(derive(Debug))]`",
+            ));
+        }
+
+        // UniFFI records, PyO3 classes and napi objects can't be generic
+        for (enabled, name) in [(attrs.uniffi, "uniffi"), (attrs.pyo3, "pyo3"), (attrs.napi, "napi")] {
+            if enabled && !generics.params.is_empty() {
+                return Err(Error::new_spanned(
+                    &generics,
+                    format!("`{}` can't be used on generic structs", name),
+                ));
+            }
+        }
+
+        if let Some(version) = &attrs.version {
+            if !fields.iter().any(|field| field.ident == *version) {
+                return Err(Error::new_spanned(version, "no field with this name"));
+            }
+        }
+
+        // Generate a new name for the "optionalized" struct
+        let optional_ident = optional_ident(&attrs, &input.ident)?;
+
+        let container = Container {
+            ident: input.ident,
+            optional_ident,
+            generics,
+            attrs,
+            fields,
+            markers,
+            skipped,
+            derives_clone,
+            runtime,
+        };
+
+        // Integrations call into the groups they build on rather than
+        // emitting them behind the caller's back. The one named in the
+        // attribute comes after those it implies, so is reported first.
+        for integration in container.attrs.integrations.iter().rev() {
+            let groups = INTEGRATIONS
+                .iter()
+                .find(|(name, _, _)| integration == name)
+                .map_or(&[][..], |(_, _, groups)| *groups);
+            if !groups.iter().all(|group| container.emits(group)) {
+                return Err(Error::new_spanned(
+                    integration,
+                    format!("`{}` requires `impls({})`", integration, groups.join(", ")),
+                ));
+            }
+        }
+
+        Ok(container)
+    }
+
+    /// An expression building the generated struct with every field unset.
+    pub fn empty(&self) -> TokenStream {
+        let idents = self.fields.iter().map(|field| &field.ident);
+        let unset = self.fields.iter().map(Field::unset);
+        let markers = self.markers.iter().map(|marker| &marker.ident);
+        quote! {
+            Self {
+                #( #idents: #unset, )*
+                #( #markers: ::std::marker::PhantomData, )*
+            }
+        }
+    }
+
+    /// The field named by `#[optionalize(version = "...")]`, if any.
+    pub fn version_field(&self) -> Option<&Field> {
+        let version = self.attrs.version.as_ref()?;
+        self.fields.iter().find(|field| field.ident == *version)
+    }
+
+    /// Whether any field is sensitive.
+    pub fn has_sensitive(&self) -> bool {
+        self.fields.iter().any(Field::is_sensitive)
+    }
+
+    /// Derives requested for the generated struct, minus those implemented by hand.
+    pub fn derives(&self) -> Vec<&Path> {
+        self.attrs
+            .derives
+            .iter()
+            // A derived `Debug` would print sensitive fields
+            .filter(|path| !(self.has_sensitive() && path.is_ident("Debug")))
+            .collect()
+    }
+
+    /// Whether the generated struct derives serde's `Serialize`.
+    pub fn derives_serialize(&self) -> bool {
+        self.attrs.derives.iter().any(|path| path_ends_with(path, "Serialize"))
+    }
+
+    /// Whether the generated struct derives serde's `Deserialize`.
+    pub fn derives_deserialize(&self) -> bool {
+        self.attrs.derives.iter().any(|path| path_ends_with(path, "Deserialize"))
+    }
+
+    /// Whether the impls of `group` are emitted: when `impls(...)` picks it
+    /// or `all`, or something else generated relies on it.
+    pub fn emits(&self, group: &str) -> bool {
+        if self.attrs.all_impls || self.attrs.impls.iter().any(|picked| picked == group) {
+            return true;
+        }
+        let attrs = &self.attrs;
+        match group {
+            "apply" => {
+                self.emits("serde")
+                    || attrs.event.is_some()
+                    || attrs.ops
+                    || attrs.shared
+                    || attrs.lens
+                    || attrs.version.is_some()
+                    || attrs.schema_version.is_some()
+            }
+            "diff" => attrs.report.is_some(),
+            "sql" => attrs.filter,
+            _ => false,
+        }
+    }
+
+    /// Whether the generated struct derives `Zeroize`, as it holds values
+    /// wiped with `zeroize`.
+    pub fn zeroizes(&self) -> bool {
+        self.fields.iter().any(Field::zeroizes)
+    }
+
+    /// Whether `Debug` was requested for the generated struct.
+    pub fn wants_debug(&self) -> bool {
+        self.attrs.derives.iter().any(|path| path.is_ident("Debug"))
+    }
+
+    /// The struct's own `where` clause extended with `predicates`, for trait
+    /// impls on the generated struct. A `bound = "..."` attribute replaces
+    /// `predicates`.
+    pub fn where_clause_with(&self, predicates: TokenStream) -> TokenStream {
+        let own = self
+            .generics
+            .where_clause
+            .iter()
+            .flat_map(|where_clause| &where_clause.predicates);
+        match &self.attrs.bound {
+            Some(bound) => quote! {
+                where #( #own, )* #( #bound, )*
+            },
+            None => quote! {
+                where #( #own, )* #predicates
+            },
+        }
+    }
+
+    /// Name of the error type reported by the generated checks.
+    pub fn error_ident(&self) -> Ident {
+        format_ident!("{}Error", self.optional_ident)
+    }
+}
+
+impl Field {
+    fn from_syn(field: syn::Field, container_attrs: &ContainerAttrs, params: &[&Ident]) -> Result<Self> {
+        let mut attrs = FieldAttrs::from_attrs(&field.attrs)?;
+
+        let option = is_option(&field.ty, &container_attrs.option_aliases);
+        // Fields of a listed type are nested unless they say otherwise
+        if attrs.nested.is_none() && !attrs.double_option && attrs.with.is_none() && attrs.collection.is_none() {
+            let value_ty = if option {
+                first_type_argument(&field.ty).unwrap_or(&field.ty)
+            } else {
+                &field.ty
+            };
+            let value_ty = if type_ends_with(value_ty, "Box") {
+                first_type_argument(value_ty).unwrap_or(value_ty)
+            } else {
+                value_ty
+            };
+            let listed = container_attrs.nested_types.iter().find(|(path, _)| match path.segments.last() {
+                Some(segment) => type_ends_with(value_ty, &segment.ident.to_string()),
+                None => false,
+            });
+            if let Some((_, twin)) = listed {
+                attrs.nested = Some(twin.clone());
+            } else if container_attrs.depth.unwrap_or(0) > 0 && !attrs.flatten && names_struct(value_ty, params) {
+                attrs.nested = Some(None);
+            }
+        }
+        // A double `Option` treats the whole `Option` as the value
+        let double_option = attrs.double_option || (option && container_attrs.double_option && attrs.nested.is_none());
+        if double_option {
+            let checked = attrs.min.is_some() || attrs.max.is_some() || attrs.regex.is_some();
+            if !option {
+                return Err(Error::new_spanned(&field.ty, "`double_option` requires an `Option` field"));
+            }
+            if attrs.nested.is_some() || attrs.flatten || attrs.empty_as_none || attrs.with.is_some() || checked {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`double_option` cannot be combined with `nested`, `flatten`, `empty_as_none`, `with`, `range` or `regex`",
+                ));
+            }
+        }
+        let option = option && !double_option;
+        let option_like = container_attrs
+            .option_likes
+            .iter()
+            .find(|(path, _)| match path.segments.last() {
+                Some(segment) => type_ends_with(&field.ty, &segment.ident.to_string()),
+                None => false,
+            })
+            .map(|(_, is_unset)| is_unset.clone());
+        if option_like.is_some() && (attrs.nested.is_some() || attrs.empty_as_none || attrs.pointer.is_some()) {
+            return Err(Error::new_spanned(
+                &field.ty,
+                "`nested`, `empty_as_none` and `pointer` cannot be used on an `option_like` field",
+            ));
+        }
+        let checked = attrs.min.is_some() || attrs.max.is_some() || attrs.regex.is_some();
+        if checked && (option_like.is_some() || attrs.nested.is_some()) {
+            return Err(Error::new_spanned(
+                &field.ty,
+                "`range` and `regex` cannot be used on `nested` or `option_like` fields",
+            ));
+        }
+        if attrs.with.is_some() != attrs.ty.is_some() {
+            return Err(Error::new_spanned(
+                &field.ty,
+                "`with = \"...\"` and `ty = \"...\"` must be used together",
+            ));
+        }
+        if attrs.with.is_some() && (option_like.is_some() || attrs.nested.is_some() || attrs.pointer.is_some()) {
+            return Err(Error::new_spanned(
+                &field.ty,
+                "`with` cannot be combined with `nested`, `pointer` or an `option_like` type",
+            ));
+        }
+        let value_ty = if option {
+            first_type_argument(&field.ty).unwrap_or(&field.ty)
+        } else {
+            &field.ty
+        };
+        // Unsized contents like `Rc<str>` can't be stored unboxed
+        let is_pointer = pointer_inner(value_ty).is_some_and(|inner| !is_unsized(inner));
+        let unwrap_pointer = match (attrs.pointer, container_attrs.pointer) {
+            (Some(PointerPolicy::Unwrap), _) if !is_pointer => {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`pointer = \"unwrap\"` requires a `Box`, `Arc` or `Rc` of a sized type",
+                ));
+            }
+            (Some(policy), _) | (None, Some(policy)) => is_pointer && policy == PointerPolicy::Unwrap,
+            (None, None) => false,
+        } && attrs.with.is_none();
+
+        if attrs.flatten {
+            let plain = !option && pointer_inner(&field.ty).is_none() && option_like.is_none();
+            let other = attrs.immutable
+                || attrs.group.is_some()
+                || attrs.sanitize_with.is_some()
+                || attrs.with.is_some()
+                || attrs.pointer.is_some()
+                || attrs.empty_as_none
+                || checked;
+            if !plain || other || container_attrs.prost {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`flatten` requires a plain struct field, and only combines with `nested = \"...\"`",
+                ));
+            }
+        }
+
+        if let Some(CollectionMerge::Entries(_)) = attrs.collection {
+            let plain = !option && (type_ends_with(&field.ty, "HashMap") || type_ends_with(&field.ty, "BTreeMap"));
+            let other = attrs.flatten
+                || attrs.with.is_some()
+                || attrs.pointer.is_some()
+                || option_like.is_some()
+                || double_option
+                || checked;
+            if !plain || other {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`merge_entries` requires a plain `HashMap` or `BTreeMap` field, without `flatten`, `with`, \
+                     `pointer`, `double_option`, `range` or `regex`",
+                ));
+            }
+
+            // Nested values are patched in place, so the patch holds a map of their patches
+            if let Some(twin) = attrs.nested.take() {
+                let (map, twin) = entries_ty(&field.ty, twin.as_ref(), container_attrs.partial)?;
+                attrs.collection = Some(CollectionMerge::Entries(Some(Box::new(twin))));
+                attrs.ty = Some(map);
+            }
+        } else if let Some(CollectionMerge::With(_)) = attrs.collection {
+            let other = attrs.nested.is_some()
+                || attrs.flatten
+                || attrs.with.is_some()
+                || attrs.pointer.is_some()
+                || option_like.is_some()
+                || double_option
+                || checked;
+            if option || other {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`merge_with` requires a plain, non-`Option` field, without `nested`, `flatten`, `with`, \
+                     `pointer`, `double_option`, `range` or `regex`",
+                ));
+            }
+        } else if attrs.collection.is_some() {
+            let plain = !option && type_ends_with(&field.ty, "Vec");
+            let other = attrs.nested.is_some()
+                || attrs.flatten
+                || attrs.with.is_some()
+                || attrs.pointer.is_some()
+                || option_like.is_some()
+                || double_option
+                || checked;
+            if !plain || other {
+                return Err(Error::new_spanned(
+                    &field.ty,
+                    "`merge_key` and `vec_merge` require a plain `Vec<T>` field, without `nested`, `flatten`, `with`, `pointer`, \
+                     `double_option`, `range` or `regex`",
+                ));
+            }
+        }
+
+        let nested_ty = match (&attrs.nested, attrs.flatten) {
+            (Some(twin), _) => Some(nested_ty(value_ty, twin.as_ref(), container_attrs.partial)?),
+            (None, true) => Some(nested_ty(value_ty, None, container_attrs.partial)?),
+            (None, false) => None,
+        };
+
+        Ok(Field {
+            attrs,
+            ident: field.ident.expect("named field"),
+            vis: field.vis,
+            ty: field.ty,
+            source_attrs: field.attrs,
+            option,
+            option_like,
+            double_option,
+            unwrap_pointer: unwrap_pointer && nested_ty.is_none(),
+            nested_ty,
+            twin_param: None,
+            secrecy: container_attrs.integrates("secrecy"),
+            zeroize: container_attrs.integrates("zeroize"),
+        })
+    }
+
+    /// The field name as it appears in error messages and metadata, without
+    /// the `r#` of a raw identifier, matching serde's field names.
+    pub fn name(&self) -> String {
+        self.ident.unraw().to_string()
+    }
+
+    /// Whether the field is already an `Option<T>`, directly or through an
+    /// `option_alias`.
+    pub fn is_option(&self) -> bool {
+        self.option
+    }
+
+    /// Expression telling whether the field is set in `self`, the generated struct.
+    pub fn is_set(&self) -> TokenStream {
+        let ident = &self.ident;
+        if self.attrs.flatten {
+            return quote! { !self.#ident.is_empty() };
+        }
+        match &self.option_like {
+            Some(is_unset) => quote! { !self.#ident.#is_unset() },
+            None => quote! { self.#ident.is_some() },
+        }
+    }
+
+    /// The value of an unset field in the generated struct.
+    pub fn unset(&self) -> TokenStream {
+        if let (true, Some(twin)) = (self.attrs.flatten, &self.nested_ty) {
+            return quote! { <#twin>::empty() };
+        }
+        match &self.option_like {
+            Some(_) => quote! { ::std::default::Default::default() },
+            None => quote! { None },
+        }
+    }
+
+    /// The name of a flattened field's error type, e.g. `AddressOptionalError`.
+    pub fn flatten_error(&self) -> Option<Path> {
+        if !self.attrs.flatten {
+            return None;
+        }
+        self.twin_error()
+    }
+
+    /// The name of a nested or flattened field's error type, e.g.
+    /// `AddressOptionalError`.
+    pub fn twin_error(&self) -> Option<Path> {
+        self.twin_item("Error")
+    }
+
+    /// Whether a nested field holds the struct's own twin, as in recursive
+    /// types such as trees.
+    pub fn is_recursive(&self, cx: &Container) -> bool {
+        let nested_ty = match &self.nested_ty {
+            Some(nested_ty) => nested_ty,
+            None => return false,
+        };
+        match pointer_inner(nested_ty).unwrap_or(nested_ty) {
+            Type::Path(TypePath { qself: None, path }) => {
+                path.segments.last().is_some_and(|segment| segment.ident == cx.optional_ident)
+            }
+            _ => false,
+        }
+    }
+
+    /// The name of an item generated alongside a nested or flattened field's
+    /// twin, e.g. `AddressOptionalError` for `"Error"`.
+    pub fn twin_item(&self, suffix: &str) -> Option<Path> {
+        if self.twin_param.is_some() {
+            return None;
+        }
+        let nested_ty = self.nested_ty.as_ref()?;
+        let twin = match pointer_inner(nested_ty).unwrap_or(nested_ty) {
+            Type::Path(TypePath { path, .. }) => path,
+            _ => return None,
+        };
+        let mut path = twin.clone();
+        let segment = path.segments.last_mut()?;
+        segment.ident = format_ident!("{}{}", segment.ident, suffix);
+        segment.arguments = PathArguments::None;
+        Some(path)
+    }
+
+    /// Path to `method` of a nested field's twin: its own method for a
+    /// generated struct, or the runtime crate's trait method for the twin of a
+    /// type parameter, whose traits may not be in scope.
+    pub fn twin_fn(&self, method: &str) -> TokenStream {
+        let nested_ty = self.nested_ty.as_ref().expect("nested field");
+        let twin = pointer_inner(nested_ty).unwrap_or(nested_ty);
+        let method = format_ident!("{}", method);
+        match &self.twin_param {
+            Some(param) => {
+                let runtime = runtime_crate().expect("twins of type parameters require the runtime crate");
+                let trait_path = match method.to_string().as_str() {
+                    "merge" => quote!(#runtime::Merge),
+                    "apply_to" => quote!(#runtime::ApplyPatch<#param>),
+                    "diff" | "invert" | "changed_fields" => quote!(#runtime::Diff<#param>),
+                    _ => quote!(#runtime::Patch<#param>),
+                };
+                quote! { <#twin as #trait_path>::#method }
+            }
+            None => quote! { <#twin>::#method },
+        }
+    }
+
+    /// Whether the field must be kept out of logs and serialized output, either
+    /// because it is marked `#[optionalize(sensitive)]` or because it holds a
+    /// `secrecy` secret.
+    pub fn is_sensitive(&self) -> bool {
+        self.attrs.sensitive || self.is_secret()
+    }
+
+    /// Whether the field is wiped with `zeroize`: sensitive values that don't
+    /// already wipe themselves like secrets do. Always `false` without
+    /// `#[optionalize(zeroize)]`.
+    pub fn zeroizes(&self) -> bool {
+        self.zeroize
+            && self.attrs.sensitive
+            && !self.is_secret()
+            && self.nested_ty.is_none()
+            && !self.attrs.flatten
+    }
+
+    /// Whether the field holds a `secrecy` secret, directly or inside an `Option`.
+    pub fn is_secret(&self) -> bool {
+        if !self.secrecy {
+            return false;
+        }
+
+        let ty = self.inner_ty();
+        ["Secret", "SecretString", "SecretBox", "SecretSlice"]
+            .iter()
+            .any(|name| type_ends_with(ty, name))
+    }
+
+    /// The `T` of an `Option<T>` field, or the field's own type otherwise.
+    pub fn value_ty(&self) -> &Type {
+        if self.option {
+            first_type_argument(&self.ty).unwrap_or(&self.ty)
+        } else {
+            &self.ty
+        }
+    }
+
+    /// The `T` of the generated `Option<T>` field.
+    pub fn inner_ty(&self) -> &Type {
+        if let Some(nested_ty) = &self.nested_ty {
+            return nested_ty;
+        }
+        // Option-like fields are set and read whole
+        if self.option_like.is_some() {
+            return &self.ty;
+        }
+        if let Some(ty) = &self.attrs.ty {
+            return ty;
+        }
+
+        let ty = self.value_ty();
+        if self.unwrap_pointer {
+            pointer_inner(ty).unwrap_or(ty)
+        } else {
+            ty
+        }
+    }
+
+    /// Converts `value`, of the generated field's inner type, into a value of
+    /// the original field.
+    pub fn to_source(&self, value: TokenStream) -> TokenStream {
+        let value = if let Some(with) = &self.attrs.with {
+            quote! { #with::from_optional(#value) }
+        } else if self.unwrap_pointer {
+            quote! { ::std::convert::From::from(#value) }
+        } else {
+            value
+        };
+        if self.is_option() {
+            quote! { Some(#value) }
+        } else {
+            value
+        }
+    }
+
+    /// Converts `value`, of the original field's type, into the value of the
+    /// generated field.
+    pub fn to_optional(&self, value: TokenStream) -> TokenStream {
+        if self.option_like.is_some() {
+            return value;
+        }
+        if self.attrs.flatten {
+            return quote! { ::std::convert::From::from(#value) };
+        }
+
+        let convert = if let Some(with) = &self.attrs.with {
+            quote! { #with::to_optional }
+        } else if let Some(CollectionMerge::Entries(Some(_))) = &self.attrs.collection {
+            let ty = self.value_ty();
+            quote! {
+                |value: #ty| value.into_iter().map(|(key, value)| (key, ::std::convert::From::from(value))).collect()
+            }
+        } else if let Some(nested_ty) = &self.nested_ty {
+            if type_ends_with(nested_ty, "Box") {
+                let ty = self.value_ty();
+                quote! { |value: #ty| ::std::boxed::Box::new(::std::convert::From::from(*value)) }
+            } else {
+                quote! { ::std::convert::From::from }
+            }
+        } else if self.unwrap_pointer {
+            let ty = self.value_ty();
+            if type_ends_with(ty, "Box") {
+                quote! { |value: #ty| *value }
+            } else {
+                // Shared contents are only cloned when something else holds them
+                quote! { <#ty>::unwrap_or_clone }
+            }
+        } else if self.is_option() {
+            return value;
+        } else {
+            return quote! { Some(#value) };
+        };
+
+        if self.is_option() {
+            quote! { #value.map(#convert) }
+        } else {
+            quote! { Some((#convert)(#value)) }
+        }
+    }
+
+    /// The type of this field in the generated struct.
+    pub fn optional_ty(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.attrs.flatten {
+            let twin = self.inner_ty();
+            quote! { #twin }
+        } else if self.unwrap_pointer || self.nested_ty.is_some() || self.attrs.ty.is_some() {
+            let inner = self.inner_ty();
+            quote! { Option<#inner> }
+        } else if self.is_option() || self.option_like.is_some() {
+            // Field is already an Option<T> or option-like, keep it as is
+            quote! { #ty }
+        } else {
+            // Wrap the field type in Option<T>
+            quote! { Option<#ty> }
+        }
+    }
+}
+
+/// `where` predicates requiring `bound` on the inner type of every field.
+///
+/// The bounds are higher-ranked so they are only checked where the method or
+/// impl is used, rather than rejected up front for fields that don't satisfy them.
+pub fn inner_bounds<'a>(fields: impl IntoIterator<Item = &'a Field>, bound: TokenStream) -> TokenStream {
+    // Flattened twins carry their own bounds
+    let tys = fields
+        .into_iter()
+        .filter(|field| !field.attrs.flatten)
+        .map(Field::inner_ty);
+    quote! {
+        #( for<'__optionalize> #tys: #bound, )*
+    }
+}
+
+/// The path of the `optionalize` runtime crate when the calling crate depends
+/// on it, under whatever name the dependency was given. Looked up for each
+/// caller, as Cargo enables a feature for every crate in the build once any of
+/// them asks for it.
+pub fn runtime_crate() -> Option<Path> {
+    let name = match crate_name("optionalize").ok()? {
+        FoundCrate::Name(name) => format_ident!("{}", name),
+        // Integration tests and doctests of the crate itself refer to it by name
+        FoundCrate::Itself => format_ident!("optionalize"),
+    };
+    Some(parse_quote!(::#name))
+}
+
+/// The name of the "optionalized" type generated for the struct `ident`.
+pub fn optional_ident(attrs: &ContainerAttrs, ident: &Ident) -> Result<Ident> {
+    match (&attrs.name, &attrs.prefix, &attrs.suffix) {
+        (Some(name), None, None) => Ok(name.clone()),
+        (Some(name), _, _) => Err(Error::new_spanned(
+            name,
+            "`name` cannot be combined with `prefix`, `suffix` or `naming`",
+        )),
+        (None, None, None) if attrs.partial => Ok(format_ident!("Partial{}", ident)),
+        (None, None, None) => Ok(format_ident!("{}Optional", ident)),
+        (None, prefix, suffix) => Ok(format_ident!(
+            "{}{}{}",
+            prefix.as_deref().unwrap_or(""),
+            ident,
+            suffix.as_deref().unwrap_or("")
+        )),
+    }
+}
+
+/// `ident` in upper camel case, e.g. `FirstName` for `first_name`.
+pub fn camel_case(ident: &Ident) -> String {
+    ident
+        .unraw()
+        .to_string()
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `ident` in snake case, e.g. `user_profile` for `UserProfile`.
+pub fn snake_case(ident: &Ident) -> String {
+    let mut snake = String::new();
+    for (i, c) in ident.unraw().to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Whether the last segment of `path` is `name`.
+pub fn path_ends_with(path: &Path, name: &str) -> bool {
+    path.segments.last().map(|s| s.ident == name).unwrap_or(false)
+}
+
+/// Whether `ty` is the bare path `ident`, such as a type parameter.
+fn type_is_ident(ty: &Type, ident: &Ident) -> bool {
+    match ty {
+        Type::Path(TypePath { qself: None, path }) => path.is_ident(ident),
+        _ => false,
+    }
+}
+
+/// Whether `ty` looks like a struct the derive can nest through its twin: a
+/// bare name without type arguments that isn't a type parameter, a primitive
+/// or a common `std` type.
+fn names_struct(ty: &Type, params: &[&Ident]) -> bool {
+    const NOT_STRUCTS: &[&str] = &[
+        "bool", "char", "str", "String", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+        "i128", "isize", "f32", "f64", "PathBuf", "Duration", "Instant", "SystemTime", "IpAddr", "Ipv4Addr",
+        "Ipv6Addr", "SocketAddr",
+    ];
+    match ty {
+        Type::Path(TypePath { qself: None, path }) => match path.get_ident() {
+            Some(ident) => !params.contains(&ident) && !NOT_STRUCTS.iter().any(|name| ident == name),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether `ty` is a path type whose last segment is `name`.
+pub fn type_ends_with(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(TypePath { path, .. }) => path_ends_with(path, name),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is an `Option`, or one of the given local aliases of it.
+fn is_option(ty: &Type, aliases: &[Path]) -> bool {
+    type_ends_with(ty, "Option")
+        || aliases.iter().any(|alias| match alias.segments.last() {
+            Some(segment) => type_ends_with(ty, &segment.ident.to_string()),
+            None => false,
+        })
+}
+
+/// Rewrites every `Self` type to the wrapped type.
+struct ReplaceSelf(Type);
+
+impl VisitMut for ReplaceSelf {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        match ty {
+            Type::Path(TypePath { qself: None, path }) if path.is_ident("Self") => *ty = self.0.clone(),
+            _ => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+}
+
+/// The `T` in `Box<T>`, `Arc<T>` or `Rc<T>`, if `ty` is one of those.
+pub fn pointer_inner(ty: &Type) -> Option<&Type> {
+    if !["Box", "Arc", "Rc"].iter().any(|name| type_ends_with(ty, name)) {
+        return None;
+    }
+    first_type_argument(ty)
+}
+
+/// The optional twin of a nested field's value type: `twin` if given, or the
+/// type's own name with `Optional` appended, kept inside a `Box` if it had one.
+fn nested_ty(value_ty: &Type, twin: Option<&Type>, partial: bool) -> Result<Type> {
+    if ["Arc", "Rc"].iter().any(|name| type_ends_with(value_ty, name)) {
+        return Err(Error::new_spanned(
+            value_ty,
+            "`nested` fields behind an `Arc` or `Rc` cannot be patched in place; \
+             store the value in a `Box` or drop `nested` to replace it whole",
+        ));
+    }
+
+    if type_ends_with(value_ty, "Box") {
+        let inner = first_type_argument(value_ty)
+            .ok_or_else(|| Error::new_spanned(value_ty, "expected `Box<T>`"))?;
+        let twin = nested_ty(inner, twin, partial)?;
+        return Ok(syn::parse_quote!(Box<#twin>));
+    }
+
+    if let Some(twin) = twin {
+        return Ok(twin.clone());
+    }
+
+    match value_ty {
+        Type::Path(TypePath { qself: None, path }) => {
+            let mut path = path.clone();
+            let last = path.segments.last_mut().expect("non-empty path");
+            last.ident = if partial {
+                format_ident!("Partial{}", last.ident)
+            } else {
+                format_ident!("{}Optional", last.ident)
+            };
+            Ok(Type::Path(TypePath { qself: None, path }))
+        }
+        other => Err(Error::new_spanned(
+            other,
+            "`nested` requires a struct type, optionally inside `Box` or `Option`; \
+             name the twin with `nested = \"...\"` for other types",
+        )),
+    }
+}
+
+/// The map type holding patches of the values of the map `ty`, along with the
+/// patch type of its values.
+fn entries_ty(ty: &Type, twin: Option<&Type>, partial: bool) -> Result<(Type, Type)> {
+    let mut map = ty.clone();
+    let value = match &mut map {
+        Type::Path(TypePath { path, .. }) => match &mut path.segments.last_mut().expect("non-empty path").arguments {
+            PathArguments::AngleBracketed(args) => args
+                .args
+                .iter_mut()
+                .filter_map(|arg| match arg {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                })
+                .nth(1),
+            _ => None,
+        },
+        _ => None,
+    };
+    let value = value.ok_or_else(|| Error::new_spanned(ty, "expected `HashMap<K, V>` or `BTreeMap<K, V>`"))?;
+    if pointer_inner(value).is_some() {
+        return Err(Error::new_spanned(
+            &*value,
+            "`nested` map values must be stored directly, not behind a pointer",
+        ));
+    }
+
+    let twin = nested_ty(value, twin, partial)?;
+    *value = twin.clone();
+    Ok((map, twin))
+}
+
+/// Whether `ty` is a primitive integer or float type.
+pub fn is_numeric(ty: &Type) -> bool {
+    const NUMERIC: &[&str] = &[
+        "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+    ];
+    match ty {
+        Type::Path(TypePath { qself: None, path }) => NUMERIC.iter().any(|name| path.is_ident(name)),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is obviously `Copy`: a primitive, a shared reference, or a tuple
+/// or array of those.
+fn is_copy(ty: &Type) -> bool {
+    const PRIMITIVES: &[&str] = &[
+        "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64",
+        "u128", "usize",
+    ];
+    match ty {
+        Type::Path(TypePath { qself: None, path }) => PRIMITIVES.iter().any(|name| path.is_ident(name)),
+        Type::Reference(reference) => reference.mutability.is_none(),
+        Type::Tuple(tuple) => tuple.elems.iter().all(is_copy),
+        Type::Array(array) => is_copy(&array.elem),
+        Type::Paren(paren) => is_copy(&paren.elem),
+        Type::Group(group) => is_copy(&group.elem),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is obviously unsized: `str`, a slice or a trait object.
+fn is_unsized(ty: &Type) -> bool {
+    match ty {
+        Type::Slice(_) | Type::TraitObject(_) => true,
+        Type::Path(TypePath { path, .. }) => path.is_ident("str"),
+        Type::Paren(paren) => is_unsized(&paren.elem),
+        Type::Group(group) => is_unsized(&group.elem),
+        _ => false,
+    }
+}
+
+/// The first type argument of a path type, e.g. the `T` of `Vec<T>`.
+pub fn first_type_argument(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Path(TypePath { path, .. }) => match &path.segments.last()?.arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
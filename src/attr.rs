@@ -0,0 +1,1147 @@
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Error, Expr, Ident, Lit, LitStr, Meta, NestedMeta, Path, Result, Token, Type, WherePredicate};
+
+/// Options read from `#[optionalize(...)]` attributes on the struct itself.
+#[derive(Default)]
+pub struct ContainerAttrs {
+    /// Derives to add to the generated struct.
+    pub derives: Vec<Path>,
+    /// Traits the generated struct must implement, checked at compile time.
+    pub asserts: Vec<Path>,
+    /// Field holding the version used for optimistic concurrency checks.
+    pub version: Option<Ident>,
+    /// Function computing the version a value moves to once a patch is applied.
+    pub next_version: Option<Path>,
+    /// Schema version of the patch, for persisted patches.
+    pub schema_version: Option<u32>,
+    /// Hook upgrading patches persisted with an older schema version.
+    pub migrate_from: Option<Path>,
+    /// Derive `prost::Message` on the generated struct.
+    pub prost: bool,
+    /// Derive Rocket's `FromForm` on the generated struct.
+    pub rocket: bool,
+    /// Derive `poem_openapi::Object` on the generated struct.
+    pub poem_openapi: bool,
+    /// Derive the `rkyv` traits on the generated struct.
+    pub rkyv: bool,
+    /// Derive `uniffi::Record` on the generated struct.
+    pub uniffi: bool,
+    /// Expose the generated struct to Python as a PyO3 `#[pyclass]`.
+    pub pyo3: bool,
+    /// Mark the generated struct as a napi-rs `#[napi(object)]`.
+    pub napi: bool,
+    /// Attributes added to every field of the generated struct.
+    pub field_attrs: Vec<Meta>,
+    /// Exact name of the generated struct.
+    pub name: Option<Ident>,
+    /// Prefix of the generated struct's name.
+    pub prefix: Option<String>,
+    /// Suffix of the generated struct's name.
+    pub suffix: Option<String>,
+    /// Documentation of the generated struct.
+    pub doc: Option<String>,
+    /// Attributes added to the generated struct itself.
+    pub attrs: Vec<Meta>,
+    /// Default representation of `Box`, `Arc` and `Rc` fields.
+    pub pointer: Option<PointerPolicy>,
+    /// Local aliases of `Option`, whose fields are treated as already optional.
+    pub option_aliases: Vec<Path>,
+    /// Option-like types kept as-is in the generated struct, with the method
+    /// telling whether a value is unset.
+    pub option_likes: Vec<(Path, Ident)>,
+    /// Types whose fields are treated as `nested` without the field attribute,
+    /// with the name of their optional twin if it isn't the default.
+    pub nested_types: Vec<(Path, Option<Type>)>,
+    /// Schema version of the generated change event, if one was requested.
+    pub event: Option<u32>,
+    /// Generate an operation-based patch alongside the value-based one.
+    pub ops: bool,
+    /// Generate a variant of the patch holding its values behind `Arc`s.
+    pub shared: bool,
+    /// Generate a typestate builder of the original struct.
+    pub builder: bool,
+    /// Generate a query-by-example filter of the original struct.
+    pub filter: bool,
+    /// Generate a variant of the patch generic over the wrapper of its fields.
+    pub wrapper: bool,
+    /// Generate a `#[repr(C)]` twin of the generated struct.
+    pub ffi: bool,
+    /// Derives of the generated diff report, if one was requested.
+    pub report: Option<Vec<Path>>,
+    /// Generate a last-write-wins variant of the patch with timestamped fields.
+    pub lww: bool,
+    /// Implement `Display` on the generated struct as `field = value` lines.
+    pub display: bool,
+    /// Treat fields holding other structs as `nested`, from `depth = N` on the
+    /// derive.
+    pub depth: Option<usize>,
+    /// Generate a bitmap-prefixed binary encoding of the generated struct.
+    pub compact: bool,
+    /// The `cfg` predicate the generated items are only emitted under.
+    pub cfg: Option<NestedMeta>,
+    /// Derived as `Partial`, so generated names default to `Partial{Struct}`.
+    pub partial: bool,
+    /// Write the generated code to a file for inspection.
+    pub debug_expand: bool,
+    /// The groups of generated impls picked with `impls(...)`.
+    pub impls: Vec<Ident>,
+    /// Emit every group of generated impls, with `impls(all)`.
+    pub all_impls: bool,
+    /// Generate borrowing getters for `String`, `Vec` and `Box` fields.
+    pub getters: bool,
+    /// Generate `Option<Inner>` as the optional form of a single-field newtype.
+    pub transparent: bool,
+    /// Generate a `macro_rules!` building the patch from `field: value` pairs,
+    /// with the macro's name if one was given.
+    pub patch_macro: Option<Option<Ident>>,
+    /// Generate typed paths into the original struct.
+    pub lens: bool,
+    /// Derive the comparison and hashing traits the original struct derives.
+    pub forward_derives: bool,
+    /// Derive `Clone` and `Copy` on the generated struct.
+    pub copy: bool,
+    /// Predicates replacing the inferred bounds of the generated impls.
+    pub bound: Option<Vec<WherePredicate>>,
+    /// Wrap every `Option` field without `nested` in a second `Option`.
+    pub double_option: bool,
+    /// Deserialize fields from the strings of form-encoded input.
+    pub form: bool,
+    /// Deserialize fields from query strings, with repeated keys for collections.
+    pub query: bool,
+    /// Fail to deserialize `null` for fields that aren't `Option`s.
+    pub reject_null: bool,
+    /// How `merge` treats fields set in both patches.
+    pub merge: MergePolicy,
+    /// Function called with the patch and the target before a patch is applied.
+    pub before_apply: Option<Path>,
+    /// Function called with the target after a patch is applied.
+    pub after_apply: Option<Path>,
+    /// Async function validating a patch against its target before it is applied.
+    pub async_validate: Option<Path>,
+    /// Include the patch's `Debug` output in its `tracing` events.
+    pub trace_values: bool,
+    /// The crates the generated code integrates with, such as `tracing` or
+    /// `serde_json`, picked one by one so crates that don't depend on them are
+    /// unaffected.
+    pub integrations: Vec<Ident>,
+}
+
+impl ContainerAttrs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut container_attrs = ContainerAttrs::default();
+
+        for meta in optionalize_metas(attrs)? {
+            match meta {
+                // #[optionalize(derive(Debug, Clone))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("derive") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) => container_attrs.derives.push(path),
+                            other => return Err(Error::new_spanned(other, "expected a derive path")),
+                        }
+                    }
+                }
+                // #[optionalize(assert(Serialize, Send, Sync))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("assert") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) => container_attrs.asserts.push(path),
+                            other => return Err(Error::new_spanned(other, "expected a trait path")),
+                        }
+                    }
+                }
+                // #[optionalize(version = "revision")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("version") => match &nv.lit {
+                    Lit::Int(_) => {
+                        return Err(Error::new_spanned(
+                            &nv.lit,
+                            "`version` names the field holding the value's version, e.g. \
+                             `version = \"revision\"`; use `schema_version = 2` for the patch's schema version",
+                        ));
+                    }
+                    lit => container_attrs.version = Some(lit_ident(lit)?),
+                },
+                // #[optionalize(schema_version = 2)]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("schema_version") => match &nv.lit {
+                    Lit::Int(version) => container_attrs.schema_version = Some(version.base10_parse()?),
+                    other => return Err(Error::new_spanned(other, "expected an integer schema version")),
+                },
+                // #[optionalize(next_version = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("next_version") => {
+                    container_attrs.next_version = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(migrate_from = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("migrate_from") => {
+                    container_attrs.migrate_from = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(prost)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("prost") => {
+                    container_attrs.prost = true;
+                }
+                // #[optionalize(rocket)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("rocket") => {
+                    container_attrs.rocket = true;
+                }
+                // #[optionalize(poem_openapi)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("poem_openapi") => {
+                    container_attrs.poem_openapi = true;
+                }
+                // #[optionalize(rkyv)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("rkyv") => {
+                    container_attrs.rkyv = true;
+                }
+                // #[optionalize(uniffi)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("uniffi") => {
+                    container_attrs.uniffi = true;
+                }
+                // #[optionalize(pyo3)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("pyo3") => {
+                    container_attrs.pyo3 = true;
+                }
+                // #[optionalize(napi)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("napi") => {
+                    container_attrs.napi = true;
+                }
+                // #[optionalize(name = "UserPatch")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => {
+                    container_attrs.name = Some(lit_ident(&nv.lit)?);
+                }
+                // #[optionalize(prefix = "Partial")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("prefix") => {
+                    container_attrs.prefix = Some(lit_str(&nv.lit)?);
+                }
+                // #[optionalize(suffix = "Patch")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("suffix") => {
+                    container_attrs.suffix = Some(lit_str(&nv.lit)?);
+                }
+                // #[optionalize(naming = "partial")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("naming") => {
+                    let (prefix, suffix) = match lit_str(&nv.lit)?.as_str() {
+                        "optional" => ("", "Optional"),
+                        "partial" => ("Partial", ""),
+                        "patch" => ("", "Patch"),
+                        "update" => ("", "Update"),
+                        _ => {
+                            return Err(Error::new_spanned(
+                                nv.lit,
+                                "expected one of \"optional\", \"partial\", \"patch\" or \"update\"",
+                            ));
+                        }
+                    };
+                    container_attrs.prefix = Some(prefix.to_string());
+                    container_attrs.suffix = Some(suffix.to_string());
+                }
+                // #[optionalize(doc = "Partial update payload for the Users API")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("doc") => {
+                    container_attrs.doc = Some(lit_str(&nv.lit)?);
+                }
+                // #[optionalize(field_attr(ts(optional)))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("field_attr") => {
+                    container_attrs.field_attrs.extend(nested_metas(list.nested)?);
+                }
+                // #[optionalize(pointer = "unwrap")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("pointer") => {
+                    container_attrs.pointer = Some(PointerPolicy::from_lit(&nv.lit)?);
+                }
+                // #[optionalize(option_alias(Maybe, Nullable))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("option_alias") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) => container_attrs.option_aliases.push(path),
+                            other => return Err(Error::new_spanned(other, "expected an alias of `Option`")),
+                        }
+                    }
+                }
+                // #[optionalize(option_like(MaybeUndefined = "is_undefined"))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("option_like") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                                let is_unset = lit_method(&nv.lit)?;
+                                container_attrs.option_likes.push((nv.path, is_unset));
+                            }
+                            other => {
+                                return Err(Error::new_spanned(
+                                    other,
+                                    "expected `Type = \"method\"`, naming the method that tells if a value is unset",
+                                ));
+                            }
+                        }
+                    }
+                }
+                // #[optionalize(nested_types(Address, Node = "NodePatch"))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("nested_types") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) => container_attrs.nested_types.push((path, None)),
+                            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                                let twin = lit_type(&nv.lit)?;
+                                container_attrs.nested_types.push((nv.path, Some(twin)));
+                            }
+                            other => {
+                                return Err(Error::new_spanned(
+                                    other,
+                                    "expected `Type` or `Type = \"TwinType\"`, naming a struct deriving `Optionalize`",
+                                ));
+                            }
+                        }
+                    }
+                }
+                // #[optionalize(double_option)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("double_option") => {
+                    container_attrs.double_option = true;
+                }
+                // #[optionalize(merge = "error_on_conflict")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("merge") => {
+                    container_attrs.merge = MergePolicy::from_lit(&nv.lit)?;
+                }
+                // #[optionalize(before_apply = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("before_apply") => {
+                    container_attrs.before_apply = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(after_apply = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("after_apply") => {
+                    container_attrs.after_apply = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(async_validate = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("async_validate") => {
+                    container_attrs.async_validate = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(trace_values)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("trace_values") => {
+                    container_attrs.trace_values = true;
+                }
+                // #[optionalize(tracing)]
+                NestedMeta::Meta(Meta::Path(path)) if INTEGRATIONS.iter().any(|(name, _, _)| path.is_ident(name)) => {
+                    container_attrs.integrate(path.get_ident().expect("integration name"));
+                }
+                // #[optionalize(reject_null)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("reject_null") => {
+                    container_attrs.reject_null = true;
+                }
+                // #[optionalize(form)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("form") => {
+                    container_attrs.form = true;
+                }
+                // #[optionalize(query)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("query") => {
+                    container_attrs.query = true;
+                }
+                // #[optionalize(copy)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("copy") => {
+                    container_attrs.copy = true;
+                }
+                // #[optionalize(forward_derives)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("forward_derives") => {
+                    container_attrs.forward_derives = true;
+                }
+                // #[optionalize(lens)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("lens") => {
+                    container_attrs.lens = true;
+                }
+                // #[optionalize(ops)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("ops") => {
+                    container_attrs.ops = true;
+                }
+                // #[optionalize(shared)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("shared") => {
+                    container_attrs.shared = true;
+                }
+                // #[optionalize(filter)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("filter") => {
+                    container_attrs.filter = true;
+                }
+                // #[optionalize(wrapper)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("wrapper") => {
+                    container_attrs.wrapper = true;
+                }
+                // #[optionalize(ffi)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("ffi") => {
+                    container_attrs.ffi = true;
+                }
+                // #[optionalize(builder)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("builder") => {
+                    container_attrs.builder = true;
+                }
+                // #[optionalize(lww)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("lww") => {
+                    container_attrs.lww = true;
+                }
+                // #[optionalize(compact)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("compact") => {
+                    container_attrs.compact = true;
+                }
+                // #[optionalize(display)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("display") => {
+                    container_attrs.display = true;
+                }
+                // #[optionalize(depth = 2)], taken out beforehand by `optionalize_all`
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("depth") => match &nv.lit {
+                    Lit::Int(lit) => container_attrs.depth = Some(lit.base10_parse()?),
+                    other => return Err(Error::new_spanned(other, "expected a number of levels, e.g. `depth = 2`")),
+                },
+                // #[optionalize(debug_expand)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("debug_expand") => {
+                    container_attrs.debug_expand = true;
+                }
+                // #[optionalize(impls(apply, merge, serde))] or #[optionalize(impls(all))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("impls") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("all") => {
+                                container_attrs.all_impls = true;
+                            }
+                            NestedMeta::Meta(Meta::Path(path))
+                                if IMPL_GROUPS.iter().any(|group| path.is_ident(group)) =>
+                            {
+                                container_attrs.impls.push(path.get_ident().expect("group name").clone());
+                            }
+                            other => {
+                                let groups: Vec<_> = IMPL_GROUPS.iter().map(|group| format!("`{}`", group)).collect();
+                                return Err(Error::new_spanned(
+                                    other,
+                                    format!("expected a group of impls, one of {} or `all`", groups.join(", ")),
+                                ));
+                            }
+                        }
+                    }
+                }
+                // #[optionalize(getters)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("getters") => {
+                    container_attrs.getters = true;
+                }
+                // #[optionalize(transparent)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("transparent") => {
+                    container_attrs.transparent = true;
+                }
+                // #[optionalize(patch_macro)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("patch_macro") => {
+                    container_attrs.patch_macro = Some(None);
+                }
+                // #[optionalize(patch_macro = "user_patch")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("patch_macro") => {
+                    container_attrs.patch_macro = Some(Some(lit_ident(&nv.lit)?));
+                }
+                // #[optionalize(report)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("report") => {
+                    container_attrs.report = Some(Vec::new());
+                }
+                // #[optionalize(report(derive(Debug, Serialize)))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("report") => {
+                    let mut derives = Vec::new();
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("derive") => {
+                                for nested in list.nested {
+                                    match nested {
+                                        NestedMeta::Meta(Meta::Path(path)) => derives.push(path),
+                                        other => return Err(Error::new_spanned(other, "expected a derive path")),
+                                    }
+                                }
+                            }
+                            other => return Err(Error::new_spanned(other, "expected `derive(...)`")),
+                        }
+                    }
+                    container_attrs.report = Some(derives);
+                }
+                // #[optionalize(event)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("event") => {
+                    container_attrs.event = Some(1);
+                }
+                // #[optionalize(event(version = 2))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("event") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("version") => match &nv.lit {
+                                Lit::Int(version) => container_attrs.event = Some(version.base10_parse()?),
+                                other => return Err(Error::new_spanned(other, "expected an integer")),
+                            },
+                            other => return Err(Error::new_spanned(other, "expected `version = ...`")),
+                        }
+                    }
+                }
+                // #[optionalize(bound = "T: Clone + Default")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("bound") => {
+                    container_attrs.bound = Some(lit_bound(&nv.lit)?);
+                }
+                // #[optionalize(cfg(feature = "api"))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("cfg") => {
+                    if list.nested.len() != 1 {
+                        return Err(Error::new_spanned(list, "`cfg` takes a single predicate, e.g. `cfg(feature = \"api\")`"));
+                    }
+                    container_attrs.cfg = list.nested.into_iter().next();
+                }
+                // #[optionalize(attr(serde(rename = "UserPatch")))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("attr") => {
+                    container_attrs.attrs.extend(nested_metas(list.nested)?);
+                }
+                other => return Err(unknown(other, "container", CONTAINER_ATTRS, FIELD_ATTRS)),
+            }
+        }
+
+        Ok(container_attrs)
+    }
+
+    /// Whether the generated code integrates with the crate `name`, e.g. `tracing`.
+    pub fn integrates(&self, name: &str) -> bool {
+        self.integrations.iter().any(|integration| integration == name)
+    }
+
+    /// Picks the integration `ident`, along with those it builds on.
+    fn integrate(&mut self, ident: &Ident) {
+        if let Some((_, Some(base), _)) = INTEGRATIONS.iter().find(|(name, _, _)| ident == name) {
+            self.integrate(&Ident::new(base, ident.span()));
+        }
+        if !self.integrates(&ident.to_string()) {
+            self.integrations.push(ident.clone());
+        }
+    }
+}
+
+/// Traits `forward_derives` copies from the original struct, all of which
+/// `Option<T>` implements whenever `T` does.
+const FORWARDED_DERIVES: &[&str] = &["PartialEq", "Eq", "PartialOrd", "Ord", "Hash"];
+
+/// The derives in `attrs` that `forward_derives` copies onto the generated struct.
+pub fn forwarded_derives(attrs: &[Attribute]) -> Result<Vec<Path>> {
+    Ok(source_derives(attrs)?
+        .into_iter()
+        .filter(|path| {
+            path.segments
+                .last()
+                .is_some_and(|segment| FORWARDED_DERIVES.iter().any(|name| segment.ident == name))
+        })
+        .collect())
+}
+
+/// Every derive listed in `attrs`, the original struct's own attributes.
+pub fn source_derives(attrs: &[Attribute]) -> Result<Vec<Path>> {
+    let mut derives = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("derive")) {
+        if let Meta::List(list) = attr.parse_meta()? {
+            for nested in list.nested {
+                if let NestedMeta::Meta(Meta::Path(path)) = nested {
+                    derives.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(derives)
+}
+
+/// Options read from `#[optionalize(...)]` attributes on a field.
+#[derive(Default)]
+pub struct FieldAttrs {
+    /// Name of the all-or-none group this field belongs to, if any.
+    pub group: Option<String>,
+    /// The field may not be changed through the patch.
+    pub immutable: bool,
+    /// Keep the field in the patch even though serde skips it.
+    pub include: bool,
+    /// The field holds secrets and must not show up in logs.
+    pub sensitive: bool,
+    /// Empty strings and collections deserialize as `None`.
+    pub empty_as_none: bool,
+    /// How the elements of a collection field are combined with the existing
+    /// ones, instead of replacing them.
+    pub collection: Option<CollectionMerge>,
+    /// The diff report lists the map field's changes key by key.
+    pub map_diff: bool,
+    /// How the diff report matches up the elements of the `Vec` field.
+    pub vec_diff: Option<VecDiff>,
+    /// Leave the field out of the per-field convenience methods.
+    pub no_helpers: bool,
+    /// The roles allowed to change the field, if it is restricted.
+    pub roles: Option<Vec<String>>,
+    /// The `Option` field is wrapped in a second `Option`, so patches can
+    /// clear it.
+    pub double_option: bool,
+    /// Function the value is passed through before it is applied.
+    pub sanitize_with: Option<Path>,
+    /// Attributes added to this field of the generated struct.
+    pub field_attrs: Vec<Meta>,
+    /// Representation of this `Box`, `Arc` or `Rc` field.
+    pub pointer: Option<PointerPolicy>,
+    /// The field holds another `Optionalize` struct and is patched through its
+    /// optional twin, optionally with an explicit twin type.
+    pub nested: Option<Option<Type>>,
+    /// The nested struct's optional twin is inlined into the generated struct,
+    /// without an `Option` around it.
+    pub flatten: bool,
+    /// Module providing `to_optional` and `from_optional` conversions between
+    /// the field's type and its patch representation.
+    pub with: Option<Path>,
+    /// Type of the patch representation, for `with` fields.
+    pub ty: Option<Type>,
+    /// Smallest value accepted by `validate`.
+    pub min: Option<Expr>,
+    /// Largest value accepted by `validate`.
+    pub max: Option<Expr>,
+    /// Pattern the value must match to pass `validate`.
+    pub regex: Option<LitStr>,
+}
+
+impl FieldAttrs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut field_attrs = FieldAttrs::default();
+        // The attribute that picked `collection`, which takes one strategy
+        let mut strategy = None;
+
+        for meta in optionalize_metas(attrs)? {
+            match meta {
+                // #[optionalize(group = "address")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("group") => {
+                    field_attrs.group = Some(lit_str(&nv.lit)?);
+                }
+                // #[optionalize(immutable)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("immutable") => {
+                    field_attrs.immutable = true;
+                }
+                // #[optionalize(include)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("include") => {
+                    field_attrs.include = true;
+                }
+                // #[optionalize(sensitive)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("sensitive") => {
+                    field_attrs.sensitive = true;
+                }
+                // #[optionalize(empty_as_none)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("empty_as_none") => {
+                    field_attrs.empty_as_none = true;
+                }
+                // #[optionalize(double_option)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("double_option") => {
+                    field_attrs.double_option = true;
+                }
+                // #[optionalize(merge_key = "name")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("merge_key") => {
+                    pick_strategy(&mut strategy, "merge_key", &nv)?;
+                    field_attrs.collection = Some(CollectionMerge::ByKey(lit_ident(&nv.lit)?));
+                }
+                // #[optionalize(no_helpers)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("no_helpers") => {
+                    field_attrs.no_helpers = true;
+                }
+                // #[optionalize(roles("admin", "owner"))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("roles") => {
+                    let roles = list
+                        .nested
+                        .iter()
+                        .map(|nested| match nested {
+                            NestedMeta::Lit(lit) => lit_str(lit),
+                            other => Err(Error::new_spanned(other, "expected a role name as a string literal")),
+                        })
+                        .collect::<Result<_>>()?;
+                    field_attrs.roles = Some(roles);
+                }
+                // #[optionalize(map_diff)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("map_diff") => {
+                    field_attrs.map_diff = true;
+                }
+                // #[optionalize(vec_diff = "position")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("vec_diff") => {
+                    if field_attrs.vec_diff.is_some() {
+                        return Err(Error::new_spanned(nv, "`vec_diff` can't be combined with `diff_key`"));
+                    }
+                    field_attrs.vec_diff = Some(VecDiff::from_lit(&nv.lit)?);
+                }
+                // #[optionalize(diff_key = "id")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("diff_key") => {
+                    if field_attrs.vec_diff.is_some() {
+                        return Err(Error::new_spanned(nv, "`diff_key` can't be combined with `vec_diff`"));
+                    }
+                    field_attrs.vec_diff = Some(VecDiff::ByKey(lit_ident(&nv.lit)?));
+                }
+                // #[optionalize(merge_entries)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("merge_entries") => {
+                    pick_strategy(&mut strategy, "merge_entries", &path)?;
+                    field_attrs.collection = Some(CollectionMerge::Entries(None));
+                }
+                // #[optionalize(vec_merge = "append")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("vec_merge") => {
+                    pick_strategy(&mut strategy, "vec_merge", &nv)?;
+                    field_attrs.collection = CollectionMerge::from_lit(&nv.lit)?;
+                }
+                // #[optionalize(merge_with = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("merge_with") => {
+                    pick_strategy(&mut strategy, "merge_with", &nv)?;
+                    field_attrs.collection = Some(CollectionMerge::With(lit_path(&nv.lit)?));
+                }
+                // #[optionalize(sanitize_with = "path::to::fn")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("sanitize_with") => {
+                    field_attrs.sanitize_with = Some(lit_path(&nv.lit)?);
+                }
+                // #[optionalize(pointer = "unwrap")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("pointer") => {
+                    field_attrs.pointer = Some(PointerPolicy::from_lit(&nv.lit)?);
+                }
+                // #[optionalize(nested)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("nested") => {
+                    field_attrs.nested = Some(None);
+                }
+                // #[optionalize(flatten)]
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("flatten") => {
+                    field_attrs.flatten = true;
+                }
+                // #[optionalize(nested = "AddressPatch")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("nested") => {
+                    field_attrs.nested = Some(Some(lit_type(&nv.lit)?));
+                }
+                // #[optionalize(with = "rfc3339", ty = "String")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("with") => {
+                    field_attrs.with = Some(lit_path(&nv.lit)?);
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("ty") => {
+                    field_attrs.ty = Some(lit_type(&nv.lit)?);
+                }
+                // #[optionalize(range(min = 0, max = 150))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("range") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("min") => {
+                                field_attrs.min = Some(lit_number(nv.lit)?);
+                            }
+                            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("max") => {
+                                field_attrs.max = Some(lit_number(nv.lit)?);
+                            }
+                            other => return Err(Error::new_spanned(other, "expected `min = ...` or `max = ...`")),
+                        }
+                    }
+                }
+                // #[optionalize(regex = "^[a-z0-9_]+$")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("regex") => match nv.lit {
+                    Lit::Str(pattern) => field_attrs.regex = Some(pattern),
+                    other => return Err(Error::new_spanned(other, "expected a string literal")),
+                },
+                // #[optionalize(field_attr(sqlx(rename = "display_name")))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("field_attr") => {
+                    field_attrs.field_attrs.extend(nested_metas(list.nested)?);
+                }
+                // #[optionalize(serde(rename = "displayName"))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("serde") => {
+                    field_attrs.field_attrs.push(Meta::List(list));
+                }
+                other => return Err(unknown(other, "field", FIELD_ATTRS, CONTAINER_ATTRS)),
+            }
+        }
+
+        Ok(field_attrs)
+    }
+}
+
+/// How a `Box<T>`, `Arc<T>` or `Rc<T>` field is represented in the generated struct.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PointerPolicy {
+    /// `Option<Box<T>>`, moved onto the target as-is.
+    Wrap,
+    /// `Option<T>`, boxed again when applied.
+    Unwrap,
+}
+
+impl PointerPolicy {
+    fn from_lit(lit: &Lit) -> Result<Self> {
+        match lit_str(lit)?.as_str() {
+            "wrap" => Ok(PointerPolicy::Wrap),
+            "unwrap" => Ok(PointerPolicy::Unwrap),
+            _ => Err(Error::new_spanned(lit, "expected \"wrap\" or \"unwrap\"")),
+        }
+    }
+}
+
+/// How the elements of a collection field are combined with the existing ones.
+pub enum CollectionMerge {
+    /// Elements with the same value of this field replace the existing one in
+    /// place, and others are appended, like a Kubernetes strategic merge.
+    ByKey(Ident),
+    /// Elements are appended after the existing ones.
+    Append,
+    /// Elements are inserted before the existing ones.
+    Prepend,
+    /// Elements are appended unless an equal one is already present.
+    Union,
+    /// Map entries are inserted key by key, replacing the value of existing
+    /// keys or, once resolved to the values' patch type, patching it in place.
+    Entries(Option<Box<Type>>),
+    /// `fn(&mut T, T)` combining the incoming value into the existing one.
+    With(Path),
+}
+
+impl CollectionMerge {
+    /// Parses a `vec_merge` strategy, where `"replace"` is the default of
+    /// overwriting the whole collection.
+    fn from_lit(lit: &Lit) -> Result<Option<Self>> {
+        match lit_str(lit)?.as_str() {
+            "replace" => Ok(None),
+            "append" => Ok(Some(CollectionMerge::Append)),
+            "prepend" => Ok(Some(CollectionMerge::Prepend)),
+            "union" => Ok(Some(CollectionMerge::Union)),
+            _ => Err(Error::new_spanned(
+                lit,
+                "expected \"replace\", \"append\", \"prepend\" or \"union\"",
+            )),
+        }
+    }
+}
+
+/// How the diff report matches up the elements of a `Vec` field.
+pub enum VecDiff {
+    /// Elements at the same index are compared.
+    Position,
+    /// Elements with the same value of this field are compared, wherever they are.
+    ByKey(Ident),
+}
+
+impl VecDiff {
+    /// Parses a `vec_diff` strategy.
+    fn from_lit(lit: &Lit) -> Result<Self> {
+        match lit_str(lit)?.as_str() {
+            "position" => Ok(VecDiff::Position),
+            _ => Err(Error::new_spanned(
+                lit,
+                "expected \"position\", or `diff_key = \"...\"` to match elements by a field",
+            )),
+        }
+    }
+}
+
+/// How `merge` treats a field set in both patches.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// The value of the first patch is kept.
+    PreferSelf,
+    /// The value of the second patch wins, as if both were applied in order.
+    #[default]
+    PreferOther,
+    /// `merge` fails with `MergeConflict`.
+    ErrorOnConflict,
+}
+
+impl MergePolicy {
+    fn from_lit(lit: &Lit) -> Result<Self> {
+        match lit_str(lit)?.as_str() {
+            "prefer_self" => Ok(MergePolicy::PreferSelf),
+            "prefer_other" => Ok(MergePolicy::PreferOther),
+            "error_on_conflict" => Ok(MergePolicy::ErrorOnConflict),
+            _ => Err(Error::new_spanned(
+                lit,
+                "expected \"prefer_self\", \"prefer_other\" or \"error_on_conflict\"",
+            )),
+        }
+    }
+}
+
+/// Every container attribute, with an example of its use.
+const CONTAINER_ATTRS: &[(&str, &str)] = &[
+    ("derive", "derive(Debug, Clone)"),
+    ("assert", "assert(Serialize, Send, Sync)"),
+    ("version", "version = \"revision\""),
+    ("schema_version", "schema_version = 2"),
+    ("next_version", "next_version = \"path::to::fn\""),
+    ("migrate_from", "migrate_from = \"path::to::fn\""),
+    ("prost", "prost"),
+    ("rocket", "rocket"),
+    ("poem_openapi", "poem_openapi"),
+    ("rkyv", "rkyv"),
+    ("uniffi", "uniffi"),
+    ("pyo3", "pyo3"),
+    ("napi", "napi"),
+    ("name", "name = \"UserPatch\""),
+    ("prefix", "prefix = \"Partial\""),
+    ("suffix", "suffix = \"Patch\""),
+    ("naming", "naming = \"partial\""),
+    ("doc", "doc = \"...\""),
+    ("field_attr", "field_attr(ts(optional))"),
+    ("pointer", "pointer = \"unwrap\""),
+    ("option_alias", "option_alias(Maybe)"),
+    ("option_like", "option_like(MaybeUndefined = \"is_undefined\")"),
+    ("nested_types", "nested_types(Address, Node = \"NodePatch\")"),
+    ("copy", "copy"),
+    ("double_option", "double_option"),
+    ("form", "form"),
+    ("query", "query"),
+    ("reject_null", "reject_null"),
+    ("merge", "merge = \"prefer_self\""),
+    ("before_apply", "before_apply = \"path::to::fn\""),
+    ("after_apply", "after_apply = \"path::to::fn\""),
+    ("async_validate", "async_validate = \"path::to::fn\""),
+    ("trace_values", "trace_values"),
+    ("forward_derives", "forward_derives"),
+    ("lens", "lens"),
+    ("event", "event(version = 2)"),
+    ("ops", "ops"),
+    ("shared", "shared"),
+    ("builder", "builder"),
+    ("filter", "filter"),
+    ("wrapper", "wrapper"),
+    ("ffi", "ffi"),
+    ("report", "report(derive(Debug, Serialize))"),
+    ("display", "display"),
+    ("lww", "lww"),
+    ("compact", "compact"),
+    ("getters", "getters"),
+    ("impls", "impls(apply, merge, serde)"),
+    ("debug_expand", "debug_expand"),
+    ("depth", "depth = 2"),
+    ("transparent", "transparent"),
+    ("patch_macro", "patch_macro = \"user_patch\""),
+    ("bound", "bound = \"T: Clone\""),
+    ("cfg", "cfg(feature = \"api\")"),
+    ("attr", "attr(serde(deny_unknown_fields))"),
+    ("secrecy", "secrecy"),
+    ("zeroize", "zeroize"),
+    ("serde_json", "serde_json"),
+    ("json_patch", "json_patch"),
+    ("history", "history"),
+    ("tracing", "tracing"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("config", "config"),
+    ("serde_qs", "serde_qs"),
+    ("axum", "axum"),
+    ("axum_extra", "axum_extra"),
+    ("actix_web", "actix_web"),
+    ("tokio_postgres", "tokio_postgres"),
+    ("rusqlite", "rusqlite"),
+    ("surrealdb", "surrealdb"),
+    ("elasticsearch", "elasticsearch"),
+    ("dynamodb", "dynamodb"),
+    ("sha2", "sha2"),
+    ("ansi", "ansi"),
+    ("similar", "similar"),
+    ("dialoguer", "dialoguer"),
+    ("merge_crate", "merge_crate"),
+    ("metrics", "metrics"),
+    ("tokio", "tokio"),
+    ("struct_patch", "struct_patch"),
+];
+
+/// The crates the generated code can integrate with, each picked with its own
+/// container attribute, along with the integration it builds on and the
+/// groups of impls its generated code calls into.
+pub const INTEGRATIONS: &[(&str, Option<&str>, &[&str])] = &[
+    ("secrecy", None, &[]),
+    ("zeroize", None, &[]),
+    ("serde_json", None, &["serde"]),
+    ("json_patch", Some("serde_json"), &["serde"]),
+    ("history", None, &["apply"]),
+    ("tracing", None, &[]),
+    ("toml", None, &["serde"]),
+    ("yaml", None, &["serde"]),
+    ("config", None, &["serde"]),
+    ("serde_qs", None, &["serde"]),
+    ("axum", Some("serde_json"), &["serde"]),
+    ("axum_extra", Some("axum"), &["serde"]),
+    ("actix_web", Some("serde_json"), &["serde"]),
+    ("tokio_postgres", None, &[]),
+    ("rusqlite", None, &[]),
+    ("surrealdb", Some("serde_json"), &["serde"]),
+    ("elasticsearch", Some("serde_json"), &["serde"]),
+    ("dynamodb", Some("serde_json"), &["serde"]),
+    ("sha2", Some("serde_json"), &["serde"]),
+    ("ansi", None, &["apply"]),
+    ("similar", None, &[]),
+    ("dialoguer", None, &["flat"]),
+    ("merge_crate", None, &["merge"]),
+    ("metrics", None, &[]),
+    ("tokio", None, &["apply"]),
+    ("struct_patch", None, &["apply", "diff"]),
+];
+
+/// The groups of generated impls `impls(...)` can pick from.
+pub const IMPL_GROUPS: &[&str] = &["apply", "merge", "diff", "visit", "flat", "query", "sql", "serde"];
+
+/// Every field attribute, with an example of its use.
+const FIELD_ATTRS: &[(&str, &str)] = &[
+    ("group", "group = \"address\""),
+    ("immutable", "immutable"),
+    ("include", "include"),
+    ("sensitive", "sensitive"),
+    ("empty_as_none", "empty_as_none"),
+    ("double_option", "double_option"),
+    ("merge_key", "merge_key = \"name\""),
+    ("vec_merge", "vec_merge = \"append\""),
+    ("merge_entries", "merge_entries"),
+    ("map_diff", "map_diff"),
+    ("no_helpers", "no_helpers"),
+    ("roles", "roles(\"admin\", \"owner\")"),
+    ("vec_diff", "vec_diff = \"position\""),
+    ("diff_key", "diff_key = \"id\""),
+    ("merge_with", "merge_with = \"path::to::fn\""),
+    ("sanitize_with", "sanitize_with = \"path::to::fn\""),
+    ("pointer", "pointer = \"unwrap\""),
+    ("nested", "nested = \"AddressPatch\""),
+    ("flatten", "flatten"),
+    ("with", "with = \"rfc3339\", ty = \"String\""),
+    ("ty", "with = \"rfc3339\", ty = \"String\""),
+    ("range", "range(min = 0, max = 150)"),
+    ("regex", "regex = \"^[a-z]+$\""),
+    ("field_attr", "field_attr(sqlx(rename = \"display_name\"))"),
+    ("serde", "serde(rename = \"displayName\")"),
+];
+
+/// A targeted error for an item that matched no attribute of this `kind`: a
+/// malformed known attribute, one that belongs on the other `kind`, or a typo.
+fn unknown(meta: NestedMeta, kind: &str, known: &[(&str, &str)], other: &[(&str, &str)]) -> Error {
+    let name = match &meta {
+        NestedMeta::Meta(meta) => meta.path().segments.last().map(|segment| segment.ident.to_string()),
+        NestedMeta::Lit(_) => None,
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return Error::new_spanned(meta, format!("expected an optionalize {} attribute", kind)),
+    };
+
+    let message = if let Some((_, usage)) = known.iter().find(|(known, _)| *known == name) {
+        format!("malformed `{}` attribute, expected `#[optionalize({})]`", name, usage)
+    } else if other.iter().any(|(other, _)| *other == name) {
+        let other_kind = if kind == "field" { "container" } else { "field" };
+        let place = if kind == "field" { "onto the struct" } else { "onto a field" };
+        format!("`{}` is a {} attribute, move it {}", name, other_kind, place)
+    } else {
+        match known.iter().find(|(known, _)| edit_distance(known, &name) <= 2) {
+            Some((suggestion, _)) => format!(
+                "unknown optionalize {} attribute `{}`, did you mean `{}`?",
+                kind, name, suggestion
+            ),
+            None => {
+                let names: Vec<_> = known.iter().map(|(name, _)| format!("`{}`", name)).collect();
+                format!(
+                    "unknown optionalize {} attribute `{}`, expected one of {}",
+                    kind,
+                    name,
+                    names.join(", ")
+                )
+            }
+        }
+    };
+    Error::new_spanned(meta, message)
+}
+
+/// Levenshtein distance between `a` and `b`, to suggest fixes for typos.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = previous + usize::from(a != *b);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Collects the nested items of every `#[optionalize(...)]` attribute in `attrs`.
+fn optionalize_metas(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut metas = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("optionalize")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => metas.extend(list.nested),
+            other => {
+                return Err(Error::new_spanned(other, "expected `#[optionalize(...)]`"));
+            }
+        }
+    }
+
+    Ok(metas)
+}
+
+/// The items of an attribute list that must all be meta items, such as the
+/// attributes in `field_attr(...)`.
+fn nested_metas(nested: impl IntoIterator<Item = NestedMeta>) -> Result<Vec<Meta>> {
+    nested
+        .into_iter()
+        .map(|nested| match nested {
+            NestedMeta::Meta(meta) => Ok(meta),
+            NestedMeta::Lit(lit) => Err(Error::new_spanned(lit, "expected an attribute")),
+        })
+        .collect()
+}
+
+/// Records `name` as the attribute picking a field's merge strategy, failing
+/// when one was already picked, by `name` itself or another attribute.
+fn pick_strategy(strategy: &mut Option<&'static str>, name: &'static str, tokens: impl quote::ToTokens) -> Result<()> {
+    match strategy.replace(name) {
+        Some(picked) if picked == name => Err(Error::new_spanned(tokens, format!("`{}` is given more than once", name))),
+        Some(picked) => Err(Error::new_spanned(tokens, format!("`{}` can't be combined with `{}`", name, picked))),
+        None => Ok(()),
+    }
+}
+
+fn lit_str(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err(Error::new_spanned(other, "expected a string literal")),
+    }
+}
+
+fn lit_path(lit: &Lit) -> Result<Path> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        other => Err(Error::new_spanned(other, "expected a string literal containing a path")),
+    }
+}
+
+fn lit_ident(lit: &Lit) -> Result<Ident> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        other => Err(Error::new_spanned(other, "expected a string literal containing a field name")),
+    }
+}
+
+/// A bound of `range(...)`: a number, or a string holding any expression such as
+/// `"-10"` or `"MAX_LEN"`.
+fn lit_number(lit: Lit) -> Result<Expr> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        Lit::Int(_) | Lit::Float(_) => Ok(syn::parse_quote!(#lit)),
+        other => Err(Error::new_spanned(other, "expected a number")),
+    }
+}
+
+fn lit_method(lit: &Lit) -> Result<Ident> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        other => Err(Error::new_spanned(other, "expected a string literal containing a method name")),
+    }
+}
+
+/// Comma-separated `where` predicates, possibly none.
+fn lit_bound(lit: &Lit) -> Result<Vec<WherePredicate>> {
+    match lit {
+        Lit::Str(s) => Ok(s
+            .parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)?
+            .into_iter()
+            .collect()),
+        other => Err(Error::new_spanned(other, "expected a string literal containing where predicates")),
+    }
+}
+
+fn lit_type(lit: &Lit) -> Result<Type> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        other => Err(Error::new_spanned(other, "expected a string literal containing a type")),
+    }
+}
+
+/// Whether the field is marked `#[serde(skip)]` or `#[serde(skip_deserializing)]`,
+/// so it can never be read from input.
+pub fn is_serde_skipped(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|nested| match nested {
+            NestedMeta::Meta(Meta::Path(path)) => path.is_ident("skip") || path.is_ident("skip_deserializing"),
+            _ => false,
+        })
+}
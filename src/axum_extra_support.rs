@@ -0,0 +1,56 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse_quote;
+
+use crate::ast::{inner_bounds, Container};
+
+/// Whether the generated struct is extracted from the query string, which
+/// takes the place of axum's JSON body extractor.
+pub fn extracts_query(cx: &Container) -> bool {
+    cx.attrs.integrates("axum_extra") && cx.attrs.query
+}
+
+/// Generates an axum `FromRequestParts` impl that extracts a filter from the
+/// query string through axum-extra's `Query`. Only emitted with
+/// `#[optionalize(axum_extra)]`, for structs with `#[optionalize(query)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !extracts_query(cx) {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (_, ty_generics, _) = cx.generics.split_for_impl();
+
+    // The extractor is generic over the router state as well, which axum
+    // needs whatever `bound` says
+    let mut generics = cx.generics.clone();
+    generics.params.push(parse_quote!(__S: Send + Sync));
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let where_clause = cx.where_clause_with(inner_bounds(&cx.fields, quote!(::serde::de::DeserializeOwned)));
+
+    quote! {
+        /// Extracts the filter from the query string, e.g. `?name=Ada&role=admin&role=owner`.
+        ///
+        /// Absent and empty parameters leave their field unset, and query
+        /// strings that don't deserialize are rejected with `400 Bad Request`.
+        impl #impl_generics ::axum::extract::FromRequestParts<__S> for #optional_ident #ty_generics
+        #where_clause
+        {
+            type Rejection = ::axum::response::Response;
+
+            async fn from_request_parts(
+                parts: &mut ::axum::http::request::Parts,
+                state: &__S,
+            ) -> Result<Self, Self::Rejection> {
+                use ::axum::response::IntoResponse;
+
+                let ::axum_extra::extract::Query(filter) =
+                    <::axum_extra::extract::Query<Self> as ::axum::extract::FromRequestParts<__S>>::from_request_parts(parts, state)
+                        .await
+                        .map_err(IntoResponse::into_response)?;
+                Ok(filter)
+            }
+        }
+    }
+}
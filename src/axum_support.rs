@@ -0,0 +1,66 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse_quote;
+
+use crate::ast::{inner_bounds, Container};
+use crate::axum_extra_support;
+
+/// Generates an axum `FromRequest` impl that extracts a ready-to-apply patch
+/// from a JSON body. Only emitted with `#[optionalize(axum)]`, and not for
+/// structs extracted from the query string instead.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("axum") || axum_extra_support::extracts_query(cx) {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (_, ty_generics, _) = cx.generics.split_for_impl();
+
+    // The extractor is generic over the router state as well, which axum
+    // needs whatever `bound` says
+    let mut generics = cx.generics.clone();
+    generics.params.push(parse_quote!(__S: Send + Sync));
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let where_clause = cx.where_clause_with(inner_bounds(&cx.fields, quote!(::serde::de::DeserializeOwned)));
+
+    quote! {
+        /// Extracts the patch from a JSON request body.
+        ///
+        /// Malformed JSON is rejected by axum's `Json` extractor, unknown or
+        /// badly typed fields with `422 Unprocessable Entity`, and patches that
+        /// set no fields with `400 Bad Request`.
+        impl #impl_generics ::axum::extract::FromRequest<__S> for #optional_ident #ty_generics
+        #where_clause
+        {
+            type Rejection = ::axum::response::Response;
+
+            async fn from_request(
+                req: ::axum::extract::Request,
+                state: &__S,
+            ) -> Result<Self, Self::Rejection> {
+                use ::axum::response::IntoResponse;
+
+                let ::axum::Json(value) =
+                    <::axum::Json<::serde_json::Value> as ::axum::extract::FromRequest<__S>>::from_request(req, state)
+                        .await
+                        .map_err(IntoResponse::into_response)?;
+
+                let patch = <Self as ::std::convert::TryFrom<::serde_json::Value>>::try_from(value)
+                    .map_err(|err| {
+                        (::axum::http::StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
+                    })?;
+
+                if patch.set_fields().is_empty() {
+                    return Err((
+                        ::axum::http::StatusCode::BAD_REQUEST,
+                        "patch does not set any fields",
+                    )
+                        .into_response());
+                }
+
+                Ok(patch)
+            }
+        }
+    }
+}
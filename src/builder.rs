@@ -0,0 +1,201 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, GenericParam, Ident};
+
+use crate::ast::{camel_case, Container, Field};
+
+/// Generates `{Optional}Builder`, whose type parameters track which required
+/// fields have been set, so `build` only exists once all of them are. Only
+/// emitted with `#[optionalize(builder)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.builder {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let builder_ident = format_ident!("{}Builder", optional_ident);
+    let set_ident = format_ident!("{}Set", builder_ident);
+    let unset_ident = format_ident!("{}Unset", builder_ident);
+    let (own_impl_generics, own_ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Fields that aren't `Option`s must be set before building; option-like
+    // fields fall back to their unset value
+    let (required, optional): (Vec<&Field>, Vec<&Field>) = cx
+        .fields
+        .iter()
+        .partition(|field| !field.option && field.option_like.is_none());
+    let states: Vec<Ident> = required.iter().map(|field| state_ident(&field.ident)).collect();
+
+    let mut generics = cx.generics.clone();
+    generics
+        .params
+        .extend(states.iter().map(|state| -> GenericParam { parse_quote!(#state = #unset_ident) }));
+    let params = &generics.params;
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    // The builder's type with the given state for each required field
+    let own_args: Vec<TokenStream> = cx
+        .generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                quote! { #ident }
+            }
+            GenericParam::Lifetime(param) => {
+                let lifetime = &param.lifetime;
+                quote! { #lifetime }
+            }
+            GenericParam::Const(param) => {
+                let ident = &param.ident;
+                quote! { #ident }
+            }
+        })
+        .collect();
+    let builder_ty = |states: Vec<TokenStream>| quote! { #builder_ident<#( #own_args, )* #( #states ),*> };
+    let unset_ty = builder_ty(states.iter().map(|_| quote!(#unset_ident)).collect());
+    let set_ty = builder_ty(states.iter().map(|_| quote!(#set_ident)).collect());
+    let generic_ty = builder_ty(states.iter().map(|state| quote!(#state)).collect());
+
+    let required_fields = required.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        quote! { #ident: ::std::option::Option<#ty> }
+    });
+    let optional_fields = optional.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        quote! { #ident: #ty }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #ident: #ty }
+    });
+    let field_idents: Vec<&Ident> = cx.fields.iter().map(|field| &field.ident).collect();
+    let marker_idents: Vec<&Option<Ident>> = cx.markers.iter().map(|marker| &marker.ident).collect();
+    // Fields left out of the patch start from their default, as serde does
+    let skipped: Vec<&Ident> = cx.skipped.iter().map(|(ident, _)| ident).collect();
+    let unset = cx.fields.iter().map(|field| {
+        if field.option_like.is_some() {
+            quote! { ::std::default::Default::default() }
+        } else {
+            quote! { ::std::option::Option::None }
+        }
+    });
+
+    let required_setters = required.iter().enumerate().map(|(index, field)| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        let others: Vec<&Ident> = field_idents.iter().copied().filter(|other| *other != ident).collect();
+        let next_ty = builder_ty(
+            states
+                .iter()
+                .enumerate()
+                .map(|(other, state)| if other == index { quote!(#set_ident) } else { quote!(#state) })
+                .collect(),
+        );
+        let doc = format!("Sets the required `{}` field.", field.name());
+        quote! {
+            #[doc = #doc]
+            pub fn #ident(self, value: #ty) -> #next_ty {
+                #builder_ident {
+                    #ident: ::std::option::Option::Some(value),
+                    #( #others: self.#others, )*
+                    #( #marker_idents: self.#marker_idents, )*
+                    __state: ::std::marker::PhantomData,
+                }
+            }
+        }
+    });
+    let optional_setters = optional.iter().map(|field| {
+        let ident = &field.ident;
+        let doc = format!("Sets the optional `{}` field.", field.name());
+        let (ty, value) = if field.option {
+            (field.value_ty(), quote!(::std::option::Option::Some(value)))
+        } else {
+            (&field.ty, quote!(value))
+        };
+        quote! {
+            #[doc = #doc]
+            pub fn #ident(mut self, value: #ty) -> Self {
+                self.#ident = #value;
+                self
+            }
+        }
+    });
+
+    let built = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        if field.option || field.option_like.is_some() {
+            return quote! { #ident: self.#ident };
+        }
+        let message = format!("`{}` is set in every complete builder", field.name());
+        quote! {
+            #ident: match self.#ident {
+                ::std::option::Option::Some(value) => value,
+                ::std::option::Option::None => ::std::unreachable!(#message),
+            }
+        }
+    });
+
+    let doc = format!(
+        "Builder of a [`{}`], whose type parameters track which required fields have been set. \
+         `build` is only available once all of them are.",
+        ident
+    );
+    let set_doc = format!("State of a required field of a [`{}`] that has been set.", builder_ident);
+    let unset_doc = format!("State of a required field of a [`{}`] that hasn't been set yet.", builder_ident);
+
+    quote! {
+        #[doc = #set_doc]
+        pub struct #set_ident;
+
+        #[doc = #unset_doc]
+        pub struct #unset_ident;
+
+        #[doc = #doc]
+        #[must_use]
+        pub struct #builder_ident<#params> #where_clause {
+            #( #required_fields, )*
+            #( #optional_fields, )*
+            #( #markers, )*
+            __state: ::std::marker::PhantomData<fn() -> ( #( #states, )* )>,
+        }
+
+        impl #own_impl_generics #ident #own_ty_generics #where_clause {
+            /// Starts building a value, with no required field set yet.
+            pub fn builder() -> #unset_ty {
+                #builder_ident {
+                    #( #field_idents: #unset, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                    __state: ::std::marker::PhantomData,
+                }
+            }
+        }
+
+        impl #impl_generics #generic_ty #where_clause {
+            #( #required_setters )*
+            #( #optional_setters )*
+        }
+
+        impl #own_impl_generics #set_ty #where_clause {
+            /// Builds the value, now that every required field is set.
+            pub fn build(self) -> #ident #own_ty_generics {
+                #ident {
+                    #( #built, )*
+                    #( #skipped: ::std::default::Default::default(), )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+    }
+}
+
+/// The type parameter tracking whether `field` has been set, e.g. `__FirstName`
+/// for `first_name`.
+fn state_ident(field: &Ident) -> Ident {
+    format_ident!("__{}", camel_case(field))
+}
@@ -0,0 +1,21 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{NestedMeta, Result};
+
+/// Marks every generated item with `#[cfg(...)]`, so none of them exist unless
+/// the predicate of `#[optionalize(cfg(...))]` holds.
+pub fn gate(cfg: Option<&NestedMeta>, expanded: TokenStream) -> Result<TokenStream> {
+    let cfg = match cfg {
+        Some(cfg) => cfg,
+        None => return Ok(expanded),
+    };
+
+    let file: syn::File = syn::parse2(expanded)?;
+    let items = file.items.iter();
+    Ok(quote! {
+        #(
+            #[cfg(#cfg)]
+            #items
+        )*
+    })
+}
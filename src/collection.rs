@@ -0,0 +1,180 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Field;
+use crate::attr::CollectionMerge;
+
+/// Generates the statements combining the `incoming` elements with the
+/// collection `list` according to the field's strategy, pushing the elements
+/// they replace onto `replaced` if given. `list` must be a place expression.
+pub fn merge_into(
+    field: &Field,
+    list: TokenStream,
+    incoming: TokenStream,
+    replaced: Option<TokenStream>,
+) -> TokenStream {
+    match &field.attrs.collection {
+        Some(CollectionMerge::ByKey(key)) => {
+            let replace = match replaced {
+                Some(replaced) => quote! { #replaced.push(::std::mem::replace(existing, element)); },
+                None => quote! { *existing = element; },
+            };
+            quote! {
+                for element in #incoming {
+                    match #list.iter_mut().find(|existing| existing.#key == element.#key) {
+                        Some(existing) => {
+                            #replace
+                        }
+                        None => #list.push(element),
+                    }
+                }
+            }
+        }
+        Some(CollectionMerge::Append) => quote! { #list.extend(#incoming); },
+        Some(CollectionMerge::Prepend) => quote! { #list.splice(0..0, #incoming); },
+        Some(CollectionMerge::Union) => quote! {
+            for element in #incoming {
+                if !#list.contains(&element) {
+                    #list.push(element);
+                }
+            }
+        },
+        Some(CollectionMerge::Entries(None)) => match replaced {
+            Some(replaced) => quote! {
+                for (key, element) in #incoming {
+                    match #list.get_mut(&key) {
+                        Some(existing) => {
+                            #replaced.insert(key, ::std::mem::replace(existing, element));
+                        }
+                        None => {
+                            #list.insert(key, element);
+                        }
+                    }
+                }
+            },
+            None => quote! { #list.extend(#incoming); },
+        },
+        // New keys start from the default value
+        Some(CollectionMerge::Entries(Some(_))) => {
+            let patch = match replaced {
+                Some(replaced) => quote! { #replaced.insert(key, patch.apply_with_inverse(existing)); },
+                None => quote! { patch.apply_to(existing); },
+            };
+            quote! {
+                for (key, patch) in #incoming {
+                    match #list.get_mut(&key) {
+                        Some(existing) => {
+                            #patch
+                        }
+                        None => {
+                            let mut value = ::std::default::Default::default();
+                            patch.apply_to(&mut value);
+                            #list.insert(key, value);
+                        }
+                    }
+                }
+            }
+        }
+        Some(CollectionMerge::With(merge)) => quote! { #merge(&mut #list, #incoming); },
+        None => quote! { #list = #incoming; },
+    }
+}
+
+/// Generates the statements combining the collection `incoming` of a later
+/// patch into the collection `list` of an earlier one.
+pub fn combine(field: &Field, list: TokenStream, incoming: TokenStream) -> TokenStream {
+    match &field.attrs.collection {
+        // Patches of the same entry are merged in turn
+        Some(CollectionMerge::Entries(Some(_))) => quote! {
+            for (key, patch) in #incoming {
+                let patch = match #list.remove(&key) {
+                    Some(existing) => existing.merge(patch),
+                    None => patch,
+                };
+                #list.insert(key, patch);
+            }
+        },
+        _ => merge_into(field, list, incoming, None),
+    }
+}
+
+/// Expression for the collection that turns `base` into `target` when merged
+/// into it, picking up added and changed elements only, or `None` if the
+/// strategy can't tell.
+pub fn diff(field: &Field, base: TokenStream, target: TokenStream) -> Option<TokenStream> {
+    let ty = field.inner_ty();
+    let diff = match &field.attrs.collection {
+        Some(CollectionMerge::With(_)) => return None,
+        Some(CollectionMerge::Append) => quote! {
+            if #target.len() > #base.len() && #target.starts_with(&#base) {
+                #target[#base.len()..].to_vec()
+            } else {
+                Vec::new()
+            }
+        },
+        Some(CollectionMerge::Prepend) => quote! {
+            if #target.len() > #base.len() && #target.ends_with(&#base) {
+                #target[..#target.len() - #base.len()].to_vec()
+            } else {
+                Vec::new()
+            }
+        },
+        Some(CollectionMerge::Entries(None)) => quote! {
+            #target
+                .iter()
+                .filter(|(key, value)| #base.get(*key) != Some(*value))
+                .map(|(key, value)| (::std::clone::Clone::clone(key), ::std::clone::Clone::clone(value)))
+                .collect::<#ty>()
+        },
+        Some(CollectionMerge::Entries(Some(twin))) => quote! {
+            #target
+                .iter()
+                .filter_map(|(key, value)| match #base.get(key) {
+                    Some(existing) if existing == value => None,
+                    Some(existing) => Some((::std::clone::Clone::clone(key), <#twin>::diff(existing, value))),
+                    None => Some((
+                        ::std::clone::Clone::clone(key),
+                        ::std::convert::From::from(::std::clone::Clone::clone(value)),
+                    )),
+                })
+                .collect::<#ty>()
+        },
+        // Keyed elements are replaced whole, so changed ones count as new
+        _ => quote! {
+            #target
+                .iter()
+                .filter(|element| !#base.contains(element))
+                .cloned()
+                .collect::<#ty>()
+        },
+    };
+    Some(diff)
+}
+
+/// Expression for the collection restoring what the collection `patch` would
+/// replace in `base`, or `None` if the strategy can't undo it.
+pub fn invert(field: &Field, patch: TokenStream, base: TokenStream) -> Option<TokenStream> {
+    let ty = field.inner_ty();
+    match &field.attrs.collection {
+        Some(CollectionMerge::ByKey(key)) => Some(quote! {
+            #base
+                .iter()
+                .filter(|existing| #patch.iter().any(|element| element.#key == existing.#key))
+                .cloned()
+                .collect::<#ty>()
+        }),
+        Some(CollectionMerge::Entries(None)) => Some(quote! {
+            #patch
+                .keys()
+                .filter_map(|key| #base.get(key).map(|value| (::std::clone::Clone::clone(key), ::std::clone::Clone::clone(value))))
+                .collect::<#ty>()
+        }),
+        Some(CollectionMerge::Entries(Some(_))) => Some(quote! {
+            #patch
+                .iter()
+                .filter_map(|(key, patch)| #base.get(key).map(|value| (::std::clone::Clone::clone(key), patch.invert(value))))
+                .collect::<#ty>()
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,185 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Error, GenericParam, Lifetime, LifetimeDef, Result};
+
+use crate::ast::{Container, Field};
+
+/// Generates `{Optional}Compact`, the patch encoded as a bitmap of its set
+/// fields followed by only their values. Only emitted with
+/// `#[optionalize(compact)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    if !cx.attrs.compact {
+        return Ok(quote! {});
+    }
+
+    // Secrets never leave the process
+    let fields: Vec<&Field> = cx.fields.iter().filter(|field| !field.is_secret()).collect();
+    let bitmap_ty = match fields.len() {
+        0..=8 => quote!(u8),
+        9..=16 => quote!(u16),
+        17..=32 => quote!(u32),
+        33..=64 => quote!(u64),
+        65..=128 => quote!(u128),
+        _ => {
+            return Err(Error::new_spanned(&cx.ident, "`compact` supports at most 128 fields"))
+        }
+    };
+
+    let optional_ident = &cx.optional_ident;
+    let compact_ident = format_ident!("{}Compact", optional_ident);
+    let visitor_ident = format_ident!("{}CompactVisitor", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+    let own: Vec<_> = cx
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates)
+        .collect();
+
+    let mut de_generics = cx.generics.clone();
+    let de = Lifetime::new("'__de", Span::call_site());
+    de_generics
+        .params
+        .insert(0, GenericParam::Lifetime(LifetimeDef::new(de.clone())));
+    let (de_impl_generics, _, _) = de_generics.split_for_impl();
+
+    let tys: Vec<_> = fields.iter().map(|field| field.inner_ty()).collect();
+    let len = fields.len() + 1;
+    let bits: Vec<_> = (0..fields.len()).map(|bit| quote!(1 << #bit)).collect();
+    let is_set = fields.iter().map(|field| field.is_set());
+    let values = fields.iter().map(|field| {
+        let ident = &field.ident;
+        if field.attrs.flatten || field.option_like.is_some() {
+            quote! { &self.#ident }
+        } else {
+            quote! { self.#ident.as_ref().unwrap() }
+        }
+    });
+    let reads = fields.iter().zip(&bits).map(|(field, bit)| {
+        let ident = &field.ident;
+        let value = if field.attrs.flatten || field.option_like.is_some() {
+            quote! { value }
+        } else {
+            quote! { Some(value) }
+        };
+        quote! {
+            if bitmap & #bit != 0 {
+                let value = ::serde::de::SeqAccess::next_element(&mut seq)?
+                    .ok_or_else(|| ::serde::de::Error::invalid_length(read, &self))?;
+                patch.#ident = #value;
+                read += 1;
+            }
+        }
+    });
+    let unknown = fields.len();
+
+    let doc = format!(
+        "A [`{}`] serialized as a bitmap of its set fields followed by only their values.",
+        optional_ident
+    );
+    let expecting = format!("a compact {}", optional_ident);
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// In binary formats without field names, such as `postcard` or
+        /// `bincode`, a patch costs the bitmap plus its set values. Every
+        /// replica must share the same field order.
+        pub struct #compact_ident <#generics> (pub #optional_ident #ty_generics) #where_clause;
+
+        impl #impl_generics ::serde::Serialize for #compact_ident #ty_generics
+        where
+            #( #own, )*
+            #( for<'__optionalize> #tys: ::serde::Serialize, )*
+        {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize_compact(serializer)
+            }
+        }
+
+        impl #de_impl_generics ::serde::Deserialize<#de> for #compact_ident #ty_generics
+        where
+            #( #own, )*
+            #( #tys: ::serde::Deserialize<#de>, )*
+        {
+            fn deserialize<D: ::serde::Deserializer<#de>>(deserializer: D) -> Result<Self, D::Error> {
+                #optional_ident::deserialize_compact(deserializer).map(#compact_ident)
+            }
+        }
+
+        #[doc(hidden)]
+        pub struct #visitor_ident <#generics> (::std::marker::PhantomData<fn() -> #optional_ident #ty_generics>) #where_clause;
+
+        impl #de_impl_generics ::serde::de::Visitor<#de> for #visitor_ident #ty_generics
+        where
+            #( #own, )*
+            #( #tys: ::serde::Deserialize<#de>, )*
+        {
+            type Value = #optional_ident #ty_generics;
+
+            fn expecting(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(#expecting)
+            }
+
+            fn visit_seq<A: ::serde::de::SeqAccess<#de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let bitmap: #bitmap_ty = ::serde::de::SeqAccess::next_element(&mut seq)?
+                    .ok_or_else(|| ::serde::de::Error::invalid_length(0, &self))?;
+                if bitmap.checked_shr(#unknown as u32).unwrap_or(0) != 0 {
+                    return Err(::serde::de::Error::custom(
+                        "compact patch sets fields this struct doesn't have",
+                    ));
+                }
+                let mut patch = <#optional_ident #ty_generics>::empty();
+                #[allow(unused_mut)]
+                let mut read = 1;
+                #( #reads )*
+                let _ = read;
+                Ok(patch)
+            }
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Serializes the patch as a bitmap of its set fields followed by
+            /// only their values, as a tuple. Usable as
+            /// `#[serde(serialize_with = "...")]`.
+            pub fn serialize_compact<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                #( for<'__optionalize> #tys: ::serde::Serialize, )*
+            {
+                #[allow(unused_mut)]
+                let mut bitmap: #bitmap_ty = 0;
+                #[allow(unused_mut)]
+                let mut len = 1;
+                #(
+                    if #is_set {
+                        bitmap |= #bits;
+                        len += 1;
+                    }
+                )*
+                let mut tuple = ::serde::Serializer::serialize_tuple(serializer, len)?;
+                ::serde::ser::SerializeTuple::serialize_element(&mut tuple, &bitmap)?;
+                #(
+                    if bitmap & #bits != 0 {
+                        ::serde::ser::SerializeTuple::serialize_element(&mut tuple, #values)?;
+                    }
+                )*
+                ::serde::ser::SerializeTuple::end(tuple)
+            }
+
+            /// Deserializes a patch written by
+            /// [`serialize_compact`](Self::serialize_compact). Usable as
+            /// `#[serde(deserialize_with = "...")]`.
+            pub fn deserialize_compact<#de, D: ::serde::Deserializer<#de>>(deserializer: D) -> Result<Self, D::Error>
+            where
+                #( #tys: ::serde::Deserialize<#de>, )*
+            {
+                ::serde::Deserializer::deserialize_tuple(
+                    deserializer,
+                    #len,
+                    #visitor_ident(::std::marker::PhantomData),
+                )
+            }
+        }
+    })
+}
@@ -0,0 +1,64 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Implements the `config` crate's `Source` for generated structs deriving
+/// `Serialize`. Only emitted with `#[optionalize(config)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("config") || !cx.derives_serialize() || !cx.emits("serde") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+    let own = cx
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates);
+
+    quote! {
+        impl #impl_generics ::config::Source for #optional_ident #ty_generics
+        where
+            #( #own, )*
+            for<'__optionalize> Self: ::serde::Serialize
+                + ::std::clone::Clone
+                + ::std::fmt::Debug
+                + ::std::marker::Send
+                + ::std::marker::Sync,
+            Self: 'static,
+        {
+            fn clone_into_box(&self) -> ::std::boxed::Box<dyn ::config::Source + Send + Sync> {
+                ::std::boxed::Box::new(::std::clone::Clone::clone(self))
+            }
+
+            /// The set fields under dotted keys, such as `server.port` for a
+            /// `nested` field, so they override lower layers key by key.
+            fn collect(&self) -> Result<::config::Map<String, ::config::Value>, ::config::ConfigError> {
+                fn flatten(
+                    prefix: &str,
+                    table: ::config::Map<String, ::config::Value>,
+                    out: &mut ::config::Map<String, ::config::Value>,
+                ) {
+                    for (key, value) in table {
+                        let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                        match value.kind {
+                            // Unset fields leave lower layers alone
+                            ::config::ValueKind::Nil => {}
+                            ::config::ValueKind::Table(nested) => flatten(&key, nested, out),
+                            kind => {
+                                out.insert(key, ::config::Value::new(None, kind));
+                            }
+                        }
+                    }
+                }
+
+                let serialized = ::config::Config::try_from(self)?;
+                let mut out = ::config::Map::new();
+                flatten("", ::config::Source::collect(&serialized)?, &mut out);
+                Ok(out)
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, pointer_inner, type_ends_with, Container, Field};
+use crate::attr::CollectionMerge;
+
+/// Generates `empty`, and `From<T>` and `From<&T>` for the optional struct,
+/// setting every field from a value of the original struct, along with
+/// `TryFrom` for the original struct from a complete patch.
+pub fn expand(cx: &Container) -> TokenStream {
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, own_where_clause) = cx.generics.split_for_impl();
+    let empty = cx.empty();
+    // `Default` can't be called in const contexts
+    let constness = if cx.fields.iter().any(|field| field.option_like.is_some()) {
+        quote! {}
+    } else {
+        quote! { const }
+    };
+
+    let where_clause = cx.where_clause_with(bounds(cx));
+
+    let fields = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let value = field.to_optional(quote!(value.#ident));
+        quote! { #ident: #value }
+    });
+    let cloned = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let value = field.to_optional(quote!(::std::clone::Clone::clone(&value.#ident)));
+        quote! { #ident: #value }
+    });
+    let markers: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    // Each field is cloned out of the borrowed value, which `bound` can't
+    // lift, so the predicates are written out rather than replaceable
+    let clone_bounds = cx.fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote! { for<'__optionalize> #ty: ::std::clone::Clone, }
+    });
+    let own_predicates = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+    let shared_bounds = bounds(cx);
+    let ref_where_clause = quote! { where #( #own_predicates, )* #( #clone_bounds )* #shared_bounds };
+
+    // Fields the patch can't set start from their default, as serde does
+    let field_idents: Vec<_> = cx.fields.iter().map(|field| &field.ident).collect();
+    let completed = cx.fields.iter().map(complete);
+    let skipped_idents = cx.skipped.iter().map(|(ident, _)| ident);
+    let skipped_bounds = cx.skipped.iter().map(|(_, ty)| {
+        quote! { for<'__optionalize> #ty: ::std::default::Default, }
+    });
+    let own_predicates = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+    let try_from_where_clause = quote! { where #( #own_predicates, )* #( #skipped_bounds )* };
+    let error_ident = cx.error_ident();
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #own_where_clause {
+            /// A patch with every field unset, which changes nothing. Usable in
+            /// `const` and `static` items unless the struct has `option_like`
+            /// fields.
+            pub #constness fn empty() -> Self {
+                #empty
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#ident #ty_generics> for #optional_ident #ty_generics
+        #where_clause
+        {
+            /// Builds a patch that sets every field to its value in `value`.
+            fn from(value: #ident #ty_generics) -> Self {
+                Self {
+                    #( #fields, )*
+                    #( #markers: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<&#ident #ty_generics> for #optional_ident #ty_generics
+        #ref_where_clause
+        {
+            /// Builds a patch that sets every field to a clone of its value in
+            /// `value`, leaving `value` untouched, e.g. to snapshot it.
+            fn from(value: &#ident #ty_generics) -> Self {
+                Self {
+                    #( #cloned, )*
+                    #( #markers: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<#optional_ident #ty_generics> for #ident #ty_generics
+        #try_from_where_clause
+        {
+            type Error = #error_ident;
+
+            /// Builds a value from a patch setting every required field, failing
+            /// with `MissingFields` otherwise. `Option` fields left unset are
+            /// `None`.
+            fn try_from(value: #optional_ident #ty_generics) -> Result<Self, Self::Error> {
+                let missing = value.missing_fields();
+                match ( #( #completed, )* ) {
+                    ( #( Some(#field_idents), )* ) => Ok(Self {
+                        #( #field_idents, )*
+                        #( #skipped_idents: ::std::default::Default::default(), )*
+                        #( #markers: ::std::marker::PhantomData, )*
+                    }),
+                    _ => Err(#error_ident::MissingFields { fields: missing }),
+                }
+            }
+        }
+    }
+}
+
+/// The value of the original field built from the patch's `value.{field}`, as
+/// an `Option` that is `None` when the field is missing.
+fn complete(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+    let sanitized = match &field.attrs.sanitize_with {
+        Some(sanitize) => quote! { #sanitize(value) },
+        None => quote! { value },
+    };
+
+    if field.attrs.flatten {
+        let ty = &field.ty;
+        return quote! { <#ty as ::std::convert::TryFrom<_>>::try_from(value.#ident).ok() };
+    }
+    // Option-like fields and double `Option`s are already values of the field
+    if field.option_like.is_some() {
+        let value = match &field.attrs.sanitize_with {
+            Some(sanitize) => quote! { #sanitize(value.#ident) },
+            None => quote! { value.#ident },
+        };
+        return quote! { Some(#value) };
+    }
+    if field.double_option {
+        return quote! { Some(value.#ident.flatten()) };
+    }
+    if let Some(CollectionMerge::Entries(Some(_))) = &field.attrs.collection {
+        return quote! {
+            value.#ident.and_then(|patches| {
+                patches
+                    .into_iter()
+                    .map(|(key, patch)| ::std::convert::TryFrom::try_from(patch).ok().map(|value| (key, value)))
+                    .collect()
+            })
+        };
+    }
+    if let Some(nested_ty) = &field.nested_ty {
+        let value_ty = field.value_ty();
+        let (source, patch, built) = if type_ends_with(nested_ty, "Box") {
+            (pointer_inner(value_ty).unwrap_or(value_ty), quote!(*patch), quote!(::std::boxed::Box::new(value)))
+        } else {
+            (value_ty, quote!(patch), quote!(value))
+        };
+        let build = quote! {
+            <#source as ::std::convert::TryFrom<_>>::try_from(#patch).ok().map(|value| #built)
+        };
+        return if field.is_option() {
+            quote! {
+                match value.#ident {
+                    Some(patch) => #build.map(Some),
+                    None => Some(None),
+                }
+            }
+        } else {
+            quote! { value.#ident.and_then(|patch| #build) }
+        };
+    }
+
+    let source = field.to_source(sanitized);
+    if field.is_option() {
+        quote! {
+            Some(match value.#ident {
+                Some(value) => #source,
+                None => None,
+            })
+        }
+    } else {
+        quote! { value.#ident.map(|value| #source) }
+    }
+}
+
+/// `where` predicates needed by [`Field::to_optional`](crate::ast::Field::to_optional).
+pub fn bounds(cx: &Container) -> TokenStream {
+    // Unboxed `Arc` and `Rc` contents are cloned when they are shared
+    let shared = cx
+        .fields
+        .iter()
+        .filter(|field| field.unwrap_pointer && !type_ends_with(field.value_ty(), "Box"));
+    inner_bounds(shared, quote!(::std::clone::Clone))
+}
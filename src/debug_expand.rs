@@ -0,0 +1,36 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use proc_macro2::{Span, TokenStream};
+use syn::{Error, Result};
+
+use crate::ast::Container;
+
+/// Writes the code generated for the struct to `optionalize/{Struct}.rs` when
+/// `#[optionalize(debug_expand)]` or `OPTIONALIZE_DEBUG_EXPAND` asks for it.
+pub fn write(cx: &Container, expanded: &TokenStream) -> Result<()> {
+    if !cx.attrs.debug_expand && env::var_os("OPTIONALIZE_DEBUG_EXPAND").is_none() {
+        return Ok(());
+    }
+
+    // Build scripts give the crate a directory of its own
+    let dir = match env::var_os("OUT_DIR").or_else(|| env::var_os("CARGO_TARGET_DIR")) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default()).join("target"),
+    }
+    .join("optionalize");
+    let path = dir.join(format!("{}.rs", cx.ident));
+
+    let written = fs::create_dir_all(&dir).and_then(|()| fs::write(&path, expanded.to_string()));
+    if let Err(err) = written {
+        return Err(Error::new(
+            Span::call_site(),
+            format!("`debug_expand` couldn't write {}: {}", path.display(), err),
+        ));
+    }
+    // The tokens print on a single line, so they are only readable formatted
+    let _ = Command::new("rustfmt").arg("--edition=2021").arg(&path).status();
+    Ok(())
+}
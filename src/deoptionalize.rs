@@ -0,0 +1,216 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Attribute, Data, DeriveInput, Error, Fields, Ident, Lit, Meta, NestedMeta, Path, Result};
+
+use crate::ast::{first_type_argument, runtime_crate, type_ends_with};
+
+/// Options read from `#[deoptionalize(...)]` attributes on the struct.
+#[derive(Default)]
+struct ContainerAttrs {
+    /// Derives to add to the generated struct.
+    derives: Vec<Path>,
+    /// Exact name of the generated struct.
+    name: Option<Ident>,
+}
+
+impl ContainerAttrs {
+    fn from_attrs(attrs: &[Attribute]) -> Result<Self> {
+        let mut container_attrs = ContainerAttrs::default();
+
+        for nested in deoptionalize_metas(attrs)? {
+            match nested {
+                // #[deoptionalize(derive(Debug, Clone))]
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("derive") => {
+                    for nested in list.nested {
+                        match nested {
+                            NestedMeta::Meta(Meta::Path(path)) => container_attrs.derives.push(path),
+                            other => return Err(Error::new_spanned(other, "expected a derive path")),
+                        }
+                    }
+                }
+                // #[deoptionalize(name = "PublishedPost")]
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("name") => match &nv.lit {
+                    Lit::Str(s) => container_attrs.name = Some(s.parse()?),
+                    other => return Err(Error::new_spanned(other, "expected a string literal")),
+                },
+                other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "unknown `deoptionalize` attribute, expected `derive(...)` or `name = \"...\"`",
+                    ))
+                }
+            }
+        }
+
+        Ok(container_attrs)
+    }
+}
+
+/// Whether a field is marked `#[deoptionalize(optional)]`, staying an `Option`.
+fn is_kept_optional(attrs: &[Attribute]) -> Result<bool> {
+    let mut optional = false;
+    for nested in deoptionalize_metas(attrs)? {
+        match nested {
+            // #[deoptionalize(optional)]
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("optional") => optional = true,
+            other => {
+                return Err(Error::new_spanned(
+                    other,
+                    "unknown `deoptionalize` field attribute, expected `optional`",
+                ))
+            }
+        }
+    }
+    Ok(optional)
+}
+
+fn deoptionalize_metas(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("deoptionalize")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => metas.extend(list.nested),
+            other => return Err(Error::new_spanned(other, "expected `#[deoptionalize(...)]`")),
+        }
+    }
+    Ok(metas)
+}
+
+/// Generates `{Struct}Required`, with the `Option` fields of the input
+/// unwrapped, along with the conversions between both.
+pub fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let attrs = ContainerAttrs::from_attrs(&input.attrs)?;
+    let ident = &input.ident;
+    let required_ident = attrs.name.unwrap_or_else(|| format_ident!("{}Required", ident));
+    let error_ident = format_ident!("{}Error", required_ident);
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generics = &input.generics.params;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new_spanned(ident, "`Deoptionalize` requires a struct with named fields")),
+        },
+        _ => return Err(Error::new_spanned(ident, "`Deoptionalize` can only be derived for structs")),
+    };
+
+    // Required fields, with the type they unwrap to
+    let mut required = Vec::new();
+    let mut kept = Vec::new();
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("named field");
+        let unwrapped = match first_type_argument(&field.ty) {
+            Some(inner) if type_ends_with(&field.ty, "Option") && !is_kept_optional(&field.attrs)? => Some(inner),
+            _ => None,
+        };
+        match unwrapped {
+            Some(inner) => required.push((field, field_ident, inner)),
+            None => kept.push((field, field_ident)),
+        }
+    }
+
+    let required_fields = required.iter().map(|(field, field_ident, inner)| {
+        let vis = &field.vis;
+        quote! { #vis #field_ident: #inner }
+    });
+    let kept_fields = kept.iter().map(|(field, field_ident)| {
+        let vis = &field.vis;
+        let ty = &field.ty;
+        quote! { #vis #field_ident: #ty }
+    });
+
+    let required_idents: Vec<_> = required.iter().map(|(_, field_ident, _)| *field_ident).collect();
+    let required_names: Vec<_> = required_idents.iter().map(|ident| ident.unraw().to_string()).collect();
+    let kept_idents: Vec<_> = kept.iter().map(|(_, field_ident)| *field_ident).collect();
+
+    let finalized = quote! {
+        Self {
+            #( #required_idents, )*
+            #( #kept_idents: value.#kept_idents, )*
+        }
+    };
+    let try_from = if required.is_empty() {
+        quote! { Ok(#finalized) }
+    } else {
+        quote! {
+            match ( #( value.#required_idents, )* ) {
+                ( #( Some(#required_idents), )* ) => Ok(#finalized),
+                ( #( #required_idents, )* ) => {
+                    let fields = [ #( (#required_names, #required_idents.is_some()) ),* ];
+                    Err(#error_ident {
+                        missing: fields.iter().filter(|(_, set)| !set).map(|(name, _)| *name).collect(),
+                    })
+                }
+            }
+        }
+    };
+
+    let derives = &attrs.derives;
+    let derive = if derives.is_empty() {
+        quote! {}
+    } else {
+        quote! { #[derive(#( #derives ),*)] }
+    };
+    let doc = format!(
+        "Finalized version of [`{}`], with every `Option` field required. Generated by `Deoptionalize`.",
+        ident
+    );
+    let runtime = if let Some(runtime) = runtime_crate() {
+        quote! {
+            impl ::std::convert::From<#error_ident> for #runtime::MissingFieldsError {
+                fn from(error: #error_ident) -> Self {
+                    Self { missing: error.missing }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let error_doc = format!("Error converting a [`{}`] into a [`{}`].", ident, required_ident);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #derive
+        #vis struct #required_ident <#generics> #where_clause {
+            #( #required_fields, )*
+            #( #kept_fields, )*
+        }
+
+        #[doc = #error_doc]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #vis struct #error_ident {
+            /// The required fields that were `None`, in declaration order.
+            pub missing: Vec<&'static str>,
+        }
+
+        impl ::std::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                write!(f, "missing required fields: {}", self.missing.join(", "))
+            }
+        }
+
+        impl ::std::error::Error for #error_ident {}
+
+        #runtime
+
+        impl #impl_generics ::std::convert::TryFrom<#ident #ty_generics> for #required_ident #ty_generics #where_clause {
+            type Error = #error_ident;
+
+            /// Unwraps every required field, listing all of those that are `None`.
+            fn try_from(value: #ident #ty_generics) -> Result<Self, Self::Error> {
+                #try_from
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#required_ident #ty_generics> for #ident #ty_generics #where_clause {
+            /// Wraps every required field back in `Some`.
+            fn from(value: #required_ident #ty_generics) -> Self {
+                Self {
+                    #( #required_idents: Some(value.#required_idents), )*
+                    #( #kept_idents: value.#kept_idents, )*
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,151 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{type_ends_with, Container, Field};
+
+/// Generates `fill_interactive`, which prompts on the terminal for every unset
+/// field. Only emitted with `#[optionalize(dialoguer)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("dialoguer") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Answers are parsed like `set_from_str`, which skips these as well
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| field.option_like.is_none() && !field.double_option)
+        .collect();
+    let parsed = fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none())
+        .map(|field| field.inner_ty());
+    // Defaults are shown as the pre-filled answer
+    let shown = fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none() && !field.is_sensitive())
+        .map(|field| field.value_ty());
+    let bounds = quote! {
+        #(
+            for<'__optionalize> #parsed: ::std::str::FromStr,
+            for<'__optionalize> <#parsed as ::std::str::FromStr>::Err: ::std::fmt::Display,
+        )*
+        #( for<'__optionalize> #shown: ::std::fmt::Display, )*
+    };
+
+    let prompts = fields.iter().copied().map(prompt);
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Prompts on the terminal for every unset field, in declaration
+            /// order, so a CLI wizard can complete a partial config. Answers are
+            /// parsed with [`set_from_str`](Self::set_from_str), and asked again
+            /// until they parse.
+            ///
+            /// The values of `defaults`, if given, are offered as the answers.
+            /// `Option` fields may be left empty, and `Option` nested fields
+            /// unset are left alone. Sensitive fields are read without echo.
+            pub fn fill_interactive(
+                &mut self,
+                defaults: Option<&#ident #ty_generics>,
+            ) -> ::dialoguer::Result<()>
+            where
+                #bounds
+            {
+                #( #prompts )*
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The statements prompting for a single field while it is unset.
+fn prompt(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+    let name = field.name();
+
+    if field.attrs.flatten {
+        return quote! {
+            self.#ident.fill_interactive(defaults.map(|defaults| &defaults.#ident))?;
+        };
+    }
+    if let Some(nested_ty) = &field.nested_ty {
+        let boxed = type_ends_with(nested_ty, "Box");
+        if field.is_option() {
+            let defaults = if boxed {
+                quote! { defaults.and_then(|defaults| defaults.#ident.as_deref()) }
+            } else {
+                quote! { defaults.and_then(|defaults| defaults.#ident.as_ref()) }
+            };
+            return quote! {
+                if let Some(nested) = &mut self.#ident {
+                    nested.fill_interactive(#defaults)?;
+                }
+            };
+        }
+        let twin = if boxed {
+            let twin = crate::ast::pointer_inner(nested_ty).unwrap_or(nested_ty);
+            quote! { ::std::boxed::Box::new(<#twin>::empty()) }
+        } else {
+            quote! { <#nested_ty>::empty() }
+        };
+        let defaults = if boxed {
+            quote! { defaults.map(|defaults| &*defaults.#ident) }
+        } else {
+            quote! { defaults.map(|defaults| &defaults.#ident) }
+        };
+        return quote! {
+            self.#ident.get_or_insert_with(|| #twin).fill_interactive(#defaults)?;
+        };
+    }
+
+    let ask = if field.is_sensitive() {
+        let empty = field.is_option();
+        quote! {
+            ::dialoguer::Password::new()
+                .with_prompt(#name)
+                .allow_empty_password(#empty)
+                .interact()?
+        }
+    } else if field.is_option() {
+        quote! {{
+            let mut input = ::dialoguer::Input::<String>::new().with_prompt(#name).allow_empty(true);
+            if let Some(Some(value)) = defaults.map(|defaults| &defaults.#ident) {
+                input = input.default(::std::string::ToString::to_string(value));
+            }
+            input.interact_text()?
+        }}
+    } else {
+        quote! {{
+            let mut input = ::dialoguer::Input::<String>::new().with_prompt(#name);
+            if let Some(defaults) = defaults {
+                input = input.default(::std::string::ToString::to_string(&defaults.#ident));
+            }
+            input.interact_text()?
+        }}
+    };
+    // An empty answer leaves an `Option` field unset
+    let skip = if field.is_option() {
+        quote! {
+            if answer.is_empty() {
+                break;
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        while self.#ident.is_none() {
+            let answer: String = #ask;
+            #skip
+            if let Err(err) = self.set_from_str(#name, &answer) {
+                eprintln!("{}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{type_ends_with, Container, Field};
+use crate::{collection, convert};
+
+/// Generates `diff`, which computes the patch between two values, `invert`,
+/// which computes the patch undoing another one, and `changed_fields`, which
+/// lists the fields a patch would actually change.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("diff") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let empty = cx.empty();
+
+    let diff_bounds = bounds(cx);
+    let invert_bounds = invert_bounds(cx);
+    let changed_bounds = changed_bounds(cx);
+
+    let diffs = cx.fields.iter().map(diff);
+    let changes = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable)
+        .map(change);
+    let inversions = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable)
+        .map(invert);
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// The patch that turns `base` into `target`, setting every field whose
+            /// value differs.
+            ///
+            /// Only `double_option` fields can be unset by a patch, so any other
+            /// `Option` field that is `None` in `target` is left unset.
+            #[must_use]
+            pub fn diff(base: &#ident #ty_generics, target: &#ident #ty_generics) -> Self
+            where
+                #diff_bounds
+            {
+                let mut patch = #empty;
+                #( #diffs )*
+                patch
+            }
+
+            /// The patch that undoes `self` once it is applied onto `base`, setting
+            /// every field it changes back to its value in `base`.
+            ///
+            /// Only `double_option` fields can be unset by a patch, so any other
+            /// `Option` field that is `None` in `base` is not restored.
+            #[must_use]
+            pub fn invert(&self, base: &#ident #ty_generics) -> Self
+            where
+                #invert_bounds
+            {
+                let mut inverse = #empty;
+                #( #inversions )*
+                inverse
+            }
+
+            /// Names of the set fields whose value would actually change if the
+            /// patch were applied onto `base`, in declaration order, so no-op
+            /// writes can be skipped.
+            pub fn changed_fields(&self, base: &#ident #ty_generics) -> Vec<&'static str>
+            where
+                #changed_bounds
+            {
+                let mut fields = Vec::new();
+                #( #changes )*
+                fields
+            }
+        }
+    }
+}
+
+/// Generates the statement setting a single field of `patch` when it differs
+/// between `base` and `target`.
+fn diff(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+
+    if field.attrs.flatten {
+        let twin = field.inner_ty();
+        return quote! { patch.#ident = <#twin>::diff(&base.#ident, &target.#ident); };
+    }
+
+    // Nested values are diffed in turn, as long as there is one on both sides
+    if let Some(nested_ty) = &field.nested_ty {
+        let boxed = type_ends_with(nested_ty, "Box");
+        let diff = field.twin_fn("diff");
+        let diff = if boxed {
+            quote! { ::std::boxed::Box::new(#diff(base, target)) }
+        } else {
+            quote! { #diff(base, target) }
+        };
+        return if field.is_option() {
+            quote! {
+                if let (Some(base), Some(target)) = (&base.#ident, &target.#ident) {
+                    if ::std::cmp::PartialEq::ne(base, target) {
+                        patch.#ident = Some(#diff);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                if ::std::cmp::PartialEq::ne(&base.#ident, &target.#ident) {
+                    let (base, target) = (&base.#ident, &target.#ident);
+                    patch.#ident = Some(#diff);
+                }
+            }
+        };
+    }
+
+    // Collections that add to the existing elements only carry the additions
+    if field.attrs.collection.is_some() {
+        let Some(diff) = collection::diff(field, quote!(base), quote!(target)) else {
+            return quote! {};
+        };
+        return quote! {
+            if ::std::cmp::PartialEq::ne(&base.#ident, &target.#ident) {
+                let (base, target) = (&base.#ident, &target.#ident);
+                let added = #diff;
+                if !added.is_empty() {
+                    patch.#ident = Some(added);
+                }
+            }
+        };
+    }
+
+    let value = field.to_optional(quote!(::std::clone::Clone::clone(&target.#ident)));
+    quote! {
+        if ::std::cmp::PartialEq::ne(&base.#ident, &target.#ident) {
+            patch.#ident = #value;
+        }
+    }
+}
+
+/// Generates the statement restoring a single set field of `self` from `base`.
+fn invert(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+
+    if field.attrs.flatten {
+        return quote! { inverse.#ident = self.#ident.invert(&base.#ident); };
+    }
+
+    if let Some(nested_ty) = &field.nested_ty {
+        let boxed = type_ends_with(nested_ty, "Box");
+        let invert = field.twin_fn("invert");
+        let inverse = if boxed {
+            quote! { ::std::boxed::Box::new(#invert(patch, base)) }
+        } else {
+            quote! { #invert(patch, base) }
+        };
+        return if field.is_option() {
+            quote! {
+                if let (Some(patch), Some(base)) = (&self.#ident, &base.#ident) {
+                    inverse.#ident = Some(#inverse);
+                }
+            }
+        } else {
+            quote! {
+                if let Some(patch) = &self.#ident {
+                    let base = &base.#ident;
+                    inverse.#ident = Some(#inverse);
+                }
+            }
+        };
+    }
+
+    if field.attrs.collection.is_some() {
+        return match collection::invert(field, quote!(patch), quote!(base)) {
+            Some(previous) => quote! {
+                if let Some(patch) = &self.#ident {
+                    let base = &base.#ident;
+                    let previous = #previous;
+                    if !previous.is_empty() {
+                        inverse.#ident = Some(previous);
+                    }
+                }
+            },
+            None => quote! {},
+        };
+    }
+
+    let is_set = field.is_set();
+    let value = field.to_optional(quote!(::std::clone::Clone::clone(&base.#ident)));
+    quote! {
+        if #is_set {
+            inverse.#ident = #value;
+        }
+    }
+}
+
+/// Generates the statement listing a single field of `self` when applying it
+/// would change `base`.
+fn change(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+    let name = field.name();
+
+    if field.attrs.flatten {
+        return quote! { fields.extend(self.#ident.changed_fields(&base.#ident)); };
+    }
+
+    // Nested patches change the value if they change any of its fields
+    if field.nested_ty.is_some() {
+        let changed_fields = field.twin_fn("changed_fields");
+        return if field.is_option() {
+            quote! {
+                if let (Some(patch), Some(base)) = (&self.#ident, &base.#ident) {
+                    if !#changed_fields(patch, base).is_empty() {
+                        fields.push(#name);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                if let Some(patch) = &self.#ident {
+                    if !#changed_fields(patch, &base.#ident).is_empty() {
+                        fields.push(#name);
+                    }
+                }
+            }
+        };
+    }
+
+    if field.option_like.is_some() {
+        let is_set = field.is_set();
+        let value = match &field.attrs.sanitize_with {
+            Some(sanitize) => quote! { &#sanitize(::std::clone::Clone::clone(&self.#ident)) },
+            None => quote! { &self.#ident },
+        };
+        return quote! {
+            if #is_set && ::std::cmp::PartialEq::ne(#value, &base.#ident) {
+                fields.push(#name);
+            }
+        };
+    }
+
+    // Compare the value `apply_to` would write
+    let value = match &field.attrs.sanitize_with {
+        Some(sanitize) => quote! { #sanitize(::std::clone::Clone::clone(value)) },
+        None => quote! { ::std::clone::Clone::clone(value) },
+    };
+    if field.attrs.collection.is_some() {
+        let merge = collection::merge_into(field, quote!(list), value, None);
+        return quote! {
+            if let Some(value) = &self.#ident {
+                let mut list = ::std::clone::Clone::clone(&base.#ident);
+                #merge
+                if ::std::cmp::PartialEq::ne(&list, &base.#ident) {
+                    fields.push(#name);
+                }
+            }
+        };
+    }
+    let value = field.to_source(value);
+    let ty = &field.ty;
+    quote! {
+        if let Some(value) = &self.#ident {
+            let value: #ty = #value;
+            if ::std::cmp::PartialEq::ne(&value, &base.#ident) {
+                fields.push(#name);
+            }
+        }
+    }
+}
+
+/// `where` predicates needed by `diff`.
+pub fn bounds(cx: &Container) -> TokenStream {
+    // Values are compared in place, and cloned into the patch when they differ
+    let conversions = convert::bounds(cx);
+    let cloned = cx
+        .fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none())
+        .map(|field| &field.ty);
+    let compared = cx
+        .fields
+        .iter()
+        .filter(|field| field.nested_ty.is_some() && !field.attrs.flatten)
+        .map(|field| &field.ty);
+    let twins = twin_bounds(cx);
+    quote! {
+        #conversions
+        #( for<'__optionalize> #cloned: ::std::cmp::PartialEq + ::std::clone::Clone, )*
+        #( for<'__optionalize> #compared: ::std::cmp::PartialEq, )*
+        #twins
+    }
+}
+
+/// `where` predicates needed by `invert`.
+pub fn invert_bounds(cx: &Container) -> TokenStream {
+    let conversions = convert::bounds(cx);
+    let cloned = cx
+        .fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none())
+        .map(|field| &field.ty);
+    let twins = twin_bounds(cx);
+    quote! {
+        #conversions
+        #( for<'__optionalize> #cloned: ::std::clone::Clone, )*
+        #twins
+    }
+}
+
+/// `where` predicates needed by `changed_fields`.
+pub fn changed_bounds(cx: &Container) -> TokenStream {
+    let conversions = convert::bounds(cx);
+    let compared = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.flatten && !field.attrs.immutable)
+        .map(|field| &field.ty);
+    let cloned = cx
+        .fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none() && !field.attrs.immutable)
+        .filter(|field| field.option_like.is_none() || field.attrs.sanitize_with.is_some())
+        .map(Field::inner_ty);
+    let twins = twin_bounds(cx);
+    quote! {
+        #conversions
+        #( for<'__optionalize> #compared: ::std::cmp::PartialEq, )*
+        #( for<'__optionalize> #cloned: ::std::clone::Clone, )*
+        #twins
+    }
+}
+
+/// The twins of type parameters are diffed through the runtime crate's `Diff`.
+fn twin_bounds(cx: &Container) -> TokenStream {
+    let twins = cx.fields.iter().filter_map(|field| {
+        let param = field.twin_param.as_ref()?;
+        let runtime = cx.runtime.as_ref()?;
+        Some(quote! { for<'__optionalize> #param::Optional: #runtime::Diff<#param>, })
+    });
+    quote! { #( #twins )* }
+}
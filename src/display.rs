@@ -0,0 +1,97 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{first_type_argument, Container, Field};
+
+/// Generates a `Display` impl printing one `field = value` line per set field.
+/// Only emitted with `#[optionalize(display)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.display {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+
+    // Every printed value must be displayable
+    let tys = cx
+        .fields
+        .iter()
+        .filter(|field| !field.is_sensitive())
+        .map(displayed_ty);
+    let where_clause = cx.where_clause_with(quote! {
+        #( for<'__optionalize> #tys: ::std::fmt::Display, )*
+    });
+
+    let lines = cx.fields.iter().map(line);
+
+    quote! {
+        impl #impl_generics ::std::fmt::Display for #optional_ident #ty_generics #where_clause {
+            /// Writes one `field = value` line per set field, in declaration order.
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                #( #lines )*
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The type whose `Display` prints the value of `field`.
+fn displayed_ty(field: &Field) -> &syn::Type {
+    if field.double_option {
+        first_type_argument(&field.ty).unwrap_or(&field.ty)
+    } else {
+        field.inner_ty()
+    }
+}
+
+/// The statements writing the line(s) of a single field when it is set.
+fn line(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+    let name = field.name();
+    let is_set = field.is_set();
+
+    if field.is_sensitive() {
+        return quote! {
+            if #is_set {
+                writeln!(f, "{} = [REDACTED]", #name)?;
+            }
+        };
+    }
+    // Flattened fields print as if they were the struct's own
+    if field.attrs.flatten {
+        return quote! {
+            ::std::fmt::Display::fmt(&self.#ident, f)?;
+        };
+    }
+    if field.nested_ty.is_some() {
+        return quote! {
+            if let Some(patch) = &self.#ident {
+                for line in ::std::string::ToString::to_string(patch).lines() {
+                    writeln!(f, "{}.{}", #name, line)?;
+                }
+            }
+        };
+    }
+    if field.double_option {
+        return quote! {
+            match &self.#ident {
+                Some(Some(value)) => writeln!(f, "{} = {}", #name, value)?,
+                Some(None) => writeln!(f, "{} = null", #name)?,
+                None => {}
+            }
+        };
+    }
+    if field.option_like.is_some() {
+        return quote! {
+            if #is_set {
+                writeln!(f, "{} = {}", #name, self.#ident)?;
+            }
+        };
+    }
+    quote! {
+        if let Some(value) = &self.#ident {
+            writeln!(f, "{} = {}", #name, value)?;
+        }
+    }
+}
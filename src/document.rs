@@ -0,0 +1,104 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Generates loaders reading a partial document of a configuration format into
+/// the optional struct, for the formats the container integrates with. Only
+/// emitted when the optional struct derives `Deserialize`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.derives_deserialize() || !cx.emits("serde") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let mut loaders = Vec::new();
+    if cx.attrs.integrates("toml") {
+        loaders.push(quote! {
+            /// Parses a partial TOML document into a patch; missing keys are unset.
+            pub fn from_toml_str(document: &str) -> Result<Self, ::toml::de::Error>
+            where
+                Self: ::serde::de::DeserializeOwned,
+            {
+                ::toml::from_str(document)
+            }
+
+            /// Parses a partial TOML document, such as an override file, and
+            /// applies it onto `target` with [`try_apply`](Self::try_apply),
+            /// returning the applied fields.
+            pub fn merge_toml(
+                target: &mut #ident #ty_generics,
+                document: &str,
+            ) -> Result<Vec<&'static str>, #error_ident>
+            where
+                Self: ::serde::de::DeserializeOwned,
+            {
+                let patch = Self::from_toml_str(document).map_err(|err| #error_ident::InvalidDocument {
+                    message: err.to_string(),
+                })?;
+                let applied = patch.set_fields();
+                patch.try_apply(target)?;
+                Ok(applied)
+            }
+        });
+    }
+
+    if cx.attrs.integrates("yaml") {
+        loaders.push(quote! {
+            /// Parses a partial YAML document into a patch; missing keys are unset.
+            pub fn from_yaml_str(document: &str) -> Result<Self, ::serde_yaml::Error>
+            where
+                Self: ::serde::de::DeserializeOwned,
+            {
+                ::serde_yaml::from_str(document)
+            }
+
+            /// Parses a partial YAML document, such as an overlay, and applies it
+            /// onto `target` with [`try_apply`](Self::try_apply), returning the
+            /// applied fields.
+            pub fn merge_yaml(
+                target: &mut #ident #ty_generics,
+                document: &str,
+            ) -> Result<Vec<&'static str>, #error_ident>
+            where
+                Self: ::serde::de::DeserializeOwned,
+            {
+                let patch = Self::from_yaml_str(document).map_err(|err| #error_ident::InvalidDocument {
+                    message: err.to_string(),
+                })?;
+                let applied = patch.set_fields();
+                patch.try_apply(target)?;
+                Ok(applied)
+            }
+        });
+    }
+
+    if cx.attrs.integrates("serde_qs") {
+        loaders.push(quote! {
+            /// Parses a query string with nested bracketed keys, such as
+            /// `name=Ada&address[city]=Paris`, into a patch; missing keys are
+            /// unset, and `nested` patches are only set when one of their keys
+            /// is present.
+            pub fn from_query_str(query: &str) -> Result<Self, ::serde_qs::Error>
+            where
+                Self: ::serde::de::DeserializeOwned,
+            {
+                ::serde_qs::from_str(query)
+            }
+        });
+    }
+
+    if loaders.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            #( #loaders )*
+        }
+    }
+}
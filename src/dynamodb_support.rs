@@ -0,0 +1,154 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container, Field};
+
+/// Generates `to_dynamo_update`, which renders the patch as a DynamoDB update
+/// expression along with its attribute names and values. Only emitted with
+/// `#[optionalize(dynamodb)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("dynamodb") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Immutable fields are never written, and secrets never leave the process
+    // through the patch
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable && !field.is_secret())
+        .collect();
+    let bounds = inner_bounds(
+        fields.iter().copied().filter(|field| field.nested_ty.is_none()),
+        quote!(::serde::Serialize),
+    );
+
+    let actions = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { self.#ident.__dynamo_update(path, placeholder, update)?; };
+        }
+
+        let named = quote! {
+            let name = format!("#{}", #name);
+            update.2.insert(name.clone(), #name.to_string());
+            let path = format!("{}{}", path, name);
+        };
+        let placeholder = quote! { format!("{}{}", placeholder, #name) };
+        // Nested patches write their own fields through document paths
+        if field.nested_ty.is_some() {
+            return quote! {
+                if let Some(patch) = &self.#ident {
+                    #named
+                    patch.__dynamo_update(&format!("{}.", path), &format!("{}_", #placeholder), update)?;
+                }
+            };
+        }
+        let set = quote! {
+            #named
+            let placeholder = #placeholder;
+            update.0.push(format!("{} = :{}", path, placeholder));
+            update.3.insert(format!(":{}", placeholder), ::serde_json::to_value(value)?);
+        };
+        if field.double_option {
+            return quote! {
+                match &self.#ident {
+                    Some(Some(value)) => {
+                        #set
+                    }
+                    Some(None) => {
+                        #named
+                        update.1.push(path);
+                    }
+                    None => {}
+                }
+            };
+        }
+        if let Some(is_unset) = &field.option_like {
+            return quote! {
+                if !self.#ident.#is_unset() {
+                    let value = &self.#ident;
+                    #set
+                }
+            };
+        }
+        quote! {
+            if let Some(value) = &self.#ident {
+                #set
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// The DynamoDB `UpdateExpression` writing the set fields, with its
+            /// `ExpressionAttributeNames` and `ExpressionAttributeValues`, e.g.
+            /// `SET #name = :name, #address.#city = :address_city REMOVE #bio`.
+            ///
+            /// Attribute names are always aliased, so reserved words are safe.
+            /// `nested` patches only write the attributes they set, and cleared
+            /// `double_option` fields are removed. Values are JSON, to be
+            /// converted into `AttributeValue`s, e.g. with `serde_dynamo`. A patch
+            /// that sets no fields renders an empty expression, which DynamoDB
+            /// rejects; check `set_fields` first.
+            #[allow(clippy::type_complexity)]
+            pub fn to_dynamo_update(
+                &self,
+            ) -> Result<
+                (
+                    String,
+                    ::std::collections::HashMap<String, String>,
+                    ::std::collections::HashMap<String, ::serde_json::Value>,
+                ),
+                ::serde_json::Error,
+            >
+            where
+                #bounds
+            {
+                let mut update = (
+                    Vec::new(),
+                    Vec::new(),
+                    ::std::collections::HashMap::new(),
+                    ::std::collections::HashMap::new(),
+                );
+                self.__dynamo_update("", "", &mut update)?;
+
+                let (sets, removes, names, values) = update;
+                let mut clauses = Vec::new();
+                if !sets.is_empty() {
+                    clauses.push(format!("SET {}", sets.join(", ")));
+                }
+                if !removes.is_empty() {
+                    clauses.push(format!("REMOVE {}", removes.join(", ")));
+                }
+                Ok((clauses.join(" "), names, values))
+            }
+
+            /// Collects the `SET` and `REMOVE` actions, names and values of the
+            /// set fields under the document `path`, used by `to_dynamo_update`.
+            #[doc(hidden)]
+            #[allow(clippy::type_complexity)]
+            pub fn __dynamo_update(
+                &self,
+                path: &str,
+                placeholder: &str,
+                update: &mut (
+                    Vec<String>,
+                    Vec<String>,
+                    ::std::collections::HashMap<String, String>,
+                    ::std::collections::HashMap<String, ::serde_json::Value>,
+                ),
+            ) -> Result<(), ::serde_json::Error>
+            where
+                #bounds
+            {
+                #( #actions )*
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container};
+
+/// Generates `to_es_partial_doc`, which renders the patch as the body of an
+/// Elasticsearch `_update` request. Only emitted with
+/// `#[optionalize(elasticsearch)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("elasticsearch") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let fields = cx.fields.iter().filter(|field| !field.is_secret() && field.nested_ty.is_none());
+    let bounds = inner_bounds(fields, quote!(::serde::Serialize));
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// The body of an Elasticsearch `_update` request writing the set
+            /// fields as a partial document, e.g. `{"doc": {"title": "..."}}`.
+            ///
+            /// Elasticsearch merges the partial document into the stored one, so
+            /// unset fields, and those `nested` patches don't set, keep their
+            /// indexed values.
+            pub fn to_es_partial_doc(&self) -> Result<::serde_json::Value, ::serde_json::Error>
+            where
+                #bounds
+            {
+                let mut body = ::serde_json::Map::new();
+                body.insert("doc".to_string(), ::serde_json::Value::Object(self.to_json_object()?));
+                Ok(::serde_json::Value::Object(body))
+            }
+        }
+    }
+}
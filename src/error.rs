@@ -0,0 +1,122 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Generates the error type returned by the generated checks on the optional struct.
+pub fn expand(cx: &Container) -> TokenStream {
+    let error_ident = cx.error_ident();
+    let doc = format!("Errors reported by the methods generated for [`{}`].", cx.optional_ident);
+
+    quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum #error_ident {
+            /// Some, but not all, fields of an all-or-none group were set.
+            IncompleteGroup {
+                group: &'static str,
+                missing: Vec<&'static str>,
+            },
+            /// The patch tried to set fields marked `#[optionalize(immutable)]`.
+            Immutable {
+                fields: Vec<&'static str>,
+            },
+            /// The patch left required fields unset, so no value could be built
+            /// from it.
+            MissingFields {
+                fields: Vec<&'static str>,
+            },
+            /// The target's version did not match the expected one.
+            VersionConflict {
+                field: &'static str,
+            },
+            /// A value could not be converted to the type of its field.
+            InvalidField {
+                field: &'static str,
+                message: String,
+            },
+            /// The input named a field the struct doesn't have.
+            UnknownField {
+                field: String,
+            },
+            /// The input was not an object of field names to values.
+            ExpectedObject {
+                found: String,
+            },
+            /// A change event was recorded with a schema version this build
+            /// doesn't know.
+            UnsupportedEvent {
+                version: u32,
+            },
+            /// A persisted patch was written with a schema version this build
+            /// can't migrate from.
+            UnsupportedVersion {
+                version: u32,
+            },
+            /// Two merged patches both set these fields.
+            MergeConflict {
+                fields: Vec<&'static str>,
+            },
+            /// A document could not be parsed into a patch.
+            InvalidDocument {
+                message: String,
+            },
+            /// Several checks failed at once, each reported as its own error.
+            Multiple {
+                errors: Vec<Self>,
+            },
+        }
+
+        impl ::std::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                match self {
+                    Self::IncompleteGroup { group, missing } => write!(
+                        f,
+                        "group `{}` is incomplete, missing: {}",
+                        group,
+                        missing.join(", ")
+                    ),
+                    Self::Immutable { fields } => {
+                        write!(f, "immutable fields cannot be changed: {}", fields.join(", "))
+                    }
+                    Self::MissingFields { fields } => write!(f, "missing fields: {}", fields.join(", ")),
+                    Self::VersionConflict { field } => {
+                        write!(f, "version conflict on `{}`", field)
+                    }
+                    Self::InvalidField { field, message } => {
+                        write!(f, "invalid value for `{}`: {}", field, message)
+                    }
+                    Self::UnknownField { field } => write!(f, "unknown field `{}`", field),
+                    Self::ExpectedObject { found } => write!(f, "expected an object, found `{}`", found),
+                    Self::UnsupportedEvent { version } => {
+                        write!(f, "unsupported change event version {}", version)
+                    }
+                    Self::UnsupportedVersion { version } => {
+                        write!(f, "unsupported patch schema version {}", version)
+                    }
+                    Self::MergeConflict { fields } => {
+                        write!(f, "fields set in both patches: {}", fields.join(", "))
+                    }
+                    Self::InvalidDocument { message } => write!(f, "invalid document: {}", message),
+                    Self::Multiple { errors } => {
+                        let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                        write!(f, "{} errors: {}", errors.len(), errors.join("; "))
+                    }
+                }
+            }
+        }
+
+        impl ::std::error::Error for #error_ident {}
+
+        impl #error_ident {
+            /// The individual errors, one per failed check, so every problem can
+            /// be reported at once.
+            pub fn into_errors(self) -> Vec<Self> {
+                match self {
+                    Self::Multiple { errors } => errors,
+                    other => vec![other],
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Result};
+
+use crate::ast::Container;
+
+/// Generates `{Optional}Event`, a serializable change event wrapping a patch,
+/// along with `replay` on the original struct. Only emitted with
+/// `#[optionalize(event)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    let version = match cx.attrs.event {
+        Some(version) => version,
+        None => return Ok(quote! {}),
+    };
+    if !cx.derives_serialize() || !cx.derives_deserialize() {
+        return Err(Error::new_spanned(
+            &cx.ident,
+            "`event` requires `#[optionalize(derive(Serialize, Deserialize))]`",
+        ));
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let event_ident = format_ident!("{}Event", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let turbofish = ty_generics.as_turbofish();
+    let generics = &cx.generics.params;
+
+    let doc = format!(
+        "A change event recording a [`{}`] applied to a [`{}`], for event logs.",
+        optional_ident, ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Serialized with its operation in an `op` tag and the schema version it
+        /// was written with, e.g. `{"op": "patch", "version": 1, "patch": {...}}`.
+        #[derive(::serde::Serialize, ::serde::Deserialize)]
+        #[serde(tag = "op", rename_all = "snake_case")]
+        pub enum #event_ident <#generics> #where_clause {
+            /// The patch was applied with `try_apply`.
+            Patch {
+                version: u32,
+                patch: #optional_ident #ty_generics,
+            },
+        }
+
+        impl #impl_generics #event_ident #ty_generics #where_clause {
+            /// Schema version written into new events.
+            pub const VERSION: u32 = #version;
+
+            /// Replays the event onto `target`. Events written with another
+            /// schema version are rejected with `UnsupportedEvent`.
+            pub fn apply_to(self, target: &mut #ident #ty_generics) -> Result<(), #error_ident> {
+                match self {
+                    Self::Patch { version, patch } => {
+                        if version != Self::VERSION {
+                            return Err(#error_ident::UnsupportedEvent { version });
+                        }
+                        patch.try_apply(target)
+                    }
+                }
+            }
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Wraps the patch in a change event at the current schema version.
+            pub fn into_event(self) -> #event_ident #ty_generics {
+                #event_ident::Patch {
+                    version: #event_ident #turbofish::VERSION,
+                    patch: self,
+                }
+            }
+        }
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Replays `events` in order, stopping at the first one that fails.
+            pub fn replay(
+                &mut self,
+                events: impl IntoIterator<Item = #event_ident #ty_generics>,
+            ) -> Result<(), #error_ident> {
+                for event in events {
+                    event.apply_to(self)?;
+                }
+                Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,112 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Error, Result};
+
+use crate::ast::{inner_bounds, Container};
+
+/// Generates `{Optional}Ffi`, a `#[repr(C)]` twin of the generated struct
+/// storing each field as a value and an `is_set` flag, with conversions both
+/// ways. Only emitted with `#[optionalize(ffi)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    if !cx.attrs.ffi {
+        return Ok(quote! {});
+    }
+
+    // Only a value and a flag cross the boundary, so every field needs a
+    // single plain value
+    if let Some(field) = cx
+        .fields
+        .iter()
+        .find(|field| field.nested_ty.is_some() || field.option_like.is_some() || field.double_option)
+    {
+        return Err(Error::new_spanned(
+            &field.ident,
+            "`ffi` doesn't support `nested`, `flatten`, `double_option` or option-like fields",
+        ));
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let ffi_ident = format_ident!("{}Ffi", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    let idents: Vec<_> = cx.fields.iter().map(|field| &field.ident).collect();
+    let flags: Vec<_> = cx
+        .fields
+        .iter()
+        .map(|field| format_ident!("{}_is_set", field.ident.unraw()))
+        .collect();
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    let fields = cx.fields.iter().zip(&flags).map(|(field, flag)| {
+        let vis = &field.vis;
+        let ident = &field.ident;
+        let ty = field.inner_ty();
+        let value_doc = format!(
+            "The value of `{}`, meaningful only when `{}` is `true`.",
+            field.name(),
+            flag
+        );
+        let flag_doc = format!("Whether the patch sets `{}`.", field.name());
+        quote! {
+            #[doc = #value_doc]
+            #vis #ident: #ty,
+            #[doc = #flag_doc]
+            #vis #flag: bool
+        }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+
+    // Unset fields still need a valid value on the C side
+    let where_with_default = cx.where_clause_with(inner_bounds(&cx.fields, quote!(::std::default::Default)));
+
+    let doc = format!(
+        "A `#[repr(C)]` twin of [`{}`] for C FFI: each field is stored as a value \
+         along with an `is_set` flag rather than as an `Option`, so its layout doesn't \
+         depend on niches.",
+        optional_ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Unset fields hold their type's default. The field types themselves
+        /// must be FFI-safe for the struct to be.
+        #[repr(C)]
+        pub struct #ffi_ident <#generics> #where_clause {
+            #( #fields, )*
+            #( #markers, )*
+        }
+
+        impl #impl_generics ::std::convert::From<#optional_ident #ty_generics> for #ffi_ident #ty_generics
+        #where_with_default
+        {
+            /// Flattens the patch, storing the default for unset fields.
+            fn from(patch: #optional_ident #ty_generics) -> Self {
+                Self {
+                    #(
+                        #flags: patch.#idents.is_some(),
+                        #idents: patch.#idents.unwrap_or_default(),
+                    )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics ::std::convert::From<#ffi_ident #ty_generics> for #optional_ident #ty_generics #where_clause {
+            /// Rebuilds the patch, ignoring the values of unset fields.
+            fn from(ffi: #ffi_ident #ty_generics) -> Self {
+                Self {
+                    #( #idents: if ffi.#flags { Some(ffi.#idents) } else { None }, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+    })
+}
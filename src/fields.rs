@@ -0,0 +1,293 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use syn::Type;
+
+use crate::ast::{first_type_argument, type_ends_with, Container, Field};
+use crate::attr::CollectionMerge;
+
+/// Generates helpers that work on the patch by field name.
+pub fn expand(cx: &Container) -> TokenStream {
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let set_fields = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let set = field.is_set();
+        // Flattened fields are listed by their own names
+        if field.attrs.flatten {
+            quote! { fields.extend(self.#ident.set_fields()); }
+        } else {
+            quote! {
+                if #set {
+                    fields.push(#name);
+                }
+            }
+        }
+    });
+
+    let set = cx.fields.iter().map(Field::is_set);
+
+    let missing = cx.fields.iter().filter_map(|field| {
+        let name = field.name();
+        let missing = missing(field)?;
+        Some(quote! {
+            if #missing {
+                fields.push(#name);
+            }
+        })
+    });
+
+    let retain = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let unset = field.unset();
+        if field.attrs.flatten {
+            quote! { self.#ident.retain_allowed(allowed); }
+        } else {
+            quote! {
+                if !allowed.contains(#name) {
+                    self.#ident = #unset;
+                }
+            }
+        }
+    });
+
+    let mask = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let unset = field.unset();
+        if field.attrs.flatten {
+            return quote! { self.#ident.retain_masked(mask); };
+        }
+        // Nested patches keep the paths below them as well
+        let unselected = if field.nested_ty.is_some() {
+            let prefix = format!("{}.", name);
+            let retain_masked = field.twin_fn("retain_masked");
+            let is_empty = field.twin_fn("is_empty");
+            quote! {
+                let below: Vec<&str> = mask.iter().filter_map(|path| path.strip_prefix(#prefix)).collect();
+                match &mut self.#ident {
+                    Some(nested) if !below.is_empty() => {
+                        #retain_masked(nested, &below);
+                        if #is_empty(nested) {
+                            self.#ident = None;
+                        }
+                    }
+                    _ => self.#ident = None,
+                }
+            }
+        } else {
+            quote! { self.#ident = #unset; }
+        };
+        quote! {
+            if !mask.contains(&#name) {
+                #unselected
+            }
+        }
+    });
+
+    // Fields without `roles` are open to every role
+    let allowed = cx.fields.iter().map(|field| {
+        let name = field.name();
+        if field.attrs.flatten {
+            let twin = field.inner_ty();
+            return quote! { fields.extend(<#twin>::allowed_fields(role)); };
+        }
+        match &field.attrs.roles {
+            Some(roles) => quote! {
+                if [#( #roles ),*].contains(&role) {
+                    fields.push(#name);
+                }
+            },
+            None => quote! { fields.push(#name); },
+        }
+    });
+    let role_retain: Vec<_> = cx
+        .fields
+        .iter()
+        .filter_map(|field| {
+            let ident = &field.ident;
+            let unset = field.unset();
+            if field.attrs.flatten {
+                return Some(quote! { self.#ident.retain_for_role(role); });
+            }
+            let roles = field.attrs.roles.as_ref()?;
+            Some(quote! {
+                if ![#( #roles ),*].contains(&role) {
+                    self.#ident = #unset;
+                }
+            })
+        })
+        .collect();
+    // Structs without restricted fields ignore the role
+    let unused_role = if role_retain.is_empty() {
+        quote! { let _ = role; }
+    } else {
+        quote! {}
+    };
+
+    // Flattened fields count as their twin's fields
+    let own_count = cx.fields.iter().filter(|field| !field.attrs.flatten).count();
+    let twins = cx
+        .fields
+        .iter()
+        .filter(|field| field.attrs.flatten)
+        .map(|field| field.inner_ty());
+
+    let getters = if cx.attrs.getters {
+        let getters = cx.fields.iter().filter_map(getter);
+        quote! {
+            impl #impl_generics #optional_ident #ty_generics #where_clause {
+                #( #getters )*
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #getters
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Number of fields a patch can set, counting those of flattened
+            /// patches, used by `summary`.
+            #[doc(hidden)]
+            pub const __FIELD_COUNT: usize = #own_count #( + <#twins>::__FIELD_COUNT )*;
+
+            /// Names of the fields that are set, in declaration order.
+            pub fn set_fields(&self) -> Vec<&'static str> {
+                let mut fields = Vec::new();
+                #( #set_fields )*
+                fields
+            }
+
+            /// Names of the fields a value can't be built without: required
+            /// fields left unset, and `nested` or flattened patches that are
+            /// themselves missing fields, in declaration order. `Option` and
+            /// option-like fields are never missing.
+            pub fn missing_fields(&self) -> Vec<&'static str> {
+                #[allow(unused_mut)]
+                let mut fields = Vec::new();
+                #( #missing )*
+                fields
+            }
+
+            /// Whether no field is set, without collecting their names like
+            /// [`set_fields`](Self::set_fields).
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                true #( && !(#set) )*
+            }
+
+            /// A one-line description of the patch naming the set fields, without
+            /// their values, e.g. `name, email (2 of 7 fields)`, for logs and
+            /// activity feeds.
+            pub fn summary(&self) -> String {
+                let fields = self.set_fields();
+                let total = Self::__FIELD_COUNT;
+                let noun = if total == 1 { "field" } else { "fields" };
+                if fields.is_empty() {
+                    format!("no changes (0 of {} {})", total, noun)
+                } else {
+                    format!("{} ({} of {} {})", fields.join(", "), fields.len(), total, noun)
+                }
+            }
+
+            /// Unsets every field whose name is not in `allowed`, so a caller can
+            /// only change the fields they are permitted to.
+            pub fn retain_allowed(&mut self, allowed: &::std::collections::HashSet<&str>) {
+                #( #retain )*
+            }
+
+            /// Unsets every field not selected by the field mask `mask`, whose
+            /// paths name fields as in a protobuf `FieldMask`, e.g. `name` or
+            /// `address.city` for a field of a `nested` patch. Naming a `nested`
+            /// field keeps all of its patch; paths naming no field select nothing.
+            pub fn retain_masked(&mut self, mask: &[&str]) {
+                #( #mask )*
+            }
+
+            /// Names of the fields `role` may change, in declaration order:
+            /// those whose `roles` list it, and those without `roles`.
+            pub fn allowed_fields(role: &str) -> Vec<&'static str> {
+                #unused_role
+                let mut fields = Vec::new();
+                #( #allowed )*
+                fields
+            }
+
+            /// Unsets every field `role` may not change, so column-level
+            /// permissions declared with `roles` are enforced on a patch before
+            /// it is applied.
+            pub fn retain_for_role(&mut self, role: &str) {
+                #unused_role
+                #( #role_retain )*
+            }
+        }
+    }
+}
+
+/// The getter borrowing the value of a `String`, `Vec` or `Box` field as
+/// `Option<&str>`, `Option<&[T]>` or `Option<&T>`, if it has one.
+fn getter(field: &Field) -> Option<TokenStream> {
+    if field.attrs.no_helpers
+        || field.attrs.flatten
+        || field.nested_ty.is_some()
+        || field.option_like.is_some()
+        || field.double_option
+    {
+        return None;
+    }
+    let ty = field.inner_ty();
+    let target: Type = if type_ends_with(ty, "String") {
+        syn::parse_quote!(str)
+    } else if type_ends_with(ty, "Vec") {
+        let element = first_type_argument(ty)?;
+        syn::parse_quote!([#element])
+    } else if type_ends_with(ty, "Box") {
+        first_type_argument(ty)?.clone()
+    } else {
+        return None;
+    };
+
+    let ident = &field.ident;
+    let doc = format!("The value of `{}`, if it's set, borrowed without cloning.", field.name());
+    Some(quote! {
+        #[doc = #doc]
+        pub fn #ident(&self) -> Option<&#target> {
+            self.#ident.as_deref()
+        }
+    })
+}
+
+/// Expression telling whether `field` keeps a value from being built from the
+/// patch, or `None` if it never does.
+fn missing(field: &Field) -> Option<TokenStream> {
+    let ident = &field.ident;
+    if field.attrs.flatten {
+        return Some(quote! { !self.#ident.missing_fields().is_empty() });
+    }
+    if field.option_like.is_some() || field.double_option {
+        return None;
+    }
+    if let Some(CollectionMerge::Entries(Some(_))) = &field.attrs.collection {
+        return Some(quote! {
+            self.#ident
+                .as_ref()
+                .map_or(true, |patches| patches.values().any(|patch| !patch.missing_fields().is_empty()))
+        });
+    }
+    let missing_fields = field.nested_ty.as_ref().map(|_| field.twin_fn("missing_fields"));
+    match (missing_fields, field.is_option()) {
+        (Some(missing_fields), true) => Some(quote! {
+            self.#ident.as_ref().is_some_and(|patch| !#missing_fields(patch).is_empty())
+        }),
+        (Some(missing_fields), false) => Some(quote! {
+            self.#ident.as_ref().map_or(true, |patch| !#missing_fields(patch).is_empty())
+        }),
+        (None, true) => None,
+        (None, false) => Some(quote! { self.#ident.is_none() }),
+    }
+}
@@ -0,0 +1,148 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Result};
+
+use crate::ast::{Container, Field};
+
+/// Generates `{Name}Filter`, whose fields hold an `optionalize::Cmp` against
+/// the original struct's, with `matches` to evaluate it in memory and
+/// `to_where_clause` to render it as SQL. Only emitted with
+/// `#[optionalize(filter)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    if !cx.attrs.filter {
+        return Ok(quote! {});
+    }
+    let runtime = match &cx.runtime {
+        Some(runtime) => runtime,
+        None => {
+            return Err(Error::new_spanned(
+                &cx.ident,
+                "`filter` requires depending on the `optionalize` crate",
+            ));
+        }
+    };
+
+    let ident = &cx.ident;
+    let filter_ident = format_ident!("{}Filter", ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+    let debug = if cx.derives().iter().any(|path| path.is_ident("Debug")) {
+        quote! { #[derive(Debug)] }
+    } else {
+        quote! {}
+    };
+
+    // Comparisons are made against plain values: secrets can't be compared,
+    // option-like values have no single one, and nested structs aren't one
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.is_secret() && field.option_like.is_none() && field.nested_ty.is_none())
+        .collect();
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    let filter_fields = fields.iter().map(|field| {
+        let vis = &field.vis;
+        let field_ident = &field.ident;
+        let ty = field.value_ty();
+        let doc = format!("Comparison the `{}` field must satisfy, if any.", field.name());
+        quote! {
+            #[doc = #doc]
+            #vis #field_ident: ::std::option::Option<#runtime::Cmp<#ty>>
+        }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+
+    let checks = fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        let value = if field.option {
+            quote! { value.#field_ident.as_ref() }
+        } else {
+            quote! { ::std::option::Option::Some(&value.#field_ident) }
+        };
+        quote! {
+            if let Some(cmp) = &self.#field_ident {
+                if !#value.map_or(false, |value| cmp.matches(value)) {
+                    return false;
+                }
+            }
+        }
+    });
+    let conditions = fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        let name = field.name();
+        quote! {
+            if let Some(cmp) = &self.#field_ident {
+                conditions.push(cmp.to_sql(#name, &placeholder, &mut values));
+            }
+        }
+    });
+
+    let tys: Vec<_> = fields.iter().map(|field| field.value_ty()).collect();
+
+    let doc = format!(
+        "A query-by-example filter of [`{}`]: every comparison that is set must hold for a value to match.",
+        ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Fields that were `Option`s compare against their value, and `None`
+        /// matches no comparison.
+        #debug
+        #[derive(Clone, PartialEq)]
+        pub struct #filter_ident <#generics> #where_clause {
+            #( #filter_fields, )*
+            #( #markers, )*
+        }
+
+        impl #impl_generics ::std::default::Default for #filter_ident #ty_generics #where_clause {
+            /// A filter that matches every value.
+            fn default() -> Self {
+                Self {
+                    #( #idents: ::std::option::Option::None, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics #filter_ident #ty_generics #where_clause {
+            /// Whether `value` satisfies every comparison that is set.
+            pub fn matches(&self, value: &#ident #ty_generics) -> bool
+            where
+                #( for<'__optionalize> #tys: ::std::cmp::PartialOrd + ::std::fmt::Display, )*
+            {
+                #( #checks )*
+                true
+            }
+
+            /// Renders the comparisons that are set as the body of a `WHERE`
+            /// clause joined with `AND`, along with the values to bind in that
+            /// order. `placeholder` renders placeholders as for `to_set_clause`.
+            /// A filter without comparisons renders `1 = 1`.
+            pub fn to_where_clause<V>(
+                &self,
+                placeholder: impl Fn(usize, &str) -> String,
+            ) -> (String, ::std::vec::Vec<V>)
+            where
+                #( for<'__optionalize> #tys: ::std::clone::Clone + ::std::convert::Into<V>, )*
+                String: ::std::convert::Into<V>,
+            {
+                let mut conditions: ::std::vec::Vec<String> = ::std::vec::Vec::new();
+                let mut values = ::std::vec::Vec::new();
+                #( #conditions )*
+                if conditions.is_empty() {
+                    return ("1 = 1".to_string(), values);
+                }
+                (conditions.join(" AND "), values)
+            }
+        }
+    })
+}
@@ -0,0 +1,227 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{type_ends_with, Container, Field};
+
+/// Generates `set_from_str`, `from_flat_map` and `parse_kv`, along with a
+/// `FromStr` impl, which fill the optional struct from string values under
+/// dotted keys such as `server.port`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("flat") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let empty = cx.empty();
+
+    // Option-like and double `Option` values have no single string form, and
+    // the keys of a type parameter's twin aren't known
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| field.option_like.is_none() && !field.double_option && field.twin_param.is_none())
+        .collect();
+    let parsed = fields
+        .iter()
+        .filter(|field| field.nested_ty.is_none())
+        .map(|field| field.inner_ty());
+    let bounds = quote! {
+        #(
+            for<'__optionalize> #parsed: ::std::str::FromStr,
+            for<'__optionalize> <#parsed as ::std::str::FromStr>::Err: ::std::fmt::Display,
+        )*
+    };
+    // Every value is parsed with `FromStr`, which `bound` can't lift, so the
+    // predicates are written out rather than replaceable
+    let own_predicates = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+    let from_str_where_clause = quote! { where #( #own_predicates, )* #bounds };
+
+    let arms = fields.iter().filter(|field| !field.attrs.flatten).map(|field| arm(field, &error_ident));
+    // Keys that aren't the parent's own fields may belong to a flattened patch
+    let flattened = fields.iter().filter_map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let flatten_error = field.flatten_error()?;
+        Some(quote! {
+            match self.#ident.set_from_str(key, value) {
+                Err(#flatten_error::UnknownField { .. }) => {}
+                Err(err) => {
+                    return Err(#error_ident::InvalidField {
+                        field: #name,
+                        message: err.to_string(),
+                    });
+                }
+                Ok(()) => return Ok(()),
+            }
+        })
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Sets the field under `key` by parsing `value` with `FromStr`. Keys
+            /// of nested fields are dotted, such as `server.port`, and set the
+            /// nested patch, creating it if needed.
+            pub fn set_from_str(&mut self, key: &str, value: &str) -> Result<(), #error_ident>
+            where
+                #bounds
+            {
+                let (head, rest) = match key.split_once('.') {
+                    Some((head, rest)) => (head, Some(rest)),
+                    None => (key, None),
+                };
+                match (head, rest) {
+                    #( #arms )*
+                    _ => {
+                        #( #flattened )*
+                        return Err(#error_ident::UnknownField {
+                            field: key.to_string(),
+                        });
+                    }
+                }
+                Ok(())
+            }
+
+            /// Builds a patch from flat key/value pairs with dotted keys, such as
+            /// `server.port=8080` from environment variables or command-line
+            /// overrides.
+            pub fn from_flat_map<I, K, V>(entries: I) -> Result<Self, #error_ident>
+            where
+                I: IntoIterator<Item = (K, V)>,
+                K: AsRef<str>,
+                V: AsRef<str>,
+                #bounds
+            {
+                let mut patch = #empty;
+                for (key, value) in entries {
+                    patch.set_from_str(key.as_ref(), value.as_ref())?;
+                }
+                Ok(patch)
+            }
+
+            /// Parses comma-separated `key=value` pairs, such as the
+            /// `name=foo,server.port=8080` of a `--set` command-line argument,
+            /// with [`set_from_str`](Self::set_from_str). Whitespace around keys
+            /// and values is trimmed.
+            ///
+            /// Values holding commas are double-quoted, as in `tags="a,b"`, with
+            /// `\"` and `\\` escaping a quote and a backslash inside the quotes.
+            /// Unterminated quotes and quotes inside unquoted values are
+            /// reported as `InvalidDocument`.
+            pub fn parse_kv(input: &str) -> Result<Self, #error_ident>
+            where
+                #bounds
+            {
+                // Commas inside quotes belong to the value
+                let mut pairs = vec![String::new()];
+                let mut quoted = false;
+                let mut chars = input.chars();
+                while let Some(c) = chars.next() {
+                    let pair = pairs.last_mut().expect("a pair");
+                    match c {
+                        ',' if !quoted => pairs.push(String::new()),
+                        '"' => {
+                            quoted = !quoted;
+                            pair.push(c);
+                        }
+                        '\\' if quoted => {
+                            pair.push(c);
+                            pair.extend(chars.next());
+                        }
+                        _ => pair.push(c),
+                    }
+                }
+                if quoted {
+                    return Err(#error_ident::InvalidDocument {
+                        message: format!("unterminated quote in `{}`", input.trim()),
+                    });
+                }
+
+                let mut patch = #empty;
+                for pair in pairs.iter().filter(|pair| !pair.trim().is_empty()) {
+                    let (key, value) = pair.split_once('=').ok_or_else(|| #error_ident::InvalidDocument {
+                        message: format!("expected `key=value`, found `{}`", pair.trim()),
+                    })?;
+                    let (key, value) = (key.trim(), value.trim());
+                    let value = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+                        Some(inner) => {
+                            let mut unquoted = String::with_capacity(inner.len());
+                            let mut chars = inner.chars();
+                            while let Some(c) = chars.next() {
+                                match c {
+                                    '\\' => unquoted.extend(chars.next()),
+                                    _ => unquoted.push(c),
+                                }
+                            }
+                            unquoted
+                        }
+                        None if value.contains('"') => {
+                            return Err(#error_ident::InvalidDocument {
+                                message: format!("quote the whole value of `{}`, found `{}`", key, value),
+                            });
+                        }
+                        None => value.to_string(),
+                    };
+                    patch.set_from_str(key, &value)?;
+                }
+                Ok(patch)
+            }
+        }
+
+        impl #impl_generics ::std::str::FromStr for #optional_ident #ty_generics #from_str_where_clause {
+            type Err = #error_ident;
+
+            /// Parses comma-separated `key=value` pairs with `parse_kv`.
+            fn from_str(input: &str) -> Result<Self, Self::Err> {
+                Self::parse_kv(input)
+            }
+        }
+    }
+}
+
+/// The match arm setting a single field from `head` and the `rest` of a key.
+fn arm(field: &Field, error_ident: &syn::Ident) -> TokenStream {
+    let ident = &field.ident;
+    let name = field.name();
+
+    if field.nested_ty.is_some() {
+        let twin_error = field.twin_error();
+        let nested_ty = field.inner_ty();
+        let twin = if type_ends_with(nested_ty, "Box") {
+            let twin = crate::ast::pointer_inner(nested_ty).unwrap_or(nested_ty);
+            quote! { ::std::boxed::Box::new(<#twin>::empty()) }
+        } else {
+            quote! { <#nested_ty>::empty() }
+        };
+        return quote! {
+            (#name, Some(rest)) => {
+                let nested = self.#ident.get_or_insert_with(|| #twin);
+                match nested.set_from_str(rest, value) {
+                    Err(#twin_error::UnknownField { field }) => {
+                        return Err(#error_ident::UnknownField {
+                            field: format!("{}.{}", #name, field),
+                        });
+                    }
+                    Err(err) => {
+                        return Err(#error_ident::InvalidField {
+                            field: #name,
+                            message: err.to_string(),
+                        });
+                    }
+                    Ok(()) => {}
+                }
+            }
+        };
+    }
+
+    quote! {
+        (#name, None) => {
+            let value = value.parse().map_err(|err| #error_ident::InvalidField {
+                field: #name,
+                message: ::std::string::ToString::to_string(&err),
+            })?;
+            self.#ident = Some(value);
+        }
+    }
+}
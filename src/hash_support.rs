@@ -0,0 +1,48 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container};
+
+/// Generates `content_hash`, a SHA-256 digest of the set fields and their
+/// values. Only emitted with `#[optionalize(sha2)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("sha2") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let fields = cx.fields.iter().filter(|field| !field.is_secret() && field.nested_ty.is_none());
+    let bounds = inner_bounds(fields, quote!(::serde::Serialize));
+    let domain = optional_ident.to_string();
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// A hex-encoded SHA-256 digest of the set fields and their values,
+            /// identical for equal patches across runs and processes, e.g. to
+            /// detect a duplicate PATCH request from its idempotency key.
+            ///
+            /// The digest covers the canonical JSON of
+            /// [`to_btree_map`](Self::to_btree_map), prefixed with the name of
+            /// the patch type, so patches of different structs don't collide.
+            /// Secret fields are left out, like everywhere the patch leaves the
+            /// process.
+            pub fn content_hash(&self) -> Result<String, ::serde_json::Error>
+            where
+                #bounds
+            {
+                use ::sha2::Digest;
+
+                let mut hasher = ::sha2::Sha256::new();
+                hasher.update(#domain.as_bytes());
+                hasher.update(b"\0");
+                hasher.update(::serde_json::to_vec(&self.to_btree_map()?)?);
+                Ok(hasher
+                    .finalize()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect())
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::Container;
+use crate::convert;
+
+/// Generates `{Optional}History`, an undo/redo stack of applied patches. Only
+/// emitted with `#[optionalize(history)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("history") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let history_ident = format_ident!("{}History", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let where_with_bounds = cx.where_clause_with(convert::bounds(cx));
+    let generics = &cx.generics.params;
+
+    let doc = format!(
+        "Undo/redo history of the [`{}`] patches applied to a [`{}`].",
+        optional_ident, ident
+    );
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// Every applied patch is recorded with its inverse, so `undo` and `redo`
+        /// walk back and forth through the changes.
+        pub struct #history_ident <#generics> #where_clause {
+            undo: Vec<#optional_ident #ty_generics>,
+            redo: Vec<#optional_ident #ty_generics>,
+        }
+
+        impl #impl_generics #history_ident #ty_generics #where_with_bounds {
+            /// Creates an empty history.
+            pub fn new() -> Self {
+                Self {
+                    undo: Vec::new(),
+                    redo: Vec::new(),
+                }
+            }
+
+            /// Applies `patch` onto `target` and records it, discarding any undone
+            /// patches.
+            pub fn apply(&mut self, target: &mut #ident #ty_generics, patch: #optional_ident #ty_generics) {
+                self.undo.push(patch.apply_with_inverse(target));
+                self.redo.clear();
+            }
+
+            /// Reverts the last applied patch. Returns `false` if there was none.
+            pub fn undo(&mut self, target: &mut #ident #ty_generics) -> bool {
+                match self.undo.pop() {
+                    Some(inverse) => {
+                        self.redo.push(inverse.apply_with_inverse(target));
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            /// Applies the last undone patch again. Returns `false` if there was none.
+            pub fn redo(&mut self, target: &mut #ident #ty_generics) -> bool {
+                match self.redo.pop() {
+                    Some(patch) => {
+                        self.undo.push(patch.apply_with_inverse(target));
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            /// Whether there is a patch to undo.
+            pub fn can_undo(&self) -> bool {
+                !self.undo.is_empty()
+            }
+
+            /// Whether there is a patch to redo.
+            pub fn can_redo(&self) -> bool {
+                !self.redo.is_empty()
+            }
+        }
+
+        impl #impl_generics ::std::default::Default for #history_ident #ty_generics #where_with_bounds {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    }
+}
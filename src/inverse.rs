@@ -0,0 +1,163 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{type_ends_with, Container, Field};
+use crate::attr::CollectionMerge;
+use crate::{apply, collection, convert};
+
+/// Generates `apply_with_inverse`, which applies the patch and returns the patch
+/// that undoes it.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("apply") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let bounds = convert::bounds(cx);
+
+    let empty = cx.empty();
+    let assignments = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable)
+        .map(assignment);
+
+    let (mutability, before) = match &cx.attrs.before_apply {
+        Some(before) => (quote!(mut), quote! { #before(&mut self, target); }),
+        None => (quote!(), quote!()),
+    };
+    let after = cx.attrs.after_apply.as_ref().map(|after| quote! { #after(target); });
+    let trace = apply::trace(cx);
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Like [`apply_to`](Self::apply_to), but returns the inverse patch,
+            /// which sets every changed field back to its previous value.
+            ///
+            /// Only `double_option` fields can be unset by a patch, so any other
+            /// `Option` field that was `None` before is not restored by the inverse,
+            /// including a `nested` value created as by `apply_to`.
+            /// The inverse covers changes the `before_apply` hook makes to the
+            /// patch, but not those the `after_apply` hook makes to `target`.
+            #[must_use]
+            pub fn apply_with_inverse(#mutability self, target: &mut #ident #ty_generics) -> Self
+            where
+                #bounds
+            {
+                #before
+                #trace
+                let mut inverse = #empty;
+                #( #assignments )*
+                #after
+                inverse
+            }
+        }
+    }
+}
+
+/// Generates the statement writing a single set field onto `target` and its
+/// previous value into `inverse`.
+fn assignment(field: &Field) -> TokenStream {
+    let field_name = &field.ident;
+
+    if field.attrs.flatten {
+        return quote! {
+            inverse.#field_name = self.#field_name.apply_with_inverse(&mut target.#field_name);
+        };
+    }
+
+    // Nested patches are inverted in place too
+    if field.nested_ty.is_some() {
+        let boxed = type_ends_with(field.inner_ty(), "Box");
+        let patch = if boxed { quote!((*patch)) } else { quote!(patch) };
+        let apply_with_inverse = field.twin_fn("apply_with_inverse");
+        let wrap = |inverse: TokenStream| {
+            if boxed {
+                quote! { ::std::boxed::Box::new(#inverse) }
+            } else {
+                inverse
+            }
+        };
+        return if field.is_option() {
+            let inner = if boxed { quote!(&mut **inner) } else { quote!(inner) };
+            let inverse = wrap(quote! { #apply_with_inverse(#patch, #inner) });
+            // As with `apply_to`, a missing value is created, which the
+            // inverse leaves in place like any other `Option` it can't unset
+            let create = apply::create_nested(field);
+            quote! {
+                if let Some(patch) = self.#field_name {
+                    match &mut target.#field_name {
+                        Some(inner) => inverse.#field_name = Some(#inverse),
+                        None => #create,
+                    }
+                }
+            }
+        } else {
+            let inner = if boxed {
+                quote!(&mut *target.#field_name)
+            } else {
+                quote!(&mut target.#field_name)
+            };
+            let inverse = wrap(quote! { #apply_with_inverse(#patch, #inner) });
+            quote! {
+                if let Some(patch) = self.#field_name {
+                    inverse.#field_name = Some(#inverse);
+                }
+            }
+        };
+    }
+
+    if field.option_like.is_some() {
+        let is_set = field.is_set();
+        let value = match &field.attrs.sanitize_with {
+            Some(sanitize) => quote! { #sanitize(self.#field_name) },
+            None => quote! { self.#field_name },
+        };
+        return quote! {
+            if #is_set {
+                inverse.#field_name = ::std::mem::replace(&mut target.#field_name, #value);
+            }
+        };
+    }
+
+    let value = match &field.attrs.sanitize_with {
+        Some(sanitize) => quote! { #sanitize(value) },
+        None => quote! { value },
+    };
+
+    // Only replaced elements can be restored, added ones stay
+    if let Some(CollectionMerge::ByKey(_) | CollectionMerge::Entries(_)) = field.attrs.collection {
+        let ty = field.inner_ty();
+        let merge = collection::merge_into(field, quote!(target.#field_name), value, Some(quote!(replaced)));
+        return quote! {
+            if let Some(value) = self.#field_name {
+                let mut replaced: #ty = ::std::default::Default::default();
+                #merge
+                if !replaced.is_empty() {
+                    inverse.#field_name = Some(replaced);
+                }
+            }
+        };
+    }
+    if field.attrs.collection.is_some() {
+        let merge = collection::merge_into(field, quote!(target.#field_name), value, None);
+        return quote! {
+            if let Some(value) = self.#field_name {
+                #merge
+            }
+        };
+    }
+
+    let value = field.to_source(value);
+    let previous = field.to_optional(quote!(previous));
+
+    quote! {
+        if let Some(value) = self.#field_name {
+            let mut previous = #value;
+            ::std::mem::swap(&mut target.#field_name, &mut previous);
+            inverse.#field_name = #previous;
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use proc_macro2::TokenStream;
+use syn::Path;
+use quote::{format_ident, quote};
+
+use crate::ast::{inner_bounds, pointer_inner, type_ends_with, Container, Field};
+
+/// Generates conversions from loosely typed `serde_json` values into the
+/// optional struct. Only emitted with `#[optionalize(serde_json)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("serde_json") || !cx.emits("serde") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let bounds = inner_bounds(&cx.fields, quote!(::serde::de::DeserializeOwned));
+    let where_with_bounds = cx.where_clause_with(bounds.clone());
+
+    let empty = cx.empty();
+
+    // Secrets never leave the process through the patch
+    let serialized: Vec<&Field> = cx.fields.iter().filter(|field| !field.is_secret()).collect();
+    let serialize_bounds = inner_bounds(
+        serialized.iter().copied().filter(|field| field.nested_ty.is_none()),
+        quote!(::serde::Serialize),
+    );
+    let entries = serialized.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { object.extend(self.#ident.to_json_object()?); };
+        }
+        if field.nested_ty.is_some() {
+            return quote! {
+                if let Some(patch) = &self.#ident {
+                    object.insert(#name.to_string(), ::serde_json::Value::Object(patch.to_json_object()?));
+                }
+            };
+        }
+        if let Some(is_unset) = &field.option_like {
+            return quote! {
+                if !self.#ident.#is_unset() {
+                    object.insert(#name.to_string(), ::serde_json::to_value(&self.#ident)?);
+                }
+            };
+        }
+        quote! {
+            if let Some(value) = &self.#ident {
+                object.insert(#name.to_string(), ::serde_json::to_value(value)?);
+            }
+        }
+    });
+
+    // Audit rows are usually readable by more people than the data itself
+    let audited: Vec<&Field> = cx.fields.iter().filter(|field| !field.is_sensitive()).collect();
+    let audited_tys = audited.iter().map(|field| &field.ty);
+    let audit_entries = audited.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        quote! {
+            let old = ::serde_json::to_value(&before.#ident)?;
+            let new = ::serde_json::to_value(&after.#ident)?;
+            if old != new {
+                document.insert(#name.to_string(), ::serde_json::json!({ "old": old, "new": new }));
+            }
+        }
+    });
+
+    // Keys that aren't the parent's own fields may belong to a flattened patch
+    let flattened = cx.fields.iter().filter_map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let flatten_error = field.flatten_error()?;
+        Some(quote! {
+            match self.#ident.set_from_json(field, value.clone()) {
+                Err(#flatten_error::UnknownField { .. }) => {}
+                Err(err) => {
+                    return Err(#error_ident::InvalidField {
+                        field: #name,
+                        message: err.to_string(),
+                    });
+                }
+                Ok(()) => return Ok(()),
+            }
+        })
+    });
+    let arms = cx.fields.iter().filter(|field| !field.attrs.flatten).map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        quote! {
+            #name => {
+                self.#ident = ::serde_json::from_value(value).map_err(|err| {
+                    #error_ident::InvalidField {
+                        field: #name,
+                        message: err.to_string(),
+                    }
+                })?;
+            }
+        }
+    });
+
+    // Nested objects are read leniently too, through a trait so the twin's
+    // bounds are only checked where the parent's are
+    let lenient_ident = format_ident!("{}Lenient", optional_ident);
+    let lenient: Vec<(&Field, Path)> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.flatten && field.attrs.collection.is_none())
+        .filter_map(|field| Some((field, field.twin_item("Lenient")?)))
+        .collect();
+    // Recursive types read themselves, with the bounds already in scope
+    let lenient_bounds: Vec<TokenStream> = lenient
+        .iter()
+        .filter(|(field, _)| !field.is_recursive(cx))
+        .map(|(field, lenient)| {
+            let nested_ty = field.nested_ty.as_ref().expect("nested field");
+            let twin = pointer_inner(nested_ty).unwrap_or(nested_ty);
+            quote! { for<'__optionalize> #twin: #lenient, }
+        })
+        .collect();
+    let own: Vec<_> = cx
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates)
+        .collect();
+    let lenient_arms = lenient.iter().map(|(field, lenient)| {
+        let ident = &field.ident;
+        let name = field.name();
+        let nested_ty = field.nested_ty.as_ref().expect("nested field");
+        let twin = pointer_inner(nested_ty).unwrap_or(nested_ty);
+        let nested = if type_ends_with(nested_ty, "Box") {
+            quote! { ::std::boxed::Box::new(nested) }
+        } else {
+            quote! { nested }
+        };
+        quote! {
+            #name if value.is_object() => {
+                let (nested, messages) = <#twin as #lenient>::__from_json_lenient(value);
+                patch.#ident = Some(#nested);
+                errors.extend(messages.into_iter().map(|message| #error_ident::InvalidField {
+                    field: #name,
+                    message,
+                }));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Sets the field called `field` from a JSON value; `null` unsets it.
+            pub fn set_from_json(
+                &mut self,
+                field: &str,
+                value: ::serde_json::Value,
+            ) -> Result<(), #error_ident>
+            where
+                #bounds
+            {
+                match field {
+                    #( #arms )*
+                    _ => {
+                        #( #flattened )*
+                        return Err(#error_ident::UnknownField {
+                            field: field.to_string(),
+                        });
+                    }
+                }
+                Ok(())
+            }
+
+            /// Deserializes a JSON object into a patch and applies it onto `target`
+            /// with [`try_apply`](Self::try_apply), returning the applied fields.
+            pub fn apply_json(
+                target: &mut #ident #ty_generics,
+                value: &::serde_json::Value,
+            ) -> Result<Vec<&'static str>, #error_ident>
+            where
+                #bounds
+            {
+                let patch = <Self as ::std::convert::TryFrom<::serde_json::Value>>::try_from(value.clone())?;
+                let applied = patch.set_fields();
+                patch.try_apply(target)?;
+                Ok(applied)
+            }
+
+            /// Renders the set fields as a JSON object, leaving unset fields out
+            /// and `nested` patches as objects of their own set fields, so it
+            /// can be merged into a stored document.
+            pub fn to_json_object(
+                &self,
+            ) -> Result<::serde_json::Map<String, ::serde_json::Value>, ::serde_json::Error>
+            where
+                #serialize_bounds
+            {
+                let mut object = ::serde_json::Map::new();
+                #( #entries )*
+                Ok(object)
+            }
+
+            /// Renders the set fields like [`to_json_object`](Self::to_json_object),
+            /// with every object sorted by key so the same patch always renders
+            /// the same way, e.g. to be compared, hashed or logged.
+            pub fn to_btree_map(
+                &self,
+            ) -> Result<::std::collections::BTreeMap<String, ::serde_json::Value>, ::serde_json::Error>
+            where
+                #serialize_bounds
+            {
+                // `preserve_order` keeps objects in insertion order, so nested
+                // ones are rebuilt in key order as well
+                fn sorted(value: ::serde_json::Value) -> ::serde_json::Value {
+                    match value {
+                        ::serde_json::Value::Object(object) => ::serde_json::Value::Object(
+                            object
+                                .into_iter()
+                                .collect::<::std::collections::BTreeMap<_, _>>()
+                                .into_iter()
+                                .map(|(key, value)| (key, sorted(value)))
+                                .collect(),
+                        ),
+                        ::serde_json::Value::Array(values) => {
+                            ::serde_json::Value::Array(values.into_iter().map(sorted).collect())
+                        }
+                        other => other,
+                    }
+                }
+
+                Ok(self
+                    .to_json_object()?
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect())
+            }
+
+            /// The fields applying the patch onto `before` would change, as a JSON
+            /// object of `{"field": {"old": ..., "new": ...}}` ready to insert
+            /// into an audit table. `before` is left untouched.
+            ///
+            /// Fields whose value wouldn't change are left out, and so are
+            /// sensitive fields.
+            pub fn audit_document(
+                &self,
+                before: &#ident #ty_generics,
+            ) -> Result<::serde_json::Map<String, ::serde_json::Value>, ::serde_json::Error>
+            where
+                for<'__optionalize> Self: ::std::clone::Clone,
+                for<'__optionalize> #ident #ty_generics: ::std::clone::Clone,
+                #( for<'__optionalize> #audited_tys: ::serde::Serialize, )*
+            {
+                let mut after = ::std::clone::Clone::clone(before);
+                ::std::clone::Clone::clone(self).apply_to(&mut after);
+
+                let mut document = ::serde_json::Map::new();
+                #( #audit_entries )*
+                Ok(document)
+            }
+
+            /// Builds a patch from field name/value pairs, e.g. the columns of a
+            /// CSV row or the output of a rules engine, failing on the first
+            /// unknown field or bad value. A name given twice keeps its last
+            /// value, and `null` values leave the field unset.
+            pub fn try_from_pairs<K: AsRef<str>>(
+                pairs: impl IntoIterator<Item = (K, ::serde_json::Value)>,
+            ) -> Result<Self, #error_ident>
+            where
+                #bounds
+            {
+                let mut patch = #empty;
+                for (key, value) in pairs {
+                    patch.set_from_json(key.as_ref(), value)?;
+                }
+                Ok(patch)
+            }
+
+            /// Builds a patch from field name/value pairs like `try_from_pairs`,
+            /// but keeps going past bad fields: the patch holds every field that
+            /// deserialized and validated, and the errors list every problem
+            /// found, so a client can be shown all of them at once. `nested`
+            /// objects are read the same way, keeping their valid fields and
+            /// reporting their errors under the field's name.
+            pub fn from_pairs_lenient<K: AsRef<str>>(
+                pairs: impl IntoIterator<Item = (K, ::serde_json::Value)>,
+            ) -> (Self, Vec<#error_ident>)
+            where
+                #bounds
+                #( #lenient_bounds )*
+            {
+                let mut patch = #empty;
+                let mut errors = Vec::new();
+                for (key, value) in pairs {
+                    match key.as_ref() {
+                        #( #lenient_arms )*
+                        key => {
+                            if let Err(err) = patch.set_from_json(key, value) {
+                                errors.push(err);
+                            }
+                        }
+                    }
+                }
+
+                // Invalid values are dropped one at a time until the rest validates
+                while let Err(err) = patch.validate() {
+                    let set = patch.set_fields().len();
+                    if let #error_ident::InvalidField { field, .. } = &err {
+                        let allowed = patch.set_fields().into_iter().filter(|name| name != field).collect();
+                        patch.retain_allowed(&allowed);
+                    }
+                    let dropped = patch.set_fields().len() < set;
+                    errors.push(err);
+                    if !dropped {
+                        break;
+                    }
+                }
+
+                (patch, errors)
+            }
+
+            /// Builds a patch from a JSON object like `try_from`, but keeps going
+            /// past bad fields like [`from_pairs_lenient`](Self::from_pairs_lenient).
+            pub fn from_json_lenient(value: ::serde_json::Value) -> (Self, Vec<#error_ident>)
+            where
+                #bounds
+                #( #lenient_bounds )*
+            {
+                match value {
+                    ::serde_json::Value::Object(map) => Self::from_pairs_lenient(map),
+                    other => {
+                        let error = #error_ident::ExpectedObject {
+                            found: other.to_string(),
+                        };
+                        (#empty, vec![error])
+                    }
+                }
+            }
+
+            /// Builds a patch from a Debezium-style change event, setting only the
+            /// columns whose value in `after` differs from `before`, so CDC
+            /// consumers can feed row changes through the same apply pipeline.
+            ///
+            /// The event may be the bare `{"before": ..., "after": ...}` payload or
+            /// wrapped in a `payload` key. Create and snapshot events, whose
+            /// `before` is `null`, set every column; delete events, whose `after`
+            /// is `null`, give an empty patch. Columns that aren't fields are
+            /// ignored, and columns that became `null` leave the field unset.
+            pub fn from_change_event(event: &::serde_json::Value) -> Result<Self, #error_ident>
+            where
+                #bounds
+            {
+                let event = event.get("payload").unwrap_or(event);
+                let row = |key: &str| match event.get(key) {
+                    None | Some(::serde_json::Value::Null) => Ok(None),
+                    Some(::serde_json::Value::Object(row)) => Ok(Some(row)),
+                    Some(other) => Err(#error_ident::ExpectedObject {
+                        found: other.to_string(),
+                    }),
+                };
+                let before = row("before")?;
+                let after = match row("after")? {
+                    Some(after) => after,
+                    None => return Ok(#empty),
+                };
+
+                let mut patch = #empty;
+                for (column, value) in after {
+                    if before.and_then(|before| before.get(column)) == Some(value) {
+                        continue;
+                    }
+                    match patch.set_from_json(column, value.clone()) {
+                        Err(#error_ident::UnknownField { .. }) => {}
+                        result => result?,
+                    }
+                }
+                Ok(patch)
+            }
+        }
+
+        #[doc(hidden)]
+        pub trait #lenient_ident: Sized {
+            fn __from_json_lenient(value: ::serde_json::Value) -> (Self, Vec<String>);
+        }
+
+        impl #impl_generics #lenient_ident for #optional_ident #ty_generics
+        where
+            #( #own, )*
+            #bounds
+            #( #lenient_bounds )*
+        {
+            fn __from_json_lenient(value: ::serde_json::Value) -> (Self, Vec<String>) {
+                let (patch, errors) = Self::from_json_lenient(value);
+                (patch, errors.iter().map(::std::string::ToString::to_string).collect())
+            }
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<
+            ::std::collections::HashMap<String, ::serde_json::Value>
+        > for #optional_ident #ty_generics
+        #where_with_bounds
+        {
+            type Error = #error_ident;
+
+            /// Builds a patch from a loose key/value map. Keys must be field names;
+            /// `null` values leave the field unset.
+            fn try_from(
+                map: ::std::collections::HashMap<String, ::serde_json::Value>,
+            ) -> Result<Self, Self::Error> {
+                Self::try_from_pairs(map)
+            }
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<
+            ::std::vec::Vec<(String, ::serde_json::Value)>
+        > for #optional_ident #ty_generics
+        #where_with_bounds
+        {
+            type Error = #error_ident;
+
+            /// Builds a patch from field name/value pairs with `try_from_pairs`,
+            /// in order.
+            fn try_from(pairs: ::std::vec::Vec<(String, ::serde_json::Value)>) -> Result<Self, Self::Error> {
+                Self::try_from_pairs(pairs)
+            }
+        }
+
+        impl #impl_generics ::std::convert::TryFrom<::serde_json::Value> for #optional_ident #ty_generics
+        #where_with_bounds
+        {
+            type Error = #error_ident;
+
+            /// Builds a patch from a JSON object, reporting offending fields by name.
+            fn try_from(value: ::serde_json::Value) -> Result<Self, Self::Error> {
+                match value {
+                    ::serde_json::Value::Object(map) => {
+                        Self::try_from(map.into_iter().collect::<::std::collections::HashMap<_, _>>())
+                    }
+                    other => Err(#error_ident::ExpectedObject {
+                        found: other.to_string(),
+                    }),
+                }
+            }
+        }
+    }
+}
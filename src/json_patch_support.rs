@@ -0,0 +1,215 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container, Field};
+
+/// Generates `apply_json_patch`, which applies RFC 6902 operations from the
+/// `json-patch` crate through the typed fields. Only emitted with
+/// `#[optionalize(json_patch)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("json_patch") || !cx.emits("serde") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Secrets can't be read back out, so they can't be addressed either
+    let fields: Vec<&Field> = cx.fields.iter().filter(|field| !field.is_secret()).collect();
+    let own: Vec<&Field> = fields.iter().copied().filter(|field| !field.attrs.flatten).collect();
+    let flattened: Vec<&Field> = fields.iter().copied().filter(|field| field.attrs.flatten).collect();
+
+    let deserialize_bounds = inner_bounds(&cx.fields, quote!(::serde::de::DeserializeOwned));
+    let serialized = own.iter().map(|field| &field.ty);
+    let bounds = quote! {
+        #deserialize_bounds
+        #( for<'__optionalize> #serialized: ::serde::Serialize, )*
+    };
+
+    let reads = own.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        quote! { #name => Some((#name, ::serde_json::to_value(&target.#ident))) }
+    });
+    let flattened_reads = flattened.iter().map(|field| {
+        let ident = &field.ident;
+        let twin = field.inner_ty();
+        quote! {
+            if let Some(found) = <#twin>::__json_patch_read(&target.#ident, field) {
+                return Some(found);
+            }
+        }
+    });
+
+    let writes = own.iter().map(|field| write(cx, field));
+    let flattened_writes = flattened.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let twin = field.inner_ty();
+        quote! {
+            match <#twin>::__json_patch_write(&mut target.#ident, field, value.clone()) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(err) => {
+                    return Err(#error_ident::InvalidField {
+                        field: #name,
+                        message: err.to_string(),
+                    });
+                }
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Applies RFC 6902 JSON Patch operations onto `target` through its
+            /// typed fields, e.g. to accept `application/json-patch+json`
+            /// requests from existing tooling.
+            ///
+            /// Every operation must target a field, such as `/name` or
+            /// `/address/city`; other paths are rejected as `UnknownField`.
+            /// Changed fields are written like a patch with
+            /// [`try_apply`](Self::try_apply), so validation and immutable fields
+            /// are enforced, and removing an `Option` field clears it. Failed
+            /// `test` operations and missing paths are reported as
+            /// `InvalidDocument`. `target` is left untouched on error.
+            pub fn apply_json_patch(
+                target: &mut #ident #ty_generics,
+                patch: &::json_patch::Patch,
+            ) -> Result<(), #error_ident>
+            where
+                for<'__optionalize> #ident #ty_generics: ::std::clone::Clone,
+                #bounds
+            {
+                let mut working = ::std::clone::Clone::clone(target);
+                for operation in &patch.0 {
+                    let mut paths = vec![operation.path()];
+                    match operation {
+                        ::json_patch::PatchOperation::Move(operation) => paths.push(&operation.from),
+                        ::json_patch::PatchOperation::Copy(operation) => paths.push(&operation.from),
+                        _ => {}
+                    }
+
+                    // The operation runs on a document of the fields it touches
+                    let mut before = ::serde_json::Map::new();
+                    for path in paths {
+                        let field = path.first().map(|token| token.decoded().into_owned()).ok_or_else(|| {
+                            #error_ident::InvalidDocument {
+                                message: format!("operation `{}` doesn't target a field", operation),
+                            }
+                        })?;
+                        let (name, value) = match Self::__json_patch_read(&working, &field) {
+                            Some(found) => found,
+                            None => return Err(#error_ident::UnknownField { field }),
+                        };
+                        let value = value.map_err(|err| #error_ident::InvalidField {
+                            field: name,
+                            message: err.to_string(),
+                        })?;
+                        before.insert(name.to_string(), value);
+                    }
+                    let mut document = ::serde_json::Value::Object(before.clone());
+                    ::json_patch::patch(&mut document, ::std::slice::from_ref(operation))
+                        .map_err(|err| #error_ident::InvalidDocument { message: err.to_string() })?;
+
+                    for (name, old) in before {
+                        let new = document.get(&name).cloned().unwrap_or(::serde_json::Value::Null);
+                        if new != old {
+                            Self::__json_patch_write(&mut working, &name, new)?;
+                        }
+                    }
+                }
+                *target = working;
+                Ok(())
+            }
+
+            /// The name and JSON value of the field called `field`, looking
+            /// through flattened fields.
+            #[doc(hidden)]
+            pub fn __json_patch_read(
+                target: &#ident #ty_generics,
+                field: &str,
+            ) -> Option<(&'static str, Result<::serde_json::Value, ::serde_json::Error>)>
+            where
+                #bounds
+            {
+                match field {
+                    #( #reads, )*
+                    _ => {
+                        #( #flattened_reads )*
+                        None
+                    }
+                }
+            }
+
+            /// Writes `value` into the field called `field`, returning whether
+            /// the struct has such a field.
+            #[doc(hidden)]
+            pub fn __json_patch_write(
+                target: &mut #ident #ty_generics,
+                field: &str,
+                value: ::serde_json::Value,
+            ) -> Result<bool, #error_ident>
+            where
+                #bounds
+            {
+                match field {
+                    #( #writes )*
+                    _ => {
+                        #( #flattened_writes )*
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// The match arm writing a new JSON value into a single field of `target`.
+fn write(cx: &Container, field: &Field) -> TokenStream {
+    let error_ident = cx.error_ident();
+    let empty = cx.empty();
+    let ident = &field.ident;
+    let name = field.name();
+
+    // A patch can't clear most fields, so `null` gets written separately
+    let cleared = if field.attrs.immutable {
+        quote! { return Err(#error_ident::Immutable { fields: vec![#name] }); }
+    } else if field.double_option {
+        quote! {
+            let mut patch = #empty;
+            patch.#ident = Some(None);
+            patch.try_apply(target)?;
+        }
+    } else if field.option && field.option_like.is_none() {
+        quote! { target.#ident = None; }
+    } else if field.option_like.is_some() {
+        quote! {
+            let mut patch = #empty;
+            patch.set_from_json(#name, value)?;
+            patch.try_apply(target)?;
+        }
+    } else {
+        quote! {
+            return Err(#error_ident::InvalidField {
+                field: #name,
+                message: "cannot be removed or set to null".to_string(),
+            });
+        }
+    };
+
+    quote! {
+        #name => {
+            if value.is_null() {
+                #cleared
+            } else {
+                let mut patch = #empty;
+                patch.set_from_json(#name, value)?;
+                patch.try_apply(target)?;
+            }
+        }
+    }
+}
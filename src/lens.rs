@@ -0,0 +1,215 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, GenericParam, Ident, PathArguments, Type, TypePath};
+
+use crate::ast::{pointer_inner, type_ends_with, Container, Field};
+
+/// Generates typed paths into the original struct, such as
+/// `User::path().address().city()`, for single-field updates deep inside a
+/// value. Only emitted with `#[optionalize(lens)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.lens {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let path_ident = path_ident(ident);
+    let lens_ident = format_ident!("{}Lens", ident);
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+
+    // The root type is the last parameter of the path; defaults can't precede it
+    let mut generics = cx.generics.clone();
+    for param in &mut generics.params {
+        if let GenericParam::Type(param) = param {
+            param.eq_token = None;
+            param.default = None;
+        }
+    }
+    generics.params.push(parse_quote!(__R));
+    // Paths are boxed closures, which hold on to the types they walk through
+    let static_bounds: Vec<_> = generics
+        .type_params()
+        .map(|param| {
+            let ident = &param.ident;
+            parse_quote! { #ident: 'static }
+        })
+        .collect::<Vec<syn::WherePredicate>>();
+    let own_static_bounds = &static_bounds[..static_bounds.len() - 1];
+    let path_fn_where_clause = cx.where_clause_with(quote! { #( #own_static_bounds, )* });
+    generics.make_where_clause().predicates.extend(static_bounds);
+    let (path_impl_generics, path_ty_generics, path_where_clause) = generics.split_for_impl();
+    let path_params = &generics.params;
+    let own_args = cx.generics.params.iter().map(|param| match param {
+        GenericParam::Type(param) => {
+            let ident = &param.ident;
+            quote!(#ident)
+        }
+        GenericParam::Lifetime(param) => {
+            let lifetime = &param.lifetime;
+            quote!(#lifetime)
+        }
+        GenericParam::Const(param) => {
+            let ident = &param.ident;
+            quote!(#ident)
+        }
+    });
+    let own_args: Vec<_> = own_args.collect();
+
+    let steps = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.no_helpers)
+        .map(|field| step(field, &lens_ident));
+
+    let path_doc = format!("A path from `__R` to a [`{}`] inside it, built with [`{}::path`].", ident, ident);
+    let lens_doc = format!("A path from `__R` to a field of a [`{}`] inside it.", ident);
+
+    quote! {
+        #[doc = #path_doc]
+        pub struct #path_ident <#path_params> #path_where_clause {
+            get: ::std::boxed::Box<dyn Fn(&mut __R) -> &mut #ident #ty_generics>,
+            path: String,
+        }
+
+        #[doc = #lens_doc]
+        pub struct #lens_ident<__R, __V> {
+            get: ::std::boxed::Box<dyn Fn(&mut __R) -> &mut __V>,
+            path: String,
+        }
+
+        impl #impl_generics #ident #ty_generics #path_fn_where_clause {
+            /// The path to the value itself, to be extended one field at a time.
+            pub fn path() -> #path_ident<#( #own_args, )* Self> {
+                #path_ident::__from_parts(::std::boxed::Box::new(#lens_ident::<Self, Self>::__lens(|root| root)), String::new())
+            }
+        }
+
+        impl #path_impl_generics #path_ident #path_ty_generics #path_where_clause {
+            #[doc(hidden)]
+            pub fn __from_parts(
+                get: ::std::boxed::Box<dyn Fn(&mut __R) -> &mut #ident #ty_generics>,
+                path: String,
+            ) -> Self {
+                Self { get, path }
+            }
+
+            /// The dotted path from the root, e.g. `"address.city"`.
+            pub fn path(&self) -> &str {
+                &self.path
+            }
+
+            /// The value at this path inside `root`.
+            pub fn get_mut<'__a>(&self, root: &'__a mut __R) -> &'__a mut #ident #ty_generics {
+                (self.get)(root)
+            }
+
+            /// Replaces the value at this path inside `root`.
+            pub fn set(&self, root: &mut __R, value: #ident #ty_generics) {
+                *(self.get)(root) = value;
+            }
+
+            /// Applies `patch` onto the value at this path inside `root`.
+            pub fn apply(&self, root: &mut __R, patch: #optional_ident #ty_generics) {
+                patch.apply_to((self.get)(root));
+            }
+
+            #( #steps )*
+        }
+
+        impl<__R: 'static, __V: 'static> #lens_ident<__R, __V> {
+            #[doc(hidden)]
+            pub fn __from_parts(get: ::std::boxed::Box<dyn Fn(&mut __R) -> &mut __V>, path: String) -> Self {
+                Self { get, path }
+            }
+
+            #[doc(hidden)]
+            pub fn __lens<F: Fn(&mut __R) -> &mut __V>(get: F) -> F {
+                get
+            }
+
+            /// The dotted path from the root, e.g. `"address.city"`.
+            pub fn path(&self) -> &str {
+                &self.path
+            }
+
+            /// The field at this path inside `root`.
+            pub fn get_mut<'__a>(&self, root: &'__a mut __R) -> &'__a mut __V {
+                (self.get)(root)
+            }
+
+            /// Replaces the field at this path inside `root`.
+            pub fn set(&self, root: &mut __R, value: __V) {
+                *(self.get)(root) = value;
+            }
+        }
+    }
+}
+
+/// The method extending a path to `field`: another path for nested structs,
+/// or a lens onto the field otherwise.
+fn step(field: &Field, lens_ident: &Ident) -> TokenStream {
+    let vis = &field.vis;
+    let ident = &field.ident;
+    let name = field.name();
+    let ty = &field.ty;
+    let doc = format!("The path to the `{}` field.", name);
+    let joined = quote! {
+        if self.path.is_empty() {
+            #name.to_string()
+        } else {
+            format!("{}.{}", self.path, #name)
+        }
+    };
+
+    // Nested structs are walked into, unless they may be missing
+    if field.nested_ty.is_some() && !field.is_option() {
+        let value_ty = field.value_ty();
+        let boxed = type_ends_with(value_ty, "Box");
+        let (target, access) = if boxed {
+            (pointer_inner(value_ty).unwrap_or(value_ty), quote! { &mut *get(root).#ident })
+        } else {
+            (value_ty, quote! { &mut get(root).#ident })
+        };
+        let nested_path = nested_path_ty(target);
+        return quote! {
+            #[doc = #doc]
+            #vis fn #ident(self) -> #nested_path {
+                let get = self.get;
+                let inner: ::std::boxed::Box<dyn Fn(&mut __R) -> &mut #target> =
+                    ::std::boxed::Box::new(#lens_ident::<__R, #target>::__lens(move |root| #access));
+                <#nested_path>::__from_parts(inner, #joined)
+            }
+        };
+    }
+
+    quote! {
+        #[doc = #doc]
+        #vis fn #ident(self) -> #lens_ident<__R, #ty> {
+            let get = self.get;
+            #lens_ident::__from_parts(
+                ::std::boxed::Box::new(#lens_ident::__lens(move |root| &mut get(root).#ident)),
+                #joined,
+            )
+        }
+    }
+}
+
+/// The path type of a nested struct: `Address` becomes `AddressPath<__R>`.
+fn nested_path_ty(ty: &Type) -> Type {
+    let mut ty = ty.clone();
+    if let Type::Path(TypePath { path, .. }) = &mut ty {
+        if let Some(segment) = path.segments.last_mut() {
+            segment.ident = path_ident(&segment.ident);
+            match &mut segment.arguments {
+                PathArguments::AngleBracketed(args) => args.args.push(parse_quote!(__R)),
+                arguments => *arguments = PathArguments::AngleBracketed(parse_quote!(<__R>)),
+            }
+        }
+    }
+    ty
+}
+
+fn path_ident(ident: &Ident) -> Ident {
+    format_ident!("{}Path", ident)
+}
@@ -1,3 +1,73 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, ItemMod};
+
+mod actix_support;
+mod ansi_support;
+mod apply;
+mod assert;
+mod ast;
+mod attr;
+mod builder;
+mod axum_extra_support;
+mod axum_support;
+mod cfg;
+mod collection;
+mod compact;
+mod config_support;
+mod convert;
+mod debug_expand;
+mod deoptionalize;
+mod dialoguer_support;
+mod diff;
+mod display;
+mod document;
+mod dynamodb_support;
+mod elasticsearch_support;
+mod error;
+mod event;
+mod ffi;
+mod fields;
+mod filter;
+mod flat;
+mod hash_support;
+mod history;
+mod inverse;
+mod json;
+mod json_patch_support;
+mod lens;
+mod lww;
+mod merge;
+mod merge_support;
+mod metrics_support;
+mod module;
+mod ops;
+mod patch_macro;
+mod postgres_support;
+mod prost_support;
+mod pyo3_support;
+mod query;
+mod redact;
+mod report;
+mod rkyv_support;
+mod runtime_support;
+mod schema_support;
+mod serde_support;
+mod shared;
+mod similar_support;
+mod sql;
+mod sqlite_support;
+mod struct_patch_support;
+mod surreal_support;
+mod tokio_support;
+mod transparent;
+mod validate;
+mod version;
+mod visit;
+mod wrapper;
+
+use ast::Container;
+
 /// The `Optionalize` macro generates a new struct with optional fields.
 ///
 /// For any struct that derives `Optionalize`, the macro will generate a new struct
@@ -6,6 +76,51 @@
 ///
 /// - If a field is of type `Option<T>`, it remains `Option<T>`.
 /// - If a field is of type `T`, it becomes `Option<T>`.
+/// - `PhantomData<T>` fields are copied as-is, and ignored by the generated
+///   methods.
+///
+/// The generated struct implements `From` the original one, setting every field,
+/// and `From` a reference to it, cloning every field to snapshot a value.
+/// The original struct implements `TryFrom` a patch that sets every field
+/// that isn't an `Option`, failing with `MissingFields` otherwise, and
+/// `missing_fields` lists the ones left unset.
+/// Patches combine with `merge`, are computed between two values with `diff`,
+/// and undone with `invert`. The generated struct and these methods are
+/// `#[must_use]`, since they only ever return a new patch. `changed_fields`
+/// lists the set fields that would actually change a given value, so no-op
+/// writes can be skipped, and `summary` describes a patch by the fields it
+/// sets, e.g. `name, email (2 of 7 fields)`, for logs without the values.
+/// The original struct gets `apply_all`, which applies a sequence of patches
+/// in order, `with_patch`, which returns a patched copy of a value that is
+/// left untouched, and `apply_to_all` applies a map of keyed patches onto a
+/// slice of values in a single pass. `apply_masked` writes only the set fields a
+/// protobuf-style field mask selects, such as `["name", "address.city"]`, so
+/// gRPC update handlers can honor the client's `update_mask`.
+/// `with_patch` is generated for generic structs and for those deriving
+/// `Clone` in a separate `#[derive(...)]` after the one with `Optionalize`,
+/// as derive macros can't see the other derives in their own list.
+///
+/// `validate_all` returns every failed check instead of the first one, and
+/// `try_apply` reports several failures at once as `Multiple`, whose
+/// `into_errors` lists them, so a client can fix every field in one round trip.
+///
+/// `accept` walks the fields of a patch with a `{Optional}FieldVisitor` (e.g.
+/// `UserOptionalFieldVisitor`), which has a method per field taking its value
+/// if it is set, so generic serializers, validators or UIs can be written as
+/// plain trait impls.
+///
+/// `from_flat_map` and `set_from_str` fill a patch from string values under
+/// dotted keys such as `server.port`, as used by environment variable and
+/// command-line overrides. Values are parsed with `FromStr`, so every field
+/// must implement it, and dotted keys reach into `nested` fields.
+/// `parse_kv`, also available through `FromStr`, parses comma-separated pairs
+/// such as `name=foo,server.port=8080` from a `--set` argument the same way,
+/// with values holding commas double-quoted, e.g. `tags="a,b"`.
+///
+/// `to_set_clause` renders the set fields as the `SET` clause of a partial
+/// `UPDATE` for any SQL driver or none: the caller picks the placeholder style
+/// (`?`, `$1` or `:name`) and the value type, which every field converts into
+/// with `Into`. The driver-specific integrations below build on the same idea.
 ///
 /// # Example
 ///
@@ -26,60 +141,1072 @@
 ///     description: Some("Test Description".to_string())
 /// };
 /// ```
-
-use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, DeriveInput, Data, Type, TypePath};
-
-#[proc_macro_derive(Optionalize)]
+///
+/// # Field attributes
+///
+/// - `#[optionalize(group = "name")]` puts the field in an all-or-none group.
+///   The generated `validate` method fails with `IncompleteGroup` when only
+///   some of the fields in a group are set.
+/// - `#[optionalize(range(min = 1, max = 10))]` makes `validate` (and so
+///   `try_apply`) fail with `InvalidField` when the set value is out of range.
+///   Either bound may be omitted, and a string such as `min = "-10"` or
+///   `max = "MAX_LEN"` holds any other expression.
+/// - `#[optionalize(regex = "^[a-z]+$")]` makes `validate` fail with
+///   `InvalidField` when the set value doesn't match the pattern. Requires the
+///   `regex` crate.
+/// - `#[optionalize(immutable)]` keeps the field in the generated struct, but
+///   `apply_to` never writes it and `try_apply` fails with `Immutable` when it
+///   is set.
+/// - `#[optionalize(roles("admin", "owner"))]` restricts the field to the
+///   listed roles: `allowed_fields(role)` names the fields a role may change,
+///   and `retain_for_role(role)` unsets the others on a patch before it is
+///   applied. Fields without `roles` are open to every role, and flattened
+///   fields keep their own `roles`.
+/// - Fields marked `#[serde(skip)]` or `#[serde(skip_deserializing)]` are
+///   internal, so they are left out of the generated struct and no patch can
+///   set them; `builder` starts them from `Default::default()`.
+///   `#[optionalize(include)]` keeps such a field in the patch.
+/// - `#[optionalize(sensitive)]` marks the field as secret. `redact` unsets it,
+///   and a requested `Debug` prints `[REDACTED]` instead of its value.
+///   With `#[optionalize(secrecy)]`, fields holding a `Secret`, `SecretString`,
+///   `SecretBox` or `SecretSlice` are sensitive automatically. They stay
+///   wrapped in the generated struct, are moved onto the target as-is by
+///   `apply_to` without ever being exposed, and are skipped when the generated
+///   struct derives `Serialize`.
+/// - `#[optionalize(empty_as_none)]` deserializes empty strings and empty
+///   collections as `None`, for form inputs that were left untouched. Requires
+///   the generated struct to derive `Deserialize`.
+/// - `#[optionalize(double_option)]` on an `Option<T>` field stores
+///   `Option<Option<T>>`, so a patch can clear the field with `Some(None)`
+///   instead of only setting it. When the generated struct derives serde
+///   traits, the field uses `serde_with::rust::double_option`, so an absent
+///   key leaves the field alone and `null` clears it. Requires the
+///   `serde_with` crate in that case. When it derives `schemars::JsonSchema`
+///   or `utoipa::ToSchema`, the field is described as a nullable property
+///   that isn't required, matching that behavior. Double `Option` fields are
+///   left out of `to_query_pairs`.
+/// - `#[optionalize(merge_key = "name")]` on a `Vec<T>` field merges lists
+///   the way a Kubernetes strategic merge patch does: `apply_to` and `merge`
+///   replace the elements whose `name` matches an incoming element and append
+///   the rest, keeping elements the patch doesn't mention. The inverse patch
+///   restores replaced elements but can't remove appended ones.
+/// - `#[optionalize(vec_merge = "append" | "prepend" | "union")]` on a
+///   `Vec<T>` field adds the patch's elements to the existing list instead of
+///   replacing it (`"replace"`, the default): after it, before it, or after it
+///   while skipping elements already present (requires `T: PartialEq`).
+///   `merge` combines the lists of both patches the same way. The inverse
+///   patch can't remove added elements, so it leaves such fields alone.
+/// - `#[optionalize(merge_entries)]` on a `HashMap` or `BTreeMap` field
+///   inserts the patch's entries key by key instead of replacing the whole map,
+///   keeping the entries it doesn't mention. Combined with `nested` (or
+///   `nested = "..."`), the patch holds a map of patches of the values, which
+///   are applied onto the existing values in place or onto `Default::default()`
+///   for new keys.
+///
+///   For these strategies, `diff` only picks up added and changed elements,
+///   since a patch can't remove any.
+/// - `#[optionalize(merge_with = "path::to::fn")]` on a non-`Option` field
+///   combines values with `fn(&mut T, T)` instead of overwriting them, e.g. to
+///   concatenate strings or union sets: `apply_to` calls it with the target's
+///   value and the patch's, and `merge` with the values of both patches. The
+///   inverse patch, `diff` and `invert` can't undo a custom merge, so they
+///   leave the field unset.
+/// - `#[optionalize(sanitize_with = "path::to::fn")]` passes the value through
+///   `fn(T) -> T` before `apply_to` writes it, e.g. to trim or lowercase input.
+/// - `#[optionalize(pointer = "wrap" | "unwrap")]` picks how a `Box<T>`,
+///   `Arc<T>` or `Rc<T>` field is represented: `"wrap"` (the default) keeps
+///   `Option<Box<T>>`, while `"unwrap"` stores a plain `Option<T>` that is
+///   boxed again when applied. `Box<Option<T>>` is not offered, since the
+///   generated helpers all treat fields as `Option`s. Pointers to unsized
+///   types such as `Box<dyn Fn()>` or `Arc<str>`, and pinned values such as
+///   `Pin<Box<dyn Future<Output = T>>>`, always keep their outer pointer.
+/// - `#[optionalize(nested)]` on a field holding another `Optionalize` struct
+///   (directly, in a `Box` or in an `Option`) stores its optional twin instead,
+///   so `Option<Box<Node>>` becomes `Option<Box<NodeOptional>>`. `apply_to`
+///   applies the nested patch onto the existing value in place, so patches
+///   recurse through any depth of nesting and only write the fields they set.
+///   A nested patch for an `Option` field that is currently `None` creates
+///   the value with `TryFrom` when it sets every required field, and is
+///   otherwise skipped by `apply_to` and rejected by `try_apply` with
+///   `InvalidField`. Use `nested = "NodePatch"` when the twin has a custom
+///   name. With the `optionalize` runtime crate, a field of a type parameter
+///   `T` can be nested too: it holds `T::Optional`, the generated struct
+///   gains `T: Optionalize + TryFrom<T::Optional>` and `T::Optional: Patch<T>`
+///   bounds, and `diff` needs `T::Optional: Diff<T>`. `set_from_str` doesn't
+///   know the keys of such a twin and rejects them as unknown fields.
+/// - `#[optionalize(flatten)]` on a plain field holding another `Optionalize`
+///   struct inlines its optional twin: the generated field holds
+///   `AddressOptional` itself rather than an `Option`, and is marked
+///   `#[serde(flatten)]` so both patches share one JSON object. The generated
+///   helpers treat the twin's fields as their own, e.g. `set_fields` and
+///   `retain_allowed` use the twin's field names, while `validate` reports the
+///   twin's errors as `InvalidField`. Field names must not clash, since serde
+///   can't prefix flattened fields. Use `nested = "AddressPatch"` alongside
+///   when the twin has a custom name.
+/// - `#[optionalize(with = "path::to::module", ty = "P")]` stores the field as
+///   `Option<P>` in the generated struct. The module provides
+///   `to_optional(T) -> P` and `from_optional(P) -> T`, used by `From` and
+///   `apply_to` respectively, e.g. to patch a timestamp through its RFC 3339
+///   string.
+/// - `#[optionalize(field_attr(...))]` adds each listed attribute to the field
+///   of the generated struct, e.g. `field_attr(sqlx(rename = "displayName"))`.
+/// - `#[optionalize(serde(...))]` is short for `field_attr(serde(...))`: the
+///   serde attribute only applies to the generated field, e.g.
+///   `serde(rename = "displayName")` when the patch's wire format differs from
+///   the original struct's.
+///
+/// # Container attributes
+///
+/// - `#[optionalize(derive(...))]` adds derives to the generated struct.
+/// - `#[optionalize(forward_derives)]` also derives `PartialEq`, `Eq`,
+///   `PartialOrd`, `Ord` and `Hash` on the generated struct when the original
+///   struct derives them, so both types work equally well as map keys or in
+///   sorted collections. Derive macros can't see the other derives in their own
+///   `#[derive(...)]` list, so list those traits in a separate `#[derive(...)]`
+///   after the one with `Optionalize`.
+/// - `#[optionalize(assert(Serialize, Send, Sync))]` checks at compile time
+///   that the generated struct implements each listed trait, so a change that
+///   drops one of them fails to build with an error pointing at the trait
+///   rather than somewhere downstream. The traits must be in scope.
+/// - `#[optionalize(bound = "T: Clone")]` replaces the bounds the macro infers
+///   for its generated trait impls, like `#[serde(bound = "...")]`, and is
+///   passed on as `#[serde(bound = "...")]` when the generated struct derives
+///   `Serialize` or `Deserialize`. An empty string removes the inferred bounds.
+/// - `#[optionalize(double_option)]` applies `double_option` to every `Option`
+///   field without `nested`.
+/// - `#[optionalize(form)]` deserializes the generated struct from the string
+///   values of `application/x-www-form-urlencoded` or multipart input, e.g.
+///   through `serde_urlencoded`: absent keys and empty values are `None`,
+///   other values are parsed with `FromStr`, and `bool` fields also accept
+///   `on`/`off`, `yes`/`no` and `1`/`0` as sent by checkboxes. Strings are
+///   kept as-is, and `nested`, `flatten`, `with`, `empty_as_none`,
+///   `double_option`, option-like and secret fields keep their own
+///   representation. Requires the generated struct to derive `Deserialize`.
+/// - `#[optionalize(query)]` shapes the generated struct as a filter taken
+///   from a query string, e.g. with axum-extra's `Query`: fields are parsed
+///   as with `form`, and `Vec`, `VecDeque`, `HashSet` and `BTreeSet` fields
+///   collect every occurrence of their key (`?role=admin&role=owner`),
+///   skipping empty ones. Requires the generated struct to derive
+///   `Deserialize`.
+/// - `#[optionalize(merge = "...")]` picks how `merge` treats fields set in
+///   both patches: `"prefer_other"` (the default) keeps the second patch's
+///   value, as for config layering, `"prefer_self"` keeps the first one, and
+///   `"error_on_conflict"` makes `merge` return a `Result` that fails with
+///   `MergeConflict`, listing the fields set on both sides. Nested and
+///   flattened patches are merged with their own policy, which must not be
+///   `"error_on_conflict"`; under `"error_on_conflict"`, a nested field set on
+///   both sides is a conflict. Whatever the policy, `merge_with` instead
+///   calls a resolver with the name and both values (as an
+///   `{Optional}FieldPair`) of every field set on both sides, which returns
+///   `{Optional}Choice::Ours` or `Theirs`; `nested` and `flatten` fields are
+///   resolved as a whole, the other side filling in what the winner leaves
+///   unset.
+/// - `#[optionalize(before_apply = "path::to::fn")]` and
+///   `#[optionalize(after_apply = "path::to::fn")]` name hooks run by
+///   `apply_to` (and so `try_apply`) and `apply_with_inverse` on every patch:
+///   `fn(&mut UserOptional, &User)` before the fields are written, e.g. to
+///   adjust or fill in the patch, and `fn(&mut User)` after, e.g. to bump
+///   `updated_at` or recompute derived fields. `try_apply` runs the
+///   `before_apply` hook ahead of its checks, so what the hook fills in is
+///   validated too.
+/// - `#[optionalize(async_validate = "path::to::fn")]` names an async
+///   validation, `async fn(&UserOptional, &User) -> Result<(), UserOptionalError>`,
+///   e.g. for uniqueness checks or permission lookups, and generates
+///   `try_apply_async`. It runs the same checks as `try_apply`, then awaits the
+///   hook, and only writes the patch if it succeeds. Any executor works, since
+///   the macro doesn't depend on one.
+/// - `#[optionalize(reject_null)]` makes deserializing the generated struct
+///   fail when a field that isn't an `Option` in the original struct is sent
+///   as `null`, instead of treating it as unset; absent keys are still unset.
+///   When the generated struct also derives `Serialize`, those fields are
+///   left out while unset rather than written as `null`, so patches round-trip.
+///   `empty_as_none` and `form` fields keep their own handling. Requires the
+///   generated struct to derive `Deserialize`.
+/// - `#[optionalize(copy)]` derives `Clone` and `Copy` on the generated
+///   struct. This happens automatically when every field is obviously `Copy`,
+///   i.e. a primitive, a shared reference, or a tuple or array of those.
+/// - `#[optionalize(doc = "...")]` documents the generated struct. Its docs
+///   always link back to the original struct.
+/// - `#[optionalize(pointer = "...")]` sets the default pointer representation
+///   for every `Box`, `Arc` and `Rc` field.
+/// - `#[optionalize(attr(...))]` adds each listed attribute to the generated
+///   struct, e.g. `attr(serde(deny_unknown_fields))`.
+/// - `#[optionalize(cfg(feature = "api"))]` marks every generated item, the
+///   struct, its error type and all the impls, with `#[cfg(...)]`, so they
+///   only exist when the predicate holds, e.g. to keep patch types out of
+///   minimal builds of a library.
+/// - `#[optionalize(name = "UserPatch")]` names the generated struct.
+/// - `#[optionalize(prefix = "...")]` and `#[optionalize(suffix = "...")]` build
+///   the name around the original one instead; the default is the `Optional`
+///   suffix.
+/// - `#[optionalize(naming = "...")]` picks a built-in convention: `"optional"`
+///   (`UserOptional`), `"partial"` (`PartialUser`), `"patch"` (`UserPatch`) or
+///   `"update"` (`UserUpdate`).
+/// - `#[optionalize(option_alias(Maybe, Nullable))]` lists local aliases of
+///   `Option`, such as `type Maybe<T> = Option<T>`, so fields using them are
+///   kept as-is rather than wrapped in a second `Option`.
+/// - `#[optionalize(nested_types(Address, Node = "NodePatch"))]` lists types
+///   deriving `Optionalize` whose fields are `nested` without the field
+///   attribute, whether held directly, in a `Box` or in an `Option`, with the
+///   name of the twin when it isn't the default. A derive macro can't tell
+///   which types implement a trait, so the types are named once on the struct
+///   instead of on every field. Fields using `nested`, `double_option`,
+///   `with` or a merge strategy keep their own handling.
+/// - `#[optionalize(depth = 1)]` on the derive makes `nested` the fields
+///   holding another struct, directly, in a `Box` or in an `Option`. Not
+///   seeing the other structs, the derive takes any bare type name other than
+///   a type parameter, a primitive or a common `std` type such as `String` or
+///   `Duration` for a struct deriving `Optionalize`, so structs holding
+///   imported types like `Uuid` should name their nested structs with
+///   `nested_types` instead. Every nested struct's own
+///   derive decides how its fields are patched, so any `depth` above zero
+///   nests one level here; inside `#[optionalize_all]`, `depth` reaches down
+///   `N` levels.
+/// - `#[optionalize(option_like(MaybeUndefined = "is_undefined"))]` lists
+///   option-like types, such as tri-state wrappers from other frameworks, that
+///   are kept as-is in the generated struct. The type must implement `Default`
+///   as its unset value, and the named method must return `true` for unset
+///   values. Option-like fields are moved onto the target whole, and are left
+///   out of `to_query_pairs`.
+/// - `#[optionalize(version = "field")]` names the field holding the version of
+///   the value and generates `apply_if_version`, which fails with
+///   `VersionConflict` when the target is not at the expected version, and
+///   otherwise applies the patch and moves the target to the next version,
+///   overriding any version the patch sets. Numeric versions are incremented;
+///   others, such as etags, need `next_version = "path::to::fn"`, called with
+///   the expected version to compute the next one.
+/// - `#[optionalize(schema_version = 2)]` sets the schema version of the
+///   patch, as `VERSION`, and generates `{Optional}Versioned` (e.g.
+///   `UserOptionalVersioned`), an envelope recording the version a patch was
+///   written with, built with `into_versioned` and serialized as
+///   `{"version": 2, "patch": {...}}` when the generated struct derives serde's
+///   traits. `into_current` and `apply_to` accept envelopes of the current
+///   version, and pass older ones to the
+///   `#[optionalize(migrate_from = "path::to::fn")]` hook, called as
+///   `migrate(version, &mut patch)` returning `Result<(), {Optional}Error>`, to
+///   bring them up to date. Other versions fail with `UnsupportedVersion`.
+/// - `#[optionalize(field_attr(...))]` adds each listed attribute to every
+///   field of the generated struct, e.g. `field_attr(ts(optional))`.
+/// - `#[optionalize(lens)]` generates typed paths into the original struct:
+///   `User::path()` returns a `UserPath`, with a method per field returning a
+///   `UserLens` onto that field, or the nested struct's own path for
+///   non-`Option` `nested` fields (which must use `lens` as well). A lens can
+///   `set` or `get_mut` its field inside a root value, and a path can also
+///   `apply` a patch there, e.g.
+///   `User::path().address().city().set(&mut user, "Paris".to_string())`.
+///   Paths hold boxed closures, so every type parameter must be `'static`.
+/// - `#[optionalize(event)]` generates `{Optional}Event` (e.g.
+///   `UserOptionalEvent`), a serializable change event tagged with its
+///   operation and schema version, plus `into_event` on the patch and `replay`
+///   on the original struct. Use `event(version = 2)` to bump the schema version;
+///   replaying events of another version fails with `UnsupportedEvent`. Requires
+///   the generated struct to derive `Serialize` and `Deserialize`.
+/// - `#[optionalize(ops)]` generates `{Optional}Ops` (e.g. `UserOptionalOps`),
+///   a patch whose fields carry an operation rather than a value: `Set` or
+///   `Unset` (back to `Default::default()`), plus `Increment` and `Decrement`
+///   on numeric fields and `Push` and `Remove` on `Vec` fields, as the
+///   `{Optional}Op`, `{Optional}NumOp` and `{Optional}ListOp` enums. Its
+///   `apply_to` performs the set operations. The generated types copy the
+///   `Clone`, `Debug`, `PartialEq`, `Eq` and serde derives, with operations in
+///   `snake_case`, e.g. `{"views": {"increment": 1}}`.
+/// - `#[optionalize(shared)]` generates `{Optional}Shared` (e.g.
+///   `UserOptionalShared`), the patch with its values behind `Arc`s: `String`
+///   as `Arc<str>`, `Vec<T>` as `Arc<[T]>` and anything else as `Arc<T>`.
+///   Cloning it only bumps reference counts, so one patch can be fanned out to
+///   many workers. Build it with `into_shared`, and use `to_patch` or
+///   `apply_to(&self, ...)` to copy the values back out.
+/// - `#[optionalize(wrapper)]` generates `{Optional}Wrapped<W>` (e.g.
+///   `UserOptionalWrapped<W>`), the patch with each field held as `W::Of<T>`
+///   for an `optionalize::Wrapper` `W`: `OptionWrapper` (the default),
+///   `CellWrapper` or `MaybeUndefinedWrapper`. It converts to and from the
+///   patch with `From`, and its `apply_to` unwraps it first. Flattened and
+///   option-like fields keep their own type. Requires the `optionalize`
+///   runtime crate. The generated struct itself isn't generic over its
+///   wrapper: a default type parameter isn't applied when a struct
+///   expression infers its generics, so every `UserOptional { .. }` literal
+///   would break, along with every method reading its fields as `Option`s.
+/// - `#[optionalize(builder)]` generates `{Optional}Builder` (e.g.
+///   `UserOptionalBuilder`), started with `User::builder()`, with a setter per
+///   field and a `build` method returning the original struct. Its type
+///   parameters track which of the fields that aren't `Option`s have been set,
+///   and `build` only exists once all of them are, so a missing field is a
+///   compile error rather than a runtime one. `Option` and option-like fields
+///   may be left out, and their setters take the inner value.
+/// - `#[optionalize(filter)]` generates `{Name}Filter` (e.g. `UserFilter`), a
+///   query-by-example filter whose fields are `Option<optionalize::Cmp<T>>`:
+///   `Eq`, `Ne`, `Gt`, `Lt`, `Like` or `In`. `matches(&user)` checks every
+///   set comparison in memory, and `to_where_clause` renders them as an SQL
+///   `WHERE` body with placeholders like `to_set_clause`. `Option` fields
+///   compare against their value and match nothing when `None`; secret,
+///   option-like and `nested` fields are left out. Requires the `optionalize`
+///   runtime crate.
+/// - `#[optionalize(ffi)]` generates `{Optional}Ffi` (e.g. `UserOptionalFfi`),
+///   a `#[repr(C)]` twin storing every field as its value and a
+///   `{field}_is_set` flag, whose layout doesn't depend on `Option` niches, so
+///   patches can cross a C FFI boundary. It converts `From` the generated
+///   struct, with unset fields holding their default, and back. The field
+///   types must themselves be FFI-safe; `nested`, `flatten`, `double_option`
+///   and option-like fields are rejected.
+/// - `#[optionalize(report)]` generates `{Name}Diff` (e.g. `UserDiff`), a
+///   report of how two values differ for audit UIs and webhooks, built with
+///   `UserDiff::between(&old, &new)`. Each field holds `None` when unchanged,
+///   or a `{Name}Change` with the value(s): `Added` and `Removed` when an
+///   `Option` field gains or loses its value, and `Changed` otherwise.
+///   Sensitive fields are never reported. Use
+///   `report(derive(Debug, Serialize))` to derive traits on both types; with
+///   serde's, changes are tagged with their `kind` and unchanged fields left
+///   out, e.g. `{"age": {"kind": "changed", "old": 36, "new": 37}}`.
+///   Mark a `HashMap` or `BTreeMap` field `#[optionalize(map_diff)]` to report
+///   it key by key instead, as a map of the same kind from each added, removed
+///   or changed key to its `{Name}Change`.
+///   Mark a `Vec` field `#[optionalize(vec_diff = "position")]` to compare its
+///   elements index by index, or `#[optionalize(diff_key = "id")]` to match
+///   them by a field wherever they are, reporting it as a list of
+///   `{Name}ElementChange`s: `Insert`, `Remove` or `Update` with the element's
+///   index, rather than the whole list.
+/// - `#[optionalize(display)]` implements `Display` on the generated struct,
+///   printing one `field = value` line per set field for logs and CLI output.
+///   Values use their own `Display`; cleared `double_option` fields print as
+///   `null`, sensitive fields as `[REDACTED]`, and `nested` patches print
+///   their lines prefixed with the field name, e.g. `address.city = Paris`.
+/// - `#[optionalize(impls(apply, merge))]` opts into the listed groups of
+///   generated impls. Without it the derive emits little more than the struct,
+///   to keep compile times down in codebases deriving hundreds of structs, and
+///   `impls(all)` emits every group. The struct, its error type, `empty`, the
+///   `From` conversions, the field helpers such as `set_fields`, and
+///   everything other attributes ask for are always emitted. The
+///   groups are:
+///   - `apply`: `apply_to`, `try_apply`, `validate`, `apply_with_inverse`,
+///     `apply_masked`, `apply_to_all`, `apply_all` and `with_patch`
+///   - `merge`: `merge` and `merge_with`
+///   - `diff`: `diff`, `invert` and `changed_fields`
+///   - `visit`: the field visitor and `accept`
+///   - `flat`: `set_from_str`, `parse_kv` and `FromStr`
+///   - `query`: `to_query_pairs`
+///   - `sql`: `set_params` and `to_set_clause`
+///   - `serde`: the `serde_json` conversions and the file loaders
+///
+///   A group other generated items rely on is emitted along with them, e.g.
+///   `apply` for `event`, while an integration with another crate fails to
+///   compile without the groups it calls into, e.g. `history` without
+///   `impls(apply)`. `nested` and `flatten` fields need their twin to emit
+///   the groups the parent does.
+/// - `#[optionalize(debug_expand)]` writes the code generated for the struct to
+///   `optionalize/{Struct}.rs` under `OUT_DIR` when the crate has a build
+///   script, or else under `CARGO_TARGET_DIR` or the crate's `target`
+///   directory, formatted with `rustfmt` when it's installed. Setting the
+///   `OPTIONALIZE_DEBUG_EXPAND` environment variable does the same for every
+///   struct. To step through the generated code, drop the derive and
+///   `include!` the file instead.
+/// - `#[optionalize(getters)]` generates a getter named after each `String`,
+///   `Vec` and `Box` field, borrowing its value like `as_deref`, e.g.
+///   `Option<&str>` for a `String` field or `Option<&[T]>` for a `Vec<T>`, so
+///   patch values can be compared or logged without cloning them.
+///   Mark internal fields `#[optionalize(no_helpers)]` to leave them out of
+///   these per-field conveniences: the getters, the `lens` paths and the
+///   `patch_macro` macro.
+/// - `#[optionalize(transparent)]` on a newtype with a single field, named
+///   or not, generates the optional form as a plain alias of `Option<Inner>`
+///   instead of a struct with one optional field, matching
+///   `#[serde(transparent)]`: a set patch serializes as the inner value and an
+///   unset one as `null`. As an alias can't carry methods, the original struct
+///   gets `apply(patch)`, `apply_all(patches)` and `into_optional()` instead,
+///   and none of the other generated items. Derives are rejected.
+/// - `#[optionalize(patch_macro)]` generates a `macro_rules!` named after the
+///   struct, e.g. `user_patch!`, or `patch_macro = "name"`, building the
+///   patch from the fields it lists: `user_patch! { name: "x", age: 3 }`.
+///   Values are wrapped in `Some` and converted with `Into`, so `&str` fills
+///   a `String` field; unlisted fields are left unset. The macro isn't
+///   exported, so it can be used after the struct in the same module.
+/// - `#[optionalize(lww)]` generates `{Optional}Lww` (e.g. `UserOptionalLww`),
+///   the patch with a `u64` timestamp on every set field, for syncing offline
+///   edits from several devices. Stamp a patch with `stamped(at)`, combine
+///   copies with `merge`, which keeps the newer value of each field and the
+///   greater one on equal timestamps, so it needs `PartialOrd` values, and get
+///   the winning patch back with `into_patch`. The `Debug`, `Clone`,
+///   `PartialEq` and serde derives of the generated struct are carried over.
+///   `flatten` fields are rejected.
+/// - `#[optionalize(compact)]` generates `{Optional}Compact` (e.g.
+///   `UserOptionalCompact`), a newtype around the patch serializing it as a
+///   bitmap of the set fields followed by only their values, for shipping
+///   patches over constrained links with `postcard` or `bincode`. The bitmap
+///   is the smallest unsigned integer with a bit per field, up to 128 fields.
+///   The same encoding is available as `serialize_compact` and
+///   `deserialize_compact` for `serialize_with` and `deserialize_with`. Both
+///   sides must agree on the field order; secrets are never encoded.
+/// - `#[optionalize(prost)]` derives `prost::Message` on the generated struct.
+///   Every field must carry a `#[prost(...)]` attribute, which is copied onto
+///   the generated field and marked `optional`. The derive accepts these
+///   attributes itself, so the original struct needn't be a `Message`. Repeated, map and oneof fields
+///   cannot be made optional in protobuf and are rejected. `prost::Message`
+///   already provides `Debug` and `Default`, so don't request those as well.
+/// - `#[optionalize(rocket)]` derives Rocket's `FromForm` on the generated
+///   struct, so it can be taken as a `Form<UserOptional>` data guard (or from
+///   the query string). Absent fields are `None`, as are malformed ones, like
+///   any `Option` in a Rocket form, and `nested` fields need their twin to
+///   derive `FromForm` as well. The crate using the derive must depend on
+///   `rocket`.
+/// - `#[optionalize(poem_openapi)]` derives `poem_openapi::Object` on the
+///   generated struct, so poem services can accept it as a JSON payload and
+///   document it, with every field optional and unset ones left out of its
+///   JSON. `nested` fields need their twin to derive `Object` as well, and
+///   the crate using the derive must depend on `poem-openapi`.
+/// - `#[optionalize(rkyv)]` derives `rkyv::Archive`, `rkyv::Serialize` and
+///   `rkyv::Deserialize` on the generated struct, so patches can be written to
+///   memory-mapped files or ring buffers and read back without a copy. The
+///   archived form, e.g. `ArchivedUserOptional`, implements `CheckBytes`, so
+///   untrusted bytes are validated by `rkyv::access`, and `rkyv::deserialize`
+///   turns it back into a patch to apply. Recursive `nested` fields, such as
+///   the `Option<Box<Node>>` of a linked list, are supported. Field types must
+///   be archivable, `nested` twins must be `rkyv` as well, and the crate using
+///   the derive must depend on `rkyv`.
+/// - `#[optionalize(uniffi)]` derives `uniffi::Record` on the generated
+///   struct of a non-generic struct, so Kotlin and Swift callers of a
+///   UniFFI-wrapped library can send patches across the bridge as data
+///   classes and structs whose properties are all optional. Field types must
+///   be supported by UniFFI, `nested` twins must be records as well, and the
+///   crate using the derive must depend on `uniffi` and call
+///   `uniffi::setup_scaffolding!()`.
+/// - `#[optionalize(pyo3)]` makes the generated struct of a non-generic
+///   struct a `#[pyclass]` with a getter and setter per field, so Python
+///   callers of an extension module can build partial updates natively, e.g.
+///   `UserOptional(name="Ada")`: the constructor takes every field as an
+///   optional keyword argument, and the class has `set_fields()` and a
+///   `__repr__` listing them. Functions can take it as `PyRef<UserOptional>`,
+///   or by value if it derives `Clone`. Field types must convert to and from
+///   Python, `nested` twins must be `#[pyclass]`es as well, and the crate
+///   using the derive must depend on `pyo3`.
+/// - `#[optionalize(napi)]` makes the generated struct of a non-generic
+///   struct a `#[napi(object)]`, so Node.js addons built with napi-rs can take
+///   partial updates as plain JavaScript objects, e.g. `{ name: "Ada" }`, with
+///   absent properties as `None` and every property optional in the generated
+///   TypeScript definitions. Fields must be `pub` and of types napi-rs
+///   supports, `nested` twins must be `#[napi(object)]`s as well, and the
+///   crate using the derive must depend on `napi` and `napi-derive`.
+///
+/// # Integrations
+///
+/// Each integration with another crate is picked per struct with a container
+/// attribute named after it, e.g. `#[optionalize(tracing, sha2)]`, rather
+/// than with a Cargo feature, since Cargo enables a feature for every crate in
+/// the build and others would then refer to crates they don't depend on.
+///
+/// # `serde_json` integration
+///
+/// With `#[optionalize(serde_json)]`, the generated struct also gets
+/// `set_from_json` and `apply_json`, and implements `TryFrom<HashMap<String,
+/// serde_json::Value>>` and `TryFrom<serde_json::Value>`, reporting values that
+/// fail to deserialize as `InvalidField`, unexpected keys as `UnknownField` and
+/// non-object values as `ExpectedObject`.
+/// `from_json_lenient` collects those errors, along with failed validations,
+/// instead of stopping at the first one, and returns the patch of the fields
+/// that were valid. `nested` objects are read the same way, so their valid
+/// fields are kept and their errors reported as `InvalidField` of the parent.
+/// `try_from_pairs` and `from_pairs_lenient` do the same from any iterator of
+/// field name/value pairs, such as the columns of a CSV row, and
+/// `TryFrom<Vec<(String, serde_json::Value)>>` builds one from ordered pairs.
+/// `to_json_object` goes the other way, rendering the set fields as a JSON
+/// object with `nested` patches as objects of their own set fields.
+/// `to_btree_map` renders the same entries sorted by key, objects within the
+/// values included, so patches can be compared, hashed and logged the same
+/// way across runs whether or not `serde_json` preserves insertion order.
+/// `from_change_event` builds a patch from a Debezium-style change event,
+/// setting only the columns that differ between its `before` and `after` rows.
+/// `audit_document(&before)` renders the fields the patch would change as
+/// `{"field": {"old": ..., "new": ...}}`, for audit tables.
+///
+/// # `json-patch` integration
+///
+/// With `#[optionalize(json_patch)]` (which implies `serde_json`), the
+/// generated struct gets `apply_json_patch`, which applies a
+/// `json_patch::Patch` of RFC 6902 operations onto a value through its typed
+/// fields, all or nothing.
+/// Paths must start with a field name; unknown ones are reported as
+/// `UnknownField`, and failed `test` operations as `InvalidDocument`.
+/// Changed fields are written with `try_apply`, and removing an `Option`
+/// field clears it. The crate using the derive must depend on `json-patch`.
+///
+/// # `history` integration
+///
+/// With `#[optionalize(history)]`, an `{Optional}History` type (e.g.
+/// `UserOptionalHistory`) records applied patches along with their inverses
+/// from `apply_with_inverse`, and supports `undo` and `redo`.
+///
+/// # `tracing` integration
+///
+/// With `#[optionalize(tracing)]`, `apply_to` (and so `try_apply`) and
+/// `apply_with_inverse` emit a `debug` event with the names of the fields the
+/// patch sets, and record them as a comma-separated list on the current span's
+/// `patched_fields` field, if it declares one (e.g. with
+/// `patched_fields = tracing::field::Empty`). With
+/// `#[optionalize(trace_values)]`, the event also carries the patch's `Debug`
+/// output, with sensitive fields redacted; this requires the generated struct
+/// to derive `Debug`. A patch setting `immutable` fields, which these
+/// methods skip, also emits a `warn` event naming them. The crate using the
+/// derive must depend on `tracing`.
+///
+/// # `zeroize` integration
+///
+/// With `#[optionalize(zeroize)]`, a generated struct with `sensitive` fields
+/// derives `zeroize::Zeroize`, wiping only those fields, so callers can clear
+/// a credential-bearing patch they hold with `patch.zeroize()`. `apply_to`
+/// also wipes the values of sensitive fields it overwrites, so replaced
+/// passwords and keys don't linger in freed memory. `ZeroizeOnDrop` isn't
+/// derived, since a `Drop` impl would keep patches from being consumed field
+/// by field, so dropping a patch doesn't wipe it: callers must call
+/// `zeroize()` on patches they drop without applying, such as rejected or
+/// merged ones, and the generated struct's docs say so. `secrecy` secrets
+/// already wipe themselves and are left alone.
+/// Sensitive field types must implement `Zeroize`, and the crate using the
+/// derive must depend on `zeroize` with its `derive` feature.
+///
+/// # `toml` integration
+///
+/// With `#[optionalize(toml)]`, generated structs deriving `Deserialize` get
+/// `from_toml_str`, which parses a partial TOML document (missing keys are
+/// unset), and `merge_toml`, which parses an override file and applies it onto
+/// a value such as the defaults with `try_apply`, reporting parse errors as
+/// `InvalidDocument`. The crate using the derive must depend on `toml`.
+///
+/// # `yaml` integration
+///
+/// With `#[optionalize(yaml)]`, generated structs deriving `Deserialize`
+/// likewise get `from_yaml_str` and `merge_yaml`, e.g. for Kubernetes-style
+/// overlays.
+/// The crate using the derive must depend on `serde_yaml`.
+///
+/// # `config` integration
+///
+/// With `#[optionalize(config)]`, generated structs deriving `Serialize`
+/// implement the `config` crate's `Source`, so a typed patch can be layered
+/// into a `ConfigBuilder` with `add_source`, alongside files and environment
+/// variables, e.g. for overrides from command-line flags. Only the set fields
+/// are collected, under dotted keys such as `server.port` for `nested`
+/// fields, so they override lower layers key by key. The generated struct
+/// must also derive `Clone` and `Debug`, and the crate using the derive must
+/// depend on `config`.
+///
+/// # `serde_qs` integration
+///
+/// With `#[optionalize(serde_qs)]`, generated structs deriving `Deserialize`
+/// get `from_query_str`, which parses a query string with nested bracketed
+/// keys, such as `term=lamp&tags[0]=desk&price[max]=50`, so filter and patch
+/// parameters can come from URLs. Missing keys are unset, and `nested` patches,
+/// optional or not, are only set when one of their keys is present.
+/// The crate using the derive must depend on `serde_qs`.
+///
+/// # `axum` integration
+///
+/// With `#[optionalize(axum)]` (which implies `serde_json`), the generated
+/// struct implements axum's `FromRequest`, so handlers can take the patch
+/// directly as an extractor. Unknown or badly typed fields are rejected with
+/// `422 Unprocessable Entity` and empty patches with `400 Bad Request`.
+///
+/// # `axum-extra` integration
+///
+/// With `#[optionalize(axum_extra)]` (which implies `axum`), generated structs
+/// with `#[optionalize(query)]` implement axum's `FromRequestParts` through
+/// axum-extra's `Query` instead of the JSON body extractor, so a handler can
+/// take `UserOptional` as the filter of `GET /users?name=Ada&age=36`.
+/// Query strings that don't deserialize are rejected with
+/// `400 Bad Request`. The crate using the derive must depend on axum-extra
+/// with its `query` feature.
+///
+/// # `actix-web` integration
+///
+/// With `#[optionalize(actix_web)]` (which implies `serde_json`), the generated
+/// struct implements actix-web's `FromRequest`, accepting `application/json`
+/// and `application/merge-patch+json` bodies, and the generated error type
+/// implements `ResponseError` so `try_apply` errors can be returned from
+/// handlers with `?`.
+///
+/// # `tokio-postgres` integration
+///
+/// With `#[optionalize(tokio_postgres)]`, the generated struct gets
+/// `postgres_params`, the `(column, value)` pairs of its set fields, and
+/// `to_postgres_set`, which renders them as the body of an `UPDATE`'s `SET`
+/// clause with numbered placeholders, along with the parameters to bind.
+/// Immutable, secret, option-like and `nested` fields are left out, and
+/// flattened fields contribute their own columns. The crate using the derive
+/// must depend on `tokio-postgres`.
+///
+/// # `rusqlite` integration
+///
+/// With `#[optionalize(rusqlite)]`, the generated struct gets `sqlite_params`,
+/// `to_sqlite_update`, which renders an `UPDATE` of a single row keyed by a
+/// column, and `sqlite_update`, which runs it on a `rusqlite::Connection`,
+/// binding only the set fields. The table and key column names are quoted
+/// with any `"` in them doubled, so they can't inject SQL. The same fields as
+/// with `tokio-postgres` are left out. The crate using the derive must depend
+/// on `rusqlite`.
+///
+/// # `surrealdb` integration
+///
+/// With `#[optionalize(surrealdb)]` (which implies `serde_json`), the generated
+/// struct gets `to_surreal_merge`, the content of a SurrealDB `MERGE` writing
+/// only the set fields, with `nested` patches as partial objects.
+///
+/// # `elasticsearch` integration
+///
+/// With `#[optionalize(elasticsearch)]` (which implies `serde_json`), the
+/// generated struct gets `to_es_partial_doc`, the `{"doc": {...}}` body of an
+/// `_update` request holding only the set fields, so an indexed document can be
+/// patched from the same struct as the database row.
+///
+/// # `dynamodb` integration
+///
+/// With `#[optionalize(dynamodb)]` (which implies `serde_json`), the generated
+/// struct gets `to_dynamo_update`, the `UpdateExpression` writing the set
+/// fields along with its `ExpressionAttributeNames` and
+/// `ExpressionAttributeValues`. `nested` patches write through document paths
+/// and cleared `double_option` fields are removed. Values are plain JSON, to be
+/// converted with e.g. `serde_dynamo`, so no AWS SDK version is assumed.
+///
+/// # `sha2` integration
+///
+/// With `#[optionalize(sha2)]` (which implies `serde_json`), the generated
+/// struct gets `content_hash`, a hex-encoded SHA-256 digest of the key-ordered
+/// JSON of its set fields, equal for equal patches across runs, so duplicate
+/// PATCH requests can be detected server-side. The crate using the derive must
+/// depend on `sha2`.
+///
+/// # `ansi` integration
+///
+/// With `#[optionalize(ansi)]`, the generated struct gets `render_diff`, which
+/// renders the fields that differ between two values as red `-` and green `+`
+/// lines for CLI tools, and `render_against`, which renders what applying the
+/// patch onto a value would change. Values are formatted with `Debug`, and
+/// sensitive fields are left out.
+///
+/// # `similar` integration
+///
+/// With `#[optionalize(similar)]`, every `String` field the patch may write
+/// gets helpers exchanging small edits instead of the whole text, e.g. for
+/// collaborative editing of long descriptions: `{field}_diff_lines` and
+/// `{field}_diff_words` compute the edits turning one text into another, as
+/// `(start, len, text)` byte ranges of the old text, and `apply_{field}_edits`
+/// applies them onto a value, failing with `InvalidField` when they don't fit
+/// its current text. The crate using the derive must depend on `similar`.
+///
+/// # `dialoguer` integration
+///
+/// With `#[optionalize(dialoguer)]`, the generated struct gets
+/// `fill_interactive`, which prompts on the terminal for every unset field,
+/// parsing the answers with `set_from_str` and asking again until they parse,
+/// so CLI wizards can complete a partial config. The values of an optional
+/// `defaults` struct are offered as the answers, `Option` fields may be left
+/// empty, `nested` patches are filled in turn, and sensitive fields are read
+/// without echo. Field types must implement `FromStr` and `Display`, those of
+/// `nested` twins included, and the crate using the derive must depend on
+/// `dialoguer`.
+///
+/// # `merge` integration
+///
+/// With `#[optionalize(merge_crate)]`, the generated struct implements the
+/// `merge` crate's `Merge`, so it plugs into tooling built around that trait,
+/// e.g.
+/// layering configuration sources. The trait merges like the inherent `merge`:
+/// the container's `merge` policy decides which side wins, and `vec_merge`,
+/// `merge_key`, `merge_entries` and `merge_with` override it per field.
+/// Patches with `merge = "error_on_conflict"` don't implement it, as the trait
+/// can't fail. The crate using the derive must depend on `merge`.
+///
+/// # `metrics` integration
+///
+/// With `#[optionalize(metrics)]`, `apply_to` records every applied patch
+/// through the `metrics` facade, labelled with the generated struct's name as
+/// `patch`: the counters `optionalize_patches_applied_total` and
+/// `optionalize_empty_patches_total`, for patches setting no field, and the
+/// histograms `optionalize_patch_fields`, the number of set fields, and
+/// `optionalize_apply_duration_seconds`. `try_apply` counts the patches its
+/// checks reject as `optionalize_patches_rejected_total`. Nothing is recorded
+/// until a recorder is installed. The crate using the derive must depend on
+/// `metrics`.
+///
+/// # `tokio` integration
+///
+/// With `#[optionalize(tokio)]`, the generated struct gets `apply_stream`,
+/// which applies patches from a `Stream` onto a value as they arrive, e.g. to
+/// keep live state in sync with a message bus. Patches go through `try_apply`,
+/// and the returned stream yields the names of the fields each one set, or the
+/// error it was rejected with; rejected patches leave the value untouched.
+/// The crate using the derive must depend on `tokio-stream`.
+///
+/// # `optionalize` runtime crate
+///
+/// The `optionalize` crate re-exports these macros along with shared traits.
+/// In crates depending on it, an original struct emitting the `apply` group
+/// implements `optionalize::Optionalize`, naming the generated struct as its
+/// `Optional` type, which implements `ApplyPatch` and, when it also emits
+/// `merge` and its `merge` can't fail, `Merge` and `Patch`. With `diff`, the
+/// generated struct implements `Diff`. It also gets `FIELDS`, the name, type,
+/// docs and original optionality of every field as `optionalize::FieldMeta`,
+/// also available as `Optionalize::FIELDS`, for admin tools and code
+/// generators. With `apply`, those without lifetime parameters implement the
+/// object-safe `AnyPatch`, so patches of different structs can be queued
+/// together as `Box<dyn AnyPatch>` and applied onto a `&mut dyn Any`. With the
+/// runtime crate's `registry` feature, non-generic structs deriving
+/// `Deserialize` also register under their path, so
+/// `optionalize::deserialize_patch` turns
+/// `{ "type": "app::models::UserOptional", "data": { ... } }` into such a
+/// boxed patch, and structs of the same name in different modules don't
+/// collide.
+/// Errors of `Deoptionalize` convert into `MissingFieldsError`. Code
+/// generic over patches can then be written once for every struct. Whether
+/// to emit these impls is decided for each crate deriving from its own
+/// dependencies, so crates built alongside one depending on `optionalize`
+/// are unaffected.
+///
+/// # `struct-patch` integration
+///
+/// With `#[optionalize(struct_patch)]`, the original struct implements
+/// `struct_patch::Patch` with the generated struct as its patch type, so code
+/// written against that trait works with both crates: `apply` is `apply_to`,
+/// `into_patch` is `From`, `into_patch_by_diff` is `diff` and
+/// `new_empty_patch` is `empty`. The crate using the derive must depend on
+/// `struct-patch`.
+///
+#[proc_macro_derive(Optionalize, attributes(optionalize, prost))]
 pub fn derive_optionalize(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
     let input = parse_macro_input!(input as DeriveInput);
 
-    // Get the struct name
-    let struct_name = input.ident.clone();
+    match expand(input, false) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// [`Optionalize`](derive@Optionalize) under another name, for codebases that
+/// speak of partial rather than optional values.
+///
+/// It takes the same `#[optionalize(...)]` attributes and generates the same
+/// items, but names them `Partial{Struct}` by default, e.g. `PartialUser` and
+/// `PartialUserError`, and so expects `nested` twins to be named
+/// `Partial{Type}` as well, like structs deriving `Partial` are.
+///
+/// ```rust
+/// use optionalize_macro::Partial;
+///
+/// #[derive(Partial)]
+/// struct User {
+///     id: i32,
+///     name: String,
+/// }
+///
+/// let patch = PartialUser { id: None, name: Some("Ada".to_string()) };
+/// assert_eq!(patch.set_fields(), vec!["name"]);
+/// ```
+#[proc_macro_derive(Partial, attributes(optionalize, prost))]
+pub fn derive_partial(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input, true) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// The reverse of [`Optionalize`](derive@Optionalize): for a struct of
+/// `Option` fields, such as a draft being filled in, generates
+/// `{Struct}Required` (e.g. `PostDraftRequired`) with those fields unwrapped.
+///
+/// `TryFrom<PostDraft>` converts the draft once every field is set, failing
+/// with `{Struct}RequiredError` listing all the missing ones, and `From` goes
+/// back. Fields that aren't `Option`s are copied as-is.
+///
+/// - `#[deoptionalize(derive(...))]` adds derives to the generated struct.
+/// - `#[deoptionalize(name = "PublishedPost")]` names the generated struct.
+/// - `#[deoptionalize(optional)]` on a field keeps it an `Option`.
+///
+/// ```rust
+/// use optionalize_macro::Deoptionalize;
+///
+/// #[derive(Deoptionalize)]
+/// #[deoptionalize(derive(Debug))]
+/// struct PostDraft {
+///     title: Option<String>,
+///     body: Option<String>,
+///     #[deoptionalize(optional)]
+///     summary: Option<String>,
+/// }
+///
+/// let draft = PostDraft { title: Some("Hello".to_string()), body: None, summary: None };
+/// let err = PostDraftRequired::try_from(draft).unwrap_err();
+/// assert_eq!(err.missing, ["body"]);
+/// ```
+#[proc_macro_derive(Deoptionalize, attributes(deoptionalize))]
+pub fn derive_deoptionalize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match deoptionalize::expand(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Derives [`Optionalize`](derive@Optionalize) for every struct with named
+/// fields in an inline module, including nested modules.
+///
+/// Arguments are shared container attributes, applied to every struct as if
+/// written in `#[optionalize(...)]` on each of them. Structs that already derive
+/// `Optionalize` are left alone.
+///
+/// `depth = N`, on a struct of the module or shared, marks `nested` the fields
+/// holding another struct of the same module, directly, in an `Option` or in a
+/// `Box`, down to `N` levels from that struct, so deep configuration trees
+/// don't need `#[optionalize(nested)]` at every level. As every struct has a
+/// single twin, a struct nested this way is nested for all its users.
+///
+/// ```rust
+/// #[optionalize_macro::optionalize_all(derive(Debug, Default))]
+/// mod dto {
+///     pub struct User {
+///         pub id: i32,
+///         pub name: String,
+///     }
+///
+///     pub struct Team {
+///         pub name: String,
+///     }
+/// }
+///
+/// let user = dto::UserOptional::default();
+/// let team = dto::TeamOptional { name: Some("Core".to_string()) };
+/// ```
+#[proc_macro_attribute]
+pub fn optionalize_all(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = proc_macro2::TokenStream::from(args);
+    let input = parse_macro_input!(input as ItemMod);
+
+    match module::expand(args, input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
 
-    // Generate a new name for the "optionalized" struct
-    let optional_struct_name = syn::Ident::new(&format!("{}Optional", struct_name), struct_name.span());
+fn expand(input: DeriveInput, partial: bool) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(expanded) = transparent::expand(&input, partial)? {
+        return Ok(expanded);
+    }
+    let cx = Container::from_input(input, partial)?;
 
-    // Build the fields for the new struct
-    let fields = if let Data::Struct(data_struct) = input.data {
-        data_struct.fields
+    let optional_ident = &cx.optional_ident;
+    let mut derives: Vec<_> = cx.derives().into_iter().map(|path| quote!(#path)).collect();
+    if cx.attrs.prost {
+        derives.push(quote!(::prost::Message));
+    }
+    if cx.attrs.rocket {
+        derives.push(quote!(::rocket::FromForm));
+    }
+    if cx.attrs.poem_openapi {
+        derives.push(quote!(::poem_openapi::Object));
+    }
+    if cx.attrs.rkyv {
+        derives.push(quote!(::rkyv::Archive, ::rkyv::Serialize, ::rkyv::Deserialize));
+    }
+    if cx.zeroizes() {
+        derives.push(quote!(::zeroize::Zeroize));
+    }
+    if cx.attrs.uniffi {
+        derives.push(quote!(::uniffi::Record));
+    }
+    let derive = if derives.is_empty() {
+        quote! {}
     } else {
-        // Only work with structs
-        return syn::Error::new_spanned(input, "Optionalize can only be used on structs")
-            .to_compile_error()
-            .into();
+        quote! { #[derive(#( #derives ),*)] }
     };
+    let mut attrs: Vec<_> = cx.attrs.attrs.iter().map(|meta| quote! { #[#meta] }).collect();
+    attrs.push(rkyv_support::container_attr(&cx));
+    attrs.push(pyo3_support::pyclass(&cx));
+    if cx.attrs.napi {
+        attrs.push(quote! { #[::napi_derive::napi(object)] });
+    }
+    // Unset fields are left out of responses rather than sent as `null`
+    if cx.attrs.poem_openapi {
+        attrs.push(quote! { #[oai(skip_serializing_if_is_none)] });
+    }
+    // Derived serde impls take the same bounds as the generated ones
+    if let Some(bound) = &cx.attrs.bound {
+        if cx.derives_serialize() || cx.derives_deserialize() {
+            let bound = quote!(#( #bound ),*).to_string();
+            attrs.push(quote! { #[serde(bound = #bound)] });
+        }
+    }
+    let (_, _, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    // Document the generated struct, always linking back to the original
+    let source_link = format!("Generated from [`{}`] by `Optionalize`.", cx.ident);
+    let summary = cx.attrs.doc.clone().unwrap_or_else(|| {
+        format!("Optional version of [`{}`], with every field wrapped in `Option`.", cx.ident)
+    });
+    let mut doc = quote! {
+        #[doc = #summary]
+        #[doc = ""]
+        #[doc = #source_link]
+    };
+    // Dropped patches aren't wiped, so warn where callers will look
+    if cx.zeroizes() {
+        doc.extend(quote! {
+            #[doc = ""]
+            #[doc = "Dropping a patch doesn't wipe its sensitive fields: call `zeroize()` on patches dropped without being applied."]
+        });
+    }
 
     // Create fields with Option types
-    let optional_fields = fields.iter().map(|field| {
-        let field_name = &field.ident;
-        let field_type = &field.ty;
-
-        // Check if the field is already an Option<T>
-        if let Type::Path(TypePath { path, .. }) = field_type {
-            if path.segments.last().map(|s| s.ident == "Option").unwrap_or(false) {
-                // Field is already an Option<T>, keep it as is
-                quote! { #field_name: #field_type }
-            } else {
-                // Wrap the field type in Option<T>
-                quote! { #field_name: Option<#field_type> }
+    let optional_fields = cx
+        .fields
+        .iter()
+        .map(|field| {
+            let vis = &field.vis;
+            let field_name = &field.ident;
+            let field_type = field.optional_ty();
+            let mut attrs = serde_support::field_attrs(&cx, field);
+            attrs.extend(schema_support::field_attrs(&cx, field));
+            if cx.attrs.prost {
+                attrs.push(prost_support::field_attr(field)?);
             }
-        } else {
-            // Wrap non-path types (like tuples) in Option<T>
-            quote! { #field_name: Option<#field_type> }
-        }
+            attrs.push(rkyv_support::field_attr(&cx, field));
+            // Only the sensitive values need wiping
+            if cx.zeroizes() && !field.zeroizes() {
+                attrs.push(quote! { #[zeroize(skip)] });
+            }
+            // Pass-through attributes, for integrations the macro doesn't know about
+            let extra = cx.attrs.field_attrs.iter().chain(&field.attrs.field_attrs);
+            attrs.extend(extra.map(|meta| quote! { #[#meta] }));
+            Ok(quote! { #( #attrs )* #vis #field_name: #field_type })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    // Marker fields are copied as-is
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
     });
 
+    let error = error::expand(&cx);
+    let assert = assert::expand(&cx);
+    let validate = validate::expand(&cx);
+    let apply = apply::expand(&cx);
+    let convert = convert::expand(&cx);
+    let inverse = inverse::expand(&cx);
+    let merge = merge::expand(&cx);
+    let diff = diff::expand(&cx);
+    let report = report::expand(&cx)?;
+    let history = history::expand(&cx);
+    let event = event::expand(&cx)?;
+    let ops = ops::expand(&cx);
+    let shared = shared::expand(&cx);
+    let wrapper = wrapper::expand(&cx)?;
+    let lww = lww::expand(&cx)?;
+    let compact = compact::expand(&cx)?;
+    let builder = builder::expand(&cx);
+    let filter = filter::expand(&cx)?;
+    let ffi = ffi::expand(&cx)?;
+    let lens = lens::expand(&cx);
+    let fields = fields::expand(&cx);
+    let visit = visit::expand(&cx);
+    let flat = flat::expand(&cx);
+    let redact = redact::expand(&cx);
+    let display = display::expand(&cx);
+    let patch_macro = patch_macro::expand(&cx);
+    let serde_support = serde_support::expand(&cx)?;
+    let version = version::expand(&cx)?;
+    let query = query::expand(&cx);
+    let sql = sql::expand(&cx);
+    let postgres = postgres_support::expand(&cx);
+    let sqlite = sqlite_support::expand(&cx);
+    let surreal = surreal_support::expand(&cx);
+    let elasticsearch = elasticsearch_support::expand(&cx);
+    let dynamodb = dynamodb_support::expand(&cx);
+    let hash = hash_support::expand(&cx);
+    let ansi = ansi_support::expand(&cx);
+    let similar = similar_support::expand(&cx);
+    let dialoguer = dialoguer_support::expand(&cx);
+    let merge_support = merge_support::expand(&cx);
+    let metrics = metrics_support::expand(&cx);
+    let runtime = runtime_support::expand(&cx);
+    let struct_patch = struct_patch_support::expand(&cx);
+    let tokio = tokio_support::expand(&cx);
+    let json = json::expand(&cx);
+    let json_patch = json_patch_support::expand(&cx);
+    let document = document::expand(&cx);
+    let config = config_support::expand(&cx);
+    let axum = axum_support::expand(&cx);
+    let axum_extra = axum_extra_support::expand(&cx);
+    let actix = actix_support::expand(&cx);
+    let pyo3 = pyo3_support::expand(&cx);
+
     // Generate the output tokens
     let expanded = quote! {
         // Define the new struct with optionalized fields
-        pub struct #optional_struct_name {
+        #doc
+        #derive
+        #( #attrs )*
+        #[must_use]
+        pub struct #optional_ident <#generics> #where_clause {
             #( #optional_fields, )*
+            #( #markers, )*
         }
-    };
 
-    TokenStream::from(expanded)
+        #error
+        #assert
+        #validate
+        #apply
+        #convert
+        #inverse
+        #merge
+        #diff
+        #report
+        #history
+        #event
+        #ops
+        #shared
+        #wrapper
+        #lww
+        #compact
+        #builder
+        #filter
+        #ffi
+        #lens
+        #fields
+        #visit
+        #flat
+        #redact
+        #display
+        #patch_macro
+        #serde_support
+        #version
+        #query
+        #sql
+        #postgres
+        #sqlite
+        #surreal
+        #elasticsearch
+        #dynamodb
+        #hash
+        #ansi
+        #similar
+        #dialoguer
+        #merge_support
+        #metrics
+        #runtime
+        #struct_patch
+        #tokio
+        #json
+        #json_patch
+        #document
+        #config
+        #axum
+        #axum_extra
+        #actix
+        #pyo3
+    };
+    let expanded = cfg::gate(cx.attrs.cfg.as_ref(), expanded)?;
+    debug_expand::write(&cx, &expanded)?;
+    Ok(expanded)
 }
 
@@ -0,0 +1,158 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Result};
+
+use crate::ast::{path_ends_with, Container};
+
+/// Generates `{Optional}Lww`, the patch with a timestamp on every set field,
+/// and a merge keeping the newer value of each field. Only emitted with
+/// `#[optionalize(lww)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    if !cx.attrs.lww {
+        return Ok(quote! {});
+    }
+
+    // Every field needs a value of its own to stamp
+    if let Some(field) = cx.fields.iter().find(|field| field.attrs.flatten) {
+        return Err(Error::new_spanned(&field.ident, "`lww` doesn't support `flatten` fields"));
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let lww_ident = format_ident!("{}Lww", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    // Only derives the stamped values support are carried over
+    let derives: Vec<_> = cx
+        .derives()
+        .into_iter()
+        .filter(|path| {
+            ["Debug", "Clone", "PartialEq", "Serialize", "Deserialize"]
+                .iter()
+                .any(|name| path_ends_with(path, name))
+        })
+        .collect();
+    let serde = cx.derives_serialize() || cx.derives_deserialize();
+    let derive = if derives.is_empty() {
+        quote! {}
+    } else {
+        quote! { #[derive(#( #derives ),*)] }
+    };
+    let (serde_bound, field_serde) = if serde {
+        let bound = match &cx.attrs.bound {
+            Some(bound) => {
+                let bound = quote!(#( #bound ),*).to_string();
+                quote! { #[serde(bound = #bound)] }
+            }
+            None => quote! {},
+        };
+        (bound, quote! { #[serde(default, skip_serializing_if = "Option::is_none")] })
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    let idents: Vec<_> = cx.fields.iter().map(|field| &field.ident).collect();
+    let tys: Vec<_> = cx.fields.iter().map(|field| field.inner_ty()).collect();
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+    let fields = cx.fields.iter().map(|field| {
+        let vis = &field.vis;
+        let ident = &field.ident;
+        let ty = field.inner_ty();
+        quote! {
+            #field_serde
+            #vis #ident: ::std::option::Option<(u64, #ty)>
+        }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+
+    let stamped = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let is_set = field.is_set();
+        if field.option_like.is_some() {
+            quote! { #ident: if #is_set { Some((at, self.#ident)) } else { None } }
+        } else {
+            quote! { #ident: self.#ident.map(|value| (at, value)) }
+        }
+    });
+    let unstamped = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        if field.option_like.is_some() {
+            let unset = field.unset();
+            quote! {
+                #ident: match self.#ident {
+                    Some((_, value)) => value,
+                    None => #unset,
+                }
+            }
+        } else {
+            quote! { #ident: self.#ident.map(|(_, value)| value) }
+        }
+    });
+
+    let doc = format!(
+        "A [`{}`] whose set fields each carry the timestamp they were written at, for syncing offline edits with last-write-wins merges.",
+        optional_ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Build one with `stamped`, combine copies from several devices with
+        /// `merge`, and turn the result back into a patch with `into_patch`.
+        #derive
+        #serde_bound
+        #[must_use]
+        pub struct #lww_ident <#generics> #where_clause {
+            #( #fields, )*
+            #( #markers, )*
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Stamps every set field with `at`, e.g. milliseconds since the
+            /// epoch or a hybrid logical clock.
+            pub fn stamped(self, at: u64) -> #lww_ident #ty_generics {
+                #lww_ident {
+                    #( #stamped, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics #lww_ident #ty_generics #where_clause {
+            /// Keeps, for every field, the value with the newer timestamp.
+            ///
+            /// On equal timestamps the greater value wins, so replicas merging
+            /// the same writes in any order converge on the same values. Values
+            /// that don't compare, such as `NaN`, keep `self`'s.
+            pub fn merge(&mut self, other: Self)
+            where
+                #( for<'__optionalize> #tys: ::std::cmp::PartialOrd, )*
+            {
+                #(
+                    if let Some((at, value)) = other.#idents {
+                        let newer = match &self.#idents {
+                            Some((current_at, current)) => (at, &value) > (*current_at, current),
+                            None => true,
+                        };
+                        if newer {
+                            self.#idents = Some((at, value));
+                        }
+                    }
+                )*
+            }
+
+            /// Drops the timestamps, keeping the newest value of every field.
+            pub fn into_patch(self) -> #optional_ident #ty_generics {
+                #optional_ident {
+                    #( #unstamped, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,312 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse_quote;
+
+use crate::ast::{camel_case, type_ends_with, Container, Field};
+use crate::attr::MergePolicy;
+use crate::collection;
+
+/// Generates `merge`, which combines two patches into one, and `merge_with`,
+/// which asks a resolver about every field set in both.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("merge") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let policy = cx.attrs.merge;
+
+    let fields = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let value = merged(field, policy);
+        quote! { #ident: #value }
+    });
+    let markers = cx.markers.iter().map(|marker| &marker.ident);
+    let merged = quote! {
+        Self {
+            #( #fields, )*
+            #( #markers: ::std::marker::PhantomData, )*
+        }
+    };
+
+    let merge = match policy {
+        MergePolicy::PreferSelf => quote! {
+            /// Combines two patches: fields set in `self` win over those set in
+            /// `other`, which only fills in the rest.
+            #[must_use]
+            #[inline]
+            pub fn merge(self, other: Self) -> Self {
+                #merged
+            }
+        },
+        MergePolicy::PreferOther => quote! {
+            /// Combines two patches, as if `self` was applied first and `other`
+            /// after it: fields set in `other` win over those set in `self`.
+            #[must_use]
+            #[inline]
+            pub fn merge(self, other: Self) -> Self {
+                #merged
+            }
+        },
+        MergePolicy::ErrorOnConflict => {
+            let conflicts = cx.fields.iter().map(conflict);
+            quote! {
+                /// Combines two patches that set disjoint fields, failing with
+                /// `MergeConflict` when both set the same field.
+                #[inline]
+                pub fn merge(self, other: Self) -> Result<Self, #error_ident> {
+                    let mut fields = Vec::new();
+                    #( #conflicts )*
+                    if !fields.is_empty() {
+                        return Err(#error_ident::MergeConflict { fields });
+                    }
+
+                    Ok(#merged)
+                }
+            }
+        }
+    };
+
+    let merge_with = merge_with(cx);
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            #merge
+        }
+
+        #merge_with
+    }
+}
+
+/// Generates `{Optional}FieldPair` and `{Optional}Choice`, and `merge_with`,
+/// which lets a resolver pick a side for every field set in both patches.
+fn merge_with(cx: &Container) -> TokenStream {
+    let optional_ident = &cx.optional_ident;
+    let pair_ident = format_ident!("{}FieldPair", optional_ident);
+    let choice_ident = format_ident!("{}Choice", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    // The pair borrows both patches
+    let mut pair_generics = cx.generics.clone();
+    pair_generics.params.insert(0, parse_quote!('__pair));
+    let pair_params = &pair_generics.params;
+    let (_, pair_ty_generics, _) = pair_generics.split_for_impl();
+    let markers: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+    let marker_tys: Vec<_> = cx.markers.iter().map(|marker| &marker.ty).collect();
+
+    let variants = cx.fields.iter().map(|field| {
+        let variant = format_ident!("{}", camel_case(&field.ident));
+        let ty = field.inner_ty();
+        let doc = format!("The `{}` field.", field.name());
+        quote! {
+            #[doc = #doc]
+            #variant { ours: &'__pair #ty, theirs: &'__pair #ty }
+        }
+    });
+    // The lifetime, and parameters only used by marker fields, still need a use
+    let marker_variant = quote! {
+        #[doc(hidden)]
+        __Marker(::std::convert::Infallible, ::std::marker::PhantomData<&'__pair ()>, #( #marker_tys ),*),
+    };
+
+    let merged = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        let variant = format_ident!("{}", camel_case(ident));
+        let theirs_win = quote! {
+            resolver(#name, #pair_ident::#variant { ours: &ours, theirs: &theirs }) == #choice_ident::Theirs
+        };
+
+        // Flattened patches only conflict on the fields both set
+        if field.attrs.flatten {
+            return quote! {
+                #ident: {
+                    let (ours, theirs) = (self.#ident, other.#ident);
+                    let set = theirs.set_fields();
+                    let overlap = ours.set_fields().iter().any(|field| set.contains(field));
+                    if overlap && !(#theirs_win) {
+                        ours.__optionalize_fill(theirs)
+                    } else {
+                        theirs.__optionalize_fill(ours)
+                    }
+                }
+            };
+        }
+
+        // Nested patches keep the fields of the losing side the winner leaves unset
+        if field.nested_ty.is_some() {
+            let fill = field.twin_fn("__optionalize_fill");
+            let (ours_first, theirs_first) = if type_ends_with(field.inner_ty(), "Box") {
+                (
+                    quote! { ::std::boxed::Box::new(#fill(*ours, *theirs)) },
+                    quote! { ::std::boxed::Box::new(#fill(*theirs, *ours)) },
+                )
+            } else {
+                (quote! { #fill(ours, theirs) }, quote! { #fill(theirs, ours) })
+            };
+            return quote! {
+                #ident: match (self.#ident, other.#ident) {
+                    (Some(ours), Some(theirs)) => Some(if #theirs_win { #theirs_first } else { #ours_first }),
+                    (ours, theirs) => theirs.or(ours),
+                }
+            };
+        }
+
+        if let Some(is_unset) = &field.option_like {
+            return quote! {
+                #ident: {
+                    let (ours, theirs) = (self.#ident, other.#ident);
+                    let theirs_win = if ours.#is_unset() || theirs.#is_unset() {
+                        !theirs.#is_unset()
+                    } else {
+                        #theirs_win
+                    };
+                    if theirs_win { theirs } else { ours }
+                }
+            };
+        }
+
+        quote! {
+            #ident: match (self.#ident, other.#ident) {
+                (Some(ours), Some(theirs)) => Some(if #theirs_win { theirs } else { ours }),
+                (ours, theirs) => theirs.or(ours),
+            }
+        }
+    });
+
+    let pair_doc = format!(
+        "A field set in both patches passed to [`{}::merge_with`], with both values.",
+        optional_ident
+    );
+    let choice_doc = format!("The side [`{}::merge_with`] keeps for a field set in both patches.", optional_ident);
+
+    quote! {
+        #[doc = #pair_doc]
+        pub enum #pair_ident<#pair_params> #where_clause {
+            #( #variants, )*
+            #marker_variant
+        }
+
+        #[doc = #choice_doc]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum #choice_ident {
+            /// Keep the value of `self`.
+            Ours,
+            /// Keep the value of `other`.
+            Theirs,
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Combines two patches, calling `resolver` with the name and both
+            /// values of every field set in both to pick the one to keep, e.g.
+            /// to ask the user or apply a per-field policy. Fields set on one
+            /// side only are kept as-is.
+            ///
+            /// `nested` and `flatten` fields are resolved as a whole: the
+            /// picked patch wins for the fields it sets, and the other one
+            /// fills in the rest. Collections are taken from the picked side.
+            pub fn merge_with(
+                self,
+                other: Self,
+                mut resolver: impl for<'__pair> FnMut(&'static str, #pair_ident #pair_ty_generics) -> #choice_ident,
+            ) -> Self {
+                Self {
+                    #( #merged, )*
+                    #( #markers: ::std::marker::PhantomData, )*
+                }
+            }
+
+            /// Combines two patches, keeping the fields of `self` over those of `rest`.
+            #[doc(hidden)]
+            #[inline]
+            pub fn __optionalize_fill(self, rest: Self) -> Self {
+                self.merge_with(rest, |_, _| #choice_ident::Ours)
+            }
+        }
+    }
+}
+
+/// The merged value of a single field of `self` and `other`.
+fn merged(field: &Field, policy: MergePolicy) -> TokenStream {
+    let ident = &field.ident;
+    let (first, second) = match policy {
+        MergePolicy::PreferSelf => (quote!(other), quote!(self)),
+        MergePolicy::PreferOther | MergePolicy::ErrorOnConflict => (quote!(self), quote!(other)),
+    };
+
+    // Flattened patches merge with their own policy, so are handed both
+    // sides in order rather than swapped for this one's
+    if field.attrs.flatten {
+        return quote! { self.#ident.merge(other.#ident) };
+    }
+
+    // Nested patches set on both sides are merged in turn, as flattened ones
+    if field.nested_ty.is_some() {
+        let merge = field.twin_fn("merge");
+        let both = if type_ends_with(field.inner_ty(), "Box") {
+            quote! { ::std::boxed::Box::new(#merge(*first, *second)) }
+        } else {
+            quote! { #merge(first, second) }
+        };
+        return quote! {
+            match (self.#ident, other.#ident) {
+                (Some(first), Some(second)) => Some(#both),
+                (first, second) => second.or(first),
+            }
+        };
+    }
+
+    if field.option_like.is_some() {
+        let is_unset = field.option_like.as_ref();
+        return quote! {
+            if #second.#ident.#is_unset() {
+                #first.#ident
+            } else {
+                #second.#ident
+            }
+        };
+    }
+
+    // Collections set on both sides combine their elements
+    if field.attrs.collection.is_some() {
+        let merge = collection::combine(field, quote!(first), quote!(second));
+        return quote! {
+            match (#first.#ident, #second.#ident) {
+                (Some(mut first), Some(second)) => {
+                    #merge
+                    Some(first)
+                }
+                (first, second) => second.or(first),
+            }
+        };
+    }
+
+    quote! { #second.#ident.or(#first.#ident) }
+}
+
+/// Generates the statement recording a single field set in both `self` and
+/// `other`.
+fn conflict(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+    let name = field.name();
+
+    // Flattened fields conflict by their own names
+    if field.attrs.flatten {
+        return quote! {
+            let theirs = other.#ident.set_fields();
+            fields.extend(self.#ident.set_fields().into_iter().filter(|field| theirs.contains(field)));
+        };
+    }
+
+    let ours = field.is_set();
+    let theirs = match &field.option_like {
+        Some(is_unset) => quote! { !other.#ident.#is_unset() },
+        None => quote! { other.#ident.is_some() },
+    };
+    quote! {
+        if #ours && #theirs {
+            fields.push(#name);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+use crate::attr::MergePolicy;
+
+/// Implements the `merge` crate's `Merge` trait for the generated struct. Only
+/// emitted with `#[optionalize(merge_crate)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    // Merges that can fail don't fit the trait either
+    if !cx.attrs.integrates("merge_crate") || cx.attrs.merge == MergePolicy::ErrorOnConflict {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let empty = cx.empty();
+
+    quote! {
+        impl #impl_generics ::merge::Merge for #optional_ident #ty_generics #where_clause {
+            /// Combines `other` into `self` with the inherent `merge`, so the
+            /// container's `merge` policy and the fields' merge strategies apply.
+            fn merge(&mut self, other: Self) {
+                let this = ::std::mem::replace(self, #empty);
+                *self = #optional_ident::merge(this, other);
+            }
+        }
+    }
+}
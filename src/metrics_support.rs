@@ -0,0 +1,87 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Generates `__set_count`, used to record the size of applied patches. Only
+/// emitted with `#[optionalize(metrics)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("metrics") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Flattened fields count as their twin's fields, like in `set_fields`
+    let counts = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        if field.attrs.flatten {
+            quote! { self.#ident.__set_count() }
+        } else {
+            let set = field.is_set();
+            quote! { (#set) as usize }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Number of set fields, like the length of `set_fields` without
+            /// collecting their names.
+            #[doc(hidden)]
+            #[inline]
+            pub fn __set_count(&self) -> usize {
+                0 #( + #counts )*
+            }
+        }
+    }
+}
+
+/// Generates the statement starting to measure a patch about to be applied.
+/// Only emitted with `#[optionalize(metrics)]`.
+pub fn start(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("metrics") {
+        return quote! {};
+    }
+
+    quote! {
+        let __optionalize_metrics = (self.__set_count(), ::std::time::Instant::now());
+    }
+}
+
+/// Generates the statements recording the size and duration of the applied
+/// patch, and whether it was empty. Only emitted with `#[optionalize(metrics)]`.
+pub fn finish(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("metrics") {
+        return quote! {};
+    }
+
+    let patch = cx.optional_ident.to_string();
+    quote! {
+        {
+            let (fields, started) = __optionalize_metrics;
+            if fields == 0 {
+                ::metrics::counter!("optionalize_empty_patches_total", "patch" => #patch).increment(1);
+            }
+            ::metrics::counter!("optionalize_patches_applied_total", "patch" => #patch).increment(1);
+            ::metrics::histogram!("optionalize_patch_fields", "patch" => #patch).record(fields as f64);
+            ::metrics::histogram!("optionalize_apply_duration_seconds", "patch" => #patch)
+                .record(started.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// Generates the statement counting a patch `try_apply` rejected, given the
+/// result of its checks. Only emitted with `#[optionalize(metrics)]`.
+pub fn rejected(cx: &Container, checked: TokenStream) -> TokenStream {
+    if !cx.attrs.integrates("metrics") {
+        return quote! {};
+    }
+
+    let patch = cx.optional_ident.to_string();
+    quote! {
+        if #checked.is_err() {
+            ::metrics::counter!("optionalize_patches_rejected_total", "patch" => #patch).increment(1);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+
+use proc_macro2::TokenStream;
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, ToTokens};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{parse_quote, Attribute, Error, Fields, Ident, Item, ItemMod, Lit, Meta, NestedMeta, Path, Result, Token, Type};
+
+use crate::ast::{first_type_argument, path_ends_with, type_ends_with};
+
+/// Adds `#[derive(Optionalize)]` and the shared `#[optionalize(...)]` attributes
+/// to every struct with named fields in `module`.
+pub fn expand(args: TokenStream, module: ItemMod) -> Result<TokenStream> {
+    // A shared `depth` makes every struct a root, and is resolved here
+    let mut args = Punctuated::<NestedMeta, Token![,]>::parse_terminated.parse2(args)?;
+    let depth = take_depth(&mut args)?;
+    expand_module(&derive_path(), &args.into_token_stream(), depth, module)
+}
+
+/// The path of the `Optionalize` derive in the calling crate: through the
+/// `optionalize` runtime crate when it depends on it, or else the macro crate
+/// itself, under whatever name the dependency was given.
+fn derive_path() -> Path {
+    let found = crate_name("optionalize")
+        .map(|found| (found, "optionalize"))
+        .or_else(|_| crate_name("optionalize-macro").map(|found| (found, "optionalize_macro")));
+    let name = match found {
+        Ok((FoundCrate::Name(name), _)) => format_ident!("{}", name),
+        // Integration tests and doctests of the crate itself refer to it by name
+        Ok((FoundCrate::Itself, name)) => format_ident!("{}", name),
+        Err(_) => format_ident!("optionalize_macro"),
+    };
+    parse_quote!(::#name::Optionalize)
+}
+
+fn expand_module(derive: &Path, args: &TokenStream, depth: Option<usize>, mut module: ItemMod) -> Result<TokenStream> {
+    let content = match &mut module.content {
+        Some((_, content)) => content,
+        None => {
+            return Err(Error::new_spanned(
+                &module,
+                "`optionalize_all` requires an inline module with a body",
+            ));
+        }
+    };
+
+    nest_to_depth(content, depth)?;
+
+    for item in content.iter_mut() {
+        match item {
+            Item::Struct(item) if matches!(item.fields, Fields::Named(_)) => {
+                if derives_optionalize(&item.attrs) {
+                    continue;
+                }
+                item.attrs.push(parse_quote!(#[derive(#derive)]));
+                if !args.is_empty() {
+                    item.attrs.push(parse_quote!(#[optionalize(#args)]));
+                }
+            }
+            Item::Mod(nested) if nested.content.is_some() => {
+                let expanded = expand_module(derive, args, depth, nested.clone())?;
+                *nested = syn::parse2(expanded)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(module.into_token_stream())
+}
+
+/// Marks `nested` the fields holding another struct of `content`, down to the
+/// `depth` of the struct they start from: its own `#[optionalize(depth = N)]`,
+/// or the shared one.
+fn nest_to_depth(content: &mut [Item], shared: Option<usize>) -> Result<()> {
+    let mut roots = Vec::new();
+    for item in content.iter_mut() {
+        if let Item::Struct(item) = item {
+            if let Some(depth) = take_struct_depth(&mut item.attrs)?.or(shared) {
+                roots.push((item.ident.clone(), depth));
+            }
+        }
+    }
+    let structs: HashSet<Ident> = content
+        .iter()
+        .filter_map(|item| match item {
+            Item::Struct(item) if matches!(item.fields, Fields::Named(_)) => Some(item.ident.clone()),
+            _ => None,
+        })
+        .collect();
+
+    // Structs reached again with no more levels left to go are skipped, which
+    // also ends recursive types
+    let mut reached: HashMap<Ident, usize> = HashMap::new();
+    let mut marked: HashSet<(Ident, Ident)> = HashSet::new();
+    while let Some((ident, depth)) = roots.pop() {
+        if depth == 0 || reached.get(&ident).is_some_and(|reached| *reached >= depth) {
+            continue;
+        }
+        reached.insert(ident.clone(), depth);
+
+        let fields = content.iter().find_map(|item| match item {
+            Item::Struct(item) if item.ident == ident => Some(&item.fields),
+            _ => None,
+        });
+        for field in fields.into_iter().flatten() {
+            let child = match nested_struct(&field.ty, &structs) {
+                Some(child) => child,
+                None => continue,
+            };
+            if !is_nested(&field.attrs) {
+                marked.insert((ident.clone(), field.ident.clone().expect("named field")));
+            }
+            roots.push((child, depth - 1));
+        }
+    }
+
+    for item in content.iter_mut() {
+        if let Item::Struct(item) = item {
+            for field in item.fields.iter_mut() {
+                if let Some(field_ident) = &field.ident {
+                    if marked.contains(&(item.ident.clone(), field_ident.clone())) {
+                        field.attrs.push(parse_quote!(#[optionalize(nested)]));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The struct of the module a field holds, directly, in an `Option` or in a
+/// `Box`.
+fn nested_struct(ty: &Type, structs: &HashSet<Ident>) -> Option<Ident> {
+    let mut ty = ty;
+    for wrapper in ["Option", "Box"] {
+        if type_ends_with(ty, wrapper) {
+            ty = first_type_argument(ty)?;
+        }
+    }
+    match ty {
+        Type::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+            let ident = &path.path.segments[0].ident;
+            structs.get(ident).cloned()
+        }
+        _ => None,
+    }
+}
+
+/// Whether a field is already marked `nested` or `flatten`.
+fn is_nested(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("optionalize"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(meta) => meta.path().is_ident("nested") || meta.path().is_ident("flatten"),
+                _ => false,
+            }),
+            _ => false,
+        })
+}
+
+/// Removes `depth = N` from the struct's `#[optionalize(...)]` attributes,
+/// returning `N`.
+fn take_struct_depth(attrs: &mut Vec<Attribute>) -> Result<Option<usize>> {
+    let mut depth = None;
+    for attr in attrs.iter_mut().filter(|attr| attr.path.is_ident("optionalize")) {
+        let mut nested = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            _ => continue,
+        };
+        if let Some(found) = take_depth(&mut nested)? {
+            depth = Some(found);
+            *attr = parse_quote!(#[optionalize(#nested)]);
+        }
+    }
+    // An attribute holding nothing but the depth is left empty
+    attrs.retain(|attr| {
+        !attr.path.is_ident("optionalize") || !matches!(attr.parse_meta(), Ok(Meta::List(list)) if list.nested.is_empty())
+    });
+    Ok(depth)
+}
+
+/// Removes `depth = N` from a list of `optionalize` arguments, returning `N`.
+fn take_depth(args: &mut Punctuated<NestedMeta, Token![,]>) -> Result<Option<usize>> {
+    let mut depth = None;
+    let mut rest = Punctuated::new();
+    for arg in std::mem::take(args) {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("depth") => match &nv.lit {
+                Lit::Int(lit) => depth = Some(lit.base10_parse()?),
+                other => return Err(Error::new_spanned(other, "expected a number of levels, e.g. `depth = 2`")),
+            },
+            other => rest.push(other),
+        }
+    }
+    *args = rest;
+    Ok(depth)
+}
+
+/// Whether a struct already has `Optionalize` in one of its derives.
+fn derives_optionalize(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("derive"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(Meta::Path(path)) => path_ends_with(path, "Optionalize"),
+                _ => false,
+            }),
+            _ => false,
+        })
+}
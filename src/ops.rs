@@ -0,0 +1,191 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::{first_type_argument, is_numeric, path_ends_with, type_ends_with, Container, Field};
+
+/// Derives copied from the generated struct onto the operation types.
+const OP_DERIVES: &[&str] = &["Clone", "Debug", "PartialEq", "Eq", "Serialize", "Deserialize"];
+
+/// Generates `{Optional}Ops`, a patch whose fields carry an operation instead
+/// of a value, along with the `{Optional}Op`, `{Optional}NumOp` and
+/// `{Optional}ListOp` operations. Only emitted with `#[optionalize(ops)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.ops {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let ops_ident = format_ident!("{}Ops", optional_ident);
+    let op_ident = format_ident!("{}Op", optional_ident);
+    let num_op_ident = format_ident!("{}NumOp", optional_ident);
+    let list_op_ident = format_ident!("{}ListOp", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    let derives: Vec<_> = cx
+        .derives()
+        .into_iter()
+        .filter(|path| OP_DERIVES.iter().any(|name| path_ends_with(path, name)))
+        .collect();
+    let serde = if cx.derives_serialize() || cx.derives_deserialize() {
+        quote! { #[serde(rename_all = "snake_case")] }
+    } else {
+        quote! {}
+    };
+    let serde_field = if cx.derives_serialize() || cx.derives_deserialize() {
+        quote! { #[serde(default, skip_serializing_if = "Option::is_none")] }
+    } else {
+        quote! {}
+    };
+
+    // Immutable fields are never written, and flattened ones have no value of their own
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable && !field.attrs.flatten)
+        .collect();
+
+    let ops_fields = fields.iter().map(|field| {
+        let vis = &field.vis;
+        let field_ident = &field.ident;
+        let ty = &field.ty;
+        let op = match op_kind(field) {
+            OpKind::Num => quote! { #num_op_ident<#ty> },
+            OpKind::List(element) => quote! { #list_op_ident<#ty, #element> },
+            OpKind::Plain => quote! { #op_ident<#ty> },
+        };
+        quote! { #serde_field #vis #field_ident: Option<#op> }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+
+    let applied = fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        let arms = match op_kind(field) {
+            OpKind::Num => quote! {
+                #num_op_ident::Set(value) => target.#field_ident = value,
+                #num_op_ident::Unset => target.#field_ident = ::std::default::Default::default(),
+                #num_op_ident::Increment(by) => target.#field_ident += by,
+                #num_op_ident::Decrement(by) => target.#field_ident -= by,
+            },
+            OpKind::List(_) => quote! {
+                #list_op_ident::Set(value) => target.#field_ident = value,
+                #list_op_ident::Unset => target.#field_ident = ::std::default::Default::default(),
+                #list_op_ident::Push(element) => target.#field_ident.push(element),
+                #list_op_ident::Remove(element) => target.#field_ident.retain(|existing| *existing != element),
+            },
+            OpKind::Plain => quote! {
+                #op_ident::Set(value) => target.#field_ident = value,
+                #op_ident::Unset => target.#field_ident = ::std::default::Default::default(),
+            },
+        };
+        quote! {
+            if let Some(op) = self.#field_ident {
+                match op {
+                    #arms
+                }
+            }
+        }
+    });
+    // Only the operations actually used need their bounds
+    let bounds = fields.iter().map(|field| {
+        let ty = &field.ty;
+        match op_kind(field) {
+            OpKind::List(element) => quote! {
+                for<'__optionalize> #ty: ::std::default::Default,
+                for<'__optionalize> #element: ::std::cmp::PartialEq,
+            },
+            _ => quote! { for<'__optionalize> #ty: ::std::default::Default, },
+        }
+    });
+
+    let doc = format!(
+        "An operation-based patch of a [`{}`], where each set field carries the operation to perform on it.",
+        ident
+    );
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// Unset fields are left untouched by `apply_to`.
+        #[derive(Default, #( #derives ),*)]
+        #[must_use]
+        pub struct #ops_ident <#generics> #where_clause {
+            #( #ops_fields, )*
+            #( #markers, )*
+        }
+
+        /// An operation on a field.
+        #[derive(#( #derives ),*)]
+        #serde
+        pub enum #op_ident<T> {
+            /// Replaces the value.
+            Set(T),
+            /// Resets the value to its default, `None` for an `Option`.
+            Unset,
+        }
+
+        /// An operation on a numeric field.
+        #[derive(#( #derives ),*)]
+        #serde
+        pub enum #num_op_ident<T> {
+            /// Replaces the value.
+            Set(T),
+            /// Resets the value to zero.
+            Unset,
+            /// Adds to the value, panicking on overflow in debug builds.
+            Increment(T),
+            /// Subtracts from the value, panicking on overflow in debug builds.
+            Decrement(T),
+        }
+
+        /// An operation on a list field.
+        #[derive(#( #derives ),*)]
+        #serde
+        pub enum #list_op_ident<T, E> {
+            /// Replaces the whole list.
+            Set(T),
+            /// Empties the list.
+            Unset,
+            /// Appends an element.
+            Push(E),
+            /// Removes every element equal to this one.
+            Remove(E),
+        }
+
+        impl #impl_generics #ops_ident #ty_generics #where_clause {
+            /// Performs the operation of every set field on `target`.
+            pub fn apply_to(self, target: &mut #ident #ty_generics)
+            where
+                #( #bounds )*
+            {
+                #( #applied )*
+            }
+        }
+    }
+}
+
+/// Which operations a field supports.
+enum OpKind<'a> {
+    /// `Set` and `Unset`.
+    Plain,
+    /// Those of `Plain`, along with `Increment` and `Decrement`.
+    Num,
+    /// Those of `Plain`, along with `Push` and `Remove` of elements of this type.
+    List(&'a syn::Type),
+}
+
+fn op_kind(field: &Field) -> OpKind<'_> {
+    if is_numeric(&field.ty) {
+        return OpKind::Num;
+    }
+    match first_type_argument(&field.ty) {
+        Some(element) if type_ends_with(&field.ty, "Vec") => OpKind::List(element),
+        _ => OpKind::Plain,
+    }
+}
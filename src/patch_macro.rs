@@ -0,0 +1,51 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::{is_numeric, snake_case, Container, Field};
+
+/// Generates `{struct}_patch!`, building the patch from `field: value` pairs.
+/// Only emitted with `#[optionalize(patch_macro)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    let name = match &cx.attrs.patch_macro {
+        Some(Some(name)) => name.clone(),
+        Some(None) => format_ident!("{}_patch", snake_case(&cx.ident)),
+        None => return quote! {},
+    };
+    let optional_ident = &cx.optional_ident;
+
+    let setters = cx.fields.iter().filter(|field| !field.attrs.no_helpers).map(setter);
+
+    quote! {
+        #[allow(unused_macros)]
+        macro_rules! #name {
+            #( #setters )*
+            ($( $field:ident : $value:expr ),* $(,)?) => {{
+                #[allow(unused_mut)]
+                let mut patch = #optional_ident::empty();
+                $( #name!(@set patch, $field, $value); )*
+                patch
+            }};
+        }
+    }
+}
+
+/// The internal rule writing a value into a single field of the patch.
+fn setter(field: &Field) -> TokenStream {
+    let ident = &field.ident;
+
+    // Fields that already are their own "unset" take the value as is
+    let value = if field.attrs.flatten || field.option_like.is_some() {
+        quote! { $value }
+    } else if field.double_option || is_numeric(field.inner_ty()) {
+        // A literal would fall back to `i32` through `Into`
+        quote! { Some($value) }
+    } else {
+        quote! { Some(::std::convert::Into::into($value)) }
+    };
+
+    quote! {
+        (@set $patch:ident, #ident, $value:expr) => {
+            $patch.#ident = #value;
+        };
+    }
+}
@@ -0,0 +1,83 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container, Field};
+use crate::sql;
+
+/// Generates `postgres_params` and `to_postgres_set`, which render the set
+/// fields as the `SET` clause of a tokio-postgres `UPDATE`. Only emitted with
+/// `#[optionalize(tokio_postgres)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("tokio_postgres") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Immutable fields are never written, secrets and option-like values
+    // can't be bound generically, and nested patches aren't column values
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable && !field.is_secret() && field.option_like.is_none())
+        .filter(|field| field.nested_ty.is_none() || field.attrs.flatten)
+        .collect();
+    let bounds = inner_bounds(
+        fields.iter().copied(),
+        quote!(::tokio_postgres::types::ToSql + ::std::marker::Sync),
+    );
+    let quoted_column = sql::quote_identifier(quote!(column));
+
+    let params = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { params.extend(self.#ident.postgres_params()); };
+        }
+        quote! {
+            if let Some(value) = &self.#ident {
+                params.push((#name, value as &(dyn ::tokio_postgres::types::ToSql + ::std::marker::Sync)));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Returns a `(column, value)` pair for every set field, in declaration
+            /// order, ready to be bound as tokio-postgres parameters.
+            pub fn postgres_params(&self) -> Vec<(&'static str, &(dyn ::tokio_postgres::types::ToSql + ::std::marker::Sync))>
+            where
+                #bounds
+            {
+                let mut params = Vec::new();
+                #( #params )*
+                params
+            }
+
+            /// Renders the set fields as the body of a `SET` clause, e.g.
+            /// `"name" = $1, "age" = $2`, along with the parameters to bind in
+            /// that order.
+            ///
+            /// Placeholders start at `$1`, so parameters of the rest of the
+            /// statement follow, e.g. `WHERE id = ${params.len() + 1}`. A patch
+            /// that sets no fields renders an empty clause, which isn't valid SQL;
+            /// check `set_fields` first.
+            pub fn to_postgres_set(&self) -> (String, Vec<&(dyn ::tokio_postgres::types::ToSql + ::std::marker::Sync)>)
+            where
+                #bounds
+            {
+                let mut set = String::new();
+                let mut values = Vec::new();
+                for (index, (column, value)) in self.postgres_params().into_iter().enumerate() {
+                    if index > 0 {
+                        set.push_str(", ");
+                    }
+                    set.push_str(&format!("{} = ${}", #quoted_column, index + 1));
+                    values.push(value);
+                }
+                (set, values)
+            }
+        }
+    }
+}
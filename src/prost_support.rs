@@ -0,0 +1,49 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Error, Meta, NestedMeta, Result};
+
+use crate::ast::Field;
+
+/// The `#[prost(...)]` attribute of a field of the generated struct: the one on
+/// the original field, with the `optional` label added.
+pub fn field_attr(field: &Field) -> Result<TokenStream> {
+    let attr = field
+        .source_attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("prost"))
+        .ok_or_else(|| {
+            Error::new_spanned(
+                &field.ident,
+                "`#[optionalize(prost)]` requires a `#[prost(...)]` attribute on every field",
+            )
+        })?;
+
+    let nested = match attr.parse_meta()? {
+        Meta::List(list) => list.nested,
+        other => return Err(Error::new_spanned(other, "expected `#[prost(...)]`")),
+    };
+
+    let label = |name: &str| {
+        nested.iter().find(|meta| match meta {
+            NestedMeta::Meta(meta) => meta.path().is_ident(name),
+            NestedMeta::Lit(_) => false,
+        })
+    };
+
+    // Protobuf has no way to mark these as absent
+    for name in ["repeated", "map", "oneof"] {
+        if let Some(meta) = label(name) {
+            return Err(Error::new_spanned(
+                meta,
+                format!("`{}` fields cannot be optionalized for prost", name),
+            ));
+        }
+    }
+
+    let nested = nested.iter();
+    if label("optional").is_some() {
+        Ok(quote! { #[prost( #( #nested ),* )] })
+    } else {
+        Ok(quote! { #[prost( #( #nested, )* optional )] })
+    }
+}
@@ -0,0 +1,78 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// The `#[pyclass]` attribute placed on the generated struct, exposing every
+/// field as a Python attribute.
+pub fn pyclass(cx: &Container) -> TokenStream {
+    if !cx.attrs.pyo3 {
+        return quote! {};
+    }
+    quote! { #[::pyo3::pyclass(get_all, set_all)] }
+}
+
+/// Generates the Python constructor and methods of the generated struct. Only
+/// emitted with `#[optionalize(pyo3)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.pyo3 {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let class_name = optional_ident.to_string();
+    let idents: Vec<_> = cx.fields.iter().map(|field| &field.ident).collect();
+    let markers = cx.markers.iter().map(|marker| &marker.ident);
+
+    // Every argument is keyword-only and may be left out; fields that aren't
+    // an `Option` in the generated struct fall back to their unset value
+    let params = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let ty = field.optional_ty();
+        if field.attrs.flatten || field.option_like.is_some() {
+            quote! { #ident: ::std::option::Option<#ty> }
+        } else {
+            quote! { #ident: #ty }
+        }
+    });
+    let values = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        if field.attrs.flatten {
+            let twin = field.inner_ty();
+            quote! { #ident.unwrap_or_else(<#twin>::empty) }
+        } else if field.option_like.is_some() {
+            quote! { #ident.unwrap_or_default() }
+        } else {
+            quote! { #ident }
+        }
+    });
+
+    quote! {
+        #[::pyo3::pymethods]
+        impl #optional_ident {
+            /// Builds a patch from keyword arguments, e.g.
+            /// `UserOptional(name="Ada")`; arguments left out leave their field
+            /// unset.
+            #[new]
+            #[pyo3(signature = (*, #( #idents = None ),*))]
+            fn __optionalize_py_new(#( #params ),*) -> Self {
+                Self {
+                    #( #idents: #values, )*
+                    #( #markers: ::std::marker::PhantomData, )*
+                }
+            }
+
+            /// The names of the fields the patch sets.
+            #[pyo3(name = "set_fields")]
+            fn __optionalize_py_set_fields(&self) -> ::std::vec::Vec<&'static str> {
+                self.set_fields()
+            }
+
+            /// Renders the patch as its class name and set fields, e.g.
+            /// `UserOptional(name, age)`.
+            fn __repr__(&self) -> String {
+                format!("{}({})", #class_name, self.set_fields().join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use syn::Type;
+
+use crate::ast::{first_type_argument, type_ends_with, Container, Field};
+
+/// Generates `to_query_pairs`, which renders the set fields as URL query pairs.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("query") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Secrets never leave the process through the patch, option-like values
+    // can't be read generically, query strings can't express clearing a
+    // double `Option`, and nested patches and maps have no single value
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.is_secret() && field.option_like.is_none() && !field.double_option)
+        .filter(|field| field.attrs.flatten || (field.nested_ty.is_none() && !is_map(field.inner_ty())))
+        .collect();
+    // Sequences are sent as one pair per element, under the same name
+    let displayed = fields.iter().filter(|field| !field.attrs.flatten).map(|field| {
+        let ty = field.inner_ty();
+        sequence_element(ty).unwrap_or(ty)
+    });
+    let bounds = quote! {
+        #( for<'__optionalize> #displayed: ::std::fmt::Display, )*
+    };
+
+    let pairs = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { pairs.extend(self.#ident.to_query_pairs()); };
+        }
+        if sequence_element(field.inner_ty()).is_some() {
+            return quote! {
+                if let Some(values) = &self.#ident {
+                    pairs.extend(values.iter().map(|value| (#name, value.to_string())));
+                }
+            };
+        }
+        quote! {
+            if let Some(value) = &self.#ident {
+                pairs.push((#name, value.to_string()));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Returns a `(name, value)` pair for every set field, in declaration
+            /// order, ready to be sent as URL query parameters. Lists and sets
+            /// give a pair per element, as in `tag=a&tag=b`, while `nested`
+            /// patches and maps are left out.
+            pub fn to_query_pairs(&self) -> Vec<(&'static str, String)>
+            where
+                #bounds
+            {
+                let mut pairs = Vec::new();
+                #( #pairs )*
+                pairs
+            }
+        }
+    }
+}
+
+/// The element type of a list or set, sent as one query pair per element.
+fn sequence_element(ty: &Type) -> Option<&Type> {
+    ["Vec", "VecDeque", "HashSet", "BTreeSet"]
+        .iter()
+        .any(|name| type_ends_with(ty, name))
+        .then(|| first_type_argument(ty))
+        .flatten()
+}
+
+/// Whether `ty` is a map, which has no query string form.
+fn is_map(ty: &Type) -> bool {
+    type_ends_with(ty, "HashMap") || type_ends_with(ty, "BTreeMap")
+}
@@ -0,0 +1,86 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{Container, Field};
+
+/// Generates `redact` and, when `Debug` is requested alongside sensitive fields,
+/// a `Debug` impl that hides their values.
+pub fn expand(cx: &Container) -> TokenStream {
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let sensitive: Vec<&Field> = cx.fields.iter().filter(|field| field.is_sensitive()).collect();
+    let sensitive_idents = sensitive.iter().map(|field| &field.ident);
+    let unset = sensitive.iter().map(|field| field.unset());
+    let flattened = cx
+        .fields
+        .iter()
+        .filter(|field| field.attrs.flatten)
+        .map(|field| &field.ident);
+
+    let debug = if cx.has_sensitive() && cx.wants_debug() {
+        debug_impl(cx)
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Unsets every field marked `#[optionalize(sensitive)]`, so the patch
+            /// can be logged safely.
+            pub fn redact(&mut self) {
+                #( self.#sensitive_idents = #unset; )*
+                #( self.#flattened.redact(); )*
+            }
+        }
+
+        #debug
+    }
+}
+
+/// A `Debug` impl that prints `Some([REDACTED])` for set sensitive fields.
+fn debug_impl(cx: &Container) -> TokenStream {
+    let optional_ident = &cx.optional_ident;
+    let name = optional_ident.to_string();
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+
+    // Every non-sensitive field must be printable
+    let mut generics = cx.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for field in cx.fields.iter().filter(|field| !field.is_sensitive()) {
+        let ty = field.optional_ty();
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: ::std::fmt::Debug));
+    }
+
+    let entries = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.is_sensitive() && field.option_like.is_some() {
+            quote! { .field(#name, &Redacted) }
+        } else if field.is_sensitive() {
+            quote! { .field(#name, &self.#ident.as_ref().map(|_| Redacted)) }
+        } else {
+            quote! { .field(#name, &self.#ident) }
+        }
+    });
+
+    quote! {
+        impl #impl_generics ::std::fmt::Debug for #optional_ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                struct Redacted;
+
+                impl ::std::fmt::Debug for Redacted {
+                    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                        f.write_str("[REDACTED]")
+                    }
+                }
+
+                f.debug_struct(#name)
+                    #( #entries )*
+                    .finish()
+            }
+        }
+    }
+}
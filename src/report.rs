@@ -0,0 +1,349 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, GenericArgument, PathArguments, Result, Type};
+
+use crate::ast::{first_type_argument, path_ends_with, type_ends_with, Container, Field};
+use crate::attr::VecDiff;
+
+/// Generates `{Name}Diff`, a report of the fields that differ between two
+/// values with their old and new values, and `{Name}Change`, the change of a
+/// single field. Only emitted with `#[optionalize(report)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    let granular = cx
+        .fields
+        .iter()
+        .find(|field| field.attrs.map_diff || field.attrs.vec_diff.is_some());
+    let derives = match (&cx.attrs.report, granular) {
+        (Some(derives), _) => derives,
+        (None, Some(field)) => {
+            return Err(Error::new_spanned(
+                &field.ident,
+                "`map_diff`, `vec_diff` and `diff_key` require `#[optionalize(report)]`",
+            ));
+        }
+        (None, None) => return Ok(quote! {}),
+    };
+    if let Some(field) = cx
+        .fields
+        .iter()
+        .find(|field| field.attrs.map_diff && (field.option || map_types(&field.ty).is_none()))
+    {
+        return Err(Error::new_spanned(&field.ty, "`map_diff` requires a `HashMap` or `BTreeMap` field"));
+    }
+    if let Some(field) = cx
+        .fields
+        .iter()
+        .find(|field| field.attrs.vec_diff.is_some() && (field.option || !type_ends_with(&field.ty, "Vec")))
+    {
+        return Err(Error::new_spanned(&field.ty, "`vec_diff` and `diff_key` require a `Vec` field"));
+    }
+
+    let ident = &cx.ident;
+    let diff_ident = format_ident!("{}Diff", ident);
+    let change_ident = format_ident!("{}Change", ident);
+    let element_ident = format_ident!("{}ElementChange", ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    // The report is meant to be shown and sent, so sensitive values stay out
+    let fields: Vec<&Field> = cx.fields.iter().filter(|field| !field.is_sensitive()).collect();
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let names: Vec<_> = fields.iter().map(|field| field.name()).collect();
+    let tys: Vec<_> = fields
+        .iter()
+        .filter(|field| !field.attrs.map_diff && field.attrs.vec_diff.is_none())
+        .map(|field| field.value_ty())
+        .collect();
+    // List fields are compared element by element
+    let elements: Vec<_> = fields
+        .iter()
+        .filter(|field| field.attrs.vec_diff.is_some())
+        .filter_map(|field| first_type_argument(&field.ty))
+        .collect();
+    // Map fields are compared entry by entry, and collected into a map of changes
+    let map_bounds = fields.iter().filter(|field| field.attrs.map_diff).map(|field| {
+        let (key, value) = map_types(&field.ty).expect("checked above");
+        let ty = entries_ty(&field.ty, &change_ident);
+        quote! {
+            for<'__optionalize> #key: ::std::clone::Clone,
+            for<'__optionalize> #value: ::std::cmp::PartialEq + ::std::clone::Clone,
+            for<'__optionalize> #ty: ::std::default::Default,
+        }
+    });
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    // Serde's attributes only apply when one of its traits is derived
+    let serde = derives
+        .iter()
+        .any(|path| path_ends_with(path, "Serialize") || path_ends_with(path, "Deserialize"));
+    let derive = if derives.is_empty() {
+        quote! {}
+    } else {
+        quote! { #[derive(#( #derives ),*)] }
+    };
+    let (change_serde, field_serde) = if serde {
+        (
+            quote! { #[serde(tag = "kind", rename_all = "snake_case")] },
+            quote! { #[serde(default, skip_serializing_if = "Option::is_none")] },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    let report_fields = fields.iter().map(|field| {
+        let vis = &field.vis;
+        let ident = &field.ident;
+        let doc = format!("How the `{}` field changed, if it did.", field.name());
+        if let (Some(_), Some(element)) = (&field.attrs.vec_diff, first_type_argument(&field.ty)) {
+            return quote! {
+                #[doc = #doc]
+                #field_serde
+                #vis #ident: ::std::option::Option<::std::vec::Vec<#element_ident<#element>>>
+            };
+        }
+        if field.attrs.map_diff {
+            let ty = entries_ty(&field.ty, &change_ident);
+            return quote! {
+                #[doc = #doc]
+                #field_serde
+                #vis #ident: ::std::option::Option<#ty>
+            };
+        }
+        let ty = field.value_ty();
+        quote! {
+            #[doc = #doc]
+            #field_serde
+            #vis #ident: ::std::option::Option<#change_ident<#ty>>
+        }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+
+    let changes = fields.iter().map(|field| {
+        let ident = &field.ident;
+        // Called through the traits, so the higher-ranked bounds are the ones used
+        let changed = quote! {
+            #change_ident::Changed {
+                old: ::std::clone::Clone::clone(old),
+                new: ::std::clone::Clone::clone(new),
+            }
+        };
+        if let Some(vec_diff) = &field.attrs.vec_diff {
+            let changes = element_changes(vec_diff, &element_ident);
+            quote! {
+                #ident: {
+                    let (old, new) = (&old.#ident, &new.#ident);
+                    let mut changes = ::std::vec::Vec::new();
+                    #changes
+                    if changes.is_empty() { None } else { Some(changes) }
+                }
+            }
+        } else if field.attrs.map_diff {
+            let ty = entries_ty(&field.ty, &change_ident);
+            quote! {
+                #ident: {
+                    let (old, new) = (&old.#ident, &new.#ident);
+                    let mut changes: #ty = ::std::default::Default::default();
+                    for (key, old) in old.iter() {
+                        match new.get(key) {
+                            None => {
+                                let old = ::std::clone::Clone::clone(old);
+                                changes.insert(::std::clone::Clone::clone(key), #change_ident::Removed { old });
+                            }
+                            Some(new) if ::std::cmp::PartialEq::ne(old, new) => {
+                                changes.insert(::std::clone::Clone::clone(key), #changed);
+                            }
+                            Some(_) => {}
+                        }
+                    }
+                    for (key, new) in new.iter() {
+                        if !old.contains_key(key) {
+                            let new = ::std::clone::Clone::clone(new);
+                            changes.insert(::std::clone::Clone::clone(key), #change_ident::Added { new });
+                        }
+                    }
+                    if changes.is_empty() { None } else { Some(changes) }
+                }
+            }
+        } else if field.option {
+            quote! {
+                #ident: match (&old.#ident, &new.#ident) {
+                    (None, Some(new)) => Some(#change_ident::Added { new: ::std::clone::Clone::clone(new) }),
+                    (Some(old), None) => Some(#change_ident::Removed { old: ::std::clone::Clone::clone(old) }),
+                    (Some(old), Some(new)) if ::std::cmp::PartialEq::ne(old, new) => Some(#changed),
+                    _ => None,
+                }
+            }
+        } else {
+            quote! {
+                #ident: {
+                    let (old, new) = (&old.#ident, &new.#ident);
+                    if ::std::cmp::PartialEq::ne(old, new) { Some(#changed) } else { None }
+                }
+            }
+        }
+    });
+
+    let diff_doc = format!(
+        "A report of the fields that differ between two [`{}`] values, with their old and new values, e.g. for audit logs and webhooks.",
+        ident
+    );
+    let change_doc = format!("How a field of a [`{}`] changed between two values.", ident);
+    let element_enum = if elements.is_empty() {
+        quote! {}
+    } else {
+        let doc = format!("How an element of a list field of a [`{}`] changed between two values.", ident);
+        quote! {
+            #[doc = #doc]
+            ///
+            /// Removed elements are at their index in the old list, inserted and
+            /// updated ones at their index in the new list.
+            #derive
+            #change_serde
+            pub enum #element_ident<T> {
+                /// The element was added to the list.
+                Insert { index: usize, new: T },
+                /// The element was taken out of the list.
+                Remove { index: usize, old: T },
+                /// The element went from one value to another.
+                Update { index: usize, old: T, new: T },
+            }
+        }
+    };
+
+    Ok(quote! {
+        #[doc = #change_doc]
+        #derive
+        #change_serde
+        pub enum #change_ident<T> {
+            /// An `Option` field went from `None` to a value.
+            Added { new: T },
+            /// An `Option` field went from a value to `None`.
+            Removed { old: T },
+            /// The field went from one value to another.
+            Changed { old: T, new: T },
+        }
+
+        #element_enum
+
+        #[doc = #diff_doc]
+        ///
+        /// Fields that didn't change are `None`, and left out when serialized.
+        /// Sensitive fields are never reported.
+        #derive
+        pub struct #diff_ident <#generics> #where_clause {
+            #( #report_fields, )*
+            #( #markers, )*
+        }
+
+        impl #impl_generics #diff_ident #ty_generics #where_clause {
+            /// Compares `old` and `new` field by field.
+            pub fn between(old: &#ident #ty_generics, new: &#ident #ty_generics) -> Self
+            where
+                #( for<'__optionalize> #tys: ::std::cmp::PartialEq + ::std::clone::Clone, )*
+                #( #map_bounds )*
+                #( for<'__optionalize> #elements: ::std::cmp::PartialEq + ::std::clone::Clone, )*
+            {
+                Self {
+                    #( #changes, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+
+            /// Whether no field changed.
+            pub fn is_empty(&self) -> bool {
+                #( self.#idents.is_none() && )* true
+            }
+
+            /// The names of the fields that changed, in declaration order.
+            pub fn changed_fields(&self) -> ::std::vec::Vec<&'static str> {
+                let fields: &[(&'static str, bool)] = &[ #( (#names, self.#idents.is_some()), )* ];
+                fields.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name).collect()
+            }
+        }
+    })
+}
+
+/// The statements pushing the element changes between the lists `old` and
+/// `new` onto `changes`.
+fn element_changes(vec_diff: &VecDiff, element_ident: &syn::Ident) -> TokenStream {
+    let clone = |value| quote! { ::std::clone::Clone::clone(#value) };
+    let (old, new) = (clone(quote!(old)), clone(quote!(new)));
+    match vec_diff {
+        VecDiff::Position => quote! {
+            for index in 0..::std::cmp::max(old.len(), new.len()) {
+                match (old.get(index), new.get(index)) {
+                    (Some(old), Some(new)) if ::std::cmp::PartialEq::ne(old, new) => {
+                        changes.push(#element_ident::Update { index, old: #old, new: #new });
+                    }
+                    (Some(old), None) => changes.push(#element_ident::Remove { index, old: #old }),
+                    (None, Some(new)) => changes.push(#element_ident::Insert { index, new: #new }),
+                    _ => {}
+                }
+            }
+        },
+        // Elements keep their identity wherever they move
+        VecDiff::ByKey(key) => quote! {
+            for (index, old) in old.iter().enumerate() {
+                if !new.iter().any(|new| new.#key == old.#key) {
+                    changes.push(#element_ident::Remove { index, old: #old });
+                }
+            }
+            for (index, new) in new.iter().enumerate() {
+                match old.iter().find(|old| old.#key == new.#key) {
+                    Some(old) if ::std::cmp::PartialEq::ne(old, new) => {
+                        changes.push(#element_ident::Update { index, old: #old, new: #new });
+                    }
+                    Some(_) => {}
+                    None => changes.push(#element_ident::Insert { index, new: #new }),
+                }
+            }
+        },
+    }
+}
+
+/// The key and value types of a `HashMap` or `BTreeMap` type.
+fn map_types(ty: &Type) -> Option<(&Type, &Type)> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "HashMap" && segment.ident != "BTreeMap" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    let mut types = arguments.args.iter().filter_map(|argument| match argument {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    Some((types.next()?, types.next()?))
+}
+
+/// The map type `ty` with its values replaced by their changes, keeping the
+/// map kind and hasher.
+fn entries_ty(ty: &Type, change_ident: &syn::Ident) -> Type {
+    let mut ty = ty.clone();
+    if let Type::Path(path) = &mut ty {
+        if let Some(PathArguments::AngleBracketed(arguments)) =
+            path.path.segments.last_mut().map(|segment| &mut segment.arguments)
+        {
+            let value = arguments
+                .args
+                .iter_mut()
+                .filter_map(|argument| match argument {
+                    GenericArgument::Type(ty) => Some(ty),
+                    _ => None,
+                })
+                .nth(1);
+            if let Some(value) = value {
+                let inner = value.clone();
+                *value = syn::parse_quote!(#change_ident<#inner>);
+            }
+        }
+    }
+    ty
+}
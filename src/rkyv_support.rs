@@ -0,0 +1,45 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{pointer_inner, type_ends_with, Container, Field};
+
+/// Whether a `nested` field holds the generated struct itself, e.g. the
+/// `Option<Box<NodeOptional>>` of a linked list.
+fn is_recursive(cx: &Container, field: &Field) -> bool {
+    let nested_ty = match &field.nested_ty {
+        Some(nested_ty) => nested_ty,
+        None => return false,
+    };
+    let twin = pointer_inner(nested_ty).unwrap_or(nested_ty);
+    type_ends_with(twin, &cx.optional_ident.to_string())
+}
+
+/// The `#[rkyv(...)]` attribute of the generated struct. The bounds rkyv would
+/// infer from recursive fields never resolve, so they are left out and the
+/// ones the `Box` needs are spelled out instead.
+pub fn container_attr(cx: &Container) -> TokenStream {
+    if !cx.attrs.rkyv || !cx.fields.iter().any(|field| is_recursive(cx, field)) {
+        return quote! {};
+    }
+    quote! {
+        #[rkyv(
+            serialize_bounds(
+                __S: ::rkyv::ser::Writer + ::rkyv::ser::Allocator,
+                __S::Error: ::rkyv::rancor::Source,
+            ),
+            deserialize_bounds(__D::Error: ::rkyv::rancor::Source),
+            bytecheck(bounds(
+                __C: ::rkyv::validation::ArchiveContext,
+                __C::Error: ::rkyv::rancor::Source,
+            )),
+        )]
+    }
+}
+
+/// The `#[rkyv(omit_bounds)]` attribute of a recursive field.
+pub fn field_attr(cx: &Container, field: &Field) -> TokenStream {
+    if !cx.attrs.rkyv || !is_recursive(cx, field) {
+        return quote! {};
+    }
+    quote! { #[rkyv(omit_bounds)] }
+}
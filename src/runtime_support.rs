@@ -0,0 +1,259 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Lit, Meta, Path, Type};
+
+use crate::ast::{Container, Field};
+use crate::attr::MergePolicy;
+use crate::{convert, diff};
+
+/// Implements the traits of the `optionalize` runtime crate for the original
+/// and generated structs, and generates `FIELDS`. Only emitted when the
+/// calling crate depends on the runtime crate, and each trait only along with
+/// the groups of impls it forwards to.
+pub fn expand(cx: &Container) -> TokenStream {
+    let runtime = match &cx.runtime {
+        Some(runtime) => runtime,
+        None => return quote! {},
+    };
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, own_where_clause) = cx.generics.split_for_impl();
+    // The twin must convert `From` the original struct
+    let where_clause = cx.where_clause_with(convert::bounds(cx));
+
+    // Merges that can fail don't fit the traits
+    let merge = if !cx.emits("apply") || !cx.emits("merge") || cx.attrs.merge == MergePolicy::ErrorOnConflict {
+        quote! {}
+    } else {
+        quote! {
+            impl #impl_generics #runtime::Merge for #optional_ident #ty_generics #own_where_clause {
+                fn merge(self, other: Self) -> Self {
+                    #optional_ident::merge(self, other)
+                }
+            }
+
+            impl #impl_generics #runtime::Patch<#ident #ty_generics> for #optional_ident #ty_generics #where_clause {
+                fn empty() -> Self {
+                    #optional_ident::empty()
+                }
+
+                fn is_empty(&self) -> bool {
+                    #optional_ident::is_empty(self)
+                }
+
+                fn missing_fields(&self) -> Vec<&'static str> {
+                    #optional_ident::missing_fields(self)
+                }
+
+                fn retain_masked(&mut self, mask: &[&str]) {
+                    #optional_ident::retain_masked(self, mask)
+                }
+
+                fn apply_with_inverse(self, target: &mut #ident #ty_generics) -> Self {
+                    #optional_ident::apply_with_inverse(self, target)
+                }
+
+                fn __optionalize_fill(self, rest: Self) -> Self {
+                    #optional_ident::__optionalize_fill(self, rest)
+                }
+            }
+        }
+    };
+
+    let diff = if cx.emits("diff") {
+        let bounds = diff::bounds(cx);
+        let invert_bounds = diff::invert_bounds(cx);
+        let changed_bounds = diff::changed_bounds(cx);
+        // The impl forwards to methods bounded on these, so `bound` can't
+        // replace them
+        let own = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+        let where_clause = quote! { where #( #own, )* #bounds #invert_bounds #changed_bounds };
+        quote! {
+            impl #impl_generics #runtime::Diff<#ident #ty_generics> for #optional_ident #ty_generics #where_clause {
+                fn diff(base: &#ident #ty_generics, target: &#ident #ty_generics) -> Self {
+                    #optional_ident::diff(base, target)
+                }
+
+                fn invert(&self, base: &#ident #ty_generics) -> Self {
+                    #optional_ident::invert(self, base)
+                }
+
+                fn changed_fields(&self, base: &#ident #ty_generics) -> Vec<&'static str> {
+                    #optional_ident::changed_fields(self, base)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let fields = cx.fields.iter().map(|field| {
+        let name = field.name();
+        let ty = type_name(&field.ty);
+        let optional = field.is_option();
+        let doc = doc(field);
+        quote! {
+            #runtime::FieldMeta {
+                name: #name,
+                ty: #ty,
+                optional: #optional,
+                doc: #doc,
+            }
+        }
+    });
+
+    let apply = if cx.emits("apply") {
+        let any_patch = any_patch(cx, runtime);
+        let registration = registration(cx, runtime);
+        quote! {
+            impl #impl_generics #runtime::Optionalize for #ident #ty_generics #where_clause {
+                type Optional = #optional_ident #ty_generics;
+
+                const FIELDS: &'static [#runtime::FieldMeta] = <#optional_ident #ty_generics>::FIELDS;
+            }
+
+            impl #impl_generics #runtime::ApplyPatch<#ident #ty_generics> for #optional_ident #ty_generics #own_where_clause {
+                fn apply_to(self, target: &mut #ident #ty_generics) {
+                    #optional_ident::apply_to(self, target);
+                }
+            }
+
+            #any_patch
+            #registration
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #own_where_clause {
+            /// The fields of the original struct, in declaration order, with
+            /// their type, whether they were already optional and their docs.
+            pub const FIELDS: &'static [#runtime::FieldMeta] = &[
+                #( #fields, )*
+            ];
+        }
+
+        #apply
+        #merge
+        #diff
+    }
+}
+
+/// Registers the generated struct with the runtime crate's patch registry
+/// under its path, e.g. `app::models::UserOptional`, for deserializable
+/// structs that can be named without generic arguments. The runtime crate's
+/// macro expands to nothing unless its `registry` feature is enabled.
+fn registration(cx: &Container, runtime: &Path) -> TokenStream {
+    if !cx.derives_deserialize() || !cx.generics.params.is_empty() {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let name = format!("::{}", optional_ident);
+
+    quote! {
+        #runtime::__register_patch!(::std::concat!(::std::module_path!(), #name), #optional_ident);
+    }
+}
+
+/// Implements `AnyPatch` for the generated struct, unless it borrows and so
+/// can't be `Any`.
+fn any_patch(cx: &Container, runtime: &Path) -> TokenStream {
+    if cx.generics.lifetimes().next().is_some() {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let name = ident.to_string();
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+    let own = cx
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates);
+
+    let (to_json, serialize_bound) = if cx.attrs.integrates("serde_json") && cx.derives_serialize() {
+        let to_json = quote! {
+            fn to_json(&self) -> Option<String> {
+                ::serde_json::to_string(self).ok()
+            }
+        };
+        (to_json, quote! { for<'__optionalize> Self: ::serde::Serialize, })
+    } else {
+        (quote! {}, quote! {})
+    };
+    // Downcasting needs both types to be `'static`
+    let where_clause = quote! {
+        where
+            #( #own, )*
+            Self: 'static,
+            #ident #ty_generics: 'static,
+            #serialize_bound
+    };
+
+    quote! {
+        impl #impl_generics #runtime::AnyPatch for #optional_ident #ty_generics #where_clause {
+            fn target_name(&self) -> &'static str {
+                #name
+            }
+
+            fn set_fields(&self) -> Vec<&'static str> {
+                #optional_ident::set_fields(self)
+            }
+
+            fn apply_any(
+                self: ::std::boxed::Box<Self>,
+                target: &mut dyn ::std::any::Any,
+            ) -> Result<(), ::std::boxed::Box<dyn #runtime::AnyPatch>> {
+                match target.downcast_mut::<#ident #ty_generics>() {
+                    Some(target) => {
+                        #optional_ident::apply_to(*self, target);
+                        Ok(())
+                    }
+                    None => Err(self),
+                }
+            }
+
+            #to_json
+
+            fn as_any(&self) -> &dyn ::std::any::Any {
+                self
+            }
+        }
+    }
+}
+
+/// `ty` as written in the source, e.g. `Option<String>`, without the spacing
+/// of its tokens.
+fn type_name(ty: &Type) -> String {
+    quote!(#ty)
+        .to_string()
+        .replace(" :: ", "::")
+        .replace(":: ", "::")
+        .replace(" < ", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace(" ;", ";")
+        .replace("& ", "&")
+}
+
+/// The field's doc comment, one line per `///` line.
+fn doc(field: &Field) -> String {
+    let lines: Vec<String> = field
+        .source_attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    lines.join("\n")
+}
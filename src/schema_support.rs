@@ -0,0 +1,28 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{path_ends_with, Container, Field};
+
+/// Schema attributes placed on a field of the generated struct, so
+/// `schemars::JsonSchema` and `utoipa::ToSchema` describe double `Option`
+/// fields as they travel: a nullable property that may be omitted.
+pub fn field_attrs(cx: &Container, field: &Field) -> Vec<TokenStream> {
+    let mut attrs = Vec::new();
+    if !field.double_option {
+        return attrs;
+    }
+
+    // Both layers show up on the wire as one nullable value, and the serde
+    // `with` module is no type schemars could describe
+    let value_ty = field.inner_ty();
+    let derives = |name: &str| cx.attrs.derives.iter().any(|path| path_ends_with(path, name));
+    if derives("JsonSchema") {
+        let with = quote!(#value_ty).to_string();
+        attrs.push(quote! { #[schemars(with = #with)] });
+    }
+    if derives("ToSchema") {
+        attrs.push(quote! { #[schema(value_type = #value_ty, required = false)] });
+    }
+
+    attrs
+}
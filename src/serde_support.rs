@@ -0,0 +1,276 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Error, Ident, Result};
+
+use crate::ast::{first_type_argument, type_ends_with, Container, Field};
+
+/// Generates the helper functions referenced by the serde attributes of the
+/// generated fields.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let mut helpers = Vec::new();
+    for field in cx.fields.iter().filter(|field| field.attrs.empty_as_none) {
+        if !cx.derives_deserialize() {
+            return Err(Error::new_spanned(
+                &field.ident,
+                "`empty_as_none` requires `#[optionalize(derive(Deserialize))]`",
+            ));
+        }
+
+        let helper = empty_as_none_ident(field);
+        let inner_ty = field.inner_ty();
+        helpers.push(quote! {
+            #[doc(hidden)]
+            pub fn #helper<'de, D>(deserializer: D) -> Result<Option<#inner_ty>, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+                #inner_ty: ::serde::Deserialize<'de>,
+            {
+                let value: Option<#inner_ty> = ::serde::Deserialize::deserialize(deserializer)?;
+                Ok(value.filter(|value| !value.is_empty()))
+            }
+        });
+    }
+
+    for field in cx.fields.iter().filter(|field| is_form_field(cx, field)) {
+        if !cx.derives_deserialize() {
+            return Err(Error::new_spanned(
+                &field.ident,
+                format!("`{}` requires `#[optionalize(derive(Deserialize))]`", form_attr(cx)),
+            ));
+        }
+
+        let helper = form_ident(field);
+        let inner_ty = field.inner_ty();
+        // Checkboxes submit `on`, and other inputs their text
+        let parse = if type_ends_with(inner_ty, "bool") {
+            quote! {
+                Some("true" | "on" | "yes" | "1") => Ok(Some(true)),
+                Some("false" | "off" | "no" | "0") => Ok(Some(false)),
+                Some(other) => Err(::serde::de::Error::custom(format!("expected a boolean, found `{}`", other))),
+            }
+        } else {
+            quote! {
+                Some(value) => value.parse().map(Some).map_err(::serde::de::Error::custom),
+            }
+        };
+        helpers.push(quote! {
+            #[doc(hidden)]
+            pub fn #helper<'de, D>(deserializer: D) -> Result<Option<#inner_ty>, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+                #inner_ty: ::std::str::FromStr,
+                <#inner_ty as ::std::str::FromStr>::Err: ::std::fmt::Display,
+            {
+                let value: Option<String> = ::serde::Deserialize::deserialize(deserializer)?;
+                match value.as_deref().map(str::trim) {
+                    None | Some("") => Ok(None),
+                    #parse
+                }
+            }
+        });
+    }
+
+    for field in cx.fields.iter().filter(|field| is_query_collection_field(cx, field)) {
+        if !cx.derives_deserialize() {
+            return Err(Error::new_spanned(
+                &field.ident,
+                "`query` requires `#[optionalize(derive(Deserialize))]`",
+            ));
+        }
+
+        let helper = query_ident(field);
+        let inner_ty = field.inner_ty();
+        let element_ty = first_type_argument(inner_ty);
+        // Every occurrence of the key is an element, and empty ones are dropped
+        helpers.push(quote! {
+            #[doc(hidden)]
+            pub fn #helper<'de, D>(deserializer: D) -> Result<Option<#inner_ty>, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+                #element_ty: ::std::str::FromStr,
+                <#element_ty as ::std::str::FromStr>::Err: ::std::fmt::Display,
+            {
+                let values: Vec<String> = ::serde::Deserialize::deserialize(deserializer)?;
+                let elements = values
+                    .iter()
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.parse::<#element_ty>().map_err(::serde::de::Error::custom))
+                    .collect::<Result<#inner_ty, D::Error>>()?;
+                Ok(Some(elements).filter(|elements| !elements.is_empty()))
+            }
+        });
+    }
+
+    for field in cx.fields.iter().filter(|field| is_reject_null_field(cx, field)) {
+        if !cx.derives_deserialize() {
+            return Err(Error::new_spanned(
+                &field.ident,
+                "`reject_null` requires `#[optionalize(derive(Deserialize))]`",
+            ));
+        }
+
+        let helper = reject_null_ident(field);
+        let inner_ty = field.inner_ty();
+        let message = format!("`{}` cannot be null", field.name());
+        helpers.push(quote! {
+            #[doc(hidden)]
+            pub fn #helper<'de, D>(deserializer: D) -> Result<Option<#inner_ty>, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+                #inner_ty: ::serde::Deserialize<'de>,
+            {
+                let value: Option<#inner_ty> = ::serde::Deserialize::deserialize(deserializer)?;
+                value
+                    .map(Some)
+                    .ok_or_else(|| ::serde::de::Error::custom(#message))
+            }
+        });
+    }
+
+    if helpers.is_empty() {
+        return Ok(quote! {});
+    }
+
+    Ok(quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            #( #helpers )*
+        }
+    })
+}
+
+/// Serde attributes placed on a field of the generated struct.
+pub fn field_attrs(cx: &Container, field: &Field) -> Vec<TokenStream> {
+    let mut attrs = Vec::new();
+
+    // Secrets never leave the process through the patch
+    if field.is_secret() && cx.derives_serialize() {
+        attrs.push(quote! { #[serde(skip_serializing)] });
+    }
+
+    // Flattened patches share the parent's object
+    if field.attrs.flatten && (cx.derives_serialize() || cx.derives_deserialize()) {
+        attrs.push(quote! { #[serde(flatten)] });
+    }
+
+    // `null` clears the field, while an absent key leaves it alone
+    if field.double_option && (cx.derives_serialize() || cx.derives_deserialize()) {
+        attrs.push(quote! {
+            #[serde(
+                with = "::serde_with::rust::double_option",
+                default,
+                skip_serializing_if = "Option::is_none"
+            )]
+        });
+    }
+
+    if field.attrs.empty_as_none && cx.derives_deserialize() {
+        let path = helper_path(cx, empty_as_none_ident(field));
+        attrs.push(quote! { #[serde(default, deserialize_with = #path)] });
+    }
+
+    if is_form_field(cx, field) && cx.derives_deserialize() {
+        let path = helper_path(cx, form_ident(field));
+        attrs.push(quote! { #[serde(default, deserialize_with = #path)] });
+    }
+
+    if is_query_collection_field(cx, field) && cx.derives_deserialize() {
+        let path = helper_path(cx, query_ident(field));
+        attrs.push(quote! { #[serde(default, deserialize_with = #path)] });
+    }
+
+    if is_reject_null_field(cx, field) && cx.derives_deserialize() {
+        let path = helper_path(cx, reject_null_ident(field));
+        attrs.push(quote! { #[serde(default, deserialize_with = #path)] });
+    }
+
+    // Unset fields are left out rather than written as the `null` rejected above
+    if is_reject_null_field(cx, field) && cx.derives_serialize() && !field.is_secret() {
+        attrs.push(quote! { #[serde(skip_serializing_if = "Option::is_none")] });
+    }
+
+    attrs
+}
+
+/// Whether `reject_null` applies to the field: one that wasn't an `Option` in
+/// the original struct, and isn't deserialized by another helper.
+fn is_reject_null_field(cx: &Container, field: &Field) -> bool {
+    cx.attrs.reject_null
+        && !field.is_option()
+        && !field.double_option
+        && field.option_like.is_none()
+        && !field.attrs.flatten
+        && !field.attrs.empty_as_none
+        && !is_form_field(cx, field)
+        && !is_query_collection_field(cx, field)
+}
+
+/// Whether `form` parses the field from a string. Strings are taken as-is, and
+/// fields with their own representation keep it.
+fn is_form_field(cx: &Container, field: &Field) -> bool {
+    (cx.attrs.form || cx.attrs.query)
+        && !type_ends_with(field.inner_ty(), "String")
+        && !is_query_collection_field(cx, field)
+        && !field.attrs.flatten
+        && !field.attrs.empty_as_none
+        && !field.double_option
+        && !field.is_secret()
+        && field.nested_ty.is_none()
+        && field.option_like.is_none()
+        && field.attrs.with.is_none()
+}
+
+/// Whether `query` parses the field from every occurrence of its key: a
+/// sequence or set collection of `FromStr` elements.
+fn is_query_collection_field(cx: &Container, field: &Field) -> bool {
+    let inner_ty = field.inner_ty();
+    cx.attrs.query
+        && QUERY_COLLECTIONS.iter().any(|name| type_ends_with(inner_ty, name))
+        && first_type_argument(inner_ty).is_some()
+        && !field.attrs.flatten
+        && !field.attrs.empty_as_none
+        && !field.double_option
+        && !field.is_secret()
+        && field.nested_ty.is_none()
+        && field.option_like.is_none()
+        && field.attrs.with.is_none()
+}
+
+/// Collections `query` fills from repeated keys.
+const QUERY_COLLECTIONS: &[&str] = &["Vec", "VecDeque", "HashSet", "BTreeSet"];
+
+/// The attribute that turned on string parsing, for error messages.
+fn form_attr(cx: &Container) -> &'static str {
+    if cx.attrs.query {
+        "query"
+    } else {
+        "form"
+    }
+}
+
+/// The path of a helper function on the generated struct, as used in serde attributes.
+fn helper_path(cx: &Container, helper: Ident) -> String {
+    let (_, ty_generics, _) = cx.generics.split_for_impl();
+    let turbofish = ty_generics.as_turbofish();
+    format!("{}{}::{}", cx.optional_ident, quote!(#turbofish), helper)
+}
+
+fn empty_as_none_ident(field: &Field) -> Ident {
+    format_ident!("__optionalize_empty_as_none_{}", field.ident.unraw())
+}
+
+fn reject_null_ident(field: &Field) -> Ident {
+    format_ident!("__optionalize_reject_null_{}", field.ident.unraw())
+}
+
+fn form_ident(field: &Field) -> Ident {
+    format_ident!("__optionalize_form_{}", field.ident.unraw())
+}
+
+fn query_ident(field: &Field) -> Ident {
+    format_ident!("__optionalize_query_{}", field.ident.unraw())
+}
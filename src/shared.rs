@@ -0,0 +1,160 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::{first_type_argument, type_ends_with, Container, Field};
+
+/// Generates `{Optional}Shared`, the patch with every value behind an `Arc` so
+/// clones are cheap, along with the conversions from and to the patch. Only
+/// emitted with `#[optionalize(shared)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.shared {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let shared_ident = format_ident!("{}Shared", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+    let debug = if cx.derives().iter().any(|path| path.is_ident("Debug")) {
+        quote! { #[derive(Debug)] }
+    } else {
+        quote! {}
+    };
+
+    let fields = cx.fields.iter().map(|field| {
+        let vis = &field.vis;
+        let field_ident = &field.ident;
+        let ty = shared_ty(field);
+        quote! { #vis #field_ident: #ty }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    let cloned = cx.fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        quote! { #field_ident: ::std::clone::Clone::clone(&self.#field_ident) }
+    });
+    let shared = cx.fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        if is_whole(field) {
+            quote! { #field_ident: ::std::sync::Arc::new(patch.#field_ident) }
+        } else {
+            quote! { #field_ident: patch.#field_ident.map(::std::sync::Arc::from) }
+        }
+    });
+    let owned = cx.fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        if is_whole(field) {
+            quote! { #field_ident: ::std::clone::Clone::clone(&*self.#field_ident) }
+        } else {
+            quote! { #field_ident: self.#field_ident.as_deref().map(::std::borrow::ToOwned::to_owned) }
+        }
+    });
+    // Values are copied out of the `Arc`s
+    let bounds = cx.fields.iter().map(|field| {
+        let ty = field.optional_ty();
+        if is_whole(field) {
+            quote! { for<'__optionalize> #ty: ::std::clone::Clone, }
+        } else {
+            let inner = field.inner_ty();
+            let shared = shared_inner(field);
+            quote! { for<'__optionalize> #shared: ::std::borrow::ToOwned<Owned = #inner>, }
+        }
+    });
+    let bounds = quote! { #( #bounds )* };
+
+    let doc = format!(
+        "A [`{}`] holding its values behind `Arc`s, so it can be cloned to many workers without copying them.",
+        optional_ident
+    );
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// `String` values are stored as `Arc<str>` and `Vec<T>` values as
+        /// `Arc<[T]>`. Build one with `into_shared`.
+        #debug
+        #[must_use]
+        pub struct #shared_ident <#generics> #where_clause {
+            #( #fields, )*
+            #( #markers, )*
+        }
+
+        impl #impl_generics ::std::clone::Clone for #shared_ident #ty_generics #where_clause {
+            /// Clones the `Arc`s, not the values behind them.
+            fn clone(&self) -> Self {
+                Self {
+                    #( #cloned, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Moves the values of the patch behind `Arc`s, for cheap clones.
+            pub fn into_shared(self) -> #shared_ident #ty_generics {
+                let patch = self;
+                #shared_ident {
+                    #( #shared, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+        }
+
+        impl #impl_generics #shared_ident #ty_generics #where_clause {
+            /// Copies the values out into a regular patch.
+            pub fn to_patch(&self) -> #optional_ident #ty_generics
+            where
+                #bounds
+            {
+                #optional_ident {
+                    #( #owned, )*
+                    #( #marker_idents: ::std::marker::PhantomData, )*
+                }
+            }
+
+            /// Applies a copy of the patch onto `target`, leaving `self` to be
+            /// applied elsewhere.
+            pub fn apply_to(&self, target: &mut #ident #ty_generics)
+            where
+                #bounds
+            {
+                self.to_patch().apply_to(target);
+            }
+        }
+    }
+}
+
+/// Whether the field is shared as a whole rather than as its `Option` value:
+/// flattened and option-like fields aren't `Option`s in the patch.
+fn is_whole(field: &Field) -> bool {
+    field.attrs.flatten || field.option_like.is_some()
+}
+
+/// The type of the field in the shared patch.
+fn shared_ty(field: &Field) -> TokenStream {
+    if is_whole(field) {
+        let ty = field.optional_ty();
+        return quote! { ::std::sync::Arc<#ty> };
+    }
+    let shared = shared_inner(field);
+    quote! { Option<::std::sync::Arc<#shared>> }
+}
+
+/// The type behind the `Arc` of a field that is an `Option` in the patch.
+fn shared_inner(field: &Field) -> TokenStream {
+    let inner = field.inner_ty();
+    if type_ends_with(inner, "String") {
+        return quote! { str };
+    }
+    match first_type_argument(inner) {
+        Some(element) if type_ends_with(inner, "Vec") => quote! { [#element] },
+        _ => quote! { #inner },
+    }
+}
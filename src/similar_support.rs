@@ -0,0 +1,126 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::{type_ends_with, Container, Field};
+
+/// Generates per-field helpers producing and applying line and word diffs of
+/// `String` fields with `similar`. Only emitted with `#[optionalize(similar)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("similar") {
+        return quote! {};
+    }
+
+    // Only fields the patch may write hold text worth editing in place
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| type_ends_with(&field.ty, "String"))
+        .filter(|field| !field.attrs.immutable && !field.attrs.no_helpers && field.attrs.with.is_none())
+        .collect();
+    if fields.is_empty() {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let helpers = fields.iter().map(|field| helpers(cx, field));
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            #( #helpers )*
+
+            /// The edits of a text diff, as `(start, len, text)` replacing
+            /// `len` bytes of the old text at byte offset `start` with `text`.
+            #[doc(hidden)]
+            pub fn __text_edits<'__optionalize>(
+                diff: &::similar::TextDiff<'__optionalize, '__optionalize, '__optionalize, str>,
+            ) -> Vec<(usize, usize, String)> {
+                let old = diff.old_slices();
+                let new = diff.new_slices();
+                let mut offsets = Vec::with_capacity(old.len() + 1);
+                let mut offset = 0;
+                offsets.push(offset);
+                for token in old {
+                    offset += token.len();
+                    offsets.push(offset);
+                }
+                diff.ops()
+                    .iter()
+                    .filter(|op| op.tag() != ::similar::DiffTag::Equal)
+                    .map(|op| {
+                        let start = offsets[op.old_range().start];
+                        let len = offsets[op.old_range().end] - start;
+                        (start, len, new[op.new_range()].concat())
+                    })
+                    .collect()
+            }
+
+            /// Applies the edits of [`__text_edits`](Self::__text_edits) onto
+            /// `text`, or leaves it untouched if they don't fit it.
+            #[doc(hidden)]
+            pub fn __apply_text_edits(text: &mut String, edits: &[(usize, usize, String)]) -> Result<(), String> {
+                let mut end = 0;
+                for (start, len, _) in edits {
+                    let fits = start.checked_add(*len).filter(|stop| *start >= end && text.get(*start..*stop).is_some());
+                    end = match fits {
+                        Some(stop) => stop,
+                        None => return Err(format!("edit at byte {} doesn't fit the text", start)),
+                    };
+                }
+                for (start, len, replacement) in edits.iter().rev() {
+                    text.replace_range(*start..*start + *len, replacement);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The diff and apply helpers of a single `String` field.
+fn helpers(cx: &Container, field: &Field) -> TokenStream {
+    let ident = &cx.ident;
+    let error_ident = cx.error_ident();
+    let (_, ty_generics, _) = cx.generics.split_for_impl();
+    let field_ident = &field.ident;
+    let name = field.name();
+    let raw = field_ident.to_string();
+    let raw = raw.trim_start_matches("r#");
+    let diff_lines = format_ident!("{}_diff_lines", raw);
+    let diff_words = format_ident!("{}_diff_words", raw);
+    let apply_edits = format_ident!("apply_{}_edits", raw);
+
+    let lines_doc = format!(
+        "The line edits turning `old` into `new`, a value of `{}`, for sending a small change to a long text instead of the whole of it.",
+        name
+    );
+    let words_doc = format!("Like [`{}`](Self::{}), but edits words rather than lines.", diff_lines, diff_lines);
+    let apply_doc = format!(
+        "Applies edits from [`{}`](Self::{}) or [`{}`](Self::{}) onto `{}` of `target`.",
+        diff_lines, diff_lines, diff_words, diff_words, name
+    );
+
+    quote! {
+        #[doc = #lines_doc]
+        ///
+        /// Each edit replaces `len` bytes at byte offset `start` of `old` with
+        /// `text`, as `(start, len, text)`.
+        pub fn #diff_lines(old: &str, new: &str) -> Vec<(usize, usize, String)> {
+            Self::__text_edits(&::similar::TextDiff::from_lines(old, new))
+        }
+
+        #[doc = #words_doc]
+        pub fn #diff_words(old: &str, new: &str) -> Vec<(usize, usize, String)> {
+            Self::__text_edits(&::similar::TextDiff::from_words(old, new))
+        }
+
+        #[doc = #apply_doc]
+        ///
+        /// Fails with `InvalidField` when the edits don't fit the current text,
+        /// e.g. because it changed since they were computed, leaving it
+        /// untouched.
+        pub fn #apply_edits(target: &mut #ident #ty_generics, edits: &[(usize, usize, String)]) -> Result<(), #error_ident> {
+            Self::__apply_text_edits(&mut target.#field_ident, edits)
+                .map_err(|message| #error_ident::InvalidField { field: #name, message })
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, GenericParam};
+
+use crate::ast::{Container, Field};
+
+/// Generates `set_params` and `to_set_clause`, which render the set fields as
+/// the `SET` clause of an `UPDATE` for any SQL driver, with values converted
+/// into a type chosen by the caller.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("sql") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (own_impl_generics, ty_generics, own_where_clause) = cx.generics.split_for_impl();
+    let quoted_column = quote_identifier(quote!(column));
+
+    // Immutable fields are never written, secrets and option-like values
+    // can't be converted generically, and nested patches aren't column values
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable && !field.is_secret() && field.option_like.is_none())
+        .filter(|field| field.nested_ty.is_none() || field.attrs.flatten)
+        .collect();
+
+    // The pairs are collected through `From`, so flattened twins can be
+    // required to provide theirs for the same value type
+    let bounds = fields.iter().map(|field| {
+        let ty = field.inner_ty();
+        if field.attrs.flatten {
+            quote! { for<'__optionalize> ::std::vec::Vec<(&'static str, __V)>: ::std::convert::From<&'__optionalize #ty> }
+        } else {
+            quote! { for<'__optionalize> #ty: ::std::clone::Clone + ::std::convert::Into<__V> }
+        }
+    });
+    let own_predicates = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+    let where_clause = quote! { where #( #own_predicates, )* #( #bounds, )* };
+
+    let mut generics = cx.generics.clone();
+    generics.params.insert(0, parse_quote!('__patch));
+    generics.params.push(GenericParam::Type(parse_quote!(__V)));
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let params = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { params.extend(::std::vec::Vec::from(&patch.#ident)); };
+        }
+        quote! {
+            if let Some(value) = &patch.#ident {
+                params.push((#name, ::std::clone::Clone::clone(value).into()));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics ::std::convert::From<&'__patch #optional_ident #ty_generics>
+            for ::std::vec::Vec<(&'static str, __V)>
+        #where_clause
+        {
+            /// Collects the `(column, value)` pairs of the set fields, as
+            /// returned by `set_params`.
+            fn from(patch: &'__patch #optional_ident #ty_generics) -> Self {
+                let mut params = ::std::vec::Vec::new();
+                #( #params )*
+                params
+            }
+        }
+
+        impl #own_impl_generics #optional_ident #ty_generics #own_where_clause {
+            /// Returns a `(column, value)` pair for every set field, in declaration
+            /// order, with the values converted with `Into` into `V`, e.g. the
+            /// value type of the SQL driver or query builder in use.
+            pub fn set_params<V>(&self) -> ::std::vec::Vec<(&'static str, V)>
+            where
+                for<'__optionalize> ::std::vec::Vec<(&'static str, V)>: ::std::convert::From<&'__optionalize Self>,
+            {
+                ::std::vec::Vec::from(self)
+            }
+
+            /// Renders the set fields as the body of a `SET` clause, along with
+            /// the values to bind in that order. `placeholder` renders the
+            /// placeholder of the value at a 1-based index for a column, which
+            /// covers every driver's style:
+            ///
+            /// - `|_, _| "?".to_string()` for `"name" = ?` (MySQL, SQLite)
+            /// - `|index, _| format!("${}", index)` for `"name" = $1` (PostgreSQL)
+            /// - `|_, column| format!(":{}", column)` for `"name" = :name`
+            ///
+            /// Columns are quoted with double quotes, doubling any in their
+            /// names. A patch that sets no fields renders an empty clause, which
+            /// isn't valid SQL; check `set_fields` first.
+            pub fn to_set_clause<V>(
+                &self,
+                placeholder: impl Fn(usize, &str) -> String,
+            ) -> (String, ::std::vec::Vec<V>)
+            where
+                for<'__optionalize> ::std::vec::Vec<(&'static str, V)>: ::std::convert::From<&'__optionalize Self>,
+            {
+                let mut set = ::std::vec::Vec::new();
+                let mut values = ::std::vec::Vec::new();
+                for (index, (column, value)) in self.set_params::<V>().into_iter().enumerate() {
+                    set.push(format!("{} = {}", #quoted_column, placeholder(index + 1, column)));
+                    values.push(value);
+                }
+                (set.join(", "), values)
+            }
+        }
+    }
+}
+
+/// An expression quoting the `&str` `identifier` as an SQL identifier, in
+/// double quotes with any `"` in it doubled, so a name can't end the
+/// identifier and inject SQL. Shared by every generator rendering statements.
+pub fn quote_identifier(identifier: TokenStream) -> TokenStream {
+    quote! {
+        ::std::format!("\"{}\"", ::std::primitive::str::replace(#identifier, '"', "\"\""))
+    }
+}
@@ -0,0 +1,107 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container, Field};
+use crate::sql;
+
+/// Generates `sqlite_params`, `to_sqlite_update` and `sqlite_update`, which
+/// write the set fields to a row with a rusqlite `UPDATE`. Only emitted with
+/// `#[optionalize(rusqlite)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("rusqlite") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    // Immutable fields are never written, secrets and option-like values
+    // can't be bound generically, and nested patches aren't column values
+    let fields: Vec<&Field> = cx
+        .fields
+        .iter()
+        .filter(|field| !field.attrs.immutable && !field.is_secret() && field.option_like.is_none())
+        .filter(|field| field.nested_ty.is_none() || field.attrs.flatten)
+        .collect();
+    let bounds = inner_bounds(fields.iter().copied(), quote!(::rusqlite::ToSql));
+    let quoted_column = sql::quote_identifier(quote!(column));
+    let quoted_table = sql::quote_identifier(quote!(table));
+    let quoted_key_column = sql::quote_identifier(quote!(key_column));
+
+    let params = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = field.name();
+        if field.attrs.flatten {
+            return quote! { params.extend(self.#ident.sqlite_params()); };
+        }
+        quote! {
+            if let Some(value) = &self.#ident {
+                params.push((#name, value as &dyn ::rusqlite::ToSql));
+            }
+        }
+    });
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Returns a `(column, value)` pair for every set field, in declaration
+            /// order, ready to be bound as rusqlite parameters.
+            pub fn sqlite_params(&self) -> Vec<(&'static str, &dyn ::rusqlite::ToSql)>
+            where
+                #bounds
+            {
+                let mut params = Vec::new();
+                #( #params )*
+                params
+            }
+
+            /// Renders the `UPDATE` statement writing the set fields to the row
+            /// of `table` whose `key_column` matches, e.g.
+            /// `UPDATE "users" SET "name" = ?1, "age" = ?2 WHERE "id" = ?3`.
+            ///
+            /// The key is bound last, after the values of `sqlite_params`.
+            /// `table` and `key_column` are quoted as identifiers, doubling any
+            /// `"` in them as SQL does, so a name read at runtime can't end the
+            /// identifier and inject SQL; it still names whatever table it says.
+            pub fn to_sqlite_update(&self, table: &str, key_column: &str) -> String
+            where
+                #bounds
+            {
+                let set: Vec<String> = self
+                    .sqlite_params()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (column, _))| format!("{} = ?{}", #quoted_column, index + 1))
+                    .collect();
+                format!(
+                    "UPDATE {} SET {} WHERE {} = ?{}",
+                    #quoted_table,
+                    set.join(", "),
+                    #quoted_key_column,
+                    set.len() + 1,
+                )
+            }
+
+            /// Writes the set fields to the row of `table` whose `key_column` is
+            /// `key`, returning the number of updated rows. A patch that sets no
+            /// fields runs no statement and updates nothing.
+            pub fn sqlite_update(
+                &self,
+                connection: &::rusqlite::Connection,
+                table: &str,
+                key_column: &str,
+                key: &dyn ::rusqlite::ToSql,
+            ) -> ::rusqlite::Result<usize>
+            where
+                #bounds
+            {
+                let mut values: Vec<&dyn ::rusqlite::ToSql> =
+                    self.sqlite_params().into_iter().map(|(_, value)| value).collect();
+                if values.is_empty() {
+                    return Ok(0);
+                }
+                values.push(key);
+                connection.execute(&self.to_sqlite_update(table, key_column), values.as_slice())
+            }
+        }
+    }
+}
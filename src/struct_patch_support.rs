@@ -0,0 +1,43 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+use crate::diff;
+
+/// Implements `struct_patch::Patch` for the original struct with the generated
+/// struct as its patch type. Only emitted with `#[optionalize(struct_patch)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("struct_patch") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, _) = cx.generics.split_for_impl();
+    // `into_patch_by_diff` needs whatever `diff` does, even under a custom `bound`
+    let own = cx.generics.where_clause.iter().flat_map(|where_clause| &where_clause.predicates);
+    let bounds = diff::bounds(cx);
+    let where_clause = quote! { where #( #own, )* #bounds };
+
+    quote! {
+        impl #impl_generics ::struct_patch::Patch<#optional_ident #ty_generics> for #ident #ty_generics
+        #where_clause
+        {
+            fn apply(&mut self, patch: #optional_ident #ty_generics) {
+                patch.apply_to(self);
+            }
+
+            fn into_patch(self) -> #optional_ident #ty_generics {
+                ::std::convert::From::from(self)
+            }
+
+            fn into_patch_by_diff(self, previous_struct: Self) -> #optional_ident #ty_generics {
+                #optional_ident::diff(&previous_struct, &self)
+            }
+
+            fn new_empty_patch() -> #optional_ident #ty_generics {
+                #optional_ident::empty()
+            }
+        }
+    }
+}
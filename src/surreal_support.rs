@@ -0,0 +1,34 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{inner_bounds, Container};
+
+/// Generates `to_surreal_merge`, which renders the patch as the content of a
+/// SurrealDB `MERGE`. Only emitted with `#[optionalize(surrealdb)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("surrealdb") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let fields = cx.fields.iter().filter(|field| !field.is_secret() && field.nested_ty.is_none());
+    let bounds = inner_bounds(fields, quote!(::serde::Serialize));
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// The content of a SurrealDB `MERGE` (or `UPDATE ... MERGE`) writing
+            /// the set fields, e.g. for `db.update(record).merge(content)`.
+            ///
+            /// Unset fields are left out and `nested` patches become objects of
+            /// their own set fields, so SurrealDB keeps what the patch doesn't
+            /// mention. Cleared `double_option` fields are written as `null`.
+            pub fn to_surreal_merge(&self) -> Result<::serde_json::Value, ::serde_json::Error>
+            where
+                #bounds
+            {
+                self.to_json_object().map(::serde_json::Value::Object)
+            }
+        }
+    }
+}
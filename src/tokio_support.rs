@@ -0,0 +1,43 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::Container;
+
+/// Generates `apply_stream`, which applies patches from an async stream as
+/// they arrive. Only emitted with `#[optionalize(tokio)]`.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.attrs.integrates("tokio") {
+        return quote! {};
+    }
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Applies every patch from `patches` onto `target` with
+            /// [`try_apply`](Self::try_apply) as it arrives, e.g. to keep live
+            /// state in sync with a message bus.
+            ///
+            /// The returned stream must be polled for patches to be applied.
+            /// It yields, per patch, the names of the fields it set, or the
+            /// error it was rejected with, in which case `target` is left as it
+            /// was and later patches still apply.
+            pub fn apply_stream<'__optionalize_target, __S>(
+                target: &'__optionalize_target mut #ident #ty_generics,
+                patches: __S,
+            ) -> impl ::tokio_stream::Stream<Item = Result<Vec<&'static str>, #error_ident>> + '__optionalize_target
+            where
+                __S: ::tokio_stream::Stream<Item = Self> + '__optionalize_target,
+            {
+                ::tokio_stream::StreamExt::map(patches, move |patch| {
+                    let fields = patch.set_fields();
+                    patch.try_apply(target)?;
+                    Ok(fields)
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, GenericParam, Member, Result};
+
+use crate::ast::{optional_ident, type_ends_with};
+use crate::attr::ContainerAttrs;
+
+/// Generates `Option<Inner>` as the optional form of a single-field newtype,
+/// instead of a struct. Only emitted with `#[optionalize(transparent)]`, and
+/// `None` otherwise.
+pub fn expand(input: &DeriveInput, partial: bool) -> Result<Option<TokenStream>> {
+    let mut attrs = ContainerAttrs::from_attrs(&input.attrs)?;
+    attrs.partial = partial;
+    if !attrs.transparent {
+        return Ok(None);
+    }
+    if let Some(derive) = attrs.derives.first() {
+        return Err(Error::new_spanned(
+            derive,
+            "`transparent` generates a type alias of `Option`, which can't take derives",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data_struct) => &data_struct.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "`transparent` requires a struct")),
+    };
+    // Marker fields don't hold a value, so a newtype may carry them as well
+    let mut values = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| !type_ends_with(&field.ty, "PhantomData"));
+    let (index, field) = match (values.next(), values.next()) {
+        (Some(value), None) => value,
+        _ => {
+            return Err(Error::new_spanned(
+                fields,
+                "`transparent` requires exactly one field besides `PhantomData` markers",
+            ));
+        }
+    };
+    let member = match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(index.into()),
+    };
+
+    let ident = &input.ident;
+    let optional_ident = optional_ident(&attrs, ident)?;
+    let ty = &field.ty;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // Bounds on type aliases aren't enforced, so only the parameters are kept
+    let params = input.generics.params.iter().map(|param| match param {
+        GenericParam::Type(param) => {
+            let ident = &param.ident;
+            quote! { #ident }
+        }
+        GenericParam::Lifetime(param) => {
+            let lifetime = &param.lifetime;
+            quote! { #lifetime }
+        }
+        GenericParam::Const(param) => {
+            let ident = &param.ident;
+            let ty = &param.ty;
+            quote! { const #ident: #ty }
+        }
+    });
+
+    let source_link = format!("Generated from [`{}`] by `Optionalize`.", ident);
+    let summary = attrs
+        .doc
+        .clone()
+        .unwrap_or_else(|| format!("Optional version of [`{}`], the `Option` of the value it wraps.", ident));
+
+    let expanded = quote! {
+        #[doc = #summary]
+        #[doc = ""]
+        #[doc = #source_link]
+        pub type #optional_ident<#( #params ),*> = ::std::option::Option<#ty>;
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Replaces the wrapped value with the one in `patch`, if it's set.
+            pub fn apply(&mut self, patch: #optional_ident #ty_generics) {
+                if let Some(value) = patch {
+                    self.#member = value;
+                }
+            }
+
+            /// Applies each patch in order, so later patches win.
+            pub fn apply_all(&mut self, patches: impl IntoIterator<Item = #optional_ident #ty_generics>) {
+                for patch in patches {
+                    self.apply(patch);
+                }
+            }
+
+            /// Builds a patch that sets the wrapped value.
+            pub fn into_optional(self) -> #optional_ident #ty_generics {
+                Some(self.#member)
+            }
+        }
+    };
+    crate::cfg::gate(attrs.cfg.as_ref(), expanded).map(Some)
+}
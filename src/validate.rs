@@ -0,0 +1,145 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::ast::{Container, Field};
+
+/// Generates `validate`, which checks the patch for internal consistency.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("apply") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let group_checks = groups(cx).into_iter().map(|(group, fields)| {
+        let names = fields.iter().map(|field| field.name());
+        let set = fields.iter().map(|field| field.is_set());
+
+        quote! {
+            {
+                let fields: &[(&'static str, bool)] = &[
+                    #( (#names, #set), )*
+                ];
+                // Either every field in the group is set, or none of them are
+                if fields.iter().any(|(_, set)| *set) && !fields.iter().all(|(_, set)| *set) {
+                    errors.push(#error_ident::IncompleteGroup {
+                        group: #group,
+                        missing: fields
+                            .iter()
+                            .filter(|(_, set)| !*set)
+                            .map(|(name, _)| *name)
+                            .collect(),
+                    });
+                }
+            }
+        }
+    });
+
+    let field_checks = cx.fields.iter().map(|field| field_checks(cx, field));
+
+    quote! {
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Checks that every `#[optionalize(group = "...")]` group is either
+            /// fully set or fully unset, and that set fields pass their `range`
+            /// and `regex` checks, failing with the first problem found.
+            pub fn validate(&self) -> Result<(), #error_ident> {
+                match self.validate_all().into_iter().next() {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                }
+            }
+
+            /// Runs the checks of [`validate`](Self::validate), returning every
+            /// problem found rather than only the first one.
+            pub fn validate_all(&self) -> Vec<#error_ident> {
+                let mut errors = Vec::new();
+                #( #group_checks )*
+                #( #field_checks )*
+                errors
+            }
+        }
+    }
+}
+
+/// Checks the value of a set field against its `range` and `regex` attributes,
+/// or validates a flattened patch.
+fn field_checks(cx: &Container, field: &Field) -> TokenStream {
+    let error_ident = cx.error_ident();
+    let ident = &field.ident;
+    let name = field.name();
+
+    // Flattened patches are validated by their own rules
+    if field.attrs.flatten {
+        return quote! {
+            for err in self.#ident.validate_all() {
+                errors.push(#error_ident::InvalidField {
+                    field: #name,
+                    message: err.to_string(),
+                });
+            }
+        };
+    }
+
+    let mut checks = Vec::new();
+
+    if let Some(min) = &field.attrs.min {
+        checks.push(quote! {
+            if *value < #min {
+                errors.push(#error_ident::InvalidField {
+                    field: #name,
+                    message: format!("must be at least {}", #min),
+                });
+            }
+        });
+    }
+    if let Some(max) = &field.attrs.max {
+        checks.push(quote! {
+            if *value > #max {
+                errors.push(#error_ident::InvalidField {
+                    field: #name,
+                    message: format!("must be at most {}", #max),
+                });
+            }
+        });
+    }
+    if let Some(regex) = &field.attrs.regex {
+        let message = format!("must match `{}`", regex.value());
+        checks.push(quote! {
+            static PATTERN: ::std::sync::OnceLock<::regex::Regex> = ::std::sync::OnceLock::new();
+            let pattern = PATTERN.get_or_init(|| ::regex::Regex::new(#regex).expect("invalid `regex` pattern"));
+            if !pattern.is_match(::std::convert::AsRef::<str>::as_ref(value)) {
+                errors.push(#error_ident::InvalidField {
+                    field: #name,
+                    message: #message.to_string(),
+                });
+            }
+        });
+    }
+
+    if checks.is_empty() {
+        return quote! {};
+    }
+    quote! {
+        if let Some(value) = &self.#ident {
+            #( #checks )*
+        }
+    }
+}
+
+/// Fields grouped by their `group` attribute, in order of first appearance.
+fn groups(cx: &Container) -> Vec<(&str, Vec<&Field>)> {
+    let mut groups: Vec<(&str, Vec<&Field>)> = Vec::new();
+
+    for field in &cx.fields {
+        if let Some(group) = &field.attrs.group {
+            match groups.iter_mut().find(|(name, _)| name == group) {
+                Some((_, fields)) => fields.push(field),
+                None => groups.push((group, vec![field])),
+            }
+        }
+    }
+
+    groups
+}
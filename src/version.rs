@@ -0,0 +1,180 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Result};
+
+use crate::ast::{is_numeric, Container};
+
+/// Generates `apply_if_version` when a version field is configured, and the
+/// versioned envelope when a schema version is.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    let envelope = envelope(cx)?;
+    let version = match cx.version_field() {
+        Some(version) => version,
+        None => {
+            if let Some(next) = &cx.attrs.next_version {
+                return Err(Error::new_spanned(
+                    next,
+                    "`next_version` requires a version field, e.g. `#[optionalize(version = \"revision\")]`",
+                ));
+            }
+            return Ok(envelope);
+        }
+    };
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let version_ident = &version.ident;
+    let version_name = version.name();
+    let version_ty = &version.ty;
+
+    // Counters move up by one, anything else through the hook
+    let next = match &cx.attrs.next_version {
+        Some(next) => quote! { #next(expected) },
+        None if is_numeric(version_ty) => quote! { *expected + 1 },
+        None => {
+            return Err(Error::new_spanned(
+                version_ty,
+                "a version field that isn't a number requires \
+                 `#[optionalize(next_version = \"path::to::fn\")]` to compute the version after a patch",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        #envelope
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Applies the patch with [`try_apply`](Self::try_apply), but only if
+            /// the target is still at the `expected` version, then moves the
+            /// target to the next version. Fails with `VersionConflict`
+            /// otherwise, so of two writers that read the same version, only
+            /// the first one's patch is applied.
+            pub fn apply_if_version(
+                self,
+                target: &mut #ident #ty_generics,
+                expected: &#version_ty,
+            ) -> Result<(), #error_ident> {
+                if target.#version_ident != *expected {
+                    return Err(#error_ident::VersionConflict { field: #version_name });
+                }
+                self.try_apply(target)?;
+                target.#version_ident = #next;
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Generates `{Optional}Versioned`, an envelope recording the schema version a
+/// patch was written with, so patches persisted in queues or event stores can
+/// be migrated once the struct evolves. Only emitted with
+/// `#[optionalize(version = N)]`.
+fn envelope(cx: &Container) -> Result<TokenStream> {
+    let version = match cx.attrs.schema_version {
+        Some(version) => version,
+        None => {
+            if let Some(migrate) = &cx.attrs.migrate_from {
+                return Err(Error::new_spanned(
+                    migrate,
+                    "`migrate_from` requires a schema version, e.g. `#[optionalize(schema_version = 2)]`",
+                ));
+            }
+            return Ok(quote! {});
+        }
+    };
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let error_ident = cx.error_ident();
+    let envelope_ident = format_ident!("{}Versioned", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let generics = &cx.generics.params;
+
+    let mut derives = Vec::new();
+    if cx.derives_serialize() {
+        derives.push(quote!(::serde::Serialize));
+    }
+    if cx.derives_deserialize() {
+        derives.push(quote!(::serde::Deserialize));
+    }
+    let derive = if derives.is_empty() {
+        quote! {}
+    } else {
+        quote! { #[derive(#( #derives ),*)] }
+    };
+    // Derived serde impls take the same bounds as the generated ones
+    let serde_bound = match &cx.attrs.bound {
+        Some(bound) if !derives.is_empty() => {
+            let bound = quote!(#( #bound ),*).to_string();
+            quote! { #[serde(bound = #bound)] }
+        }
+        _ => quote! {},
+    };
+
+    // Older patches go through the hook, and newer ones can't be understood
+    let older = match &cx.attrs.migrate_from {
+        Some(migrate) => quote! {
+            if self.version < <#optional_ident #ty_generics>::VERSION {
+                let mut patch = self.patch;
+                #migrate(self.version, &mut patch)?;
+                return Ok(patch);
+            }
+        },
+        None => quote! {},
+    };
+
+    let doc = format!(
+        "A [`{}`] along with the schema version it was written with, for patches persisted in queues or event stores.",
+        optional_ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Serialized as `{"version": 2, "patch": {...}}`.
+        #derive
+        #serde_bound
+        pub struct #envelope_ident <#generics> #where_clause {
+            /// The schema version the patch was written with.
+            pub version: u32,
+            /// The patch, as written.
+            pub patch: #optional_ident #ty_generics,
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Schema version of the patch, written into new envelopes.
+            pub const VERSION: u32 = #version;
+
+            /// Wraps the patch in an envelope at the current schema version.
+            pub fn into_versioned(self) -> #envelope_ident #ty_generics {
+                #envelope_ident {
+                    version: Self::VERSION,
+                    patch: self,
+                }
+            }
+        }
+
+        impl #impl_generics #envelope_ident #ty_generics #where_clause {
+            /// Upgrades the patch to the current schema version. Patches written
+            /// with an older version go through the `migrate_from` hook, and
+            /// those it can't handle, or written with a newer version, fail with
+            /// `UnsupportedVersion`.
+            pub fn into_current(self) -> Result<#optional_ident #ty_generics, #error_ident> {
+                if self.version == <#optional_ident #ty_generics>::VERSION {
+                    return Ok(self.patch);
+                }
+                #older
+                Err(#error_ident::UnsupportedVersion { version: self.version })
+            }
+
+            /// Upgrades the patch with [`into_current`](Self::into_current) and
+            /// applies it onto `target` with `try_apply`.
+            pub fn apply_to(self, target: &mut #ident #ty_generics) -> Result<(), #error_ident> {
+                self.into_current()?.try_apply(target)
+            }
+        }
+    })
+}
@@ -0,0 +1,76 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::ast::Container;
+
+/// Generates the `{Optional}FieldVisitor` trait, with a method per field, and
+/// `accept`, which calls each of them with the state of its field.
+pub fn expand(cx: &Container) -> TokenStream {
+    if !cx.emits("visit") {
+        return quote! {};
+    }
+
+    let optional_ident = &cx.optional_ident;
+    let visitor_ident = format_ident!("{}FieldVisitor", optional_ident);
+    let (impl_generics, ty_generics, where_clause) = cx.generics.split_for_impl();
+    let params = &cx.generics.params;
+
+    let methods = cx.fields.iter().map(|field| {
+        let method = format_ident!("visit_{}", field.name());
+        let ty = field.inner_ty();
+        // Flattened twins are always there, with their own set fields
+        let (value_ty, doc) = if field.attrs.flatten {
+            (quote! { &#ty }, format!("Visits the flattened `{}` patch.", field.name()))
+        } else {
+            (
+                quote! { ::std::option::Option<&#ty> },
+                format!("Visits the `{}` field, with its value if it is set.", field.name()),
+            )
+        };
+        quote! {
+            #[doc = #doc]
+            #[allow(unused_variables)]
+            fn #method(&mut self, value: #value_ty) {}
+        }
+    });
+
+    let visits = cx.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let method = format_ident!("visit_{}", field.name());
+        let value = if field.attrs.flatten {
+            quote! { &self.#ident }
+        } else if field.option_like.is_some() {
+            let is_set = field.is_set();
+            quote! {
+                if #is_set {
+                    ::std::option::Option::Some(&self.#ident)
+                } else {
+                    ::std::option::Option::None
+                }
+            }
+        } else {
+            quote! { self.#ident.as_ref() }
+        };
+        quote! { visitor.#method(#value); }
+    });
+
+    let doc = format!(
+        "Visitor over the fields of a [`{}`], called by its `accept` method. Every method does nothing by default.",
+        optional_ident
+    );
+
+    quote! {
+        #[doc = #doc]
+        pub trait #visitor_ident<#params> #where_clause {
+            #( #methods )*
+        }
+
+        impl #impl_generics #optional_ident #ty_generics #where_clause {
+            /// Calls the method of `visitor` for each field, in declaration order,
+            /// with the field's value if it is set.
+            pub fn accept(&self, visitor: &mut impl #visitor_ident #ty_generics) {
+                #( #visits )*
+            }
+        }
+    }
+}
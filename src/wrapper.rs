@@ -0,0 +1,129 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Error, GenericParam, Result};
+
+use crate::ast::{Container, Field};
+
+/// Generates `{Optional}Wrapped<W>`, the patch with every field held in an
+/// `optionalize::Wrapper` of the caller's choice rather than an `Option`,
+/// along with the conversions from and to the patch. Only emitted with
+/// `#[optionalize(wrapper)]`.
+pub fn expand(cx: &Container) -> Result<TokenStream> {
+    if !cx.attrs.wrapper {
+        return Ok(quote! {});
+    }
+    let runtime = match &cx.runtime {
+        Some(runtime) => runtime,
+        None => {
+            return Err(Error::new_spanned(
+                &cx.ident,
+                "`wrapper` requires depending on the `optionalize` crate",
+            ));
+        }
+    };
+
+    let ident = &cx.ident;
+    let optional_ident = &cx.optional_ident;
+    let wrapped_ident = format_ident!("{}Wrapped", optional_ident);
+    let (_, ty_generics, where_clause) = cx.generics.split_for_impl();
+
+    let mut wrapped_generics = cx.generics.clone();
+    wrapped_generics
+        .params
+        .push(parse_quote!(__W: #runtime::Wrapper));
+    let (wrapped_impl_generics, wrapped_ty_generics, _) = wrapped_generics.split_for_impl();
+    // The declaration defaults the wrapper to `Option`, which impls can't
+    let mut declared_generics = wrapped_generics.clone();
+    if let Some(GenericParam::Type(param)) = declared_generics.params.last_mut() {
+        param.eq_token = Some(Default::default());
+        param.default = Some(parse_quote!(#runtime::OptionWrapper));
+    }
+    let declared = &declared_generics.params;
+
+    let fields = cx.fields.iter().map(|field| {
+        let vis = &field.vis;
+        let field_ident = &field.ident;
+        let ty = if is_wrapped(field) {
+            let inner = field.inner_ty();
+            quote! { <__W as #runtime::Wrapper>::Of<#inner> }
+        } else {
+            field.optional_ty()
+        };
+        quote! { #vis #field_ident: #ty }
+    });
+    let markers = cx.markers.iter().map(|marker| {
+        let vis = &marker.vis;
+        let ident = &marker.ident;
+        let ty = &marker.ty;
+        quote! { #vis #ident: #ty }
+    });
+    let marker_idents: Vec<_> = cx.markers.iter().map(|marker| &marker.ident).collect();
+
+    let wrap = cx.fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        if is_wrapped(field) {
+            quote! { #field_ident: <__W as #runtime::Wrapper>::wrap(patch.#field_ident) }
+        } else {
+            quote! { #field_ident: patch.#field_ident }
+        }
+    });
+    let unwrap = cx.fields.iter().map(|field| {
+        let field_ident = &field.ident;
+        if is_wrapped(field) {
+            quote! { #field_ident: <__W as #runtime::Wrapper>::unwrap(wrapped.#field_ident) }
+        } else {
+            quote! { #field_ident: wrapped.#field_ident }
+        }
+    });
+
+    let doc = format!(
+        "A [`{}`] with every field held in an `optionalize::Wrapper`, `Option` by default.",
+        optional_ident
+    );
+    let apply_doc = format!(
+        "Unwraps the patch and writes every set field onto `target`, as [`{}::apply_to`] does.",
+        optional_ident
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        ///
+        /// Flattened and option-like fields keep their own type.
+        pub struct #wrapped_ident <#declared> #where_clause {
+            #( #fields, )*
+            #( #markers, )*
+        }
+
+        impl #wrapped_impl_generics ::std::convert::From<#optional_ident #ty_generics> for #wrapped_ident #wrapped_ty_generics #where_clause {
+            fn from(patch: #optional_ident #ty_generics) -> Self {
+                #wrapped_ident {
+                    #( #wrap, )*
+                    #( #marker_idents: patch.#marker_idents, )*
+                }
+            }
+        }
+
+        impl #wrapped_impl_generics ::std::convert::From<#wrapped_ident #wrapped_ty_generics> for #optional_ident #ty_generics #where_clause {
+            fn from(wrapped: #wrapped_ident #wrapped_ty_generics) -> Self {
+                #optional_ident {
+                    #( #unwrap, )*
+                    #( #marker_idents: wrapped.#marker_idents, )*
+                }
+            }
+        }
+
+        impl #wrapped_impl_generics #wrapped_ident #wrapped_ty_generics #where_clause {
+            #[doc = #apply_doc]
+            #[inline]
+            pub fn apply_to(self, target: &mut #ident #ty_generics) {
+                <#optional_ident #ty_generics>::from(self).apply_to(target)
+            }
+        }
+    })
+}
+
+/// Whether the field is held in the wrapper: everything but flattened twins
+/// and option-like fields, which track their own unset state.
+fn is_wrapped(field: &Field) -> bool {
+    !field.attrs.flatten && field.option_like.is_none()
+}
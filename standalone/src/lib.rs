@@ -0,0 +1,3 @@
+//! A crate using `optionalize_macro` without the `optionalize` runtime crate,
+//! built in the same workspace as crates depending on it, so generated code
+//! that assumes the runtime crate fails to build here.
@@ -0,0 +1,33 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct deriving without the runtime crate
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Theme {
+    pub dark: bool,
+    pub accent: String,
+}
+
+#[test]
+fn test_without_runtime() {
+    let mut theme = Theme { dark: false, accent: "blue".to_string() };
+    let patch = ThemeOptional { dark: Some(true), accent: None };
+    patch.apply_to(&mut theme);
+    assert_eq!(theme, Theme { dark: true, accent: "blue".to_string() });
+}
+
+/// Test struct picking no groups of impls
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq))]
+pub struct Banner {
+    pub text: String,
+    pub visible: bool,
+}
+
+#[test]
+fn test_without_impls() {
+    let banner = Banner { text: "Sale".to_string(), visible: true };
+    let patch = BannerOptional::from(banner);
+    assert_eq!(patch.set_fields(), vec!["text", "visible"]);
+    assert_ne!(patch, BannerOptional::empty());
+}
@@ -0,0 +1,46 @@
+use actix_web::http::header::{ContentType, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use actix_web::FromRequest;
+use optionalize_macro::Optionalize;
+use serde::Deserialize;
+
+/// Test struct whose patches are taken straight from a request body
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, Deserialize), actix_web, impls(serde))]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+async fn extract(content_type: &str, body: &'static str) -> Result<AccountOptional, StatusCode> {
+    let (req, mut payload) = TestRequest::default()
+        .insert_header((CONTENT_TYPE, content_type))
+        .set_payload(body)
+        .to_http_parts();
+    AccountOptional::from_request(&req, &mut payload)
+        .await
+        .map_err(|err| err.as_response_error().status_code())
+}
+
+#[actix_web::test]
+async fn test_actix_extractor() {
+    let json = ContentType::json().to_string();
+    assert_eq!(
+        extract(&json, r#"{"name": "Ada"}"#).await,
+        Ok(AccountOptional {
+            name: Some("Ada".to_string()),
+            age: None,
+        })
+    );
+    assert_eq!(
+        extract("application/merge-patch+json", r#"{"age": 37}"#).await,
+        Ok(AccountOptional { name: None, age: Some(37) })
+    );
+
+    // Each kind of bad request gets its own status
+    assert_eq!(extract("text/plain", r#"{"age": 37}"#).await, Err(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    assert_eq!(extract(&json, "{").await, Err(StatusCode::BAD_REQUEST));
+    assert_eq!(extract(&json, "{}").await, Err(StatusCode::BAD_REQUEST));
+    assert_eq!(extract(&json, r#"{"age": "old"}"#).await, Err(StatusCode::UNPROCESSABLE_ENTITY));
+}
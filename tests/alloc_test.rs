@@ -0,0 +1,85 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use optionalize_macro::Optionalize;
+
+/// Counts the allocations made on the current thread, so other tests running
+/// alongside don't interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Test structs updated on the hot path of request handling
+#[derive(Optionalize)]
+#[optionalize(derive(Clone), impls(apply, merge))]
+pub struct Limits {
+    pub requests: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(derive(Clone), impls(apply, merge))]
+pub struct Account {
+    #[optionalize(immutable)]
+    pub id: u64,
+    pub name: String,
+    pub tags: Vec<String>,
+    #[optionalize(range(max = 150))]
+    pub age: u8,
+    #[optionalize(nested)]
+    pub limits: Limits,
+}
+
+#[test]
+fn test_apply_does_not_allocate() {
+    let mut account = Account {
+        id: 1,
+        name: "Ada".to_string(),
+        tags: Vec::new(),
+        age: 36,
+        limits: Limits { requests: 10 },
+    };
+    let patch = AccountOptional {
+        id: None,
+        name: Some("Grace".to_string()),
+        tags: Some(vec!["admin".to_string()]),
+        age: Some(37),
+        limits: Some(LimitsOptional { requests: Some(20) }),
+    };
+    let other = AccountOptional {
+        id: None,
+        name: None,
+        tags: None,
+        age: Some(38),
+        limits: None,
+    };
+
+    // Values are moved into place rather than copied
+    let (first, second) = (patch.clone(), other.clone());
+    assert_eq!(allocations(|| first.merge(second).apply_to(&mut account)), 0);
+    assert_eq!(allocations(|| patch.try_apply(&mut account).unwrap()), 0);
+    assert_eq!(allocations(|| assert!(!other.is_empty())), 0);
+    assert_eq!(account.name, "Grace");
+    assert_eq!(account.limits.requests, 20);
+}
@@ -0,0 +1,1093 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct with an immutable identifier
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply, merge, diff))]
+struct Account {
+    #[optionalize(immutable)]
+    pub id: i32,
+    pub name: String,
+    pub email: Option<String>,
+}
+
+#[test]
+fn test_apply_immutable() {
+    let mut account = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: None,
+    };
+
+    // Setting only mutable fields applies cleanly
+    let patch = AccountOptional {
+        id: None,
+        name: Some("grace".to_string()),
+        email: Some("grace@example.com".to_string()),
+    };
+    assert_eq!(patch.try_apply(&mut account), Ok(()));
+    assert_eq!(
+        account,
+        Account {
+            id: 1,
+            name: "grace".to_string(),
+            email: Some("grace@example.com".to_string()),
+        }
+    );
+
+    // Setting an immutable field is rejected and leaves the target untouched
+    let patch = AccountOptional {
+        id: Some(2),
+        name: Some("mallory".to_string()),
+        email: None,
+    };
+    assert_eq!(
+        patch.try_apply(&mut account),
+        Err(AccountOptionalError::Immutable { fields: vec!["id"] })
+    );
+    assert_eq!(account.name, "grace");
+
+    // `apply_to` skips immutable fields instead of failing
+    let patch = AccountOptional {
+        id: Some(2),
+        name: Some("mallory".to_string()),
+        email: None,
+    };
+    patch.apply_to(&mut account);
+    assert_eq!(account.id, 1);
+    assert_eq!(account.name, "mallory");
+}
+
+#[test]
+fn test_infallible_apply_ignores_immutable() {
+    let mut account = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: None,
+    };
+    let patch = |name: &str| AccountOptional {
+        id: Some(2),
+        name: Some(name.to_string()),
+        email: None,
+    };
+
+    // Every way of applying that can't fail ignores the immutable field
+    patch("grace").apply_to(&mut account);
+    assert_eq!((account.id, account.name.as_str()), (1, "grace"));
+    patch("hedy").apply_masked(&mut account, &["id", "name"]);
+    assert_eq!((account.id, account.name.as_str()), (1, "hedy"));
+    account.apply_all([patch("joan"), patch("katherine")]);
+    assert_eq!((account.id, account.name.as_str()), (1, "katherine"));
+}
+
+fn trim(value: String) -> String {
+    value.trim().to_string()
+}
+
+fn lowercase(value: String) -> String {
+    value.to_lowercase()
+}
+
+/// Test struct normalizing its fields on apply
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+struct Contact {
+    #[optionalize(sanitize_with = "trim")]
+    pub name: String,
+    #[optionalize(sanitize_with = "lowercase")]
+    pub email: String,
+    #[optionalize(sanitize_with = "trim")]
+    pub note: Option<String>,
+}
+
+#[test]
+fn test_apply_sanitize_with() {
+    let mut contact = Contact {
+        name: "Ada".to_string(),
+        email: "ada@example.com".to_string(),
+        note: None,
+    };
+
+    let patch = ContactOptional {
+        name: Some("  Grace  ".to_string()),
+        email: Some("Grace@Example.COM".to_string()),
+        note: Some(" likes COBOL ".to_string()),
+    };
+    patch.apply_to(&mut contact);
+
+    // Set fields are run through their sanitizers before being written
+    assert_eq!(
+        contact,
+        Contact {
+            name: "Grace".to_string(),
+            email: "grace@example.com".to_string(),
+            note: Some("likes COBOL".to_string()),
+        }
+    );
+}
+
+/// Test struct guarded by a revision counter
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(version = "revision")]
+struct Document {
+    pub revision: u64,
+    pub title: String,
+}
+
+#[test]
+fn test_apply_if_version() {
+    let mut document = Document {
+        revision: 3,
+        title: "Draft".to_string(),
+    };
+
+    // A patch based on a stale revision is rejected
+    let patch = DocumentOptional {
+        revision: Some(4),
+        title: Some("Stale".to_string()),
+    };
+    assert_eq!(
+        patch.apply_if_version(&mut document, &2),
+        Err(DocumentOptionalError::VersionConflict { field: "revision" })
+    );
+    assert_eq!(document.title, "Draft");
+
+    // A patch based on the current revision goes through
+    let patch = DocumentOptional {
+        revision: Some(4),
+        title: Some("Final".to_string()),
+    };
+    assert_eq!(patch.apply_if_version(&mut document, &3), Ok(()));
+    assert_eq!(
+        document,
+        Document {
+            revision: 4,
+            title: "Final".to_string(),
+        }
+    );
+}
+
+/// Test struct guarded by an etag computed by a hook
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(version = "etag", next_version = "next_etag")]
+struct Page {
+    pub etag: String,
+    pub body: String,
+}
+
+fn next_etag(etag: &str) -> String {
+    let n: u32 = etag.trim_start_matches('v').parse().unwrap();
+    format!("v{}", n + 1)
+}
+
+#[test]
+fn test_apply_if_version_conflict() {
+    let mut document = Document {
+        revision: 3,
+        title: "Draft".to_string(),
+    };
+
+    // Two writers read revision 3; only the first one wins
+    let first = DocumentOptional {
+        revision: None,
+        title: Some("First".to_string()),
+    };
+    let second = DocumentOptional {
+        revision: None,
+        title: Some("Second".to_string()),
+    };
+    assert_eq!(first.apply_if_version(&mut document, &3), Ok(()));
+    assert_eq!(
+        second.apply_if_version(&mut document, &3),
+        Err(DocumentOptionalError::VersionConflict { field: "revision" })
+    );
+    assert_eq!(
+        document,
+        Document {
+            revision: 4,
+            title: "First".to_string(),
+        }
+    );
+
+    let mut page = Page {
+        etag: "v1".to_string(),
+        body: "Hello".to_string(),
+    };
+    let patch = PageOptional {
+        etag: None,
+        body: Some("Hello, world".to_string()),
+    };
+    assert_eq!(patch.apply_if_version(&mut page, &"v1".to_string()), Ok(()));
+    assert_eq!(page.etag, "v2");
+}
+
+/// Test struct holding shared and boxed data
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(pointer = "unwrap", impls(apply))]
+struct Asset {
+    pub payload: std::sync::Arc<String>,
+    #[optionalize(pointer = "wrap")]
+    pub thumbnail: Box<[u8; 2]>,
+    // Unsized contents stay behind their pointer
+    pub caption: Option<std::rc::Rc<str>>,
+}
+
+#[test]
+fn test_pointer_policy() {
+    let mut asset = Asset {
+        payload: std::sync::Arc::new("old".to_string()),
+        thumbnail: Box::new([1, 1]),
+        caption: None,
+    };
+
+    // Unwrapped fields hold the plain value, wrapped ones keep the pointer
+    let patch = AssetOptional {
+        payload: Some("new".to_string()),
+        thumbnail: Some(Box::new([2, 2])),
+        caption: Some("a cat".into()),
+    };
+    patch.apply_to(&mut asset);
+
+    assert_eq!(*asset.payload, "new");
+    assert_eq!(*asset.thumbnail, [2, 2]);
+    assert_eq!(asset.caption.as_deref(), Some("a cat"));
+}
+
+/// Converts between seconds and a `"<n>s"` duration string
+mod seconds {
+    pub fn to_optional(value: u64) -> String {
+        format!("{}s", value)
+    }
+
+    pub fn from_optional(value: String) -> u64 {
+        value.trim_end_matches('s').parse().unwrap_or_default()
+    }
+}
+
+/// Test struct with a field patched through a different representation
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(pointer = "unwrap", impls(apply))]
+struct Timer {
+    pub retries: std::rc::Rc<u32>,
+    #[optionalize(with = "seconds", ty = "String")]
+    pub timeout: u64,
+    #[optionalize(with = "seconds", ty = "String")]
+    pub delay: Option<u64>,
+}
+
+#[test]
+fn test_with_conversion() {
+    let mut timer = Timer {
+        retries: std::rc::Rc::new(1),
+        timeout: 30,
+        delay: None,
+    };
+
+    // `From` converts each field to its patch representation
+    let patch = TimerOptional::from(Timer {
+        retries: std::rc::Rc::new(3),
+        timeout: 90,
+        delay: Some(5),
+    });
+    assert_eq!(patch.retries, Some(3));
+    assert_eq!(patch.timeout, Some("90s".to_string()));
+    assert_eq!(patch.delay, Some("5s".to_string()));
+
+    // `apply_to` converts them back
+    patch.apply_to(&mut timer);
+    assert_eq!(
+        timer,
+        Timer {
+            retries: std::rc::Rc::new(3),
+            timeout: 90,
+            delay: Some(5),
+        }
+    );
+}
+
+#[test]
+fn test_from_ref() {
+    let timer = Timer {
+        retries: std::rc::Rc::new(2),
+        timeout: 45,
+        delay: None,
+    };
+
+    // The snapshot clones each field, leaving the value usable
+    let snapshot = TimerOptional::from(&timer);
+    assert_eq!(snapshot.retries, Some(2));
+    assert_eq!(snapshot.timeout, Some("45s".to_string()));
+    assert_eq!(snapshot.delay, None);
+    assert_eq!(snapshot.set_fields(), vec!["retries", "timeout"]);
+
+    let mut restored = Timer {
+        retries: std::rc::Rc::new(9),
+        timeout: 1,
+        delay: Some(1),
+    };
+    snapshot.apply_to(&mut restored);
+    assert_eq!(restored.retries, timer.retries);
+    assert_eq!(restored.timeout, 45);
+}
+
+#[test]
+fn test_apply_all() {
+    let mut account = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: None,
+    };
+
+    // Later patches win over earlier ones
+    let queued = vec![
+        AccountOptional {
+            id: None,
+            name: Some("grace".to_string()),
+            email: Some("grace@example.com".to_string()),
+        },
+        AccountOptional {
+            id: None,
+            name: Some("hopper".to_string()),
+            email: None,
+        },
+    ];
+    account.apply_all(queued);
+    assert_eq!(
+        account,
+        Account {
+            id: 1,
+            name: "hopper".to_string(),
+            email: Some("grace@example.com".to_string()),
+        }
+    );
+}
+
+/// Test struct treated as an immutable value
+#[derive(Optionalize)]
+#[derive(Clone, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Money {
+    pub amount: i64,
+    pub currency: String,
+}
+
+#[test]
+fn test_with_patch() {
+    let price = Money {
+        amount: 100,
+        currency: "EUR".to_string(),
+    };
+    let discounted = price.with_patch(MoneyOptional { amount: Some(80), currency: None });
+
+    assert_eq!(discounted, Money { amount: 80, currency: "EUR".to_string() });
+    assert_eq!(price.amount, 100);
+}
+
+#[test]
+fn test_apply_with_inverse() {
+    let mut account = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: None,
+    };
+
+    // The inverse sets the changed fields back, and skips immutable ones
+    let patch = AccountOptional {
+        id: Some(2),
+        name: Some("grace".to_string()),
+        email: None,
+    };
+    let inverse = patch.apply_with_inverse(&mut account);
+    assert_eq!(inverse.set_fields(), vec!["name"]);
+    assert_eq!(inverse.name, Some("ada".to_string()));
+
+    inverse.apply_to(&mut account);
+    assert_eq!(account.name, "ada");
+}
+
+#[test]
+fn test_merge_diff_invert() {
+    let base = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: Some("ada@example.com".to_string()),
+    };
+    let target = Account {
+        id: 1,
+        name: "grace".to_string(),
+        email: Some("grace@example.com".to_string()),
+    };
+
+    // `diff` sets exactly the fields that differ
+    let patch = AccountOptional::diff(&base, &target);
+    assert_eq!(patch.set_fields(), vec!["name", "email"]);
+
+    // `merge` lets the later patch win
+    let first = AccountOptional {
+        id: None,
+        name: Some("first".to_string()),
+        email: Some("first@example.com".to_string()),
+    };
+    let merged = first.merge(AccountOptional {
+        id: None,
+        name: Some("second".to_string()),
+        email: None,
+    });
+    assert_eq!(merged.name, Some("second".to_string()));
+    assert_eq!(merged.email, Some("first@example.com".to_string()));
+
+    // `invert` undoes a patch applied onto `base`
+    let inverse = patch.invert(&base);
+    let mut account = base;
+    patch.apply_to(&mut account);
+    assert_eq!(account, target);
+    inverse.apply_to(&mut account);
+    assert_eq!(account.name, "ada");
+    assert_eq!(account.email, Some("ada@example.com".to_string()));
+}
+
+#[test]
+fn test_changed_fields() {
+    let account = Account {
+        id: 1,
+        name: "ada".to_string(),
+        email: Some("ada@example.com".to_string()),
+    };
+
+    // Setting a field to its current value is a no-op, as is an immutable field
+    let patch = AccountOptional {
+        id: Some(2),
+        name: Some("ada".to_string()),
+        email: Some("countess@example.com".to_string()),
+    };
+    assert_eq!(patch.set_fields(), vec!["id", "name", "email"]);
+    assert_eq!(patch.changed_fields(&account), vec!["email"]);
+    assert!(AccountOptional::empty().changed_fields(&account).is_empty());
+}
+
+/// Test struct for layered configuration, where earlier layers win
+#[derive(Optionalize)]
+#[optionalize(merge = "prefer_self", impls(merge))]
+#[allow(dead_code)]
+struct Layer {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Test struct whose patches must never overwrite each other
+#[derive(Optionalize)]
+#[optionalize(merge = "error_on_conflict", impls(merge))]
+#[allow(dead_code)]
+struct Ledger {
+    pub owner: String,
+    pub balance: i64,
+    pub note: Option<String>,
+}
+
+#[test]
+fn test_merge_policy() {
+    let merged = LayerOptional {
+        host: Some("override.local".to_string()),
+        port: None,
+    }
+    .merge(LayerOptional {
+        host: Some("default.local".to_string()),
+        port: Some(80),
+    });
+    assert_eq!(merged.host.as_deref(), Some("override.local"));
+    assert_eq!(merged.port, Some(80));
+
+    // Disjoint patches merge, overlapping ones are rejected
+    let owner = LedgerOptional {
+        owner: Some("ada".to_string()),
+        balance: None,
+        note: None,
+    };
+    let balance = LedgerOptional {
+        owner: None,
+        balance: Some(10),
+        note: Some("opening".to_string()),
+    };
+    let merged = owner.merge(balance).unwrap();
+    assert_eq!(merged.set_fields(), vec!["owner", "balance", "note"]);
+
+    let conflicting = LedgerOptional {
+        owner: None,
+        balance: Some(-5),
+        note: Some("refund".to_string()),
+    };
+    assert_eq!(
+        merged.merge(conflicting).map(|merged| merged.set_fields()),
+        Err(LedgerOptionalError::MergeConflict {
+            fields: vec!["balance", "note"]
+        })
+    );
+}
+
+/// Test struct layering `prefer_self` patches over one another
+#[derive(Optionalize)]
+#[optionalize(merge = "prefer_self", impls(merge))]
+#[allow(dead_code)]
+struct Stack {
+    pub name: String,
+    #[optionalize(nested)]
+    pub primary: Layer,
+    #[optionalize(flatten)]
+    pub fallback: Layer,
+}
+
+#[test]
+fn test_nested_prefer_self() {
+    let layer = |host: &str| LayerOptional {
+        host: Some(host.to_string()),
+        port: None,
+    };
+    let merged = StackOptional {
+        name: Some("first".to_string()),
+        primary: Some(layer("first.local")),
+        fallback: layer("first.fallback"),
+    }
+    .merge(StackOptional {
+        name: Some("second".to_string()),
+        primary: Some(layer("second.local")),
+        fallback: layer("second.fallback"),
+    });
+
+    // The first patch wins at every level
+    assert_eq!(merged.name.as_deref(), Some("first"));
+    assert_eq!(merged.primary.unwrap().host.as_deref(), Some("first.local"));
+    assert_eq!(merged.fallback.host.as_deref(), Some("first.fallback"));
+}
+
+/// Test structs edited concurrently by two users
+#[derive(Optionalize)]
+#[optionalize(impls(merge))]
+#[allow(dead_code)]
+pub struct Venue {
+    pub city: String,
+    pub capacity: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(merge = "error_on_conflict", impls(merge))]
+#[allow(dead_code)]
+struct Meetup {
+    pub title: String,
+    pub note: Option<String>,
+    #[optionalize(nested)]
+    pub venue: Venue,
+}
+
+#[test]
+fn test_merge_resolver() {
+    let ours = MeetupOptional {
+        title: Some("Rust night".to_string()),
+        note: Some("bring snacks".to_string()),
+        venue: Some(VenueOptional {
+            city: Some("Paris".to_string()),
+            capacity: None,
+        }),
+    };
+    let theirs = MeetupOptional {
+        title: Some("Rust evening".to_string()),
+        note: None,
+        venue: Some(VenueOptional {
+            city: Some("Lyon".to_string()),
+            capacity: Some(40),
+        }),
+    };
+
+    // Only fields set on both sides reach the resolver
+    let mut asked = Vec::new();
+    let merged = ours.merge_with(theirs, |field, pair| {
+        asked.push(field);
+        match pair {
+            MeetupOptionalFieldPair::Title { ours, theirs } => {
+                assert_eq!((ours.as_str(), theirs.as_str()), ("Rust night", "Rust evening"));
+                MeetupOptionalChoice::Theirs
+            }
+            _ => MeetupOptionalChoice::Ours,
+        }
+    });
+    assert_eq!(asked, ["title", "venue"]);
+    assert_eq!(merged.title.as_deref(), Some("Rust evening"));
+    assert_eq!(merged.note.as_deref(), Some("bring snacks"));
+
+    // The losing nested patch still fills in the fields the winner leaves unset
+    let venue = merged.venue.unwrap();
+    assert_eq!(venue.city.as_deref(), Some("Paris"));
+    assert_eq!(venue.capacity, Some(40));
+}
+
+/// Test struct keeping derived fields up to date on every patch
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(before_apply = "trim_title", after_apply = "touch", impls(apply))]
+struct Article {
+    pub title: String,
+    pub slug: String,
+    pub revision: u32,
+}
+
+fn trim_title(patch: &mut ArticleOptional, _article: &Article) {
+    patch.title = patch.title.take().map(|title| title.trim().to_string());
+}
+
+fn touch(article: &mut Article) {
+    article.slug = article.title.to_lowercase().replace(' ', "-");
+    article.revision += 1;
+}
+
+#[test]
+fn test_apply_hooks() {
+    let mut article = Article {
+        title: "Draft".to_string(),
+        slug: "draft".to_string(),
+        revision: 1,
+    };
+
+    let patch = ArticleOptional {
+        title: Some("  Hello World ".to_string()),
+        slug: None,
+        revision: None,
+    };
+    let inverse = patch.apply_with_inverse(&mut article);
+    assert_eq!(
+        article,
+        Article {
+            title: "Hello World".to_string(),
+            slug: "hello-world".to_string(),
+            revision: 2,
+        }
+    );
+
+    // The hooks run again when the inverse is applied
+    inverse.apply_to(&mut article);
+    assert_eq!(article.slug, "draft");
+    assert_eq!(article.revision, 3);
+}
+
+/// Test struct whose `before_apply` hook fills in the patch
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(before_apply = "to_percent", impls(apply))]
+struct Gauge {
+    #[optionalize(range(max = 100))]
+    pub level: u8,
+}
+
+fn to_percent(patch: &mut GaugeOptional, _gauge: &Gauge) {
+    patch.level = patch.level.map(|level| level.saturating_mul(10));
+}
+
+#[test]
+fn test_try_apply_checks_hooked_patch() {
+    let mut gauge = Gauge { level: 0 };
+
+    // The hook runs once, before the checks
+    GaugeOptional { level: Some(5) }.try_apply(&mut gauge).unwrap();
+    assert_eq!(gauge.level, 50);
+
+    // What the hook writes is validated too
+    let result = GaugeOptional { level: Some(20) }.try_apply(&mut gauge);
+    assert!(matches!(result, Err(GaugeOptionalError::InvalidField { field: "level", .. })));
+    assert_eq!(gauge.level, 50);
+}
+
+/// Container of a pod, matched by name when merging
+#[derive(Clone, Debug, PartialEq)]
+pub struct Container {
+    pub name: String,
+    pub image: String,
+}
+
+/// Test struct whose containers are merged by name
+#[derive(Optionalize, Clone, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, PartialEq), impls(apply, merge, diff))]
+pub struct Pod {
+    pub replicas: u32,
+    #[optionalize(merge_key = "name")]
+    pub containers: Vec<Container>,
+}
+
+fn container(name: &str, image: &str) -> Container {
+    Container {
+        name: name.to_string(),
+        image: image.to_string(),
+    }
+}
+
+#[test]
+fn test_merge_key() {
+    let mut pod = Pod {
+        replicas: 1,
+        containers: vec![container("app", "app:1"), container("proxy", "envoy:1")],
+    };
+    let original = pod.clone();
+
+    let patch = PodOptional {
+        replicas: None,
+        containers: Some(vec![container("app", "app:2"), container("metrics", "exporter:1")]),
+    };
+    assert_eq!(patch.changed_fields(&pod), vec!["containers"]);
+    let inverse = patch.clone().apply_with_inverse(&mut pod);
+    assert_eq!(
+        pod.containers,
+        vec![container("app", "app:2"), container("proxy", "envoy:1"), container("metrics", "exporter:1")]
+    );
+
+    // Only the replaced element is restored
+    inverse.apply_to(&mut pod);
+    assert_eq!(pod.containers[..2], original.containers[..]);
+    assert_eq!(pod.containers.len(), 3);
+
+    // Merging patches matches elements by key as well
+    let merged = patch.merge(PodOptional {
+        replicas: Some(2),
+        containers: Some(vec![container("metrics", "exporter:2")]),
+    });
+    assert_eq!(
+        merged.containers,
+        Some(vec![container("app", "app:2"), container("metrics", "exporter:2")])
+    );
+}
+
+/// Test struct combining list fields with each strategy
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, PartialEq), impls(apply, merge, diff), merge_crate)]
+pub struct Playlist {
+    pub tracks: Vec<u32>,
+    #[optionalize(vec_merge = "append")]
+    pub appended: Vec<u32>,
+    #[optionalize(vec_merge = "prepend")]
+    pub prepended: Vec<u32>,
+    #[optionalize(vec_merge = "union")]
+    pub tags: Vec<&'static str>,
+}
+
+#[test]
+fn test_vec_merge() {
+    let mut playlist = Playlist {
+        tracks: vec![1, 2],
+        appended: vec![1, 2],
+        prepended: vec![1, 2],
+        tags: vec!["rock", "live"],
+    };
+
+    let first = PlaylistOptional {
+        tracks: Some(vec![3]),
+        appended: Some(vec![3]),
+        prepended: Some(vec![3]),
+        tags: Some(vec!["live", "indie"]),
+    };
+    let second = PlaylistOptional {
+        tracks: Some(vec![4]),
+        appended: Some(vec![4]),
+        prepended: Some(vec![4]),
+        tags: Some(vec!["indie", "demo"]),
+    };
+
+    // Merging first is the same as applying both in order
+    let merged = first.clone().merge(second.clone());
+    assert_eq!(merged.prepended, Some(vec![4, 3]));
+    assert_eq!(merged.tags, Some(vec!["live", "indie", "demo"]));
+    first.apply_to(&mut playlist);
+    second.apply_to(&mut playlist);
+    assert_eq!(
+        playlist,
+        Playlist {
+            tracks: vec![4],
+            appended: vec![1, 2, 3, 4],
+            prepended: vec![4, 3, 1, 2],
+            tags: vec!["rock", "live", "indie", "demo"],
+        }
+    );
+
+    // Tags already present change nothing
+    let known = PlaylistOptional {
+        tracks: None,
+        appended: None,
+        prepended: None,
+        tags: Some(vec!["rock"]),
+    };
+    assert!(known.changed_fields(&playlist).is_empty());
+}
+
+#[test]
+fn test_merge_trait() {
+    let first = PlaylistOptional {
+        tracks: Some(vec![3]),
+        appended: Some(vec![3]),
+        prepended: None,
+        tags: Some(vec!["live"]),
+    };
+    let second = PlaylistOptional {
+        tracks: Some(vec![4]),
+        appended: Some(vec![4]),
+        prepended: Some(vec![4]),
+        tags: Some(vec!["live", "demo"]),
+    };
+
+    // The trait merges in place with the same strategies as `merge`
+    let mut layered = first.clone();
+    merge::Merge::merge(&mut layered, second.clone());
+    assert_eq!(layered, first.merge(second));
+    assert_eq!(layered.appended, Some(vec![3, 4]));
+    assert_eq!(layered.tags, Some(vec!["live", "demo"]));
+}
+
+/// Test struct whose fields combine values with custom functions
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, PartialEq), impls(apply, merge))]
+pub struct Changelog {
+    #[optionalize(merge_with = "append_line")]
+    pub notes: String,
+    #[optionalize(merge_with = "union")]
+    pub authors: std::collections::BTreeSet<String>,
+    pub version: u32,
+}
+
+fn append_line(notes: &mut String, line: String) {
+    if !notes.is_empty() {
+        notes.push('\n');
+    }
+    notes.push_str(&line);
+}
+
+fn union(authors: &mut std::collections::BTreeSet<String>, incoming: std::collections::BTreeSet<String>) {
+    authors.extend(incoming);
+}
+
+#[test]
+fn test_merge_with() {
+    let mut changelog = Changelog {
+        notes: "Initial release".to_string(),
+        authors: ["ada".to_string()].into(),
+        version: 1,
+    };
+
+    let first = ChangelogOptional {
+        notes: Some("Fix parser".to_string()),
+        authors: Some(["grace".to_string()].into()),
+        version: Some(2),
+    };
+    let second = ChangelogOptional {
+        notes: Some("Add docs".to_string()),
+        authors: Some(["ada".to_string()].into()),
+        version: None,
+    };
+
+    let merged = first.merge(second);
+    assert_eq!(merged.notes.as_deref(), Some("Fix parser\nAdd docs"));
+    merged.apply_to(&mut changelog);
+    assert_eq!(
+        changelog,
+        Changelog {
+            notes: "Initial release\nFix parser\nAdd docs".to_string(),
+            authors: ["ada".to_string(), "grace".to_string()].into(),
+            version: 2,
+        }
+    );
+}
+
+/// Test struct whose patches are shared between threads
+#[derive(Optionalize, Clone, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), shared)]
+pub struct Report {
+    pub title: String,
+    pub body: Vec<u8>,
+    pub revision: u32,
+}
+
+#[test]
+fn test_shared_patch() {
+    let shared = ReportOptional {
+        title: Some("Report".to_string()),
+        body: Some(vec![0; 1024]),
+        revision: None,
+    }
+    .into_shared();
+
+    // Clones point to the same payload
+    let clone = shared.clone();
+    assert!(std::sync::Arc::ptr_eq(shared.body.as_ref().unwrap(), clone.body.as_ref().unwrap()));
+    assert_eq!(shared.title.as_deref(), Some("Report"));
+
+    let workers: Vec<_> = (0..4)
+        .map(|revision| {
+            let patch = shared.clone();
+            std::thread::spawn(move || {
+                let mut document = Report {
+                    title: String::new(),
+                    body: Vec::new(),
+                    revision,
+                };
+                patch.apply_to(&mut document);
+                document
+            })
+        })
+        .collect();
+    for (revision, worker) in workers.into_iter().enumerate() {
+        let document = worker.join().unwrap();
+        assert_eq!(document.title, "Report");
+        assert_eq!(document.body.len(), 1024);
+        assert_eq!(document.revision, revision as u32);
+    }
+
+    assert_eq!(shared.to_patch().revision, None);
+}
+
+/// Test struct updated in batches
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Sku {
+    pub code: &'static str,
+    pub stock: u32,
+}
+
+#[test]
+fn test_apply_to_all() {
+    let mut skus = vec![
+        Sku { code: "A1", stock: 5 },
+        Sku { code: "B2", stock: 0 },
+        Sku { code: "C3", stock: 9 },
+    ];
+    let patches = std::collections::HashMap::from([
+        ("B2", SkuOptional { code: None, stock: Some(12) }),
+        ("Z9", SkuOptional { code: None, stock: Some(1) }),
+    ]);
+
+    let unmatched = SkuOptional::apply_to_all(&mut skus, |sku| sku.code, patches);
+    assert_eq!(skus[1], Sku { code: "B2", stock: 12 });
+    assert_eq!((skus[0].stock, skus[2].stock), (5, 9));
+    assert_eq!(unmatched.keys().collect::<Vec<_>>(), vec![&"Z9"]);
+}
+
+/// Test struct whose changes are shown on a terminal
+#[derive(Optionalize, Clone, Debug, PartialEq)]
+#[optionalize(derive(Clone), ansi, impls(apply))]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub license: Option<String>,
+    #[optionalize(sensitive)]
+    pub token: String,
+}
+
+#[test]
+fn test_render_diff() {
+    let base = Package {
+        name: "optionalize".to_string(),
+        version: "0.1.0".to_string(),
+        license: None,
+        token: "abc".to_string(),
+    };
+    let patch = PackageOptional {
+        name: Some("optionalize".to_string()),
+        version: Some("0.2.0".to_string()),
+        license: Some("MIT".to_string()),
+        token: Some("def".to_string()),
+    };
+
+    // Unchanged and sensitive fields are left out
+    let rendered = patch.render_against(&base);
+    assert_eq!(
+        rendered,
+        "\x1b[31m- version: \"0.1.0\"\x1b[0m\n\
+         \x1b[32m+ version: \"0.2.0\"\x1b[0m\n\
+         \x1b[31m- license: None\x1b[0m\n\
+         \x1b[32m+ license: Some(\"MIT\")\x1b[0m\n"
+    );
+    assert_eq!(base.token, "abc");
+    assert_eq!(PackageOptional::render_diff(&base, &base), "");
+}
+
+/// Test struct edited offline on several devices
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), lww)]
+pub struct Note {
+    pub title: String,
+    pub body: String,
+    pub pinned: bool,
+}
+
+#[test]
+fn test_lww_merge() {
+    let phone = NoteOptional {
+        title: Some("Groceries".to_string()),
+        body: Some("milk".to_string()),
+        pinned: None,
+    }
+    .stamped(10);
+    let laptop = NoteOptional {
+        title: Some("Shopping".to_string()),
+        body: Some("milk, eggs".to_string()),
+        pinned: Some(true),
+    }
+    .stamped(5);
+    let tablet = NoteOptional {
+        title: None,
+        body: Some("milk, eggs, bread".to_string()),
+        pinned: None,
+    }
+    .stamped(20);
+
+    // The newest write of every field wins, whatever the merge order
+    let mut merged = phone;
+    merged.merge(tablet);
+    merged.merge(laptop);
+    assert_eq!(merged.title, Some((10, "Groceries".to_string())));
+    assert_eq!(
+        merged.into_patch(),
+        NoteOptional {
+            title: Some("Groceries".to_string()),
+            body: Some("milk, eggs, bread".to_string()),
+            pinned: Some(true),
+        }
+    );
+
+    // Writes stamped alike settle on the same value in either order
+    let titled = |title: &str| NoteOptional {
+        title: Some(title.to_string()),
+        body: None,
+        pinned: None,
+    };
+    let left = || titled("A").stamped(7);
+    let right = || titled("B").stamped(7);
+    let mut forward = left();
+    forward.merge(right());
+    let mut backward = right();
+    backward.merge(left());
+    assert_eq!(forward, backward);
+    assert_eq!(forward.title, Some((7, "B".to_string())));
+}
+
+/// Test struct whose long text is edited in place
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(similar)]
+pub struct Draft {
+    pub title: String,
+    pub body: String,
+}
+
+#[test]
+fn test_text_diff() {
+    let old = "First line.\nSecond line.\nThird line.\n";
+    let new = "First line.\nSecond line, edited.\nThird line.\n";
+    let mut draft = Draft {
+        title: "Notes".to_string(),
+        body: old.to_string(),
+    };
+
+    // Only the changed line travels
+    let edits = DraftOptional::body_diff_lines(old, new);
+    assert_eq!(edits, vec![(12, 13, "Second line, edited.\n".to_string())]);
+    DraftOptional::apply_body_edits(&mut draft, &edits).unwrap();
+    assert_eq!(draft.body, new);
+
+    let edits = DraftOptional::title_diff_words("Notes", "Meeting notes");
+    DraftOptional::apply_title_edits(&mut draft, &edits).unwrap();
+    assert_eq!(draft.title, "Meeting notes");
+
+    // Edits that don't fit the current text are rejected
+    let stale = vec![(40, 5, "x".to_string())];
+    assert!(DraftOptional::apply_title_edits(&mut draft, &stale).is_err());
+    assert_eq!(draft.title, "Meeting notes");
+}
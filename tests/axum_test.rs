@@ -0,0 +1,36 @@
+use axum::body::Body;
+use axum::extract::{FromRequest, Request};
+use axum::http::StatusCode;
+use optionalize_macro::Optionalize;
+use serde::Deserialize;
+
+/// Test struct whose patches are taken straight from a JSON request body
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, Deserialize), axum, impls(serde))]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+fn json_request(body: &'static str) -> Request {
+    Request::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_axum_extractor() {
+    let patch = AccountOptional::from_request(json_request(r#"{"age": 37}"#), &())
+        .await
+        .unwrap();
+    assert_eq!(patch, AccountOptional { name: None, age: Some(37) });
+
+    // Badly typed fields and empty patches are rejected
+    let rejection = AccountOptional::from_request(json_request(r#"{"age": "old"}"#), &())
+        .await
+        .unwrap_err();
+    assert_eq!(rejection.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let rejection = AccountOptional::from_request(json_request("{}"), &()).await.unwrap_err();
+    assert_eq!(rejection.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,59 @@
+use std::convert::TryFrom;
+
+use optionalize_macro::Deoptionalize;
+
+/// Test struct filled in step by step before being published
+#[derive(Deoptionalize, Debug, Clone, PartialEq)]
+#[deoptionalize(derive(Debug, Clone, PartialEq))]
+struct PostDraft {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub r#type: Option<&'static str>,
+    #[deoptionalize(optional)]
+    pub summary: Option<String>,
+    pub author_id: u64,
+}
+
+#[test]
+fn test_deoptionalize() {
+    let mut draft = PostDraft {
+        title: Some("Hello".to_string()),
+        body: None,
+        r#type: None,
+        summary: None,
+        author_id: 7,
+    };
+
+    // Every missing field is reported at once
+    let err = PostDraftRequired::try_from(draft.clone()).unwrap_err();
+    assert_eq!(err.missing, ["body", "type"]);
+    assert_eq!(err.to_string(), "missing required fields: body, type");
+
+    draft.body = Some("World".to_string());
+    draft.r#type = Some("article");
+    let post = PostDraftRequired::try_from(draft.clone()).unwrap();
+    assert_eq!(
+        post,
+        PostDraftRequired {
+            title: "Hello".to_string(),
+            body: "World".to_string(),
+            r#type: "article",
+            summary: None,
+            author_id: 7,
+        }
+    );
+    assert_eq!(PostDraft::from(post), draft);
+}
+
+/// Test struct with nothing to unwrap
+#[derive(Deoptionalize)]
+#[deoptionalize(name = "Finished")]
+struct Counter<T> {
+    pub count: T,
+}
+
+#[test]
+fn test_deoptionalize_without_options() {
+    let finished = Finished::try_from(Counter { count: 3 }).unwrap();
+    assert_eq!(finished.count, 3);
+}
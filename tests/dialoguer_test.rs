@@ -0,0 +1,37 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct completed on the terminal
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq), dialoguer, impls(flat))]
+#[allow(dead_code)]
+pub struct Account {
+    pub name: String,
+    pub nickname: Option<String>,
+    #[optionalize(sensitive)]
+    pub password: String,
+}
+
+#[test]
+fn test_fill_interactive_complete() {
+    // Nothing is unset, so nothing is asked, terminal or not
+    let mut patch = AccountOptional {
+        name: Some("Ada".to_string()),
+        nickname: Some("Countess".to_string()),
+        password: Some("hunter2".to_string()),
+    };
+    let defaults = Account {
+        name: "Grace".to_string(),
+        nickname: Some("amazing".to_string()),
+        password: "secret".to_string(),
+    };
+    patch.fill_interactive(Some(&defaults)).unwrap();
+    patch.fill_interactive(None).unwrap();
+    assert_eq!(
+        patch,
+        AccountOptional {
+            name: Some("Ada".to_string()),
+            nickname: Some("Countess".to_string()),
+            password: Some("hunter2".to_string()),
+        }
+    );
+}
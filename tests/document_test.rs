@@ -0,0 +1,157 @@
+use optionalize_macro::Optionalize;
+use serde::Deserialize;
+
+/// Test struct for configuration layered from default and override files
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Deserialize), impls(serde), toml, yaml)]
+struct ServerConfig {
+    pub host: String,
+    #[optionalize(range(min = 1))]
+    pub port: u16,
+    pub workers: Option<u32>,
+}
+
+fn defaults() -> ServerConfig {
+    ServerConfig {
+        host: "localhost".to_string(),
+        port: 8080,
+        workers: None,
+    }
+}
+
+#[test]
+fn test_toml() {
+    let patch = ServerConfigOptional::from_toml_str("port = 9000").unwrap();
+    assert_eq!(patch.set_fields(), vec!["port"]);
+
+    // Overrides are applied onto the defaults in one call
+    let mut config = defaults();
+    let applied = ServerConfigOptional::merge_toml(&mut config, "host = \"0.0.0.0\"\nworkers = 4").unwrap();
+    assert_eq!(applied, vec!["host", "workers"]);
+    assert_eq!(
+        config,
+        ServerConfig {
+            host: "0.0.0.0".to_string(),
+            port: 8080,
+            workers: Some(4),
+        }
+    );
+
+    // Malformed or invalid overrides leave the value untouched
+    assert!(matches!(
+        ServerConfigOptional::merge_toml(&mut config, "port = "),
+        Err(ServerConfigOptionalError::InvalidDocument { .. })
+    ));
+    assert!(matches!(
+        ServerConfigOptional::merge_toml(&mut config, "port = 0"),
+        Err(ServerConfigOptionalError::InvalidField { field: "port", .. })
+    ));
+    assert_eq!(config.port, 8080);
+}
+
+#[test]
+fn test_yaml() {
+    let patch = ServerConfigOptional::from_yaml_str("workers: 8").unwrap();
+    assert_eq!(patch.set_fields(), vec!["workers"]);
+
+    // An overlay only touches the keys it mentions
+    let mut config = defaults();
+    let applied = ServerConfigOptional::merge_yaml(&mut config, "host: example.com\nport: 443\n").unwrap();
+    assert_eq!(applied, vec!["host", "port"]);
+    assert_eq!(
+        config,
+        ServerConfig {
+            host: "example.com".to_string(),
+            port: 443,
+            workers: None,
+        }
+    );
+
+    assert!(matches!(
+        ServerConfigOptional::merge_yaml(&mut config, "port: [1"),
+        Err(ServerConfigOptionalError::InvalidDocument { .. })
+    ));
+}
+
+/// Test structs filled from nested bracketed query strings
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, Deserialize), impls(serde), serde_qs)]
+pub struct Range {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, Deserialize), impls(serde), serde_qs)]
+pub struct Search {
+    pub term: String,
+    pub page: u32,
+    pub tags: Vec<String>,
+    #[optionalize(nested)]
+    pub price: Range,
+    #[optionalize(nested)]
+    pub rating: Option<Range>,
+}
+
+#[test]
+fn test_query_str() {
+    let patch = SearchOptional::from_query_str("term=lamp&page=2&tags[0]=desk&tags[1]=led&price[max]=50").unwrap();
+    assert_eq!(patch.term.as_deref(), Some("lamp"));
+    assert_eq!(patch.page, Some(2));
+    assert_eq!(patch.tags, Some(vec!["desk".to_string(), "led".to_string()]));
+    assert_eq!(patch.price, Some(RangeOptional { min: None, max: Some(50) }));
+    assert_eq!(patch.rating, None);
+
+    // Nested optional structs are only set when their keys are present
+    let patch = SearchOptional::from_query_str("rating[min]=4").unwrap();
+    assert_eq!(patch.set_fields(), vec!["rating"]);
+    assert_eq!(patch.rating, Some(RangeOptional { min: Some(4), max: None }));
+
+    let patch = SearchOptional::from_query_str("").unwrap();
+    assert!(patch.is_empty());
+    assert!(SearchOptional::from_query_str("page=two").is_err());
+}
+
+/// Test structs layered into a `config` builder as a source
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, serde::Serialize), impls(serde), config)]
+pub struct Listener {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, serde::Serialize), impls(serde), config)]
+pub struct AppConfig {
+    pub name: String,
+    pub debug: bool,
+    #[optionalize(nested)]
+    pub server: Listener,
+}
+
+#[test]
+fn test_config_source() {
+    let overrides = AppConfigOptional {
+        name: None,
+        debug: Some(true),
+        server: Some(ListenerOptional { host: None, port: Some(9000) }),
+    };
+    let config = config::Config::builder()
+        .set_default("name", "app")
+        .unwrap()
+        .set_default("debug", false)
+        .unwrap()
+        .set_default("server.host", "localhost")
+        .unwrap()
+        .set_default("server.port", 8080)
+        .unwrap()
+        .add_source(overrides)
+        .build()
+        .unwrap();
+
+    // Set fields win, and unset ones leave the lower layers alone
+    assert_eq!(config.get_string("name").unwrap(), "app");
+    assert!(config.get_bool("debug").unwrap());
+    assert_eq!(config.get_string("server.host").unwrap(), "localhost");
+    assert_eq!(config.get_int("server.port").unwrap(), 9000);
+}
@@ -0,0 +1,44 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use optionalize_macro::Optionalize;
+
+type BoxFuture = Pin<Box<dyn Future<Output = u32> + Send>>;
+
+/// Test struct holding trait objects and pinned futures
+#[derive(Optionalize)]
+#[optionalize(pointer = "unwrap", impls(apply))]
+#[allow(dead_code)]
+struct Job {
+    pub name: String,
+    pub callback: Box<dyn Fn(u32) -> u32 + Send + Sync>,
+    pub task: Pin<Box<dyn Future<Output = u32> + Send>>,
+    pub aliased: Option<BoxFuture>,
+    pub shared: std::sync::Arc<dyn std::fmt::Display + Send + Sync>,
+}
+
+#[test]
+fn test_dyn_and_pinned_fields() {
+    let mut job = Job {
+        name: "old".to_string(),
+        callback: Box::new(|x| x),
+        task: Box::pin(async { 1 }),
+        aliased: None,
+        shared: std::sync::Arc::new(1),
+    };
+
+    // The outer smart pointer is wrapped in `Option`, even with `pointer = "unwrap"`
+    let patch = JobOptional {
+        name: None,
+        callback: Some(Box::new(|x| x * 2)),
+        task: None,
+        aliased: Some(Box::pin(async { 2 })),
+        shared: Some(std::sync::Arc::new("shown")),
+    };
+    assert_eq!(patch.set_fields(), vec!["callback", "aliased", "shared"]);
+    patch.apply_to(&mut job);
+
+    assert_eq!((job.callback)(21), 42);
+    assert!(job.aliased.is_some());
+    assert_eq!(job.shared.to_string(), "shown");
+}
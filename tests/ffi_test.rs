@@ -0,0 +1,48 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct patched from C
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq), ffi)]
+#[allow(dead_code)]
+struct Sensor {
+    pub id: u32,
+    pub threshold: f64,
+    pub r#type: Option<u8>,
+}
+
+/// Stand-in for a C function receiving the patch
+extern "C" fn set_field_count(patch: *const SensorOptionalFfi) -> u32 {
+    let patch = unsafe { &*patch };
+    [patch.id_is_set, patch.threshold_is_set, patch.type_is_set]
+        .iter()
+        .filter(|set| **set)
+        .count() as u32
+}
+
+#[test]
+fn test_ffi_round_trip() {
+    let patch = SensorOptional {
+        id: None,
+        threshold: Some(0.5),
+        r#type: Some(3),
+    };
+
+    // Unset fields carry their default next to a cleared flag
+    let ffi = SensorOptionalFfi::from(patch);
+    assert!(!ffi.id_is_set);
+    assert_eq!(ffi.id, 0);
+    assert!(ffi.threshold_is_set && ffi.type_is_set);
+    assert_eq!(set_field_count(&ffi), 2);
+
+    // Values of unset fields are ignored on the way back
+    let mut ffi = ffi;
+    ffi.id = 42;
+    assert_eq!(
+        SensorOptional::from(ffi),
+        SensorOptional {
+            id: None,
+            threshold: Some(0.5),
+            r#type: Some(3),
+        }
+    );
+}
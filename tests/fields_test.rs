@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+use optionalize_macro::Optionalize;
+
+/// Test struct with fields of differing sensitivity
+#[derive(Optionalize)]
+#[allow(dead_code)]
+struct Profile {
+    pub display_name: String,
+    pub bio: Option<String>,
+    pub role: String,
+}
+
+#[test]
+fn test_retain_allowed() {
+    let mut patch = ProfileOptional {
+        display_name: Some("Ada".to_string()),
+        bio: Some("Mathematician".to_string()),
+        role: Some("admin".to_string()),
+    };
+
+    // Strip everything the caller may not modify
+    let allowed: HashSet<&str> = ["display_name", "bio"].into_iter().collect();
+    patch.retain_allowed(&allowed);
+
+    assert_eq!(patch.display_name.as_deref(), Some("Ada"));
+    assert_eq!(patch.bio.as_deref(), Some("Mathematician"));
+    assert_eq!(patch.role, None);
+}
+
+/// Test struct used as a sparse search filter
+#[derive(Optionalize)]
+#[optionalize(impls(query))]
+#[allow(dead_code)]
+struct Search {
+    pub term: String,
+    pub page: u32,
+    pub exact: Option<bool>,
+}
+
+#[test]
+fn test_to_query_pairs() {
+    let filter = SearchOptional {
+        term: Some("rust macros".to_string()),
+        page: None,
+        exact: Some(true),
+    };
+
+    // Only set fields show up, in declaration order
+    assert_eq!(
+        filter.to_query_pairs(),
+        vec![("term", "rust macros".to_string()), ("exact", "true".to_string())]
+    );
+}
+
+/// Test structs with nested and list fields in a query string
+#[derive(Optionalize)]
+#[optionalize(impls(query))]
+#[allow(dead_code)]
+struct Region {
+    pub name: String,
+}
+
+#[derive(Optionalize)]
+#[optionalize(impls(query))]
+#[allow(dead_code)]
+struct Listing {
+    pub query: String,
+    pub tags: Vec<String>,
+    #[optionalize(nested)]
+    pub region: Region,
+}
+
+#[test]
+fn test_to_query_pairs_lists() {
+    let filter = ListingOptional {
+        query: Some("loft".to_string()),
+        tags: Some(vec!["quiet".to_string(), "sunny".to_string()]),
+        region: Some(RegionOptional {
+            name: Some("north".to_string()),
+        }),
+    };
+
+    // Lists repeat their name, nested patches have no single value
+    assert_eq!(
+        filter.to_query_pairs(),
+        vec![
+            ("query", "loft".to_string()),
+            ("tags", "quiet".to_string()),
+            ("tags", "sunny".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_set_fields() {
+    let patch = ProfileOptional {
+        display_name: None,
+        bio: Some("Mathematician".to_string()),
+        role: Some("admin".to_string()),
+    };
+
+    assert_eq!(patch.set_fields(), vec!["bio", "role"]);
+}
+
+/// Test struct with overrides kept in constants
+#[derive(Optionalize)]
+#[allow(dead_code)]
+struct Settings {
+    pub theme: String,
+    pub notifications: bool,
+}
+
+/// Per-tenant overrides, built at compile time from empty patches
+const NO_OVERRIDE: SettingsOptional = SettingsOptional::empty();
+static OVERRIDES: [(&str, SettingsOptional); 2] = [
+    ("default", SettingsOptional::empty()),
+    (
+        "quiet",
+        SettingsOptional {
+            theme: None,
+            notifications: Some(false),
+        },
+    ),
+];
+
+#[test]
+fn test_const_empty() {
+    assert!(NO_OVERRIDE.set_fields().is_empty());
+    assert!(OVERRIDES[0].1.set_fields().is_empty());
+    assert_eq!(OVERRIDES[1].1.set_fields(), vec!["notifications"]);
+}
+
+/// Test structs walked with a visitor
+#[derive(Optionalize)]
+#[optionalize(impls(visit))]
+#[allow(dead_code)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(impls(visit))]
+#[allow(dead_code)]
+struct Poster {
+    pub title: String,
+    pub subtitle: Option<String>,
+    #[optionalize(flatten)]
+    pub size: Dimensions,
+}
+
+/// Visitor describing a patch, without knowing about its other fields
+#[derive(Default)]
+struct Describe {
+    lines: Vec<String>,
+}
+
+impl PosterOptionalFieldVisitor for Describe {
+    fn visit_title(&mut self, value: Option<&String>) {
+        self.lines.push(format!("title: {:?}", value));
+    }
+
+    fn visit_size(&mut self, value: &DimensionsOptional) {
+        self.lines.push(format!("size: {:?}", value.set_fields()));
+    }
+}
+
+#[test]
+fn test_accept_visitor() {
+    let patch = PosterOptional {
+        title: Some("Metropolis".to_string()),
+        subtitle: Some("1927".to_string()),
+        size: DimensionsOptional {
+            width: None,
+            height: Some(100),
+        },
+    };
+
+    // Fields the visitor doesn't override are skipped
+    let mut describe = Describe::default();
+    patch.accept(&mut describe);
+    assert_eq!(describe.lines, ["title: Some(\"Metropolis\")", "size: [\"height\"]"]);
+
+    let mut describe = Describe::default();
+    PosterOptional::empty().accept(&mut describe);
+    assert_eq!(describe.lines, ["title: None", "size: []"]);
+}
+
+#[test]
+fn test_summary() {
+    let patch = PosterOptional {
+        title: Some("Metropolis".to_string()),
+        subtitle: None,
+        size: DimensionsOptional {
+            width: Some(70),
+            height: None,
+        },
+    };
+    // Flattened fields count as the poster's own
+    assert_eq!(patch.summary(), "title, width (2 of 4 fields)");
+    assert_eq!(PosterOptional::empty().summary(), "no changes (0 of 4 fields)");
+}
+
+/// Test struct whose patch values are borrowed in place
+#[derive(Optionalize)]
+#[optionalize(getters)]
+pub struct Article {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub cover: Box<[u8; 2]>,
+    pub subtitle: Option<String>,
+    pub views: u64,
+}
+
+#[test]
+fn test_getters() {
+    let patch = ArticleOptional {
+        title: Some("Hello".to_string()),
+        tags: Some(vec!["rust".to_string()]),
+        cover: None,
+        subtitle: Some("World".to_string()),
+        views: Some(3),
+    };
+    assert_eq!(patch.title(), Some("Hello"));
+    assert_eq!(patch.tags(), Some(&["rust".to_string()][..]));
+    assert_eq!(patch.cover(), None);
+    assert_eq!(patch.subtitle(), Some("World"));
+    assert_eq!(patch.views, Some(3));
+}
+
+/// Test struct keeping an internal field out of the per-field helpers
+#[derive(Optionalize)]
+#[optionalize(getters, patch_macro)]
+pub struct Release {
+    pub version: String,
+    // A getter would clash with the generated `summary`
+    #[optionalize(no_helpers)]
+    pub summary: String,
+}
+
+#[test]
+fn test_no_helpers() {
+    let patch = release_patch! { version: "1.2.0" };
+    assert_eq!(patch.version(), Some("1.2.0"));
+    assert_eq!(patch.summary(), "version (1 of 2 fields)");
+}
+
+/// Test struct whose fields only some roles may change
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), impls(diff))]
+pub struct Account {
+    pub display_name: String,
+    #[optionalize(roles("admin", "owner"))]
+    pub email: String,
+    #[optionalize(roles("admin"))]
+    pub plan: String,
+}
+
+#[test]
+fn test_roles() {
+    assert_eq!(AccountOptional::allowed_fields("admin"), vec!["display_name", "email", "plan"]);
+    assert_eq!(AccountOptional::allowed_fields("owner"), vec!["display_name", "email"]);
+    assert_eq!(AccountOptional::allowed_fields("viewer"), vec!["display_name"]);
+
+    let mut patch = AccountOptional {
+        display_name: Some("Ada".to_string()),
+        email: Some("ada@example.com".to_string()),
+        plan: Some("enterprise".to_string()),
+    };
+    patch.retain_for_role("owner");
+    assert_eq!(patch.set_fields(), vec!["display_name", "email"]);
+    patch.retain_for_role("viewer");
+    assert_eq!(patch.set_fields(), vec!["display_name"]);
+}
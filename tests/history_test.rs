@@ -0,0 +1,64 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct edited through an undo/redo history
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(history, impls(apply))]
+struct Document {
+    pub title: String,
+    pub body: String,
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut document = Document {
+        title: "Draft".to_string(),
+        body: "".to_string(),
+    };
+    let mut history = DocumentOptionalHistory::new();
+
+    history.apply(
+        &mut document,
+        DocumentOptional {
+            title: Some("Notes".to_string()),
+            body: None,
+        },
+    );
+    history.apply(
+        &mut document,
+        DocumentOptional {
+            title: None,
+            body: Some("Hello".to_string()),
+        },
+    );
+    assert_eq!(document.title, "Notes");
+    assert_eq!(document.body, "Hello");
+
+    // Undo walks back one patch at a time
+    assert!(history.undo(&mut document));
+    assert_eq!(document.body, "");
+    assert!(history.undo(&mut document));
+    assert_eq!(document.title, "Draft");
+    assert!(!history.undo(&mut document));
+
+    // Redo replays them
+    assert!(history.redo(&mut document));
+    assert_eq!(document.title, "Notes");
+    assert!(history.can_redo());
+
+    // A new patch discards what was left to redo
+    history.apply(
+        &mut document,
+        DocumentOptional {
+            title: Some("Final".to_string()),
+            body: None,
+        },
+    );
+    assert!(!history.can_redo());
+    assert_eq!(
+        document,
+        Document {
+            title: "Final".to_string(),
+            body: "".to_string(),
+        }
+    );
+}
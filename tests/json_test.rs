@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use optionalize_macro::Optionalize;
+use serde_json::{json, Value};
+
+/// Test struct fed from dynamic key/value sources
+#[derive(Optionalize)]
+#[optionalize(impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Settings {
+    pub theme: String,
+    pub font_size: u32,
+    pub motto: Option<String>,
+}
+
+#[test]
+fn test_try_from_map() {
+    let map: HashMap<String, Value> = [
+        ("theme".to_string(), json!("dark")),
+        ("motto".to_string(), Value::Null),
+    ]
+    .into_iter()
+    .collect();
+
+    // Known keys are deserialized into their fields
+    let patch = SettingsOptional::try_from(map).unwrap();
+    assert_eq!(patch.theme.as_deref(), Some("dark"));
+    assert_eq!(patch.font_size, None);
+    assert_eq!(patch.motto, None);
+
+    // A badly typed value is reported by field name
+    let map: HashMap<String, Value> = [("font_size".to_string(), json!("big"))].into_iter().collect();
+    match SettingsOptional::try_from(map) {
+        Err(SettingsOptionalError::InvalidField { field, .. }) => assert_eq!(field, "font_size"),
+        _ => panic!("expected an invalid field error"),
+    }
+
+    // So is a key that isn't a field
+    let map: HashMap<String, Value> = [("colour".to_string(), json!("red"))].into_iter().collect();
+    assert!(matches!(
+        SettingsOptional::try_from(map),
+        Err(SettingsOptionalError::UnknownField { field }) if field == "colour"
+    ));
+}
+
+#[test]
+fn test_try_from_value() {
+    // A JSON object converts directly
+    let patch = SettingsOptional::try_from(json!({ "font_size": 14, "motto": "Be kind" })).unwrap();
+    assert_eq!(patch.theme, None);
+    assert_eq!(patch.font_size, Some(14));
+    assert_eq!(patch.motto.as_deref(), Some("Be kind"));
+
+    // Anything other than an object is rejected
+    assert_eq!(
+        SettingsOptional::try_from(json!([1, 2])).err().map(|err| err.to_string()),
+        Some("expected an object, found `[1,2]`".to_string())
+    );
+}
+
+#[test]
+fn test_apply_json() {
+    let mut settings = Settings {
+        theme: "light".to_string(),
+        font_size: 12,
+        motto: None,
+    };
+
+    // Deserialize and apply in one step, learning which fields changed
+    let applied = SettingsOptional::apply_json(&mut settings, &json!({ "theme": "dark", "font_size": 16 }));
+    assert_eq!(applied, Ok(vec!["theme", "font_size"]));
+    assert_eq!(settings.theme, "dark");
+    assert_eq!(settings.font_size, 16);
+    assert_eq!(settings.motto, None);
+}
+
+/// Test struct with checks run on every submitted field
+#[derive(Optionalize)]
+#[optionalize(impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Signup {
+    #[optionalize(range(min = 13))]
+    pub age: u32,
+    #[optionalize(range(max = 20))]
+    pub handle_length: u32,
+    pub email: String,
+}
+
+#[test]
+fn test_from_json_lenient() {
+    // Every problem is reported, and the valid fields are kept
+    let (patch, errors) = SignupOptional::from_json_lenient(json!({
+        "age": 9,
+        "email": "ada@example.com",
+        "handle_length": "long",
+        "nickname": "countess",
+    }));
+    assert_eq!(patch.set_fields(), vec!["email"]);
+
+    let fields: Vec<String> = errors
+        .iter()
+        .map(|err| match err {
+            SignupOptionalError::InvalidField { field, .. } => field.to_string(),
+            SignupOptionalError::UnknownField { field } => field.clone(),
+            other => panic!("unexpected error {}", other),
+        })
+        .collect();
+    assert_eq!(fields, vec!["handle_length", "nickname", "age"]);
+
+    let (patch, errors) = SignupOptional::from_json_lenient(json!([1, 2]));
+    assert!(patch.set_fields().is_empty());
+    assert!(matches!(errors[..], [SignupOptionalError::ExpectedObject { .. }]));
+}
+
+/// Test structs whose nested objects are read leniently as well
+#[derive(Optionalize)]
+#[optionalize(derive(serde::Deserialize), impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Contact {
+    pub phone: String,
+    #[optionalize(range(max = 5))]
+    pub priority: u8,
+}
+
+#[derive(Optionalize)]
+#[optionalize(impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Profile {
+    pub name: String,
+    #[optionalize(nested)]
+    pub contact: Contact,
+}
+
+#[test]
+fn test_from_json_lenient_nested() {
+    let (patch, errors) = ProfileOptional::from_json_lenient(json!({
+        "name": "Ada",
+        "contact": { "phone": "555-0100", "priority": "high" },
+    }));
+    assert_eq!(patch.set_fields(), vec!["name", "contact"]);
+    let contact = patch.contact.unwrap();
+    assert_eq!(contact.phone.as_deref(), Some("555-0100"));
+    assert_eq!(contact.priority, None);
+
+    // Nested errors are reported under the parent's field
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], ProfileOptionalError::InvalidField { field: "contact", message }
+        if message.contains("priority")));
+}
+
+#[test]
+fn test_try_from_pairs() {
+    // A CSV row yields its header and cells in order
+    let header = ["email", "age", "handle_length"];
+    let row = [json!("ada@example.com"), json!(36), json!(null)];
+    let patch = SignupOptional::try_from_pairs(header.iter().zip(row)).unwrap();
+    assert_eq!(patch.set_fields(), vec!["age", "email"]);
+
+    let pairs = vec![("age".to_string(), json!(30)), ("age".to_string(), json!(31))];
+    let patch = SignupOptional::try_from(pairs).unwrap();
+    assert_eq!(patch.age, Some(31));
+
+    let err = SignupOptional::try_from_pairs([("nickname", json!("countess"))]).err().unwrap();
+    assert!(matches!(err, SignupOptionalError::UnknownField { .. }));
+
+    let (patch, errors) = SignupOptional::from_pairs_lenient([("email", json!("a@b.c")), ("age", json!("old"))]);
+    assert_eq!(patch.set_fields(), vec!["email"]);
+    assert_eq!(errors.len(), 1);
+}
+
+/// Test structs stored as documents with nested objects
+#[derive(Optionalize)]
+#[optionalize(derive(serde::Deserialize), impls(serde), serde_json, dynamodb, sha2)]
+#[allow(dead_code)]
+pub struct Geo {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Optionalize)]
+#[optionalize(derive(serde::Deserialize), impls(serde), serde_json, surrealdb, elasticsearch, dynamodb, sha2)]
+#[allow(dead_code)]
+struct Venue {
+    pub name: String,
+    #[optionalize(double_option)]
+    pub website: Option<String>,
+    #[optionalize(nested)]
+    pub geo: Geo,
+}
+
+#[test]
+fn test_to_json_object() {
+    let patch = VenueOptional {
+        name: None,
+        website: Some(None),
+        geo: Some(GeoOptional {
+            lat: Some(48.85),
+            lon: None,
+        }),
+    };
+    let object = Value::Object(patch.to_json_object().unwrap());
+    assert_eq!(object, json!({ "website": null, "geo": { "lat": 48.85 } }));
+    assert_eq!(patch.to_surreal_merge().unwrap(), object);
+    assert_eq!(patch.to_es_partial_doc().unwrap(), json!({ "doc": object }));
+
+    let (expression, names, values) = patch.to_dynamo_update().unwrap();
+    assert_eq!(expression, "SET #geo.#lat = :geo_lat REMOVE #website");
+    assert_eq!(names.len(), 3);
+    assert_eq!(names["#geo"], "geo");
+    assert_eq!(values, HashMap::from([(":geo_lat".to_string(), json!(48.85))]));
+}
+
+#[test]
+fn test_to_btree_map() {
+    let patch = VenueOptional {
+        name: Some("Opera".to_string()),
+        website: Some(None),
+        geo: Some(GeoOptional {
+            lon: Some(2.33),
+            lat: Some(48.87),
+        }),
+    };
+    let map = patch.to_btree_map().unwrap();
+    assert_eq!(map.keys().collect::<Vec<_>>(), ["geo", "name", "website"]);
+    assert_eq!(
+        serde_json::to_string(&map).unwrap(),
+        r#"{"geo":{"lat":48.87,"lon":2.33},"name":"Opera","website":null}"#
+    );
+}
+
+#[test]
+fn test_content_hash() {
+    let patch = VenueOptional {
+        name: Some("Opera".to_string()),
+        website: None,
+        geo: Some(GeoOptional {
+            lat: Some(48.87),
+            lon: Some(2.33),
+        }),
+    };
+    let hash = patch.content_hash().unwrap();
+    assert_eq!(hash.len(), 64);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+    // Equal patches hash the same, whatever order they were built in
+    let mut same = VenueOptional::try_from(json!({ "geo": { "lon": 2.33, "lat": 48.87 }, "name": "Opera" })).unwrap();
+    assert_eq!(same.content_hash().unwrap(), hash);
+    same.website = Some(None);
+    assert_ne!(same.content_hash().unwrap(), hash);
+
+    // Empty patches of different structs don't collide
+    assert_ne!(VenueOptional::empty().content_hash().unwrap(), GeoOptional::empty().content_hash().unwrap());
+}
+
+/// Test struct kept in sync from a CDC stream
+#[derive(Optionalize)]
+#[optionalize(impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Order {
+    pub status: String,
+    pub total: u32,
+    pub note: Option<String>,
+}
+
+#[test]
+fn test_from_change_event() {
+    // Only the changed columns of an update are set; unknown columns are ignored
+    let event = json!({
+        "payload": {
+            "op": "u",
+            "before": { "id": 7, "status": "pending", "total": 40, "note": null },
+            "after": { "id": 7, "status": "shipped", "total": 40, "note": "fragile" },
+        }
+    });
+    let patch = OrderOptional::from_change_event(&event).unwrap();
+    assert_eq!(patch.status.as_deref(), Some("shipped"));
+    assert_eq!(patch.total, None);
+    assert_eq!(patch.note.as_deref(), Some("fragile"));
+
+    // A create sets every column, a delete nothing
+    let created = json!({ "op": "c", "before": null, "after": { "status": "new", "total": 5 } });
+    let patch = OrderOptional::from_change_event(&created).unwrap();
+    assert_eq!((patch.status.as_deref(), patch.total), (Some("new"), Some(5)));
+    let deleted = json!({ "op": "d", "before": { "status": "new" }, "after": null });
+    assert_eq!(OrderOptional::from_change_event(&deleted).unwrap().set_fields(), Vec::<&str>::new());
+
+    // Badly typed columns are reported by field name
+    let event = json!({ "before": null, "after": { "total": "lots" } });
+    match OrderOptional::from_change_event(&event) {
+        Err(OrderOptionalError::InvalidField { field, .. }) => assert_eq!(field, "total"),
+        _ => panic!("expected an invalid field error"),
+    }
+}
+
+/// Test struct whose changes are written to an audit table
+#[derive(Optionalize, Clone)]
+#[optionalize(derive(Clone), impls(serde), serde_json)]
+#[allow(dead_code)]
+struct Invoice {
+    pub amount: u32,
+    pub paid: bool,
+    pub memo: Option<String>,
+    #[optionalize(sensitive)]
+    pub card: String,
+}
+
+#[test]
+fn test_audit_document() {
+    let before = Invoice {
+        amount: 100,
+        paid: false,
+        memo: None,
+        card: "4242".to_string(),
+    };
+    let patch = InvoiceOptional {
+        amount: Some(100),
+        paid: Some(true),
+        memo: Some("wire".to_string()),
+        card: Some("1111".to_string()),
+    };
+
+    // Unchanged and sensitive fields are left out
+    let document = patch.audit_document(&before).unwrap();
+    assert_eq!(
+        Value::Object(document),
+        json!({
+            "paid": { "old": false, "new": true },
+            "memo": { "old": null, "new": "wire" },
+        })
+    );
+    assert_eq!(before.card, "4242");
+}
+
+/// Test struct nested in one updated through JSON Patch
+#[derive(Optionalize, Clone, Debug, PartialEq, serde::Serialize)]
+#[optionalize(derive(serde::Deserialize), impls(serde), serde_json, json_patch)]
+pub struct Shipping {
+    pub city: String,
+    pub express: bool,
+}
+
+/// Test struct updated through JSON Patch
+#[derive(Optionalize, Clone, Debug, PartialEq)]
+#[optionalize(derive(serde::Deserialize), impls(serde), serde_json, json_patch)]
+pub struct Cart {
+    #[optionalize(immutable)]
+    pub id: u32,
+    pub items: Vec<String>,
+    pub coupon: Option<String>,
+    #[optionalize(nested)]
+    pub shipping: Shipping,
+}
+
+#[test]
+fn test_apply_json_patch() {
+    let mut cart = Cart {
+        id: 1,
+        items: vec!["tea".to_string()],
+        coupon: Some("SPRING".to_string()),
+        shipping: Shipping { city: "Oslo".to_string(), express: false },
+    };
+    let patch: json_patch::Patch = serde_json::from_value(json!([
+        { "op": "test", "path": "/id", "value": 1 },
+        { "op": "add", "path": "/items/-", "value": "jam" },
+        { "op": "remove", "path": "/coupon" },
+        { "op": "replace", "path": "/shipping/city", "value": "Bergen" },
+    ]))
+    .unwrap();
+    CartOptional::apply_json_patch(&mut cart, &patch).unwrap();
+    assert_eq!(cart.items, vec!["tea", "jam"]);
+    assert_eq!(cart.coupon, None);
+    assert_eq!(cart.shipping, Shipping { city: "Bergen".to_string(), express: false });
+
+    // Failing operations leave the target untouched
+    let before = cart.clone();
+    let failing = [
+        json!([{ "op": "replace", "path": "/items", "value": [] }, { "op": "replace", "path": "/id", "value": 2 }]),
+        json!([{ "op": "add", "path": "/owner", "value": "ada" }]),
+        json!([{ "op": "test", "path": "/shipping/express", "value": true }]),
+        json!([{ "op": "remove", "path": "/items" }]),
+    ];
+    let errors: Vec<_> = failing
+        .into_iter()
+        .map(|patch| {
+            let patch: json_patch::Patch = serde_json::from_value(patch).unwrap();
+            CartOptional::apply_json_patch(&mut cart, &patch).unwrap_err()
+        })
+        .collect();
+    assert_eq!(cart, before);
+    assert_eq!(errors[0], CartOptionalError::Immutable { fields: vec!["id"] });
+    assert_eq!(errors[1], CartOptionalError::UnknownField { field: "owner".to_string() });
+    assert!(matches!(errors[2], CartOptionalError::InvalidDocument { .. }));
+    assert!(matches!(errors[3], CartOptionalError::InvalidField { field: "items", .. }));
+}
@@ -0,0 +1,54 @@
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use metrics_util::MetricKind;
+use optionalize_macro::Optionalize;
+
+/// Test struct whose applies are recorded as metrics
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(metrics, impls(apply))]
+pub struct Thermostat {
+    pub target: u8,
+    #[optionalize(range(max = 30))]
+    pub eco: u8,
+    pub mode: String,
+}
+
+#[test]
+fn test_apply_metrics() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    metrics::with_local_recorder(&recorder, || {
+        let mut thermostat = Thermostat {
+            target: 20,
+            eco: 16,
+            mode: "auto".to_string(),
+        };
+        ThermostatOptional { target: Some(21), mode: Some("heat".to_string()), eco: None }.apply_to(&mut thermostat);
+        ThermostatOptional::empty().apply_to(&mut thermostat);
+        let rejected = ThermostatOptional { eco: Some(40), ..ThermostatOptional::empty() };
+        assert!(rejected.try_apply(&mut thermostat).is_err());
+    });
+
+    let metrics: Vec<_> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| {
+            let (kind, key) = key.into_parts();
+            assert_eq!(key.labels().next().map(|label| label.value().to_string()).as_deref(), Some("ThermostatOptional"));
+            (kind, key.name().to_string(), value)
+        })
+        .collect();
+    let find = |name: &str| metrics.iter().find(|(_, found, _)| found == name).map(|(kind, _, value)| (*kind, value));
+
+    assert_eq!(find("optionalize_patches_applied_total"), Some((MetricKind::Counter, &DebugValue::Counter(2))));
+    assert_eq!(find("optionalize_empty_patches_total"), Some((MetricKind::Counter, &DebugValue::Counter(1))));
+    assert_eq!(find("optionalize_patches_rejected_total"), Some((MetricKind::Counter, &DebugValue::Counter(1))));
+    match find("optionalize_patch_fields") {
+        Some((MetricKind::Histogram, DebugValue::Histogram(values))) => {
+            assert_eq!(values.iter().map(|value| value.into_inner()).collect::<Vec<_>>(), vec![2.0, 0.0]);
+        }
+        other => panic!("unexpected patch_fields metric: {:?}", other),
+    }
+    assert!(find("optionalize_apply_duration_seconds").is_some());
+}
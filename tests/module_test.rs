@@ -0,0 +1,113 @@
+use optionalize_macro::optionalize_all;
+
+/// Test module of DTOs sharing one configuration
+#[optionalize_all(derive(Debug, PartialEq))]
+#[allow(dead_code)]
+mod dto {
+    use optionalize_macro::Optionalize;
+
+    pub struct User {
+        pub id: i32,
+        pub name: String,
+    }
+
+    // Already derived structs are left alone
+    #[derive(Optionalize)]
+    pub struct Team {
+        pub name: String,
+    }
+
+    pub mod billing {
+        pub struct Invoice {
+            pub total: u64,
+        }
+    }
+}
+
+#[test]
+fn test_optionalize_all() {
+    // Every struct got a twin, with the shared derives applied
+    let user = dto::UserOptional {
+        id: None,
+        name: Some("Ada".to_string()),
+    };
+    assert_eq!(
+        user,
+        dto::UserOptional {
+            id: None,
+            name: Some("Ada".to_string()),
+        }
+    );
+
+    let team = dto::TeamOptional { name: None };
+    assert_eq!(team.set_fields(), Vec::<&str>::new());
+
+    let invoice = dto::billing::InvoiceOptional { total: Some(42) };
+    assert_eq!(format!("{:?}", invoice), "InvoiceOptional { total: Some(42) }");
+}
+
+/// Test module of a configuration tree nested by depth
+#[optionalize_all(derive(Debug, PartialEq))]
+#[allow(dead_code)]
+mod config {
+    #[derive(Debug, Clone, PartialEq)]
+    #[optionalize(depth = 2)]
+    pub struct Server {
+        pub host: String,
+        pub tls: Option<Tls>,
+        pub limits: Limits,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Tls {
+        pub cert: Cert,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Cert {
+        pub path: String,
+        pub key: Key,
+    }
+
+    // Values nested patches replace whole still go through their helpers
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    pub struct Key {
+        pub bits: u32,
+    }
+
+    impl std::str::FromStr for Key {
+        type Err = std::num::ParseIntError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Ok(Key { bits: s.parse()? })
+        }
+    }
+
+    impl std::fmt::Display for Key {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.bits)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Limits {
+        pub connections: u32,
+    }
+}
+
+#[test]
+fn test_optionalize_all_depth() {
+    // Two levels from `Server` are nested, the third is replaced whole
+    let patch = config::ServerOptional {
+        host: None,
+        tls: Some(config::TlsOptional {
+            cert: Some(config::CertOptional {
+                path: Some("cert.pem".to_string()),
+                key: None,
+            }),
+        }),
+        limits: Some(config::LimitsOptional { connections: Some(64) }),
+    };
+    let key: Option<config::Key> = patch.tls.and_then(|tls| tls.cert).and_then(|cert| cert.key);
+    assert!(key.is_none());
+}
@@ -0,0 +1,31 @@
+use napi::bindgen_prelude::{FromNapiValue, ToNapiValue, TypeName, ValidateNapiValue};
+use optionalize_macro::Optionalize;
+
+/// Test struct whose patches are taken from JavaScript
+#[derive(Optionalize)]
+#[optionalize(napi, impls(apply))]
+#[allow(dead_code)]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+fn assert_napi_object<T: FromNapiValue + ToNapiValue + TypeName + ValidateNapiValue>() {}
+
+#[test]
+fn test_napi_object() {
+    assert_napi_object::<AccountOptional>();
+    assert_eq!(AccountOptional::type_name(), "AccountOptional");
+
+    // The fields stay plain options, so absent properties read as `None`
+    let patch = AccountOptional {
+        name: None,
+        age: Some(37),
+    };
+    let mut account = Account {
+        name: "Ada".to_string(),
+        age: 36,
+    };
+    patch.apply_to(&mut account);
+    assert_eq!((account.name.as_str(), account.age), ("Ada", 37));
+}
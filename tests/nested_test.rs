@@ -0,0 +1,782 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct that refers to itself
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+struct Node {
+    pub value: i32,
+    #[optionalize(nested)]
+    pub next: Option<Box<Node>>,
+}
+
+#[test]
+fn test_recursive_struct() {
+    let mut list = Node {
+        value: 1,
+        next: Some(Box::new(Node {
+            value: 2,
+            next: None,
+        })),
+    };
+
+    // Patch the second node without touching the first
+    let patch = NodeOptional {
+        value: None,
+        next: Some(Box::new(NodeOptional {
+            value: Some(20),
+            next: None,
+        })),
+    };
+    patch.apply_to(&mut list);
+
+    assert_eq!(
+        list,
+        Node {
+            value: 1,
+            next: Some(Box::new(Node {
+                value: 20,
+                next: None,
+            })),
+        }
+    );
+}
+
+/// Test struct whose recursive field is replaced wholesale
+#[derive(Optionalize)]
+#[optionalize(impls(apply))]
+#[allow(dead_code)]
+struct Tree {
+    pub label: String,
+    pub children: Vec<Tree>,
+}
+
+#[test]
+fn test_recursive_struct_without_nesting() {
+    let mut tree = Tree {
+        label: "root".to_string(),
+        children: Vec::new(),
+    };
+
+    let patch = TreeOptional {
+        label: None,
+        children: Some(vec![Tree {
+            label: "leaf".to_string(),
+            children: Vec::new(),
+        }]),
+    };
+    patch.apply_to(&mut tree);
+
+    assert_eq!(tree.children.len(), 1);
+    assert_eq!(tree.children[0].label, "leaf");
+}
+
+/// Test structs reached through typed paths
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(lens)]
+struct City {
+    pub name: String,
+    pub population: u32,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(lens)]
+struct Office {
+    pub label: String,
+    #[optionalize(nested)]
+    pub city: Box<City>,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(lens)]
+struct Company<T: 'static> {
+    pub name: String,
+    #[optionalize(nested)]
+    pub office: Office,
+    pub extra: T,
+}
+
+#[test]
+fn test_lens_paths() {
+    let mut company = Company {
+        name: "Acme".to_string(),
+        office: Office {
+            label: "HQ".to_string(),
+            city: Box::new(City {
+                name: "Berlin".to_string(),
+                population: 3_600_000,
+            }),
+        },
+        extra: (),
+    };
+
+    // A single deeply nested field is set through its path
+    let city_name = Company::<()>::path().office().city().name();
+    assert_eq!(city_name.path(), "office.city.name");
+    city_name.set(&mut company, "Paris".to_string());
+    assert_eq!(company.office.city.name, "Paris");
+
+    // A path to a nested struct applies a patch in place
+    Company::<()>::path().office().city().apply(
+        &mut company,
+        CityOptional {
+            name: None,
+            population: Some(2_100_000),
+        },
+    );
+    assert_eq!(
+        *company.office.city,
+        City {
+            name: "Paris".to_string(),
+            population: 2_100_000,
+        }
+    );
+
+    *Company::<()>::path().office().label().get_mut(&mut company) = "Main".to_string();
+    assert_eq!(company.office.label, "Main");
+}
+
+/// Test structs flattened into a single patch
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, serde::Serialize, serde::Deserialize), impls(apply))]
+struct Billing {
+    pub street: String,
+    pub zip: String,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, serde::Serialize, serde::Deserialize), impls(apply))]
+struct Customer {
+    pub name: String,
+    #[optionalize(flatten)]
+    pub billing: Billing,
+}
+
+#[test]
+fn test_flatten() {
+    let mut customer = Customer {
+        name: "Ada".to_string(),
+        billing: Billing {
+            street: "1 Main St".to_string(),
+            zip: "12345".to_string(),
+        },
+    };
+
+    // The nested fields sit in the same JSON object as the parent's
+    let patch: CustomerOptional = serde_json::from_str(r#"{"name": "Grace", "zip": "54321"}"#).unwrap();
+    assert_eq!(
+        patch,
+        CustomerOptional {
+            name: Some("Grace".to_string()),
+            billing: BillingOptional {
+                street: None,
+                zip: Some("54321".to_string()),
+            },
+        }
+    );
+    assert_eq!(patch.set_fields(), vec!["name", "zip"]);
+
+    patch.apply_to(&mut customer);
+    assert_eq!(customer.name, "Grace");
+    assert_eq!(
+        customer.billing,
+        Billing {
+            street: "1 Main St".to_string(),
+            zip: "54321".to_string(),
+        }
+    );
+
+    // An empty patch leaves the flattened twin empty too
+    assert_eq!(CustomerOptional::empty().set_fields(), Vec::<&str>::new());
+}
+
+/// Test structs overridden through dotted keys
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply, flat))]
+struct Listener {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply, flat))]
+struct AppConfig {
+    pub name: String,
+    #[optionalize(nested)]
+    pub server: Listener,
+    #[optionalize(nested)]
+    pub admin: Option<Box<Listener>>,
+}
+
+#[test]
+fn test_flat_map() {
+    let patch = AppConfigOptional::from_flat_map([("server.port", "8080"), ("name", "api")]).unwrap();
+    let mut config = AppConfig {
+        name: "app".to_string(),
+        server: Listener {
+            host: "localhost".to_string(),
+            port: 80,
+        },
+        admin: None,
+    };
+    patch.apply_to(&mut config);
+    assert_eq!(config.name, "api");
+    assert_eq!(
+        config.server,
+        Listener {
+            host: "localhost".to_string(),
+            port: 8080,
+        }
+    );
+
+    // Nested keys create the nested patch on demand
+    let mut patch = AppConfigOptional::empty();
+    patch.set_from_str("admin.host", "127.0.0.1").unwrap();
+    assert_eq!(patch.admin.map(|admin| admin.host), Some(Some("127.0.0.1".to_string())));
+
+    // Unknown keys are reported in full, bad values by their top-level field
+    assert_eq!(
+        AppConfigOptional::from_flat_map([("server.tls", "on")]).map(|patch| patch.set_fields()),
+        Err(AppConfigOptionalError::UnknownField {
+            field: "server.tls".to_string()
+        })
+    );
+    assert!(matches!(
+        AppConfigOptional::from_flat_map([("server.port", "http")]),
+        Err(AppConfigOptionalError::InvalidField { field: "server", .. })
+    ));
+}
+
+#[test]
+fn test_parse_kv() {
+    // `--set` style arguments parse into a typed patch
+    let patch: AppConfigOptional = "name=api, server.port=8080,".parse().unwrap();
+    assert_eq!(patch.name.as_deref(), Some("api"));
+    assert_eq!(patch.server.map(|server| server.port), Some(Some(8080)));
+    assert_eq!(AppConfigOptional::parse_kv("").unwrap().set_fields(), Vec::<&str>::new());
+
+    // Errors point at the offending pair
+    assert_eq!(
+        AppConfigOptional::parse_kv("name=api,verbose").map(|patch| patch.set_fields()),
+        Err(AppConfigOptionalError::InvalidDocument {
+            message: "expected `key=value`, found `verbose`".to_string()
+        })
+    );
+    assert!(matches!(
+        "server.port=http".parse::<AppConfigOptional>(),
+        Err(AppConfigOptionalError::InvalidField { field: "server", .. })
+    ));
+
+    // Quoted values keep their commas, and stray quotes are rejected
+    let patch = AppConfigOptional::parse_kv(r#"name="api, \"v2\"", server.host=h"#).unwrap();
+    assert_eq!(patch.name.as_deref(), Some(r#"api, "v2""#));
+    assert_eq!(patch.server.and_then(|server| server.host).as_deref(), Some("h"));
+    assert_eq!(
+        AppConfigOptional::parse_kv(r#"name="api,v2"#).map(|patch| patch.set_fields()),
+        Err(AppConfigOptionalError::InvalidDocument {
+            message: r#"unterminated quote in `name="api,v2`"#.to_string()
+        })
+    );
+    assert_eq!(
+        AppConfigOptional::parse_kv(r#"name=a"b"c"#).map(|patch| patch.set_fields()),
+        Err(AppConfigOptionalError::InvalidDocument {
+            message: r#"quote the whole value of `name`, found `a"b"c`"#.to_string()
+        })
+    );
+}
+
+/// Test structs whose map entries are merged key by key
+#[derive(Optionalize, Clone, Debug, Default, PartialEq)]
+#[optionalize(derive(Clone, Debug, PartialEq), impls(apply, merge, diff))]
+pub struct Backend {
+    pub host: String,
+    pub weight: u32,
+}
+
+#[derive(Optionalize, Clone, Debug, PartialEq)]
+#[optionalize(derive(Clone, Debug, PartialEq), impls(apply, merge, diff))]
+pub struct Gateway {
+    #[optionalize(merge_entries)]
+    pub labels: std::collections::BTreeMap<String, String>,
+    #[optionalize(merge_entries, nested)]
+    pub backends: std::collections::BTreeMap<String, Backend>,
+}
+
+#[test]
+fn test_merge_entries() {
+    use std::collections::BTreeMap;
+
+    let backend = |host: &str, weight| Backend {
+        host: host.to_string(),
+        weight,
+    };
+    let mut gateway = Gateway {
+        labels: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+        backends: BTreeMap::from([("api".to_string(), backend("api.local", 1))]),
+    };
+    let original = gateway.clone();
+
+    let patch = GatewayOptional {
+        labels: Some(BTreeMap::from([("team".to_string(), "core".to_string())])),
+        backends: Some(BTreeMap::from([
+            (
+                "api".to_string(),
+                BackendOptional {
+                    host: None,
+                    weight: Some(5),
+                },
+            ),
+            (
+                "auth".to_string(),
+                BackendOptional {
+                    host: Some("auth.local".to_string()),
+                    weight: None,
+                },
+            ),
+        ])),
+    };
+    let inverse = patch.clone().apply_with_inverse(&mut gateway);
+    assert_eq!(gateway.labels.len(), 2);
+    assert_eq!(gateway.backends["api"], backend("api.local", 5));
+    assert_eq!(gateway.backends["auth"], backend("auth.local", 0));
+
+    // Existing entries are restored, new ones stay
+    let mut restored = gateway.clone();
+    inverse.apply_to(&mut restored);
+    assert_eq!(restored.backends["api"], original.backends["api"]);
+    assert_eq!(restored.backends.len(), 2);
+
+    // Patches merge entry by entry, and diffs only carry changed entries
+    let merged = patch.merge(GatewayOptional {
+        labels: None,
+        backends: Some(BTreeMap::from([(
+            "api".to_string(),
+            BackendOptional {
+                host: Some("api.internal".to_string()),
+                weight: None,
+            },
+        )])),
+    });
+    assert_eq!(merged.backends.as_ref().unwrap()["api"].weight, Some(5));
+    assert_eq!(merged.backends.as_ref().unwrap()["api"].host.as_deref(), Some("api.internal"));
+
+    let diff = GatewayOptional::diff(&original, &gateway);
+    assert_eq!(diff.labels, Some(BTreeMap::from([("team".to_string(), "core".to_string())])));
+    assert_eq!(diff.backends.as_ref().unwrap()["api"], BackendOptional { host: None, weight: Some(5) });
+    let mut rebuilt = original.clone();
+    diff.apply_to(&mut rebuilt);
+    assert_eq!(rebuilt, gateway);
+}
+
+/// Test structs nested by type rather than per field
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(name = "ScreenPatch", impls(apply))]
+pub struct Screen {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(nested_types(Screen = "ScreenPatch", Device), impls(apply))]
+pub struct Device {
+    pub model: String,
+    pub screen: Screen,
+    pub external: Option<Screen>,
+    pub parent: Option<Box<Device>>,
+    // Lists of a listed type are still replaced whole
+    pub mirrors: Vec<Screen>,
+}
+
+#[test]
+fn test_nested_types() {
+    let screen = Screen {
+        width: 1920,
+        height: 1080,
+    };
+    let mut device = Device {
+        model: "Tablet".to_string(),
+        screen: Screen { ..screen },
+        external: Some(Screen { ..screen }),
+        parent: None,
+        mirrors: Vec::new(),
+    };
+
+    let patch = DeviceOptional {
+        model: None,
+        screen: Some(ScreenPatch {
+            width: None,
+            height: Some(1200),
+        }),
+        external: Some(ScreenPatch {
+            width: Some(3840),
+            height: None,
+        }),
+        parent: None,
+        mirrors: Some(vec![Screen { ..screen }]),
+    };
+    patch.apply_to(&mut device);
+
+    assert_eq!(device.screen, Screen { width: 1920, height: 1200 });
+    assert_eq!(device.external, Some(Screen { width: 3840, height: 1080 }));
+    assert_eq!(device.mirrors, [screen]);
+}
+
+/// Test structs nested by the derive's `depth`
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Gutter {
+    pub top: u32,
+    pub bottom: u32,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(depth = 1, impls(apply))]
+pub struct Page {
+    pub title: String,
+    pub margin: Gutter,
+    pub footer: Option<Box<Gutter>>,
+    pub timeout: std::time::Duration,
+}
+
+#[test]
+fn test_derive_depth() {
+    let mut page = Page {
+        title: "Home".to_string(),
+        margin: Gutter { top: 10, bottom: 10 },
+        footer: Some(Box::new(Gutter { top: 4, bottom: 4 })),
+        timeout: std::time::Duration::from_secs(30),
+    };
+
+    let patch = PageOptional {
+        title: None,
+        margin: Some(GutterOptional {
+            top: None,
+            bottom: Some(20),
+        }),
+        footer: Some(Box::new(GutterOptional {
+            top: Some(8),
+            bottom: None,
+        })),
+        timeout: None,
+    };
+    patch.apply_to(&mut page);
+
+    assert_eq!(page.margin, Gutter { top: 10, bottom: 20 });
+    assert_eq!(page.footer, Some(Box::new(Gutter { top: 8, bottom: 4 })));
+}
+
+/// Test structs nested several levels deep
+#[derive(Optionalize, Debug, Clone, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Retry {
+    pub attempts: u8,
+    pub backoff_ms: u64,
+}
+
+#[derive(Optionalize, Debug, Clone, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Client {
+    pub timeout_ms: u64,
+    #[optionalize(nested)]
+    pub retry: Retry,
+    #[optionalize(nested)]
+    pub fallback: Option<Retry>,
+}
+
+#[derive(Optionalize, Debug, Clone, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Service {
+    pub name: String,
+    #[optionalize(nested)]
+    pub client: Box<Client>,
+}
+
+#[test]
+fn test_recursive_apply() {
+    let original = Service {
+        name: "billing".to_string(),
+        client: Box::new(Client {
+            timeout_ms: 500,
+            retry: Retry {
+                attempts: 3,
+                backoff_ms: 100,
+            },
+            fallback: None,
+        }),
+    };
+
+    // Only the innermost field is written, every sibling on the way is kept
+    let patch = ServiceOptional {
+        name: None,
+        client: Some(Box::new(ClientOptional {
+            timeout_ms: None,
+            retry: Some(RetryOptional {
+                attempts: Some(5),
+                backoff_ms: None,
+            }),
+            fallback: Some(RetryOptional {
+                attempts: Some(1),
+                backoff_ms: None,
+            }),
+        })),
+    };
+    let mut service = original.clone();
+    patch.apply_to(&mut service);
+
+    let mut expected = original;
+    expected.client.retry.attempts = 5;
+    // There is no fallback to patch, so it stays unset
+    assert_eq!(service, expected);
+}
+
+#[test]
+fn test_create_missing_nested() {
+    let mut client = Client {
+        timeout_ms: 500,
+        retry: Retry {
+            attempts: 3,
+            backoff_ms: 100,
+        },
+        fallback: None,
+    };
+
+    // A patch missing fields can't create the fallback, which try_apply reports
+    let incomplete = ClientOptional {
+        timeout_ms: None,
+        retry: None,
+        fallback: Some(RetryOptional {
+            attempts: Some(1),
+            backoff_ms: None,
+        }),
+    };
+    assert_eq!(incomplete.fallback.as_ref().unwrap().missing_fields(), ["backoff_ms"]);
+    assert!(matches!(
+        incomplete.try_apply(&mut client),
+        Err(ClientOptionalError::InvalidField { field: "fallback", .. })
+    ));
+    assert_eq!(client.fallback, None);
+
+    // A complete one creates it
+    let complete = ClientOptional {
+        timeout_ms: None,
+        retry: None,
+        fallback: Some(RetryOptional {
+            attempts: Some(1),
+            backoff_ms: Some(50),
+        }),
+    };
+    assert_eq!(complete.try_apply(&mut client), Ok(()));
+    assert_eq!(
+        client.fallback,
+        Some(Retry {
+            attempts: 1,
+            backoff_ms: 50,
+        })
+    );
+
+    // Values are built from patches through nested fields as well
+    let patch = ClientOptional {
+        timeout_ms: Some(250),
+        retry: Some(RetryOptional {
+            attempts: Some(2),
+            backoff_ms: None,
+        }),
+        fallback: None,
+    };
+    assert_eq!(patch.missing_fields(), ["retry"]);
+    assert_eq!(
+        Client::try_from(patch),
+        Err(ClientOptionalError::MissingFields { fields: vec!["retry"] })
+    );
+    let patch = ClientOptional {
+        timeout_ms: Some(250),
+        retry: Some(RetryOptional {
+            attempts: Some(2),
+            backoff_ms: Some(10),
+        }),
+        fallback: None,
+    };
+    assert_eq!(
+        Client::try_from(patch),
+        Ok(Client {
+            timeout_ms: 250,
+            retry: Retry {
+                attempts: 2,
+                backoff_ms: 10,
+            },
+            fallback: None,
+        })
+    );
+}
+
+#[test]
+fn test_inverse_of_missing_nested() {
+    let mut client = Client {
+        timeout_ms: 500,
+        retry: Retry {
+            attempts: 3,
+            backoff_ms: 100,
+        },
+        fallback: None,
+    };
+
+    // The fallback is created as by apply_to, even when keeping the inverse
+    let patch = ClientOptional {
+        timeout_ms: Some(250),
+        retry: None,
+        fallback: Some(RetryOptional {
+            attempts: Some(1),
+            backoff_ms: Some(50),
+        }),
+    };
+    let inverse = patch.apply_with_inverse(&mut client);
+    assert_eq!(
+        client.fallback,
+        Some(Retry {
+            attempts: 1,
+            backoff_ms: 50,
+        })
+    );
+
+    // The inverse restores the timeout, but can't unset the created fallback
+    assert!(inverse.fallback.is_none());
+    inverse.apply_to(&mut client);
+    assert_eq!(client.timeout_ms, 500);
+    assert!(client.fallback.is_some());
+}
+
+/// Test struct nested in a patch printed as lines
+#[derive(Optionalize)]
+#[optionalize(display)]
+pub struct Theme {
+    pub accent: String,
+    pub dark: bool,
+}
+
+/// Test struct whose patches are printed as lines
+#[derive(Optionalize)]
+#[optionalize(display)]
+pub struct Profile {
+    pub handle: String,
+    #[optionalize(double_option)]
+    pub bio: Option<String>,
+    #[optionalize(nested)]
+    pub theme: Theme,
+    #[optionalize(sensitive)]
+    pub password: String,
+    pub age: u8,
+}
+
+#[test]
+fn test_display_lines() {
+    let patch = ProfileOptional {
+        handle: Some("ada".to_string()),
+        bio: Some(None),
+        theme: Some(ThemeOptional { accent: Some("teal".to_string()), dark: Some(true) }),
+        password: Some("hunter2".to_string()),
+        age: None,
+    };
+    assert_eq!(
+        patch.to_string(),
+        "handle = ada\nbio = null\ntheme.accent = teal\ntheme.dark = true\npassword = [REDACTED]\n"
+    );
+    assert_eq!(ProfileOptional::empty().to_string(), "");
+}
+
+/// Test structs built with their generated patch macro
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq), patch_macro)]
+pub struct Margin {
+    pub top: u32,
+    pub bottom: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq), patch_macro = "page")]
+pub struct PageLayout {
+    pub title: String,
+    #[optionalize(double_option)]
+    pub footer: Option<String>,
+    #[optionalize(nested)]
+    pub margin: Margin,
+    pub columns: u8,
+}
+
+#[test]
+fn test_patch_macro() {
+    let patch = page! {
+        title: "Report",
+        footer: None,
+        margin: margin_patch! { top: 10 },
+    };
+    assert_eq!(
+        patch,
+        PageLayoutOptional {
+            title: Some("Report".to_string()),
+            footer: Some(None),
+            margin: Some(MarginOptional { top: Some(10), bottom: None }),
+            columns: None,
+        }
+    );
+    assert_eq!(page! {}, PageLayoutOptional::empty());
+    assert_eq!(page! { columns: 2 }.columns, Some(2));
+}
+
+/// Test structs updated through a gRPC-style field mask
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Venue {
+    pub city: String,
+    pub capacity: u32,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+pub struct Concert {
+    pub title: String,
+    pub price: u32,
+    #[optionalize(nested)]
+    pub venue: Venue,
+}
+
+#[test]
+fn test_apply_masked() {
+    let mut concert = Concert {
+        title: "Opening".to_string(),
+        price: 40,
+        venue: Venue {
+            city: "Lyon".to_string(),
+            capacity: 800,
+        },
+    };
+    let patch = || ConcertOptional {
+        title: Some("Encore".to_string()),
+        price: Some(55),
+        venue: Some(VenueOptional {
+            city: Some("Nice".to_string()),
+            capacity: Some(1200),
+        }),
+    };
+
+    // Only the masked fields are written, through nested paths as well
+    patch().apply_masked(&mut concert, &["price", "venue.capacity", "unknown"]);
+    assert_eq!(concert.title, "Opening");
+    assert_eq!(concert.price, 55);
+    assert_eq!(
+        concert.venue,
+        Venue {
+            city: "Lyon".to_string(),
+            capacity: 1200,
+        }
+    );
+
+    // Naming a nested field selects all of its patch
+    let mut masked = patch();
+    masked.retain_masked(&["venue"]);
+    assert_eq!(masked.set_fields(), vec!["venue"]);
+    assert_eq!(masked.venue.as_ref().unwrap().set_fields(), vec!["city", "capacity"]);
+
+    // A mask selecting nothing below a nested field drops it
+    let mut masked = patch();
+    masked.retain_masked(&["title", "venue.seats"]);
+    assert_eq!(masked.set_fields(), vec!["title"]);
+}
@@ -1,4 +1,4 @@
-use optionalize_macro::Optionalize; // Import the procedural macro from the optionalize_macro crate
+use optionalize_macro::{Optionalize, Partial}; // Import the procedural macro from the optionalize_macro crate
 
 /// Test struct to derive `Optionalize`
 #[derive(Optionalize, Debug, PartialEq)]
@@ -41,4 +41,447 @@ fn test_optionalize_macro() {
 
     // Verify that the generated optionalized struct matches the expected result
     assert_eq!(optionalized, expected);
-}
\ No newline at end of file
+}
+
+/// Test structs using each naming option
+#[derive(Optionalize)]
+#[optionalize(name = "ArticleDraft", impls(apply))]
+#[allow(dead_code)]
+struct Article {
+    pub title: String,
+}
+
+#[derive(Optionalize)]
+#[optionalize(prefix = "Partial", impls(apply))]
+#[allow(dead_code)]
+struct Comment {
+    pub body: String,
+}
+
+#[derive(Optionalize)]
+#[optionalize(naming = "update", impls(apply))]
+#[allow(dead_code)]
+struct Tag {
+    pub label: String,
+}
+
+#[test]
+fn test_naming() {
+    let draft = ArticleDraft { title: None };
+    let comment = PartialComment { body: None };
+    let tag = TagUpdate { label: None };
+
+    // The error type follows the generated name
+    assert_eq!(draft.validate(), Ok::<(), ArticleDraftError>(()));
+    assert_eq!(comment.validate(), Ok::<(), PartialCommentError>(()));
+    assert_eq!(tag.validate(), Ok::<(), TagUpdateError>(()));
+}
+
+/// Test structs derived under the `Partial` name
+#[derive(Partial, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), impls(apply))]
+pub struct Author {
+    pub name: String,
+}
+
+#[derive(Partial)]
+#[optionalize(derive(Debug, PartialEq), impls(apply))]
+pub struct Book {
+    pub title: String,
+    #[optionalize(nested)]
+    pub author: Author,
+}
+
+#[test]
+fn test_partial_derive() {
+    let patch = PartialBook {
+        title: None,
+        author: Some(PartialAuthor { name: Some("Le Guin".to_string()) }),
+    };
+    let mut book = Book {
+        title: "The Dispossessed".to_string(),
+        author: Author { name: "Ursula".to_string() },
+    };
+    assert_eq!(patch.validate(), Ok::<(), PartialBookError>(()));
+    patch.apply_to(&mut book);
+    assert_eq!(book.author, Author { name: "Le Guin".to_string() });
+}
+
+/// Test struct with a generic marker parameter
+#[derive(Optionalize)]
+#[allow(dead_code)]
+struct Id<T> {
+    pub raw: u64,
+    pub marker: std::marker::PhantomData<T>,
+}
+
+#[test]
+fn test_phantom_data_is_kept() {
+    struct User;
+
+    // The marker stays a plain `PhantomData` rather than an `Option`
+    let patch: IdOptional<User> = IdOptional {
+        raw: Some(7),
+        marker: std::marker::PhantomData,
+    };
+    assert_eq!(patch.set_fields(), vec!["raw"]);
+}
+
+/// Test struct with defaulted type and const parameters
+#[derive(Optionalize)]
+#[optionalize(impls(apply))]
+#[allow(dead_code)]
+struct Buffer<T = String, const N: usize = 4> {
+    pub label: T,
+    pub slots: [u8; N],
+}
+
+#[test]
+fn test_generic_defaults() {
+    // The defaults carry over, so the bare name still works
+    let patch: BufferOptional = BufferOptional {
+        label: Some("inbox".to_string()),
+        slots: None,
+    };
+    let mut buffer: Buffer = Buffer {
+        label: String::new(),
+        slots: [0; 4],
+    };
+    patch.apply_to(&mut buffer);
+    assert_eq!(buffer.label, "inbox");
+
+    let patch: BufferOptional<u32, 2> = BufferOptional {
+        label: None,
+        slots: Some([1, 2]),
+    };
+    assert_eq!(patch.set_fields(), vec!["slots"]);
+}
+
+/// Test struct referring to itself through `Self`
+#[derive(Optionalize)]
+#[optionalize(impls(apply))]
+#[allow(dead_code)]
+pub struct Category {
+    pub name: String,
+    pub parent: Option<Box<Self>>,
+    pub children: Vec<Self>,
+}
+
+#[test]
+fn test_self_in_field_types() {
+    // `Self` still means `Category` in the generated struct
+    let patch = CategoryOptional {
+        name: None,
+        parent: None,
+        children: Some(vec![Category {
+            name: "Child".to_string(),
+            parent: None,
+            children: Vec::new(),
+        }]),
+    };
+    let mut category = Category {
+        name: "Root".to_string(),
+        parent: None,
+        children: Vec::new(),
+    };
+    patch.apply_to(&mut category);
+    assert_eq!(category.children[0].name, "Child");
+}
+
+type Maybe<T> = Option<T>;
+
+/// Test struct using a local alias of `Option`
+#[derive(Optionalize)]
+#[optionalize(option_alias(Maybe), impls(apply))]
+#[allow(dead_code)]
+struct Contact {
+    pub email: String,
+    pub phone: Maybe<String>,
+}
+
+#[test]
+fn test_option_alias() {
+    let mut contact = Contact {
+        email: "old@example.com".to_string(),
+        phone: None,
+    };
+
+    // `phone` stays a `Maybe<String>` instead of becoming `Option<Maybe<String>>`
+    let patch = ContactOptional {
+        email: None,
+        phone: Some("555-0100".to_string()),
+    };
+    patch.apply_to(&mut contact);
+    assert_eq!(contact.phone, Some("555-0100".to_string()));
+}
+
+/// A tri-state value, as found in GraphQL frameworks
+#[derive(Debug, Default, PartialEq)]
+pub enum MaybeUndefined<T> {
+    #[default]
+    Undefined,
+    Null,
+    Value(T),
+}
+
+impl<T> MaybeUndefined<T> {
+    fn is_undefined(&self) -> bool {
+        matches!(self, MaybeUndefined::Undefined)
+    }
+}
+
+/// Test struct using an option-like type
+#[derive(Optionalize)]
+#[optionalize(option_like(MaybeUndefined = "is_undefined"), impls(apply))]
+#[allow(dead_code)]
+struct Profile {
+    pub name: String,
+    pub bio: MaybeUndefined<String>,
+    pub avatar: MaybeUndefined<String>,
+}
+
+#[test]
+fn test_option_like() {
+    let mut profile = Profile {
+        name: "old".to_string(),
+        bio: MaybeUndefined::Value("bio".to_string()),
+        avatar: MaybeUndefined::Value("avatar.png".to_string()),
+    };
+
+    // Option-like fields are kept as-is, so they can still be cleared explicitly
+    let patch = ProfileOptional {
+        name: None,
+        bio: MaybeUndefined::Null,
+        avatar: MaybeUndefined::Undefined,
+    };
+    assert_eq!(patch.set_fields(), vec!["bio"]);
+    patch.apply_to(&mut profile);
+
+    assert_eq!(profile.bio, MaybeUndefined::Null);
+    assert_eq!(profile.avatar, MaybeUndefined::Value("avatar.png".to_string()));
+}
+
+/// Test struct forwarding its comparison and hashing derives
+#[derive(Optionalize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[optionalize(forward_derives, derive(Debug))]
+struct Version {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[test]
+fn test_forward_derives() {
+    let bump_minor = VersionOptional {
+        major: None,
+        minor: Some(1),
+    };
+    let bump_major = VersionOptional {
+        major: Some(1),
+        minor: None,
+    };
+
+    // The generated struct can be sorted, compared and hashed like the original
+    let mut patches = vec![bump_major, bump_minor];
+    patches.sort();
+    assert_eq!(patches[0].major, None);
+
+    let unique: std::collections::HashSet<_> = patches.into_iter().collect();
+    assert_eq!(unique.len(), 2);
+}
+
+/// Test struct of plain values, whose patch is `Copy`
+#[derive(Optionalize)]
+#[allow(dead_code)]
+struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub label: Option<char>,
+}
+
+/// Test struct opting into `Copy` for a field type the macro can't recognize
+#[derive(Clone, Copy)]
+pub struct Celsius(pub f32);
+
+#[derive(Optionalize)]
+#[optionalize(copy)]
+#[allow(dead_code)]
+struct Reading {
+    pub temperature: Celsius,
+}
+
+#[test]
+fn test_copy() {
+    let patch = PointOptional {
+        x: Some(1.0),
+        y: None,
+        label: None,
+    };
+    let copy = patch;
+    assert_eq!(patch.set_fields(), copy.set_fields());
+
+    let reading = ReadingOptional {
+        temperature: Some(Celsius(21.5)),
+    };
+    let copy = reading;
+    assert_eq!(reading.temperature.map(|celsius| celsius.0), copy.temperature.map(|celsius| celsius.0));
+}
+
+/// Raw identifiers keep their `r#` in code but not in field names
+#[derive(Optionalize)]
+#[optionalize(lens)]
+#[allow(dead_code)]
+struct Token {
+    pub r#type: String,
+    pub r#async: bool,
+}
+
+#[test]
+fn test_raw_identifiers() {
+    let patch = TokenOptional {
+        r#type: Some("keyword".to_string()),
+        r#async: None,
+    };
+    assert_eq!(patch.set_fields(), vec!["type"]);
+
+    let mut token = Token {
+        r#type: "ident".to_string(),
+        r#async: false,
+    };
+    patch.apply_to(&mut token);
+    assert_eq!(token.r#type, "keyword");
+
+    Token::path().r#async().set(&mut token, true);
+    assert!(token.r#async);
+    assert_eq!(Token::path().r#type().path(), "type");
+}
+
+/// Test struct with a typestate builder
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(builder)]
+struct Invite<T> {
+    pub email: String,
+    pub r#type: T,
+    pub note: Option<String>,
+    pub marker: std::marker::PhantomData<T>,
+}
+
+#[test]
+fn test_builder() {
+    // Required fields can be set in any order, and optional ones left out
+    let invite = Invite::builder().r#type(2u8).email("a@example.com".to_string()).build();
+    assert_eq!(
+        invite,
+        Invite {
+            email: "a@example.com".to_string(),
+            r#type: 2,
+            note: None,
+            marker: std::marker::PhantomData,
+        }
+    );
+
+    let invite = Invite::builder()
+        .email("b@example.com".to_string())
+        .note("Welcome".to_string())
+        .r#type(1u8)
+        .build();
+    assert_eq!(invite.note.as_deref(), Some("Welcome"));
+
+    // A builder missing a required field has no `build` method, so it can
+    // only be passed around with its state spelled out
+    let partial: InviteOptionalBuilder<u8, InviteOptionalBuilderSet, InviteOptionalBuilderUnset> =
+        Invite::builder().email("c@example.com".to_string());
+    assert_eq!(partial.r#type(3).build().r#type, 3);
+}
+
+/// Test structs emitting only some groups of generated impls
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq))]
+pub struct Pixel {
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), impls(diff, merge))]
+pub struct Window {
+    pub title: String,
+    pub width: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(impls(all))]
+pub struct Screen {
+    pub height: u32,
+}
+
+#[test]
+fn test_impls_groups() {
+    // The struct and its field helpers are always there
+    let pixel = PixelOptional { x: Some(1), y: None };
+    assert_eq!(pixel.set_fields(), vec!["x"]);
+    assert_eq!(PixelOptional::empty(), PixelOptional { x: None, y: None });
+
+    let base = Window { title: "Editor".to_string(), width: 80 };
+    let target = Window { title: "Editor".to_string(), width: 120 };
+    let patch = WindowOptional::diff(&base, &target).merge(WindowOptional {
+        title: Some("Viewer".to_string()),
+        width: None,
+    });
+    assert_eq!(
+        patch,
+        WindowOptional {
+            title: Some("Viewer".to_string()),
+            width: Some(120),
+        }
+    );
+
+    let mut patch = ScreenOptional::empty();
+    patch.set_from_str("height", "1080").unwrap();
+    assert_eq!(patch.to_query_pairs(), vec![("height", "1080".to_string())]);
+}
+
+/// Test struct whose generated code is written out for inspection
+#[derive(Optionalize)]
+#[optionalize(debug_expand)]
+pub struct Dump {
+    pub value: u32,
+}
+
+#[test]
+fn test_debug_expand() {
+    let target = match option_env!("CARGO_TARGET_DIR") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("target"),
+    };
+    let written = std::fs::read_to_string(target.join("optionalize/Dump.rs")).unwrap();
+    assert!(written.contains("pub struct DumpOptional"));
+    assert_eq!(DumpOptional { value: Some(1) }.set_fields(), vec!["value"]);
+}
+
+/// Test structs whose generated items only exist under a `cfg`
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(cfg(test), impls(apply))]
+pub struct Enabled {
+    pub value: u32,
+}
+
+#[derive(Optionalize)]
+#[optionalize(cfg(not(test)), patch_macro)]
+#[allow(dead_code)]
+pub struct Hidden {
+    pub value: u32,
+}
+
+/// Would clash with the generated struct if it were emitted
+#[allow(dead_code)]
+pub struct HiddenOptional;
+
+#[test]
+fn test_cfg_gated() {
+    let mut enabled = Enabled { value: 1 };
+    EnabledOptional { value: Some(2) }.apply_to(&mut enabled);
+    assert_eq!(enabled.value, 2);
+
+    let _ = HiddenOptional;
+}
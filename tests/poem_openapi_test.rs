@@ -0,0 +1,30 @@
+use optionalize_macro::Optionalize;
+use poem_openapi::registry::{MetaSchemaRef, Registry};
+use poem_openapi::types::{ParseFromJSON, ToJSON, Type};
+use serde_json::json;
+
+/// Test struct whose patches are accepted and documented by poem services
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(poem_openapi, derive(Debug, PartialEq))]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+#[test]
+fn test_poem_openapi_object() {
+    let patch = AccountOptional::parse_from_json(Some(json!({ "age": 37 }))).unwrap();
+    assert_eq!(patch, AccountOptional { name: None, age: Some(37) });
+    assert_eq!(patch.to_json(), Some(json!({ "age": 37 })));
+
+    // Every field is documented as optional
+    let mut registry = Registry::new();
+    AccountOptional::register(&mut registry);
+    let name = match AccountOptional::schema_ref() {
+        MetaSchemaRef::Reference(name) => name,
+        MetaSchemaRef::Inline(_) => panic!("expected a named schema"),
+    };
+    let schema = &registry.schemas[&name];
+    assert_eq!(schema.properties.len(), 2);
+    assert!(schema.required.is_empty());
+}
@@ -0,0 +1,45 @@
+use optionalize_macro::Optionalize;
+use prost::Message;
+
+/// Test struct whose patches are encoded as protobuf messages, without being
+/// one itself
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(prost, derive(Clone, PartialEq), impls(apply))]
+pub struct Account {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint32, tag = "2")]
+    pub age: u32,
+    #[prost(bool, optional, tag = "3")]
+    pub verified: Option<bool>,
+}
+
+#[test]
+fn test_prost_round_trip() {
+    let patch = AccountOptional {
+        name: None,
+        age: Some(0),
+        verified: Some(true),
+    };
+
+    let bytes = patch.encode_to_vec();
+    let decoded = AccountOptional::decode(bytes.as_slice()).unwrap();
+    // A zero set explicitly is kept apart from an unset field
+    assert_eq!(decoded, patch);
+
+    let mut account = Account {
+        name: "Ada".to_string(),
+        age: 36,
+        verified: None,
+    };
+    decoded.apply_to(&mut account);
+    assert_eq!(
+        account,
+        Account {
+            name: "Ada".to_string(),
+            age: 0,
+            verified: Some(true),
+        }
+    );
+    assert!(AccountOptional::decode(&[][..]).unwrap().is_empty());
+}
@@ -0,0 +1,32 @@
+use optionalize_macro::Optionalize;
+use pyo3::{py_run, Python};
+
+/// Test struct whose patches are built from Python
+#[derive(Optionalize)]
+#[optionalize(pyo3)]
+#[allow(dead_code)]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+#[test]
+fn test_pyo3_class() {
+    Python::attach(|py| {
+        let cls = py.get_type::<AccountOptional>();
+        py_run!(
+            py,
+            cls,
+            r#"
+patch = cls(age=37)
+assert patch.name is None and patch.age == 37
+assert patch.set_fields() == ["age"]
+assert repr(patch) == "AccountOptional(age)"
+
+patch.name = "Ada"
+assert patch.set_fields() == ["name", "age"]
+assert cls().set_fields() == []
+"#
+        );
+    });
+}
@@ -0,0 +1,32 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct holding a credential
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Clone))]
+#[allow(dead_code)]
+struct Login {
+    pub username: String,
+    #[optionalize(sensitive)]
+    pub password: String,
+}
+
+#[test]
+fn test_redact() {
+    let patch = LoginOptional {
+        username: Some("ada".to_string()),
+        password: Some("hunter2".to_string()),
+    };
+
+    // Debug output never contains the secret
+    let debug = format!("{:?}", patch);
+    assert_eq!(
+        debug,
+        r#"LoginOptional { username: Some("ada"), password: Some([REDACTED]) }"#
+    );
+
+    // Redacting blanks sensitive fields but keeps the rest
+    let mut redacted = patch.clone();
+    redacted.redact();
+    assert_eq!(redacted.username.as_deref(), Some("ada"));
+    assert_eq!(redacted.password, None);
+}
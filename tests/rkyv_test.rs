@@ -0,0 +1,91 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct whose patches are archived with rkyv
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(rkyv, derive(Debug, PartialEq), impls(apply))]
+pub struct Quote {
+    pub symbol: String,
+    pub bid: u64,
+    pub ask: u64,
+    pub venue: Option<String>,
+}
+
+#[test]
+fn test_rkyv_archive() {
+    let patch = QuoteOptional {
+        symbol: None,
+        bid: Some(101),
+        ask: Some(103),
+        venue: None,
+    };
+    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&patch).unwrap();
+
+    // Read in place, after validating the bytes
+    let archived = rkyv::access::<ArchivedQuoteOptional, rkyv::rancor::Error>(&bytes).unwrap();
+    assert_eq!(archived.bid.as_ref().map(|bid| bid.to_native()), Some(101));
+    assert!(archived.symbol.is_none());
+
+    let patch: QuoteOptional = rkyv::deserialize::<_, rkyv::rancor::Error>(archived).unwrap();
+    let mut quote = Quote {
+        symbol: "ACME".to_string(),
+        bid: 100,
+        ask: 102,
+        venue: Some("XNYS".to_string()),
+    };
+    patch.apply_to(&mut quote);
+    assert_eq!((quote.bid, quote.ask), (101, 103));
+    assert_eq!(quote.symbol, "ACME");
+
+    // Corrupted bytes are rejected rather than read
+    let mut corrupted = bytes.clone();
+    corrupted.iter_mut().for_each(|byte| *byte = 0xff);
+    assert!(rkyv::access::<ArchivedQuoteOptional, rkyv::rancor::Error>(&corrupted).is_err());
+}
+
+/// Test structs whose nested patches, recursive or not, are archived as well
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(rkyv, derive(Debug, PartialEq), impls(apply))]
+pub struct Level {
+    pub price: u64,
+    pub size: u64,
+}
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(rkyv, derive(Debug, PartialEq), impls(apply))]
+pub struct Book {
+    pub symbol: String,
+    #[optionalize(nested)]
+    pub top: Level,
+    #[optionalize(nested)]
+    pub next: Option<Box<Book>>,
+}
+
+#[test]
+fn test_rkyv_nested() {
+    let patch = BookOptional {
+        symbol: None,
+        top: Some(LevelOptional { price: Some(99), size: None }),
+        next: Some(Box::new(BookOptional {
+            symbol: Some("ACME.B".to_string()),
+            top: None,
+            next: None,
+        })),
+    };
+    let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&patch).unwrap();
+    let archived = rkyv::access::<ArchivedBookOptional, rkyv::rancor::Error>(&bytes).unwrap();
+    let restored: BookOptional = rkyv::deserialize::<_, rkyv::rancor::Error>(archived).unwrap();
+    assert_eq!(restored, patch);
+
+    let mut book = Book {
+        symbol: "ACME".to_string(),
+        top: Level { price: 100, size: 5 },
+        next: Some(Box::new(Book {
+            symbol: "ACME.A".to_string(),
+            top: Level { price: 101, size: 1 },
+            next: None,
+        })),
+    };
+    restored.apply_to(&mut book);
+    assert_eq!(book.top, Level { price: 99, size: 5 });
+    assert_eq!(book.next.unwrap().symbol, "ACME.B");
+}
@@ -0,0 +1,29 @@
+use optionalize_macro::Optionalize;
+use rocket::form::Form;
+
+/// Test struct whose patches are submitted as forms
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(rocket, derive(Debug, PartialEq))]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+    pub newsletter: Option<bool>,
+}
+
+#[test]
+fn test_rocket_form() {
+    let patch: AccountOptional = Form::parse("age=37&newsletter=true").unwrap();
+    assert_eq!(
+        patch,
+        AccountOptional {
+            name: None,
+            age: Some(37),
+            newsletter: Some(true),
+        }
+    );
+    assert!(Form::<AccountOptional>::parse("").unwrap().is_empty());
+
+    // Like any `Option` in a Rocket form, a malformed value reads as unset
+    let patch: AccountOptional = Form::parse("name=Ada&age=old").unwrap();
+    assert_eq!(patch.set_fields(), vec!["name"]);
+}
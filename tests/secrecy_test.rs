@@ -0,0 +1,32 @@
+use optionalize_macro::Optionalize;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+
+/// Test struct holding a `secrecy` secret
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Serialize), secrecy, impls(apply))]
+#[allow(dead_code)]
+struct ApiKey {
+    pub label: String,
+    pub token: SecretString,
+}
+
+#[test]
+fn test_secret_fields() {
+    let patch = ApiKeyOptional {
+        label: Some("ci".to_string()),
+        token: Some(SecretString::from("s3cr3t")),
+    };
+
+    // The secret is neither printed nor serialized
+    assert!(!format!("{:?}", patch).contains("s3cr3t"));
+    assert_eq!(serde_json::to_string(&patch).unwrap(), r#"{"label":"ci"}"#);
+
+    // Applying moves the still-wrapped secret onto the target
+    let mut key = ApiKey {
+        label: "old".to_string(),
+        token: SecretString::from("old"),
+    };
+    patch.apply_to(&mut key);
+    assert_eq!(key.token.expose_secret(), "s3cr3t");
+}
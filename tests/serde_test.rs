@@ -0,0 +1,734 @@
+use optionalize_macro::Optionalize;
+use serde::{Deserialize, Serialize};
+
+/// Test struct backing an HTML form
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Deserialize))]
+#[allow(dead_code)]
+struct SignupForm {
+    #[optionalize(empty_as_none)]
+    pub nickname: String,
+    #[optionalize(empty_as_none)]
+    pub tags: Vec<String>,
+    #[optionalize(empty_as_none)]
+    pub bio: Option<String>,
+    pub age: u32,
+}
+
+#[test]
+fn test_empty_as_none() {
+    // Untouched inputs come through as empty values and become `None`
+    let patch: SignupFormOptional =
+        serde_json::from_str(r#"{ "nickname": "", "tags": [], "bio": "" }"#).unwrap();
+    assert_eq!(patch.nickname, None);
+    assert_eq!(patch.tags, None);
+    assert_eq!(patch.bio, None);
+    assert_eq!(patch.age, None);
+
+    // Non-empty values are kept as usual
+    let patch: SignupFormOptional =
+        serde_json::from_str(r#"{ "nickname": "ada", "tags": ["x"], "age": 36 }"#).unwrap();
+    assert_eq!(patch.nickname.as_deref(), Some("ada"));
+    assert_eq!(patch.tags, Some(vec!["x".to_string()]));
+    assert_eq!(patch.bio, None);
+    assert_eq!(patch.age, Some(36));
+}
+
+/// Test struct whose patch uses a different wire format
+#[derive(Optionalize)]
+#[optionalize(derive(Deserialize), field_attr(serde(default)))]
+#[allow(dead_code)]
+struct Listing {
+    #[optionalize(field_attr(serde(rename = "displayTitle")))]
+    pub title: String,
+    pub price: u32,
+}
+
+#[test]
+fn test_field_attr_pass_through() {
+    // The per-field rename applies, and the container-wide attribute reaches every field
+    let patch: ListingOptional = serde_json::from_str(r#"{ "displayTitle": "Lamp" }"#).unwrap();
+    assert_eq!(patch.title.as_deref(), Some("Lamp"));
+    assert_eq!(patch.price, None);
+}
+
+/// Test struct whose patch rejects unknown keys
+#[derive(Optionalize)]
+#[optionalize(derive(Deserialize), attr(serde(deny_unknown_fields)))]
+#[allow(dead_code)]
+struct Coupon {
+    pub code: String,
+}
+
+#[test]
+fn test_attr_pass_through() {
+    // The container attribute reached the generated struct
+    assert!(serde_json::from_str::<CouponOptional>(r#"{ "code": "SAVE10" }"#).is_ok());
+    assert!(serde_json::from_str::<CouponOptional>(r#"{ "cod": "SAVE10" }"#).is_err());
+}
+
+/// Test struct whose patches are persisted to an event log
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Serialize, Deserialize), event(version = 2))]
+struct Order {
+    pub status: String,
+    pub quantity: u32,
+}
+
+#[test]
+fn test_change_events() {
+    let patch = OrderOptional {
+        status: Some("shipped".to_string()),
+        quantity: None,
+    };
+    let json = serde_json::to_string(&patch.into_event()).unwrap();
+    assert_eq!(
+        json,
+        r#"{"op":"patch","version":2,"patch":{"status":"shipped","quantity":null}}"#
+    );
+
+    // Events read back from the log replay in order
+    let mut order = Order {
+        status: "new".to_string(),
+        quantity: 1,
+    };
+    let events: Vec<OrderOptionalEvent> = vec![
+        serde_json::from_str(&json).unwrap(),
+        serde_json::from_str(r#"{"op":"patch","version":2,"patch":{"status":null,"quantity":3}}"#).unwrap(),
+    ];
+    assert_eq!(order.replay(events), Ok(()));
+    assert_eq!(
+        order,
+        Order {
+            status: "shipped".to_string(),
+            quantity: 3,
+        }
+    );
+
+    // Events from an unknown schema version are rejected
+    let event: OrderOptionalEvent =
+        serde_json::from_str(r#"{"op":"patch","version":1,"patch":{"status":"lost","quantity":null}}"#).unwrap();
+    assert_eq!(
+        event.apply_to(&mut order),
+        Err(OrderOptionalError::UnsupportedEvent { version: 1 })
+    );
+}
+
+/// Test struct whose patches sit in a queue across deployments; version 1
+/// stored the delay in seconds, version 2 in milliseconds
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Serialize, Deserialize), schema_version = 2, migrate_from = "migrate_job")]
+struct Job {
+    pub name: String,
+    pub delay: u64,
+}
+
+fn migrate_job(version: u32, patch: &mut JobOptional) -> Result<(), JobOptionalError> {
+    if version != 1 {
+        return Err(JobOptionalError::UnsupportedVersion { version });
+    }
+    patch.delay = patch.delay.map(|seconds| seconds * 1000);
+    Ok(())
+}
+
+#[test]
+fn test_versioned_patches() {
+    let patch = JobOptional {
+        name: None,
+        delay: Some(1500),
+    };
+    assert_eq!(JobOptional::VERSION, 2);
+    let json = serde_json::to_string(&patch.into_versioned()).unwrap();
+    assert_eq!(json, r#"{"version":2,"patch":{"name":null,"delay":1500}}"#);
+
+    // Current patches are applied as-is, older ones are migrated first
+    let mut job = Job {
+        name: "backup".to_string(),
+        delay: 0,
+    };
+    let queued: JobOptionalVersioned = serde_json::from_str(&json).unwrap();
+    assert_eq!(queued.apply_to(&mut job), Ok(()));
+    assert_eq!(job.delay, 1500);
+    let queued: JobOptionalVersioned = serde_json::from_str(r#"{"version":1,"patch":{"delay":3}}"#).unwrap();
+    assert_eq!(queued.apply_to(&mut job), Ok(()));
+    assert_eq!(job.delay, 3000);
+
+    // Patches written by a newer build can't be understood
+    let queued: JobOptionalVersioned = serde_json::from_str(r#"{"version":3,"patch":{"delay":3}}"#).unwrap();
+    assert_eq!(
+        queued.into_current().map(drop),
+        Err(JobOptionalError::UnsupportedVersion { version: 3 })
+    );
+}
+
+/// Typed id that deserializes for any `T`, unlike serde's inferred bound
+#[derive(Debug, PartialEq)]
+struct Key<T>(u64, std::marker::PhantomData<T>);
+
+impl<'de, T> Deserialize<'de> for Key<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(|id| Key(id, std::marker::PhantomData))
+    }
+}
+
+/// Test struct overriding the bounds of its generated impls
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Deserialize), bound = "")]
+#[allow(dead_code)]
+struct Link<T> {
+    pub target: Key<T>,
+    pub label: String,
+}
+
+#[test]
+fn test_custom_bound() {
+    // Not `Deserialize`, which serde would otherwise require
+    struct Page;
+
+    let patch: LinkOptional<Page> = serde_json::from_str(r#"{ "target": 7, "label": null }"#).unwrap();
+    assert_eq!(patch.set_fields(), vec!["target"]);
+    assert_eq!(patch.target.map(|key| key.0), Some(7));
+}
+
+/// Test struct whose optional fields can be cleared by a patch
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, Serialize, Deserialize), double_option, impls(apply))]
+struct Profile {
+    pub name: String,
+    pub nickname: Option<String>,
+    pub website: Option<String>,
+}
+
+#[test]
+fn test_double_option() {
+    // Absent keys keep the value, `null` clears it
+    let patch: ProfileOptional = serde_json::from_str(r#"{ "nickname": null }"#).unwrap();
+    assert_eq!(patch.nickname, Some(None));
+    assert_eq!(patch.website, None);
+    assert_eq!(patch.set_fields(), vec!["nickname"]);
+
+    let mut profile = Profile {
+        name: "Ada".to_string(),
+        nickname: Some("countess".to_string()),
+        website: Some("ada.dev".to_string()),
+    };
+    patch.apply_to(&mut profile);
+    assert_eq!(profile.nickname, None);
+    assert_eq!(profile.website, Some("ada.dev".to_string()));
+
+    // Unset fields are skipped when serializing
+    let patch = ProfileOptional {
+        name: None,
+        nickname: Some(None),
+        website: Some(Some("lovelace.dev".to_string())),
+    };
+    assert_eq!(
+        serde_json::to_string(&patch).unwrap(),
+        r#"{"name":null,"nickname":null,"website":"lovelace.dev"}"#
+    );
+}
+
+/// Test struct backing an HTML form sent to a PATCH endpoint
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Deserialize), form)]
+#[allow(dead_code)]
+struct Preferences {
+    pub theme: String,
+    pub font_size: u8,
+    pub newsletter: bool,
+    pub volume: Option<f32>,
+}
+
+#[test]
+fn test_form() {
+    let patch: PreferencesOptional = serde_urlencoded::from_str("font_size=14&newsletter=on&volume=").unwrap();
+    assert_eq!(patch.font_size, Some(14));
+    assert_eq!(patch.newsletter, Some(true));
+    assert_eq!(patch.set_fields(), vec!["font_size", "newsletter"]);
+
+    let patch: PreferencesOptional = serde_urlencoded::from_str("theme=dark&newsletter=0&volume=0.5").unwrap();
+    assert_eq!(patch.theme.as_deref(), Some("dark"));
+    assert_eq!(patch.newsletter, Some(false));
+    assert_eq!(patch.volume, Some(0.5));
+
+    // Values that don't parse are rejected
+    assert!(serde_urlencoded::from_str::<PreferencesOptional>("font_size=huge").is_err());
+    assert!(serde_urlencoded::from_str::<PreferencesOptional>("newsletter=maybe").is_err());
+}
+
+/// Test struct filtering a `GET /users` listing
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Deserialize), query)]
+#[allow(dead_code)]
+struct Member {
+    pub name: String,
+    pub age: u8,
+    pub active: bool,
+    pub roles: Vec<String>,
+    pub teams: std::collections::BTreeSet<u32>,
+}
+
+#[test]
+fn test_query() {
+    // Deserialized the way axum-extra's `Query` does, with repeated keys
+    let patch: MemberOptional = serde_html_form::from_str("name=Ada&age=36&role=&roles=admin&roles=owner").unwrap();
+    assert_eq!(patch.name.as_deref(), Some("Ada"));
+    assert_eq!(patch.age, Some(36));
+    assert_eq!(patch.roles, Some(vec!["admin".to_string(), "owner".to_string()]));
+    assert_eq!(patch.set_fields(), vec!["name", "age", "roles"]);
+
+    let patch: MemberOptional = serde_html_form::from_str("active=on&teams=3&teams=1&teams=&age=").unwrap();
+    assert_eq!(patch.active, Some(true));
+    assert_eq!(patch.teams, Some([1, 3].into_iter().collect()));
+    assert_eq!(patch.set_fields(), vec!["active", "teams"]);
+
+    // Empty collections leave the field unset
+    let patch: MemberOptional = serde_html_form::from_str("roles=&teams=").unwrap();
+    assert!(patch.set_fields().is_empty());
+    assert!(serde_html_form::from_str::<MemberOptional>("teams=first").is_err());
+}
+
+/// Test struct whose changes are sent to an audit webhook
+#[derive(Optionalize)]
+#[optionalize(report(derive(Debug, PartialEq, Serialize, Deserialize)))]
+#[allow(dead_code)]
+struct Customer {
+    pub name: String,
+    pub tier: u8,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    #[optionalize(sensitive)]
+    pub password: String,
+}
+
+#[test]
+fn test_diff_report() {
+    let old = Customer {
+        name: "Ada".to_string(),
+        tier: 1,
+        phone: Some("555-0100".to_string()),
+        email: None,
+        password: "hunter2".to_string(),
+    };
+    let new = Customer {
+        name: "Ada".to_string(),
+        tier: 2,
+        phone: None,
+        email: Some("ada@example.com".to_string()),
+        password: "correct horse".to_string(),
+    };
+
+    let report = CustomerDiff::between(&old, &new);
+    assert_eq!(report.tier, Some(CustomerChange::Changed { old: 1, new: 2 }));
+    assert_eq!(report.changed_fields(), vec!["tier", "phone", "email"]);
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        serde_json::json!({
+            "tier": { "kind": "changed", "old": 1, "new": 2 },
+            "phone": { "kind": "removed", "old": "555-0100" },
+            "email": { "kind": "added", "new": "ada@example.com" },
+        })
+    );
+
+    // Webhook consumers can read it back
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<CustomerDiff>(&json).unwrap(), report);
+    assert!(CustomerDiff::between(&old, &old).is_empty());
+}
+
+/// Test struct whose map of labels is reported key by key
+#[derive(Optionalize)]
+#[optionalize(report(derive(Debug, Serialize)))]
+#[allow(dead_code)]
+struct Bucket {
+    pub region: String,
+    #[optionalize(map_diff)]
+    pub labels: std::collections::BTreeMap<String, String>,
+}
+
+#[test]
+fn test_map_diff_report() {
+    let labels = |entries: &[(&str, &str)]| {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+    let old = Bucket {
+        region: "eu".to_string(),
+        labels: labels(&[("env", "dev"), ("owner", "ada"), ("tier", "hot")]),
+    };
+    let new = Bucket {
+        region: "eu".to_string(),
+        labels: labels(&[("env", "prod"), ("tier", "hot"), ("team", "storage")]),
+    };
+
+    // Only the keys that changed are listed, not the whole map
+    let report = BucketDiff::between(&old, &new);
+    assert_eq!(report.changed_fields(), vec!["labels"]);
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        serde_json::json!({
+            "labels": {
+                "env": { "kind": "changed", "old": "dev", "new": "prod" },
+                "owner": { "kind": "removed", "old": "ada" },
+                "team": { "kind": "added", "new": "storage" },
+            },
+        })
+    );
+    assert!(BucketDiff::between(&new, &new).is_empty());
+}
+
+/// Test element of lists reported element by element
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Track {
+    pub id: u32,
+    pub title: String,
+}
+
+/// Test struct whose lists are reported element by element
+#[derive(Optionalize)]
+#[optionalize(report(derive(Debug, Serialize)))]
+#[allow(dead_code)]
+struct Playlist {
+    #[optionalize(diff_key = "id")]
+    pub tracks: Vec<Track>,
+    #[optionalize(vec_diff = "position")]
+    pub tags: Vec<String>,
+}
+
+#[test]
+fn test_vec_diff_report() {
+    let track = |id, title: &str| Track { id, title: title.to_string() };
+    let old = Playlist {
+        tracks: vec![track(1, "Intro"), track(2, "Song"), track(3, "Outro")],
+        tags: vec!["jazz".to_string(), "live".to_string()],
+    };
+    let new = Playlist {
+        tracks: vec![track(2, "Song (remaster)"), track(1, "Intro"), track(4, "Encore")],
+        tags: vec!["jazz".to_string(), "studio".to_string(), "2024".to_string()],
+    };
+
+    // Keyed elements are matched wherever they moved to
+    let report = PlaylistDiff::between(&old, &new);
+    assert_eq!(
+        serde_json::to_value(&report).unwrap(),
+        serde_json::json!({
+            "tracks": [
+                { "kind": "remove", "index": 2, "old": { "id": 3, "title": "Outro" } },
+                {
+                    "kind": "update",
+                    "index": 0,
+                    "old": { "id": 2, "title": "Song" },
+                    "new": { "id": 2, "title": "Song (remaster)" },
+                },
+                { "kind": "insert", "index": 2, "new": { "id": 4, "title": "Encore" } },
+            ],
+            "tags": [
+                { "kind": "update", "index": 1, "old": "live", "new": "studio" },
+                { "kind": "insert", "index": 2, "new": "2024" },
+            ],
+        })
+    );
+    assert!(PlaylistDiff::between(&new, &new).is_empty());
+}
+
+/// Test struct documented in an OpenAPI spec
+#[derive(Optionalize)]
+#[optionalize(derive(Serialize, Deserialize, schemars::JsonSchema, utoipa::ToSchema), double_option)]
+#[allow(dead_code)]
+struct Offer {
+    pub title: String,
+    pub discount: Option<u8>,
+}
+
+#[test]
+fn test_double_option_schema() {
+    use utoipa::PartialSchema;
+
+    // Omitted and `null` are both accepted, so the property is nullable but optional
+    let schema = serde_json::to_value(schemars::schema_for!(OfferOptional)).unwrap();
+    assert_eq!(schema["properties"]["discount"]["type"], serde_json::json!(["integer", "null"]));
+    assert_eq!(schema.get("required"), None);
+
+    let schema = serde_json::to_value(OfferOptional::schema()).unwrap();
+    assert_eq!(schema["properties"]["discount"]["type"], serde_json::json!(["integer", "null"]));
+    assert_eq!(schema.get("required"), None);
+}
+
+/// Test struct whose required values can't be sent as `null`
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, Deserialize), reject_null)]
+#[allow(dead_code)]
+struct Shipment {
+    pub carrier: String,
+    pub weight: u32,
+    pub tracking: Option<String>,
+}
+
+#[test]
+fn test_reject_null() {
+    let patch: ShipmentOptional = serde_json::from_str(r#"{ "weight": 12, "tracking": null }"#).unwrap();
+    assert_eq!(patch.set_fields(), vec!["weight"]);
+
+    let err = serde_json::from_str::<ShipmentOptional>(r#"{ "carrier": null }"#).unwrap_err();
+    assert!(err.to_string().contains("`carrier` cannot be null"));
+}
+
+/// Test struct whose patches are written and read back with `reject_null`
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq, Serialize, Deserialize), reject_null)]
+#[allow(dead_code)]
+struct Parcel {
+    pub carrier: String,
+    pub weight: u32,
+    pub tracking: Option<String>,
+}
+
+#[test]
+fn test_reject_null_round_trip() {
+    let patch = ParcelOptional {
+        carrier: None,
+        weight: Some(3),
+        tracking: None,
+    };
+    let json = serde_json::to_string(&patch).unwrap();
+    // Fields that were `Option`s may be `null`, and are still written as such
+    assert_eq!(json, r#"{"weight":3,"tracking":null}"#);
+    assert_eq!(serde_json::from_str::<ParcelOptional>(&json).unwrap(), patch);
+}
+
+/// Test struct patched through operations
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug, PartialEq, Serialize, Deserialize), ops)]
+pub struct PostStats {
+    pub title: String,
+    pub views: u64,
+    pub tags: Vec<String>,
+    pub pinned_by: Option<String>,
+}
+
+#[test]
+fn test_ops() {
+    let mut stats = PostStats {
+        title: "Hello".to_string(),
+        views: 10,
+        tags: vec!["rust".to_string(), "draft".to_string()],
+        pinned_by: Some("ada".to_string()),
+    };
+
+    let ops: PostStatsOptionalOps = serde_json::from_value(serde_json::json!({
+        "views": { "increment": 5 },
+        "tags": { "remove": "draft" },
+        "pinned_by": "unset",
+    }))
+    .unwrap();
+    assert_eq!(ops.views, Some(PostStatsOptionalNumOp::Increment(5)));
+    ops.apply_to(&mut stats);
+    assert_eq!(
+        stats,
+        PostStats {
+            title: "Hello".to_string(),
+            views: 15,
+            tags: vec!["rust".to_string()],
+            pinned_by: None,
+        }
+    );
+
+    let ops = PostStatsOptionalOps {
+        title: Some(PostStatsOptionalOp::Set("Hi".to_string())),
+        views: Some(PostStatsOptionalNumOp::Decrement(1)),
+        tags: Some(PostStatsOptionalListOp::Push("news".to_string())),
+        pinned_by: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&ops).unwrap(),
+        serde_json::json!({
+            "title": { "set": "Hi" },
+            "views": { "decrement": 1 },
+            "tags": { "push": "news" },
+        })
+    );
+    ops.apply_to(&mut stats);
+    assert_eq!((stats.title.as_str(), stats.views), ("Hi", 14));
+    assert_eq!(stats.tags, vec!["rust".to_string(), "news".to_string()]);
+}
+
+/// Test structs whose generated struct is checked for traits at compile time
+#[derive(Optionalize)]
+#[optionalize(derive(Clone, Serialize), assert(Serialize, Send, Sync, Clone))]
+#[allow(dead_code)]
+struct Webhook {
+    pub url: String,
+    pub retries: u8,
+}
+
+#[derive(Optionalize)]
+#[optionalize(derive(Clone), assert(Clone, Send))]
+#[allow(dead_code)]
+struct Envelope<T: Clone + Send> {
+    pub payload: T,
+}
+
+#[test]
+fn test_trait_assertions() {
+    let patch = WebhookOptional {
+        url: Some("https://example.com".to_string()),
+        retries: None,
+    };
+    assert_eq!(
+        serde_json::to_value(patch.clone()).unwrap(),
+        serde_json::json!({ "url": "https://example.com", "retries": null })
+    );
+    assert!(EnvelopeOptional::<u8> { payload: Some(1) }.clone().payload.is_some());
+}
+
+/// Test struct with internal fields serde never reads
+#[derive(Optionalize, Serialize, Deserialize, Debug, PartialEq)]
+#[optionalize(builder, derive(Debug, PartialEq, Deserialize), impls(apply))]
+struct Session {
+    pub user_id: u64,
+    #[serde(skip)]
+    pub cache_hits: u32,
+    #[serde(skip_deserializing)]
+    pub created_at: String,
+    #[serde(skip_deserializing)]
+    #[optionalize(include)]
+    pub expires_at: Option<String>,
+}
+
+#[test]
+fn test_serde_skipped_fields() {
+    // Skipped fields aren't part of the patch, unless they are included
+    let patch: SessionOptional =
+        serde_json::from_str(r#"{"user_id": 7, "cache_hits": 3, "expires_at": "tomorrow"}"#).unwrap();
+    assert_eq!(
+        patch,
+        SessionOptional {
+            user_id: Some(7),
+            expires_at: Some("tomorrow".to_string()),
+        }
+    );
+
+    let mut session = Session::builder().user_id(1).build();
+    assert_eq!(session.cache_hits, 0);
+    session.cache_hits = 12;
+    patch.apply_to(&mut session);
+    assert_eq!(session.user_id, 7);
+    assert_eq!(session.cache_hits, 12);
+}
+
+/// Test struct whose patch uses its own wire names
+#[derive(Optionalize, Serialize, Deserialize)]
+#[optionalize(derive(Debug, PartialEq, Serialize, Deserialize))]
+struct Author {
+    #[optionalize(serde(rename = "displayName"))]
+    pub display_name: String,
+    #[optionalize(serde(alias = "mail"))]
+    pub email: String,
+}
+
+#[test]
+fn test_patch_only_serde_attrs() {
+    let patch: AuthorOptional = serde_json::from_str(r#"{"displayName": "Ada", "mail": "ada@example.com"}"#).unwrap();
+    assert_eq!(
+        patch,
+        AuthorOptional {
+            display_name: Some("Ada".to_string()),
+            email: Some("ada@example.com".to_string()),
+        }
+    );
+
+    // The original struct keeps its own names
+    let author = Author {
+        display_name: "Ada".to_string(),
+        email: "ada@example.com".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_string(&author).unwrap(),
+        r#"{"display_name":"Ada","email":"ada@example.com"}"#
+    );
+}
+
+/// Test newtypes whose optional form is a plain `Option`
+#[derive(Optionalize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(transparent)]
+#[optionalize(transparent)]
+pub struct Email(pub String);
+
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(transparent, name = "MaybeScore")]
+pub struct Score<T> {
+    pub value: T,
+    pub unit: std::marker::PhantomData<T>,
+}
+
+#[test]
+fn test_transparent_newtype() {
+    let patch: EmailOptional = serde_json::from_str("\"ada@example.com\"").unwrap();
+    assert_eq!(patch, Some("ada@example.com".to_string()));
+    assert_eq!(serde_json::to_string(&Email("ada@example.com".to_string())).unwrap(), "\"ada@example.com\"");
+    assert_eq!(serde_json::to_string(&EmailOptional::None).unwrap(), "null");
+
+    let mut email = Email("old@example.com".to_string());
+    email.apply(None);
+    assert_eq!(email.0, "old@example.com");
+    email.apply_all(vec![patch, None]);
+    assert_eq!(email.into_optional(), Some("ada@example.com".to_string()));
+
+    let mut score = Score { value: 1, unit: std::marker::PhantomData };
+    let patch: MaybeScore<i32> = Some(2);
+    score.apply(patch);
+    assert_eq!(score.value, 2);
+}
+
+/// Test struct sent as compact binary patches
+#[derive(Optionalize)]
+#[optionalize(derive(Debug, PartialEq), compact, double_option)]
+#[allow(dead_code)]
+pub struct Sensor {
+    pub id: u32,
+    pub label: String,
+    pub reading: f64,
+    pub note: Option<String>,
+}
+
+#[test]
+fn test_compact_encoding() {
+    let patch = SensorOptional {
+        id: None,
+        label: None,
+        reading: Some(21.5),
+        note: Some(None),
+    };
+    let bytes = postcard::to_allocvec(&SensorOptionalCompact(patch)).unwrap();
+    // The bitmap, the reading and the cleared note
+    assert_eq!(bytes.len(), 1 + 8 + 1);
+    assert_eq!(bytes[0], 0b1100);
+
+    let SensorOptionalCompact(decoded) = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.reading, Some(21.5));
+    assert_eq!(decoded.note, Some(None));
+    assert_eq!(decoded.label, None);
+
+    // Much smaller than the JSON of the same patch
+    let patch = SensorOptional {
+        id: Some(7),
+        label: Some("kitchen".to_string()),
+        reading: None,
+        note: None,
+    };
+    let bytes = postcard::to_allocvec(&SensorOptionalCompact(patch)).unwrap();
+    assert_eq!(bytes, [0b11, 7, 7, b'k', b'i', b't', b'c', b'h', b'e', b'n']);
+    let SensorOptionalCompact(decoded) = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.label.as_deref(), Some("kitchen"));
+
+    // Fields the struct doesn't have are rejected
+    assert!(postcard::from_bytes::<SensorOptionalCompact>(&[0b10000]).is_err());
+
+    // Self-describing formats get an array
+    let json = serde_json::to_string(&SensorOptionalCompact(SensorOptional::empty())).unwrap();
+    assert_eq!(json, "[0]");
+    let SensorOptionalCompact(decoded) = serde_json::from_str("[1,3]").unwrap();
+    assert_eq!(decoded.id, Some(3));
+}
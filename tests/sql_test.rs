@@ -0,0 +1,148 @@
+use optionalize_macro::Optionalize;
+
+/// Test structs stored as rows of a `users` table
+#[derive(Optionalize)]
+#[optionalize(impls(sql), tokio_postgres, rusqlite)]
+#[allow(dead_code)]
+pub struct Address {
+    pub city: String,
+    pub zip: Option<String>,
+}
+
+#[derive(Optionalize)]
+#[optionalize(impls(sql), tokio_postgres, rusqlite)]
+#[allow(dead_code)]
+struct User {
+    #[optionalize(immutable)]
+    pub id: i64,
+    pub name: String,
+    pub age: Option<i32>,
+    #[optionalize(double_option)]
+    pub bio: Option<String>,
+    #[optionalize(flatten)]
+    pub address: Address,
+}
+
+/// Driver-agnostic value type the set clause binds
+#[derive(Debug, PartialEq)]
+enum SqlValue {
+    Null,
+    Int(i64),
+    Text(String),
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+
+impl From<i32> for SqlValue {
+    fn from(value: i32) -> Self {
+        SqlValue::Int(value.into())
+    }
+}
+
+impl From<Option<String>> for SqlValue {
+    fn from(value: Option<String>) -> Self {
+        value.map_or(SqlValue::Null, SqlValue::Text)
+    }
+}
+
+#[test]
+fn test_set_clause() {
+    let patch = UserOptional {
+        id: Some(7),
+        name: Some("Ada".to_string()),
+        age: Some(37),
+        bio: Some(None),
+        address: AddressOptional {
+            city: Some("Paris".to_string()),
+            zip: None,
+        },
+    };
+
+    let (set, values) = patch.to_set_clause::<SqlValue>(|_, _| "?".to_string());
+    assert_eq!(set, "\"name\" = ?, \"age\" = ?, \"bio\" = ?, \"city\" = ?");
+    assert_eq!(
+        values,
+        [
+            SqlValue::Text("Ada".to_string()),
+            SqlValue::Int(37),
+            SqlValue::Null,
+            SqlValue::Text("Paris".to_string()),
+        ]
+    );
+
+    let (set, _) = patch.to_set_clause::<SqlValue>(|index, _| format!("${}", index));
+    assert_eq!(set, "\"name\" = $1, \"age\" = $2, \"bio\" = $3, \"city\" = $4");
+    let (set, _) = patch.to_set_clause::<SqlValue>(|_, column| format!(":{}", column));
+    assert_eq!(set, "\"name\" = :name, \"age\" = :age, \"bio\" = :bio, \"city\" = :city");
+}
+
+#[test]
+fn test_postgres_set() {
+    let patch = UserOptional {
+        id: Some(7),
+        name: Some("Ada".to_string()),
+        age: None,
+        bio: Some(None),
+        address: AddressOptional {
+            city: Some("Paris".to_string()),
+            zip: None,
+        },
+    };
+
+    let (set, params) = patch.to_postgres_set();
+    assert_eq!(set, "\"name\" = $1, \"bio\" = $2, \"city\" = $3");
+    let params: Vec<String> = params.iter().map(|param| format!("{:?}", param)).collect();
+    assert_eq!(params, ["\"Ada\"", "None", "\"Paris\""]);
+
+    let columns: Vec<&str> = patch.postgres_params().into_iter().map(|(column, _)| column).collect();
+    assert_eq!(columns, ["name", "bio", "city"]);
+}
+
+#[test]
+fn test_sqlite_update() {
+    let connection = rusqlite::Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, age INTEGER, bio TEXT, city TEXT, zip TEXT);
+             INSERT INTO users VALUES (1, 'Ada', 36, 'Mathematician', 'London', 'N1');",
+        )
+        .unwrap();
+
+    let patch = UserOptional {
+        id: None,
+        name: None,
+        age: Some(37),
+        bio: Some(None),
+        address: AddressOptional {
+            city: Some("Paris".to_string()),
+            zip: None,
+        },
+    };
+    assert_eq!(
+        patch.to_sqlite_update("users", "id"),
+        "UPDATE \"users\" SET \"age\" = ?1, \"bio\" = ?2, \"city\" = ?3 WHERE \"id\" = ?4"
+    );
+    assert_eq!(patch.sqlite_update(&connection, "users", "id", &1).unwrap(), 1);
+
+    // Quotes in names stay inside the identifier
+    assert_eq!(
+        patch.to_sqlite_update("users\" SET age = 0; --", "id"),
+        "UPDATE \"users\"\" SET age = 0; --\" SET \"age\" = ?1, \"bio\" = ?2, \"city\" = ?3 WHERE \"id\" = ?4"
+    );
+    assert!(patch.sqlite_update(&connection, "users\" SET age = 0; --", "id", &1).is_err());
+
+    let row: (String, i32, Option<String>, String, String) = connection
+        .query_row("SELECT name, age, bio, city, zip FROM users WHERE id = 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })
+        .unwrap();
+    assert_eq!(row, ("Ada".to_string(), 37, None, "Paris".to_string(), "N1".to_string()));
+
+    // Empty patches don't touch the table
+    let empty = UserOptional::empty();
+    assert_eq!(empty.sqlite_update(&connection, "users", "id", &1).unwrap(), 0);
+}
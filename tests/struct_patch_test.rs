@@ -0,0 +1,55 @@
+use optionalize_macro::Optionalize;
+use struct_patch::Patch;
+
+/// Test struct patched through the `struct_patch::Patch` trait
+#[derive(Optionalize, Debug, Clone, PartialEq)]
+#[optionalize(derive(Debug, PartialEq), struct_patch, impls(apply, diff))]
+struct Settings {
+    pub theme: String,
+    pub font_size: u8,
+    pub locale: Option<String>,
+}
+
+/// Generic code written against `struct_patch`
+fn patch_twice<T: Patch<P>, P>(value: &mut T, first: P, second: P) {
+    value.apply(first);
+    value.apply(second);
+}
+
+#[test]
+fn test_struct_patch() {
+    let defaults = Settings {
+        theme: "light".to_string(),
+        font_size: 12,
+        locale: None,
+    };
+    let mut settings = defaults.clone();
+
+    let mut first = Settings::new_empty_patch();
+    first.theme = Some("dark".to_string());
+    let mut second = SettingsOptional::empty();
+    second.font_size = Some(14);
+    patch_twice(&mut settings, first, second);
+    assert_eq!(
+        settings,
+        Settings {
+            theme: "dark".to_string(),
+            font_size: 14,
+            locale: None,
+        }
+    );
+
+    let patch = settings.clone().into_patch_by_diff(defaults.clone());
+    assert_eq!(
+        patch,
+        SettingsOptional {
+            theme: Some("dark".to_string()),
+            font_size: Some(14),
+            locale: None,
+        }
+    );
+
+    let mut restored = defaults;
+    restored.apply(settings.clone().into_patch());
+    assert_eq!(restored, settings);
+}
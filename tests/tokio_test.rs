@@ -0,0 +1,42 @@
+use optionalize_macro::Optionalize;
+use tokio_stream::StreamExt;
+
+/// Test struct kept in sync with a stream of patches
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(derive(Debug), tokio, impls(apply))]
+pub struct Lamp {
+    pub on: bool,
+    #[optionalize(range(max = 100))]
+    pub brightness: u8,
+    pub color: String,
+}
+
+#[tokio::test]
+async fn test_apply_stream() {
+    let mut lamp = Lamp {
+        on: false,
+        brightness: 50,
+        color: "white".to_string(),
+    };
+    let patches = tokio_stream::iter(vec![
+        LampOptional { on: Some(true), ..LampOptional::empty() },
+        LampOptional { brightness: Some(200), color: Some("red".to_string()), ..LampOptional::empty() },
+        LampOptional { brightness: Some(80), color: Some("amber".to_string()), ..LampOptional::empty() },
+    ]);
+
+    let summaries: Vec<_> = LampOptional::apply_stream(&mut lamp, patches).collect().await;
+    assert_eq!(summaries.len(), 3);
+    assert_eq!(summaries[0].as_ref().unwrap(), &vec!["on"]);
+    assert!(summaries[1].is_err());
+    assert_eq!(summaries[2].as_ref().unwrap(), &vec!["brightness", "color"]);
+
+    // The rejected patch left no trace
+    assert_eq!(
+        lamp,
+        Lamp {
+            on: true,
+            brightness: 80,
+            color: "amber".to_string(),
+        }
+    );
+}
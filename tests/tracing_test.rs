@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+
+use optionalize_macro::Optionalize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+/// Subscriber with a single span, keeping the recorded span fields and event
+/// fields as text
+#[derive(Clone, Default)]
+struct Recorder {
+    fields: Arc<Mutex<Vec<String>>>,
+    span: Arc<Mutex<Option<&'static Metadata<'static>>>>,
+}
+
+impl Visit for Recorder {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.fields.lock().unwrap().push(format!("{}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        *self.span.lock().unwrap() = Some(attributes.metadata());
+        Id::from_u64(1)
+    }
+
+    fn current_span(&self) -> Current {
+        match *self.span.lock().unwrap() {
+            Some(metadata) => Current::new(Id::from_u64(1), metadata),
+            None => Current::none(),
+        }
+    }
+
+    fn record(&self, _: &Id, values: &Record<'_>) {
+        values.record(&mut self.clone());
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        event.record(&mut self.clone());
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// Test struct whose patches are traced along with their values
+#[derive(Optionalize)]
+#[optionalize(derive(Debug), trace_values, tracing, impls(apply))]
+#[allow(dead_code)]
+struct Credentials {
+    pub username: String,
+    #[optionalize(sensitive)]
+    pub password: String,
+    pub enabled: bool,
+}
+
+#[test]
+fn test_traced_apply() {
+    let recorder = Recorder::default();
+    let mut credentials = Credentials {
+        username: "ada".to_string(),
+        password: "hunter2".to_string(),
+        enabled: true,
+    };
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let span = tracing::info_span!("update", patched_fields = tracing::field::Empty);
+        let _guard = span.enter();
+        CredentialsOptional {
+            username: None,
+            password: Some("correct horse".to_string()),
+            enabled: Some(false),
+        }
+        .apply_to(&mut credentials);
+    });
+
+    // Names are recorded on the span and the event, and secrets stay redacted
+    let recorded = recorder.fields.lock().unwrap();
+    assert!(recorded.contains(&"patched_fields=\"password,enabled\"".to_string()));
+    assert!(recorded.contains(&"fields=password,enabled".to_string()));
+    assert!(recorded.iter().any(|field| field.starts_with("value=") && field.contains("[REDACTED]")));
+    assert!(!recorded.iter().any(|field| field.contains("correct horse")));
+}
+
+/// Test struct with a field patches can't change
+#[derive(Optionalize)]
+#[optionalize(tracing, impls(apply))]
+#[allow(dead_code)]
+struct Order {
+    #[optionalize(immutable)]
+    pub id: u64,
+    pub note: String,
+}
+
+#[test]
+fn test_traced_immutable() {
+    let recorder = Recorder::default();
+    let mut order = Order {
+        id: 1,
+        note: String::new(),
+    };
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        order.apply_all([OrderOptional {
+            id: Some(2),
+            note: Some("gift".to_string()),
+        }]);
+    });
+
+    // The skipped field is reported rather than dropped silently
+    assert_eq!((order.id, order.note.as_str()), (1, "gift"));
+    let recorded = recorder.fields.lock().unwrap();
+    assert!(recorded.contains(&"message=skipping immutable fields set by the patch".to_string()));
+    assert!(recorded.iter().any(|field| field.starts_with("error=") && field.contains("id")));
+}
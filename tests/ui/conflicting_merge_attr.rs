@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub struct Pod {
+    #[optionalize(vec_merge = "append", merge_entries)]
+    pub labels: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub struct Pod {
+    #[optionalize(merge_key = "name")]
+    #[optionalize(merge_key = "image")]
+    pub containers: Vec<Container>,
+}
+
+pub struct Container {
+    pub name: String,
+    pub image: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub enum Shape {
+    Circle { radius: f64 },
+    Square { side: f64 },
+}
+
+fn main() {}
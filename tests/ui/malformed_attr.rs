@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+#[optionalize(suffix)]
+pub struct User {
+    pub name: String,
+}
+
+fn main() {}
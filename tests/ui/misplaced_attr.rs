@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+#[optionalize(sensitive)]
+pub struct User {
+    pub password: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+#[optionalize(history)]
+pub struct Document {
+    pub title: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub struct User {
+    #[optionalize(imutable)]
+    pub id: u64,
+}
+
+fn main() {}
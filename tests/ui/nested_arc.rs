@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub struct Address {
+    pub city: String,
+}
+
+#[derive(Optionalize)]
+pub struct User {
+    #[optionalize(nested)]
+    pub address: Arc<Address>,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub struct Point(i32, i32);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use optionalize_macro::Optionalize;
+
+#[derive(Optionalize)]
+pub union Bits {
+    pub int: u32,
+    pub float: f32,
+}
+
+fn main() {}
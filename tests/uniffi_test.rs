@@ -0,0 +1,31 @@
+use optionalize_macro::Optionalize;
+use uniffi::{Lift, Lower};
+
+uniffi::setup_scaffolding!();
+
+/// Test struct whose patches cross the FFI boundary
+#[derive(Optionalize)]
+#[optionalize(uniffi, derive(Debug, PartialEq))]
+#[allow(dead_code)]
+pub struct Account {
+    pub name: String,
+    pub age: u32,
+}
+
+#[test]
+fn test_uniffi_record() {
+    let patch = AccountOptional {
+        name: None,
+        age: Some(37),
+    };
+    let mut buf = Vec::new();
+    <AccountOptional as Lower<UniFfiTag>>::write(patch, &mut buf);
+    let read = <AccountOptional as Lift<UniFfiTag>>::try_read(&mut buf.as_slice()).unwrap();
+    assert_eq!(
+        read,
+        AccountOptional {
+            name: None,
+            age: Some(37),
+        }
+    );
+}
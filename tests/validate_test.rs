@@ -0,0 +1,238 @@
+use optionalize_macro::Optionalize;
+
+/// Test struct with an all-or-none address group
+#[derive(Optionalize)]
+#[optionalize(impls(apply))]
+#[allow(dead_code)]
+struct Customer {
+    pub name: String,
+    #[optionalize(group = "address")]
+    pub street: String,
+    #[optionalize(group = "address")]
+    pub city: String,
+    #[optionalize(group = "address")]
+    pub zip: Option<String>,
+}
+
+#[test]
+fn test_validate_groups() {
+    // No address fields set at all is fine
+    let patch = CustomerOptional {
+        name: Some("Ada".to_string()),
+        street: None,
+        city: None,
+        zip: None,
+    };
+    assert_eq!(patch.validate(), Ok(()));
+
+    // Every address field set is fine too
+    let patch = CustomerOptional {
+        name: None,
+        street: Some("1 Main St".to_string()),
+        city: Some("Springfield".to_string()),
+        zip: Some("12345".to_string()),
+    };
+    assert_eq!(patch.validate(), Ok(()));
+
+    // A partially set group is rejected with the missing fields listed
+    let patch = CustomerOptional {
+        name: None,
+        street: Some("1 Main St".to_string()),
+        city: None,
+        zip: None,
+    };
+    assert_eq!(
+        patch.validate(),
+        Err(CustomerOptionalError::IncompleteGroup {
+            group: "address",
+            missing: vec!["city", "zip"],
+        })
+    );
+}
+
+const MAX_AGE: u8 = 150;
+
+/// Test struct with value checks
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(impls(apply))]
+struct Member {
+    #[optionalize(regex = "^[a-z0-9_]+$")]
+    pub handle: String,
+    #[optionalize(range(min = 13, max = "MAX_AGE"))]
+    pub age: u8,
+    #[optionalize(range(min = "-12"))]
+    pub utc_offset: Option<i8>,
+}
+
+#[test]
+fn test_validate_field_checks() {
+    let mut member = Member {
+        handle: "ada".to_string(),
+        age: 36,
+        utc_offset: None,
+    };
+
+    // Values within their checks pass
+    let patch = MemberOptional {
+        handle: Some("grace_h".to_string()),
+        age: Some(MAX_AGE),
+        utc_offset: Some(-5),
+    };
+    assert_eq!(patch.validate(), Ok(()));
+
+    // A failed check is reported by field, and `try_apply` leaves the target untouched
+    let patch = MemberOptional {
+        handle: Some("Grace Hopper".to_string()),
+        age: Some(40),
+        utc_offset: None,
+    };
+    assert_eq!(
+        patch.try_apply(&mut member),
+        Err(MemberOptionalError::InvalidField {
+            field: "handle",
+            message: "must match `^[a-z0-9_]+$`".to_string(),
+        })
+    );
+    assert_eq!(member.age, 36);
+
+    let patch = MemberOptional {
+        handle: None,
+        age: Some(12),
+        utc_offset: Some(-13),
+    };
+    assert_eq!(
+        patch.validate(),
+        Err(MemberOptionalError::InvalidField {
+            field: "age",
+            message: "must be at least 13".to_string(),
+        })
+    );
+
+    let patch = MemberOptional {
+        handle: None,
+        age: None,
+        utc_offset: Some(-13),
+    };
+    assert_eq!(
+        patch.validate(),
+        Err(MemberOptionalError::InvalidField {
+            field: "utc_offset",
+            message: "must be at least -12".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_try_apply_reports_every_error() {
+    let mut member = Member {
+        handle: "ada".to_string(),
+        age: 36,
+        utc_offset: None,
+    };
+
+    // Every failed check is reported, not just the first
+    let patch = MemberOptional {
+        handle: Some("Ada!".to_string()),
+        age: Some(12),
+        utc_offset: Some(-13),
+    };
+    assert_eq!(patch.validate_all().len(), 3);
+    let errors = patch.try_apply(&mut member).unwrap_err().into_errors();
+    let fields: Vec<_> = errors
+        .iter()
+        .map(|err| match err {
+            MemberOptionalError::InvalidField { field, .. } => *field,
+            other => panic!("unexpected error {}", other),
+        })
+        .collect();
+    assert_eq!(fields, vec!["handle", "age", "utc_offset"]);
+    assert_eq!(member.age, 36);
+
+    // Immutable fields are reported alongside failed checks
+    let mut account = Account {
+        handle: "ada".to_string(),
+        id: 1,
+    };
+    let patch = AccountOptional {
+        handle: Some("Ada".to_string()),
+        id: Some(2),
+    };
+    let err = patch.try_apply(&mut account).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "2 errors: invalid value for `handle`: must match `^[a-z]+$`; immutable fields cannot be changed: id"
+    );
+}
+
+/// Handles that are already taken, as a stand-in for a database lookup
+const TAKEN_HANDLES: &[&str] = &["admin", "root"];
+
+async fn check_handle_available(patch: &AccountOptional, _account: &Account) -> Result<(), AccountOptionalError> {
+    match &patch.handle {
+        Some(handle) if TAKEN_HANDLES.contains(&handle.as_str()) => Err(AccountOptionalError::InvalidField {
+            field: "handle",
+            message: "is already taken".to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Test struct with an async validation
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(async_validate = "check_handle_available", impls(apply))]
+struct Account {
+    #[optionalize(regex = "^[a-z]+$")]
+    pub handle: String,
+    #[optionalize(immutable)]
+    pub id: u32,
+}
+
+/// Polls `future` to completion, for futures that never wait on anything
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn test_try_apply_async() {
+    let mut account = Account {
+        handle: "ada".to_string(),
+        id: 1,
+    };
+
+    // The async hook rejects the patch, leaving the target untouched
+    let patch = AccountOptional {
+        handle: Some("admin".to_string()),
+        id: None,
+    };
+    assert_eq!(
+        block_on(patch.try_apply_async(&mut account)),
+        Err(AccountOptionalError::InvalidField {
+            field: "handle",
+            message: "is already taken".to_string(),
+        })
+    );
+    assert_eq!(account.handle, "ada");
+
+    // The synchronous checks still apply
+    let patch = AccountOptional {
+        handle: Some("grace".to_string()),
+        id: Some(2),
+    };
+    assert_eq!(
+        block_on(patch.try_apply_async(&mut account)),
+        Err(AccountOptionalError::Immutable { fields: vec!["id"] })
+    );
+
+    let patch = AccountOptional {
+        handle: Some("grace".to_string()),
+        id: None,
+    };
+    assert_eq!(block_on(patch.try_apply_async(&mut account)), Ok(()));
+    assert_eq!(account.handle, "grace");
+}
@@ -0,0 +1,44 @@
+use optionalize_macro::Optionalize;
+use zeroize::Zeroize;
+
+/// Test struct whose credential-bearing patches are wiped
+#[derive(Optionalize, Debug, PartialEq)]
+#[optionalize(zeroize, impls(apply))]
+pub struct Login {
+    pub username: String,
+    #[optionalize(sensitive)]
+    pub password: String,
+    #[optionalize(sensitive)]
+    pub otp_seed: Option<Vec<u8>>,
+}
+
+#[test]
+fn test_zeroize_sensitive_fields() {
+    let mut patch = LoginOptional {
+        username: Some("ada".to_string()),
+        password: Some("hunter2".to_string()),
+        otp_seed: Some(vec![1, 2, 3]),
+    };
+
+    // Only the sensitive values are wiped
+    patch.zeroize();
+    assert_eq!(patch.username.as_deref(), Some("ada"));
+    assert_eq!(patch.password, None);
+    assert_eq!(patch.otp_seed, None);
+
+    // Applying still writes the new values over the wiped old ones
+    let mut login = Login {
+        username: "ada".to_string(),
+        password: "old".to_string(),
+        otp_seed: None,
+    };
+    LoginOptional {
+        username: None,
+        password: Some("new".to_string()),
+        otp_seed: Some(vec![9]),
+    }
+    .apply_to(&mut login);
+    assert_eq!(login.password, "new");
+    assert_eq!(login.otp_seed, Some(vec![9]));
+    assert_eq!(login.username, "ada");
+}